@@ -0,0 +1,187 @@
+use super::{LoadBalancingAlgorithm, RequestContext, ServerState};
+use crate::rng::SharedRng;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Virtual nodes placed on the ring per server by default
+pub const DEFAULT_VIRTUAL_NODES: usize = 100;
+
+// Distinct server lists whose rings are kept before the cache starts over
+const MAX_CACHED_RINGS: usize = 64;
+
+type Ring = BTreeMap<u64, String>;
+
+/// Consistent hashing on a ring of virtual nodes, keyed by client IP.
+/// Adding or removing a server only remaps the keys that land on its nodes.
+#[derive(Clone)]
+pub struct ConsistentHash {
+    virtual_nodes: usize,
+    // One ring per server list seen, since the balancer filters the list
+    // per request (draining, ejected and already-tried backends)
+    rings: Arc<RwLock<HashMap<Vec<String>, Arc<Ring>>>>,
+    requests_served: Arc<RwLock<HashMap<String, usize>>>,
+    rng: SharedRng,
+}
+
+impl Default for ConsistentHash {
+    fn default() -> Self {
+        Self::new(DEFAULT_VIRTUAL_NODES)
+    }
+}
+
+impl ConsistentHash {
+    pub fn new(virtual_nodes: usize) -> Self {
+        Self {
+            virtual_nodes: virtual_nodes.max(1),
+            rings: Arc::new(RwLock::new(HashMap::new())),
+            requests_served: Arc::new(RwLock::new(HashMap::new())),
+            rng: SharedRng::default(),
+        }
+    }
+
+    /// Draw the keys used without a client address from `rng`
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Requests sent to each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        ServerState::from_requests(&*self.requests_served.read().await)
+    }
+
+    fn hash(key: &str) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Ring built from exactly `servers`, so concurrent callers with other
+    // lists never see each other's servers
+    async fn ring_for(&self, servers: &[String]) -> Arc<Ring> {
+        if let Some(ring) = self.rings.read().await.get(servers) {
+            return Arc::clone(ring);
+        }
+
+        let mut ring = Ring::new();
+        for server in servers {
+            for node in 0..self.virtual_nodes {
+                ring.insert(Self::hash(&format!("{}#{}", server, node)), server.clone());
+            }
+        }
+        let mut rings = self.rings.write().await;
+        if rings.len() >= MAX_CACHED_RINGS {
+            rings.clear();
+        }
+        Arc::clone(
+            rings
+                .entry(servers.to_vec())
+                .or_insert_with(|| Arc::new(ring)),
+        )
+    }
+
+    /// Server owning `key`: the first ring node clockwise from its hash
+    pub async fn server_for_key(&self, servers: &[String], key: &str) -> Option<String> {
+        if servers.is_empty() {
+            return None;
+        }
+        let ring = self.ring_for(servers).await;
+
+        let hash = Self::hash(key);
+        ring.range(hash..)
+            .next()
+            .or_else(|| ring.iter().next())
+            .map(|(_, server)| server.clone())
+            .filter(|server| servers.contains(server))
+    }
+
+    async fn select(&self, servers: &[String], key: &str) -> Option<String> {
+        let server = self.server_for_key(servers, key).await?;
+        let mut requests = self.requests_served.write().await;
+        *requests.entry(server.clone()).or_insert(0) += 1;
+        Some(server)
+    }
+}
+
+impl LoadBalancingAlgorithm for ConsistentHash {
+    fn next_server<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        // Without a client to key on, spread requests with a random key
+        Box::pin(async move {
+            let key = self.rng.clone().gen::<u64>().to_string();
+            self.select(servers, &key).await
+        })
+    }
+
+    fn next_server_for<'a>(
+        &'a self,
+        servers: &'a [String],
+        request: &'a RequestContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        match request.client_addr {
+            Some(addr) => Box::pin(async move {
+                let key = addr.ip().to_canonical().to_string();
+                self.select(servers, &key).await
+            }),
+            None => self.next_server(servers),
+        }
+    }
+
+    fn connection_started(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn connection_ended(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.requests_served.write().await.clear();
+        })
+    }
+
+    fn get_metrics(
+        &self,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = HashMap<String, String>> + Send + 'static>,
+    > {
+        let this = self.clone();
+        Box::pin(async move {
+            let requests = this.requests_served.read().await;
+            let total_requests: usize = requests.values().sum();
+
+            requests
+                .iter()
+                .map(|(server, count)| {
+                    let percentage = if total_requests > 0 {
+                        (*count as f64 / total_requests as f64) * 100.0
+                    } else {
+                        0.0
+                    };
+                    (
+                        server.clone(),
+                        format!(
+                            "Requests: {}, Distribution: {:.1}%, Virtual nodes: {}",
+                            count, percentage, this.virtual_nodes
+                        ),
+                    )
+                })
+                .collect()
+        })
+    }
+}
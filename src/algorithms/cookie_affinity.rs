@@ -0,0 +1,153 @@
+use super::{Algorithm, LoadBalancingAlgorithm, RequestContext, ServerState};
+use crate::rng::SharedRng;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::{sync::RwLock, time::Duration};
+
+/// Cookie carrying the session ID used for affinity
+pub const SESSION_COOKIE: &str = "LB_SESSION";
+
+/// Sticky sessions: pins each session to the backend picked for its first request
+#[derive(Clone)]
+pub struct CookieAffinity {
+    inner: Box<Algorithm>,
+    sessions: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl CookieAffinity {
+    /// Wrap `inner`, which picks the backend for sessions not yet pinned
+    pub fn new(inner: Algorithm) -> Self {
+        Self {
+            inner: Box::new(inner),
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Make the inner algorithm's random choices from `rng`
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.inner = Box::new(self.inner.with_rng(rng));
+        self
+    }
+
+    /// State of the inner algorithm, which makes every pick
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        self.inner.state().await.servers
+    }
+
+    /// Forget every session pinned to a backend that was removed
+    pub async fn evict_server(&self, server: &str) {
+        let mut sessions = self.sessions.write().await;
+        sessions.retain(|_, pinned| pinned != server);
+    }
+
+    async fn select(&self, servers: &[String], request: &RequestContext) -> Option<String> {
+        let session = match request.session.as_deref() {
+            Some(session) => session,
+            None => return self.inner.next_server(servers).await,
+        };
+
+        {
+            let mut sessions = self.sessions.write().await;
+            match sessions.get(session) {
+                Some(server) if servers.contains(server) || request.draining.contains(server) => {
+                    return Some(server.clone())
+                }
+                // Pinned backend is gone, so re-pin below
+                Some(_) => {
+                    sessions.remove(session);
+                }
+                None => {}
+            }
+        }
+
+        let server = self.inner.next_server(servers).await?;
+        let mut sessions = self.sessions.write().await;
+        sessions.insert(session.to_string(), server.clone());
+        Some(server)
+    }
+}
+
+impl LoadBalancingAlgorithm for CookieAffinity {
+    fn next_server<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        self.inner.next_server(servers)
+    }
+
+    fn next_server_for<'a>(
+        &'a self,
+        servers: &'a [String],
+        request: &'a RequestContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        Box::pin(async move { self.select(servers, request).await })
+    }
+
+    fn session_cookie(&self) -> Option<&'static str> {
+        Some(SESSION_COOKIE)
+    }
+
+    fn initialize<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        self.inner.initialize(servers)
+    }
+
+    fn connection_started(
+        &self,
+        server: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        self.inner.connection_started(server)
+    }
+
+    fn connection_ended(
+        &self,
+        server: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        self.inner.connection_ended(server)
+    }
+
+    fn record_latency(
+        &self,
+        server: &str,
+        latency: Duration,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        self.inner.record_latency(server, latency)
+    }
+
+    fn record_result(
+        &self,
+        server: &str,
+        latency: Option<Duration>,
+        success: bool,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        self.inner.record_result(server, latency, success)
+    }
+
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.inner.reset_metrics().await;
+        })
+    }
+
+    fn get_metrics(
+        &self,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = HashMap<String, String>> + Send + 'static>,
+    > {
+        let this = self.clone();
+        Box::pin(async move {
+            let mut metrics = this.inner.get_metrics().await;
+            let sessions = this.sessions.read().await;
+            for (server, metric) in metrics.iter_mut() {
+                let pinned = sessions.values().filter(|s| *s == server).count();
+                metric.push_str(&format!(", Sessions: {}", pinned));
+            }
+            metrics
+        })
+    }
+}
@@ -0,0 +1,137 @@
+use super::{LoadBalancingAlgorithm, ServerState};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::{sync::RwLock, time::Duration};
+
+/// Weight given to each new latency sample by default
+pub const DEFAULT_EWMA_DECAY: f64 = 0.5;
+
+/// Routes to the backend with the lowest exponentially weighted moving average
+/// latency. Each sample moves the average by `decay` of the difference, so a
+/// backend that suddenly slows down is avoided within a few requests.
+#[derive(Clone)]
+pub struct Ewma {
+    decay: f64,
+    averages: Arc<RwLock<HashMap<String, f64>>>,
+    requests_served: Arc<RwLock<HashMap<String, usize>>>,
+}
+
+impl Default for Ewma {
+    fn default() -> Self {
+        Self::new(DEFAULT_EWMA_DECAY)
+    }
+}
+
+impl Ewma {
+    /// `decay` is the weight (0.0-1.0) of the newest sample
+    pub fn new(decay: f64) -> Self {
+        Self {
+            decay: decay.clamp(0.0, 1.0),
+            averages: Arc::new(RwLock::new(HashMap::new())),
+            requests_served: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Requests sent to each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        ServerState::from_requests(&*self.requests_served.read().await)
+    }
+
+    pub async fn record_latency(&self, server: &str, latency: Duration) {
+        let sample = latency.as_secs_f64() * 1000.0;
+        let mut averages = self.averages.write().await;
+        averages
+            .entry(server.to_string())
+            .and_modify(|average| *average += self.decay * (sample - *average))
+            .or_insert(sample);
+    }
+
+    async fn record_request(&self, server: &str) {
+        let mut requests = self.requests_served.write().await;
+        *requests.entry(server.to_string()).or_insert(0) += 1;
+    }
+}
+
+impl LoadBalancingAlgorithm for Ewma {
+    fn next_server<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        Box::pin(async move {
+            // Servers without samples yet score zero, so each gets tried
+            let server = {
+                let averages = self.averages.read().await;
+                servers
+                    .iter()
+                    .min_by(|a, b| {
+                        let a = averages.get(*a).unwrap_or(&0.0);
+                        let b = averages.get(*b).unwrap_or(&0.0);
+                        a.total_cmp(b)
+                    })
+                    .cloned()?
+            };
+            self.record_request(&server).await;
+            Some(server)
+        })
+    }
+
+    fn connection_started(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn connection_ended(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn record_latency(
+        &self,
+        server: &str,
+        latency: Duration,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let server = server.to_string();
+        let this = self.clone();
+        Box::pin(async move {
+            this.record_latency(&server, latency).await;
+        })
+    }
+
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.requests_served.write().await.clear();
+        })
+    }
+
+    fn get_metrics(
+        &self,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = HashMap<String, String>> + Send + 'static>,
+    > {
+        let this = self.clone();
+        Box::pin(async move {
+            let averages = this.averages.read().await;
+            let requests = this.requests_served.read().await;
+            requests
+                .iter()
+                .map(|(server, count)| {
+                    (
+                        server.clone(),
+                        format!(
+                            "EWMA: {:.1}ms, Requests: {}",
+                            averages.get(server).unwrap_or(&0.0),
+                            count
+                        ),
+                    )
+                })
+                .collect()
+        })
+    }
+}
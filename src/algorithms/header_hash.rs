@@ -0,0 +1,137 @@
+use super::{ConsistentHash, LoadBalancingAlgorithm, RequestContext, RoundRobin, ServerState};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Header hashed by `header-hash` unless configured otherwise
+pub const DEFAULT_AFFINITY_HEADER: &str = "X-User-Id";
+
+/// Affinity by request header: requests with the same value of the header
+/// (e.g. a user ID) land on the same backend via a consistent-hash ring.
+/// Requests without the header are spread round-robin.
+#[derive(Clone)]
+pub struct HeaderHash {
+    header: String,
+    ring: ConsistentHash,
+    fallback: RoundRobin,
+    requests_served: Arc<RwLock<HashMap<String, usize>>>,
+}
+
+impl Default for HeaderHash {
+    fn default() -> Self {
+        Self::new(DEFAULT_AFFINITY_HEADER)
+    }
+}
+
+impl HeaderHash {
+    pub fn new(header: &str) -> Self {
+        Self {
+            header: header.to_string(),
+            ring: ConsistentHash::default(),
+            fallback: RoundRobin::new(),
+            requests_served: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Requests sent to each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        ServerState::from_requests(&*self.requests_served.read().await)
+    }
+
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    async fn select(&self, servers: &[String], request: &RequestContext) -> Option<String> {
+        let server = match request.affinity_key.as_deref() {
+            Some(key) => self.ring.server_for_key(servers, key).await?,
+            None => self.fallback.next_server(servers).await?,
+        };
+        let mut requests = self.requests_served.write().await;
+        *requests.entry(server.clone()).or_insert(0) += 1;
+        Some(server)
+    }
+}
+
+impl LoadBalancingAlgorithm for HeaderHash {
+    fn next_server<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        Box::pin(async move { self.select(servers, &RequestContext::default()).await })
+    }
+
+    fn next_server_for<'a>(
+        &'a self,
+        servers: &'a [String],
+        request: &'a RequestContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        Box::pin(async move { self.select(servers, request).await })
+    }
+
+    fn affinity_header(&self) -> Option<&str> {
+        Some(&self.header)
+    }
+
+    fn initialize<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            let mut requests = self.requests_served.write().await;
+            for server in servers {
+                requests.entry(server.clone()).or_insert(0);
+            }
+        })
+    }
+
+    fn connection_started(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn connection_ended(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.requests_served.write().await.clear();
+        })
+    }
+
+    fn get_metrics(
+        &self,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = HashMap<String, String>> + Send + 'static>,
+    > {
+        let this = self.clone();
+        Box::pin(async move {
+            let requests = this.requests_served.read().await;
+            let total_requests: usize = requests.values().sum();
+
+            requests
+                .iter()
+                .map(|(server, count)| {
+                    let percentage = if total_requests > 0 {
+                        (*count as f64 / total_requests as f64) * 100.0
+                    } else {
+                        0.0
+                    };
+                    (
+                        server.clone(),
+                        format!("Requests: {}, Distribution: {:.1}%", count, percentage),
+                    )
+                })
+                .collect()
+        })
+    }
+}
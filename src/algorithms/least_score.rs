@@ -0,0 +1,206 @@
+use super::{LoadBalancingAlgorithm, ServerState};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::{
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+/// Score points per millisecond of p95 latency by default
+pub const DEFAULT_LATENCY_WEIGHT: f64 = 1.0;
+/// Score points per percentage point of failed requests by default
+pub const DEFAULT_ERROR_WEIGHT: f64 = 10.0;
+/// Recent requests per backend that go into its score
+pub const SCORE_WINDOW: usize = 100;
+/// How long a request counts toward its backend's score by default
+pub const DEFAULT_SAMPLE_MAX_AGE: Duration = Duration::from_secs(30);
+
+// One finished request: its latency if the backend answered, and whether it
+// counts as a success
+struct Sample {
+    latency: Option<Duration>,
+    success: bool,
+    at: Instant,
+}
+
+/// Routes to the backend with the lowest health score, combining p95 latency
+/// and error rate over its last `SCORE_WINDOW` requests:
+/// `latency_weight * p95_ms + error_weight * error_percent`. Requests older
+/// than the max age stop counting, so a backend shunned after a bad stretch
+/// scores fresh and gets tried again.
+#[derive(Clone)]
+pub struct LeastScore {
+    latency_weight: f64,
+    error_weight: f64,
+    max_age: Duration,
+    samples: Arc<RwLock<HashMap<String, VecDeque<Sample>>>>,
+    requests_served: Arc<RwLock<HashMap<String, usize>>>,
+}
+
+impl Default for LeastScore {
+    fn default() -> Self {
+        Self::new(DEFAULT_LATENCY_WEIGHT, DEFAULT_ERROR_WEIGHT)
+    }
+}
+
+impl LeastScore {
+    pub fn new(latency_weight: f64, error_weight: f64) -> Self {
+        Self {
+            latency_weight: latency_weight.max(0.0),
+            error_weight: error_weight.max(0.0),
+            max_age: DEFAULT_SAMPLE_MAX_AGE,
+            samples: Arc::new(RwLock::new(HashMap::new())),
+            requests_served: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Forget requests once they're older than `max_age`
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Requests sent to each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        ServerState::from_requests(&*self.requests_served.read().await)
+    }
+
+    pub async fn record_result(&self, server: &str, latency: Option<Duration>, success: bool) {
+        let mut samples = self.samples.write().await;
+        let window = samples.entry(server.to_string()).or_default();
+        if window.len() == SCORE_WINDOW {
+            window.pop_front();
+        }
+        window.push_back(Sample {
+            latency,
+            success,
+            at: Instant::now(),
+        });
+    }
+
+    /// Current score for `server` (lower is better), or `None` when it hasn't
+    /// served anything within the max age
+    pub async fn score(&self, server: &str) -> Option<f64> {
+        let samples = self.samples.read().await;
+        samples
+            .get(server)
+            .and_then(|window| self.score_window(window))
+    }
+
+    fn score_window(&self, window: &VecDeque<Sample>) -> Option<f64> {
+        let recent: Vec<&Sample> = window
+            .iter()
+            .filter(|s| s.at.elapsed() <= self.max_age)
+            .collect();
+        if recent.is_empty() {
+            return None;
+        }
+        let failures = recent.iter().filter(|s| !s.success).count();
+        let error_percent = failures as f64 * 100.0 / recent.len() as f64;
+
+        let mut latencies: Vec<f64> = recent
+            .iter()
+            .filter_map(|s| s.latency)
+            .map(|l| l.as_secs_f64() * 1000.0)
+            .collect();
+        latencies.sort_by(f64::total_cmp);
+        let p95 = match latencies.len() {
+            0 => 0.0,
+            n => latencies[(n * 95).div_ceil(100) - 1],
+        };
+
+        Some(self.latency_weight * p95 + self.error_weight * error_percent)
+    }
+
+    async fn record_request(&self, server: &str) {
+        let mut requests = self.requests_served.write().await;
+        *requests.entry(server.to_string()).or_insert(0) += 1;
+    }
+}
+
+impl LoadBalancingAlgorithm for LeastScore {
+    fn next_server<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        Box::pin(async move {
+            // Servers without recent samples score zero, so each gets tried
+            let server = {
+                let samples = self.samples.read().await;
+                let score = |server: &String| {
+                    samples
+                        .get(server)
+                        .and_then(|window| self.score_window(window))
+                        .unwrap_or(0.0)
+                };
+                servers
+                    .iter()
+                    .min_by(|a, b| score(a).total_cmp(&score(b)))
+                    .cloned()?
+            };
+            self.record_request(&server).await;
+            Some(server)
+        })
+    }
+
+    fn connection_started(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn connection_ended(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn record_result(
+        &self,
+        server: &str,
+        latency: Option<Duration>,
+        success: bool,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let server = server.to_string();
+        let this = self.clone();
+        Box::pin(async move {
+            this.record_result(&server, latency, success).await;
+        })
+    }
+
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.requests_served.write().await.clear();
+        })
+    }
+
+    fn get_metrics(
+        &self,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = HashMap<String, String>> + Send + 'static>,
+    > {
+        let this = self.clone();
+        Box::pin(async move {
+            let samples = this.samples.read().await;
+            let requests = this.requests_served.read().await;
+            requests
+                .iter()
+                .map(|(server, count)| {
+                    let score = samples
+                        .get(server)
+                        .and_then(|window| this.score_window(window))
+                        .unwrap_or(0.0);
+                    (
+                        server.clone(),
+                        format!("Score: {:.1}, Requests: {}", score, count),
+                    )
+                })
+                .collect()
+        })
+    }
+}
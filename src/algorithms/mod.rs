@@ -1,7 +1,62 @@
-use rand::{thread_rng, Rng};
-use std::collections::HashMap;
+mod consistent_hash;
+mod cookie_affinity;
+mod ewma;
+mod header_hash;
+mod least_score;
+mod random;
+mod state;
+mod weighted_least_connections;
+mod weighted_p2c;
+
+pub use consistent_hash::{ConsistentHash, DEFAULT_VIRTUAL_NODES};
+pub use cookie_affinity::{CookieAffinity, SESSION_COOKIE};
+pub use ewma::{Ewma, DEFAULT_EWMA_DECAY};
+pub use header_hash::{HeaderHash, DEFAULT_AFFINITY_HEADER};
+pub use least_score::{
+    LeastScore, DEFAULT_ERROR_WEIGHT, DEFAULT_LATENCY_WEIGHT, DEFAULT_SAMPLE_MAX_AGE, SCORE_WINDOW,
+};
+pub use random::Random;
+pub use state::{AlgorithmState, ServerState};
+pub use weighted_least_connections::WeightedLeastConnections;
+pub use weighted_p2c::WeightedP2C;
+
+use crate::rng::SharedRng;
+use rand::Rng;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, time::Duration};
+
+/// Most recent client IPs `IpHash` lists in its metrics; older ones are
+/// forgotten so any number of clients can't grow the list without bound
+pub const MAX_TRACKED_IPS: usize = 64;
+
+/// Names accepted by `Algorithm::new`
+pub const ALGORITHM_NAMES: &[&str] = &[
+    "round-robin",
+    "least-connections",
+    "weighted-round-robin",
+    "weighted-least-connections",
+    "weighted-p2c",
+    "ip-hash",
+    "consistent-hash",
+    "random",
+    "ewma",
+    "least-score",
+    "cookie-affinity",
+    "header-hash",
+];
+
+/// Details of the incoming request that selection can take into account
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub client_addr: Option<SocketAddr>,
+    pub session: Option<String>,
+    /// Value of the algorithm's affinity header, if the request sent it
+    pub affinity_key: Option<String>,
+    /// Backends taking no new work; already-pinned sessions may still use them
+    pub draining: Vec<String>,
+}
 
 /// Trait defining the interface for load balancing algorithms
 pub trait LoadBalancingAlgorithm: Send + Sync + Clone {
@@ -11,6 +66,55 @@ pub trait LoadBalancingAlgorithm: Send + Sync + Clone {
         servers: &'a [String],
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>>;
 
+    /// Select the next server for a specific request (defaults to `next_server`)
+    fn next_server_for<'a>(
+        &'a self,
+        servers: &'a [String],
+        _request: &'a RequestContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        self.next_server(servers)
+    }
+
+    /// Pick from `servers` `n` times in a row and count how often each was
+    /// chosen, for checking an algorithm's distribution directly. Picks are
+    /// recorded as usual but open no connections.
+    fn select_n<'a>(
+        &'a self,
+        servers: &'a [String],
+        n: usize,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = HashMap<String, usize>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let mut counts = HashMap::new();
+            for _ in 0..n {
+                let Some(server) = self.next_server(servers).await else {
+                    break;
+                };
+                *counts.entry(server).or_insert(0) += 1;
+            }
+            counts
+        })
+    }
+
+    /// Set up per-server state for a known server list ahead of traffic, so
+    /// selection needn't build it lazily and metrics list every server
+    fn initialize<'a>(
+        &'a self,
+        _servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        Box::pin(async {})
+    }
+
+    /// Cookie the balancer should maintain for session affinity, if any
+    fn session_cookie(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Request header whose value the balancer should pass on for affinity
+    fn affinity_header(&self) -> Option<&str> {
+        None
+    }
+
     /// Track when a connection starts
     fn connection_started(
         &self,
@@ -23,6 +127,37 @@ pub trait LoadBalancingAlgorithm: Send + Sync + Clone {
         server: &str,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>>;
 
+    /// Feed back how long a request to `server` took
+    fn record_latency(
+        &self,
+        _server: &str,
+        _latency: Duration,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    /// Feed back how a request to `server` ended: its latency if the backend
+    /// answered, and whether it succeeded (no 5xx, no connection failure).
+    /// Defaults to `record_latency` for answered requests
+    fn record_result(
+        &self,
+        server: &str,
+        latency: Option<Duration>,
+        _success: bool,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        match latency {
+            Some(latency) => self.record_latency(server, latency),
+            None => Box::pin(async {}),
+        }
+    }
+
+    /// Clear accumulated counters so metrics reflect only new traffic
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
     /// Get server metrics
     fn get_metrics(
         &self,
@@ -38,6 +173,14 @@ pub enum Algorithm {
     LeastConnections(LeastConnections),
     WeightedRoundRobin(WeightedRoundRobin),
     IpHash(IpHash),
+    CookieAffinity(CookieAffinity),
+    HeaderHash(HeaderHash),
+    WeightedLeastConnections(WeightedLeastConnections),
+    WeightedP2C(WeightedP2C),
+    ConsistentHash(ConsistentHash),
+    Random(Random),
+    Ewma(Ewma),
+    LeastScore(LeastScore),
 }
 
 impl Algorithm {
@@ -49,9 +192,82 @@ impl Algorithm {
                 Algorithm::WeightedRoundRobin(WeightedRoundRobin::new(weights))
             }
             "ip-hash" => Algorithm::IpHash(IpHash::new()),
+            "cookie-affinity" => Algorithm::CookieAffinity(CookieAffinity::new(
+                Algorithm::RoundRobin(RoundRobin::new()),
+            )),
+            "header-hash" => Algorithm::HeaderHash(HeaderHash::default()),
+            "weighted-least-connections" => {
+                Algorithm::WeightedLeastConnections(WeightedLeastConnections::new(weights))
+            }
+            "weighted-p2c" => Algorithm::WeightedP2C(WeightedP2C::new(weights)),
+            "consistent-hash" => {
+                Algorithm::ConsistentHash(ConsistentHash::new(DEFAULT_VIRTUAL_NODES))
+            }
+            "random" => Algorithm::Random(Random::new()),
+            "ewma" => Algorithm::Ewma(Ewma::new(DEFAULT_EWMA_DECAY)),
+            "least-score" => Algorithm::LeastScore(LeastScore::default()),
             _ => Algorithm::RoundRobin(RoundRobin::new()), // Default to round-robin
         }
     }
+
+    /// Make every random choice this algorithm makes from `rng`, so a seeded
+    /// source reproduces its picks
+    pub fn with_rng(self, rng: SharedRng) -> Self {
+        match self {
+            Algorithm::WeightedRoundRobin(wrr) => Algorithm::WeightedRoundRobin(wrr.with_rng(rng)),
+            Algorithm::IpHash(ih) => Algorithm::IpHash(ih.with_rng(rng)),
+            Algorithm::CookieAffinity(ca) => Algorithm::CookieAffinity(ca.with_rng(rng)),
+            Algorithm::ConsistentHash(ch) => Algorithm::ConsistentHash(ch.with_rng(rng)),
+            Algorithm::Random(r) => Algorithm::Random(r.with_rng(rng)),
+            Algorithm::WeightedP2C(p2c) => Algorithm::WeightedP2C(p2c.with_rng(rng)),
+            other => other,
+        }
+    }
+
+    /// Per-server selection state: requests sent, plus active connections
+    /// and weights for the algorithms that track them
+    pub fn state(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = AlgorithmState> + Send + '_>> {
+        Box::pin(async move {
+            let servers = match self {
+                Algorithm::RoundRobin(rr) => rr.state().await,
+                Algorithm::LeastConnections(lc) => lc.state().await,
+                Algorithm::WeightedRoundRobin(wrr) => wrr.state().await,
+                Algorithm::IpHash(ih) => ih.state().await,
+                Algorithm::CookieAffinity(ca) => ca.state().await,
+                Algorithm::HeaderHash(hh) => hh.state().await,
+                Algorithm::WeightedLeastConnections(wlc) => wlc.state().await,
+                Algorithm::WeightedP2C(p2c) => p2c.state().await,
+                Algorithm::ConsistentHash(ch) => ch.state().await,
+                Algorithm::Random(r) => r.state().await,
+                Algorithm::Ewma(e) => e.state().await,
+                Algorithm::LeastScore(ls) => ls.state().await,
+            };
+            AlgorithmState {
+                algorithm: self.name(),
+                servers,
+            }
+        })
+    }
+
+    /// Name this algorithm is registered under in `Algorithm::new`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::RoundRobin(_) => "round-robin",
+            Algorithm::LeastConnections(_) => "least-connections",
+            Algorithm::WeightedRoundRobin(_) => "weighted-round-robin",
+            Algorithm::IpHash(_) => "ip-hash",
+            Algorithm::CookieAffinity(_) => "cookie-affinity",
+            Algorithm::HeaderHash(_) => "header-hash",
+            Algorithm::WeightedLeastConnections(_) => "weighted-least-connections",
+            Algorithm::WeightedP2C(_) => "weighted-p2c",
+            Algorithm::ConsistentHash(_) => "consistent-hash",
+            Algorithm::Random(_) => "random",
+            Algorithm::Ewma(_) => "ewma",
+            Algorithm::LeastScore(_) => "least-score",
+        }
+    }
 }
 
 impl LoadBalancingAlgorithm for Algorithm {
@@ -64,6 +280,55 @@ impl LoadBalancingAlgorithm for Algorithm {
             Algorithm::LeastConnections(lc) => lc.next_server(servers),
             Algorithm::WeightedRoundRobin(wrr) => wrr.next_server(servers),
             Algorithm::IpHash(ih) => ih.next_server(servers),
+            Algorithm::CookieAffinity(ca) => ca.next_server(servers),
+            Algorithm::HeaderHash(hh) => hh.next_server(servers),
+            Algorithm::WeightedLeastConnections(wlc) => wlc.next_server(servers),
+            Algorithm::WeightedP2C(p2c) => p2c.next_server(servers),
+            Algorithm::ConsistentHash(ch) => ch.next_server(servers),
+            Algorithm::Random(r) => r.next_server(servers),
+            Algorithm::Ewma(e) => e.next_server(servers),
+            Algorithm::LeastScore(ls) => ls.next_server(servers),
+        }
+    }
+
+    fn next_server_for<'a>(
+        &'a self,
+        servers: &'a [String],
+        request: &'a RequestContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        match self {
+            Algorithm::CookieAffinity(ca) => ca.next_server_for(servers, request),
+            Algorithm::HeaderHash(hh) => hh.next_server_for(servers, request),
+            Algorithm::ConsistentHash(ch) => ch.next_server_for(servers, request),
+            Algorithm::IpHash(ih) => ih.next_server_for(servers, request),
+            _ => self.next_server(servers),
+        }
+    }
+
+    fn session_cookie(&self) -> Option<&'static str> {
+        match self {
+            Algorithm::CookieAffinity(ca) => ca.session_cookie(),
+            _ => None,
+        }
+    }
+
+    fn affinity_header(&self) -> Option<&str> {
+        match self {
+            Algorithm::HeaderHash(hh) => hh.affinity_header(),
+            _ => None,
+        }
+    }
+
+    fn initialize<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        match self {
+            Algorithm::RoundRobin(rr) => rr.initialize(servers),
+            Algorithm::WeightedRoundRobin(wrr) => wrr.initialize(servers),
+            Algorithm::CookieAffinity(ca) => ca.initialize(servers),
+            Algorithm::HeaderHash(hh) => hh.initialize(servers),
+            _ => Box::pin(async {}),
         }
     }
 
@@ -80,6 +345,16 @@ impl LoadBalancingAlgorithm for Algorithm {
             }
             Algorithm::WeightedRoundRobin(_) => Box::pin(async {}),
             Algorithm::IpHash(_) => Box::pin(async {}),
+            Algorithm::CookieAffinity(ca) => ca.connection_started(&server),
+            Algorithm::HeaderHash(_) => Box::pin(async {}),
+            Algorithm::WeightedLeastConnections(wlc) => {
+                LoadBalancingAlgorithm::connection_started(wlc, &server)
+            }
+            Algorithm::WeightedP2C(p2c) => LoadBalancingAlgorithm::connection_started(p2c, &server),
+            Algorithm::ConsistentHash(_) => Box::pin(async {}),
+            Algorithm::Random(_) => Box::pin(async {}),
+            Algorithm::Ewma(_) => Box::pin(async {}),
+            Algorithm::LeastScore(_) => Box::pin(async {}),
         }
     }
 
@@ -96,9 +371,71 @@ impl LoadBalancingAlgorithm for Algorithm {
             }
             Algorithm::WeightedRoundRobin(_) => Box::pin(async {}),
             Algorithm::IpHash(_) => Box::pin(async {}),
+            Algorithm::CookieAffinity(ca) => ca.connection_ended(&server),
+            Algorithm::HeaderHash(_) => Box::pin(async {}),
+            Algorithm::WeightedLeastConnections(wlc) => {
+                LoadBalancingAlgorithm::connection_ended(wlc, &server)
+            }
+            Algorithm::WeightedP2C(p2c) => LoadBalancingAlgorithm::connection_ended(p2c, &server),
+            Algorithm::ConsistentHash(_) => Box::pin(async {}),
+            Algorithm::Random(_) => Box::pin(async {}),
+            Algorithm::Ewma(_) => Box::pin(async {}),
+            Algorithm::LeastScore(_) => Box::pin(async {}),
+        }
+    }
+
+    fn record_latency(
+        &self,
+        server: &str,
+        latency: Duration,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        match self {
+            Algorithm::Ewma(e) => LoadBalancingAlgorithm::record_latency(e, server, latency),
+            Algorithm::CookieAffinity(ca) => ca.record_latency(server, latency),
+            _ => Box::pin(async {}),
+        }
+    }
+
+    fn record_result(
+        &self,
+        server: &str,
+        latency: Option<Duration>,
+        success: bool,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        match self {
+            Algorithm::LeastScore(ls) => {
+                LoadBalancingAlgorithm::record_result(ls, server, latency, success)
+            }
+            Algorithm::CookieAffinity(ca) => ca.record_result(server, latency, success),
+            _ => match latency {
+                Some(latency) => self.record_latency(server, latency),
+                None => Box::pin(async {}),
+            },
         }
     }
 
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            match &this {
+                Algorithm::RoundRobin(rr) => rr.reset_metrics().await,
+                Algorithm::LeastConnections(lc) => lc.reset_metrics().await,
+                Algorithm::WeightedRoundRobin(wrr) => wrr.reset_metrics().await,
+                Algorithm::IpHash(ih) => ih.reset_metrics().await,
+                Algorithm::CookieAffinity(ca) => ca.reset_metrics().await,
+                Algorithm::HeaderHash(hh) => hh.reset_metrics().await,
+                Algorithm::WeightedLeastConnections(wlc) => wlc.reset_metrics().await,
+                Algorithm::WeightedP2C(p2c) => p2c.reset_metrics().await,
+                Algorithm::ConsistentHash(ch) => ch.reset_metrics().await,
+                Algorithm::Random(r) => r.reset_metrics().await,
+                Algorithm::Ewma(e) => e.reset_metrics().await,
+                Algorithm::LeastScore(ls) => ls.reset_metrics().await,
+            }
+        })
+    }
+
     fn get_metrics(
         &self,
     ) -> std::pin::Pin<
@@ -121,6 +458,20 @@ impl LoadBalancingAlgorithm for Algorithm {
                 let ih = ih.clone();
                 Box::pin(async move { ih.get_metrics().await })
             }
+            Algorithm::CookieAffinity(ca) => ca.get_metrics(),
+            Algorithm::HeaderHash(hh) => hh.get_metrics(),
+            Algorithm::WeightedLeastConnections(wlc) => {
+                let wlc = wlc.clone();
+                Box::pin(async move { wlc.get_metrics().await })
+            }
+            Algorithm::WeightedP2C(p2c) => {
+                let p2c = p2c.clone();
+                Box::pin(async move { p2c.get_metrics().await })
+            }
+            Algorithm::ConsistentHash(ch) => ch.get_metrics(),
+            Algorithm::Random(r) => r.get_metrics(),
+            Algorithm::Ewma(e) => e.get_metrics(),
+            Algorithm::LeastScore(ls) => ls.get_metrics(),
         }
     }
 }
@@ -132,6 +483,12 @@ pub struct RoundRobin {
     requests_served: Arc<RwLock<HashMap<String, usize>>>,
 }
 
+impl Default for RoundRobin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RoundRobin {
     pub fn new() -> Self {
         Self {
@@ -140,6 +497,11 @@ impl RoundRobin {
         }
     }
 
+    /// Requests sent to each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        ServerState::from_requests(&*self.requests_served.read().await)
+    }
+
     async fn record_request(&self, server: &str) {
         let mut requests = self.requests_served.write().await;
         *requests.entry(server.to_string()).or_insert(0) += 1;
@@ -163,6 +525,18 @@ impl LoadBalancingAlgorithm for RoundRobin {
         })
     }
 
+    fn initialize<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            let mut requests = self.requests_served.write().await;
+            for server in servers {
+                requests.entry(server.clone()).or_insert(0);
+            }
+        })
+    }
+
     fn connection_started(
         &self,
         _: &str,
@@ -177,6 +551,15 @@ impl LoadBalancingAlgorithm for RoundRobin {
         Box::pin(async {})
     }
 
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.requests_served.write().await.clear();
+        })
+    }
+
     fn get_metrics(
         &self,
     ) -> std::pin::Pin<
@@ -213,6 +596,12 @@ pub struct LeastConnections {
     successful_requests: Arc<RwLock<HashMap<String, usize>>>,
 }
 
+impl Default for LeastConnections {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LeastConnections {
     pub fn new() -> Self {
         Self {
@@ -229,6 +618,24 @@ impl LeastConnections {
         *total.entry(server.to_string()).or_insert(0) += 1;
     }
 
+    /// Active connections and requests sent for each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        let connections = self.connections.read().await;
+        let total = self.total_requests.read().await;
+        connections
+            .keys()
+            .chain(total.keys())
+            .map(|server| {
+                let state = ServerState {
+                    active_connections: Some(*connections.get(server).unwrap_or(&0)),
+                    total_requests: *total.get(server).unwrap_or(&0),
+                    weight: None,
+                };
+                (server.clone(), state)
+            })
+            .collect()
+    }
+
     pub async fn connection_ended(&self, server: &str) {
         let mut connections = self.connections.write().await;
         let mut successful = self.successful_requests.write().await;
@@ -280,7 +687,7 @@ impl LoadBalancingAlgorithm for LeastConnections {
             servers
                 .iter()
                 .min_by_key(|server| connections.get(*server).unwrap_or(&0))
-                .map(|s| s.clone())
+                .cloned()
         })
     }
 
@@ -306,6 +713,17 @@ impl LoadBalancingAlgorithm for LeastConnections {
         })
     }
 
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.connections.write().await.clear();
+            this.total_requests.write().await.clear();
+            this.successful_requests.write().await.clear();
+        })
+    }
+
     fn get_metrics(
         &self,
     ) -> std::pin::Pin<
@@ -322,23 +740,62 @@ impl LoadBalancingAlgorithm for LeastConnections {
     }
 }
 
-/// Weighted round-robin implementation with randomized weights
+/// Weighted round-robin implementation with randomized weights. A server
+/// weighted 0 gets no new requests but stays in the metrics.
 #[derive(Clone)]
 pub struct WeightedRoundRobin {
-    current: Arc<RwLock<usize>>,
+    // Smooth WRR running totals: each pick adds every server's weight and
+    // takes the combined weight back off the winner
+    current_weights: Arc<RwLock<HashMap<String, i64>>>,
     weights: Arc<RwLock<HashMap<String, u32>>>,
     requests_served: Arc<RwLock<HashMap<String, usize>>>,
+    rng: SharedRng,
 }
 
 impl WeightedRoundRobin {
     pub fn new(weights: Option<HashMap<String, u32>>) -> Self {
         Self {
-            current: Arc::new(RwLock::new(0)),
+            current_weights: Arc::new(RwLock::new(HashMap::new())),
             weights: Arc::new(RwLock::new(weights.unwrap_or_default())),
             requests_served: Arc::new(RwLock::new(HashMap::new())),
+            rng: SharedRng::default(),
         }
     }
 
+    /// Draw weights for unweighted servers from `rng`
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Current weight of every server
+    pub async fn weights(&self) -> HashMap<String, u32> {
+        self.weights.read().await.clone()
+    }
+
+    /// Replace the weights of the given servers, leaving the rest as they are
+    pub async fn set_weights(&self, weights: &HashMap<String, u32>) {
+        self.weights.write().await.extend(weights.clone());
+    }
+
+    /// Weight and requests sent for each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        let weights = self.weights.read().await;
+        let requests = self.requests_served.read().await;
+        weights
+            .keys()
+            .chain(requests.keys())
+            .map(|server| {
+                let state = ServerState {
+                    active_connections: None,
+                    total_requests: *requests.get(server).unwrap_or(&0),
+                    weight: weights.get(server).copied(),
+                };
+                (server.clone(), state)
+            })
+            .collect()
+    }
+
     pub async fn get_metrics(&self) -> HashMap<String, String> {
         let weights = self.weights.read().await;
         let requests = self.requests_served.read().await;
@@ -365,8 +822,15 @@ impl WeightedRoundRobin {
     }
 
     async fn ensure_weights(&self, servers: &[String]) {
+        // Only take the write lock when a server is missing its weight
+        {
+            let weights = self.weights.read().await;
+            if servers.iter().all(|server| weights.contains_key(server)) {
+                return;
+            }
+        }
         let mut weights = self.weights.write().await;
-        let mut rng = thread_rng();
+        let mut rng = self.rng.clone();
 
         for server in servers {
             if !weights.contains_key(server) {
@@ -393,23 +857,51 @@ impl LoadBalancingAlgorithm for WeightedRoundRobin {
 
             self.ensure_weights(servers).await;
 
+            // nginx-style smooth weighted round robin: heavier servers win
+            // more often but their turns are interleaved with the others
             let weights = self.weights.read().await;
-            let total_weight: u32 = servers.iter().map(|s| weights.get(s).unwrap_or(&1)).sum();
-
-            let mut current = self.current.write().await;
-            *current = (*current + 1) % (total_weight as usize);
-
-            let mut accumulator = 0;
+            let mut current_weights = self.current_weights.write().await;
+            let mut total_weight = 0;
+            let mut best: Option<(&String, i64)> = None;
             for server in servers {
-                accumulator += weights.get(server).unwrap_or(&1);
-                if (*current as u32) < accumulator {
-                    self.record_request(server).await;
-                    return Some(server.clone());
+                let weight = i64::from(*weights.get(server).unwrap_or(&1));
+                // Weight 0 drains a server: it's never picked and builds up no
+                // credit to spend once it's weighted again
+                if weight == 0 {
+                    current_weights.insert(server.clone(), 0);
+                    continue;
+                }
+                let current = current_weights.entry(server.clone()).or_insert(0);
+                *current += weight;
+                total_weight += weight;
+                // Ties go to the server listed first
+                if best.is_none_or(|(_, top)| *current > top) {
+                    best = Some((server, *current));
                 }
             }
 
-            self.record_request(&servers[0]).await;
-            Some(servers[0].clone())
+            let (server, _) = best?;
+            if let Some(current) = current_weights.get_mut(server) {
+                *current -= total_weight;
+            }
+            drop(current_weights);
+            self.record_request(server).await;
+            Some(server.clone())
+        })
+    }
+
+    fn initialize<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            self.ensure_weights(servers).await;
+            let mut current_weights = self.current_weights.write().await;
+            let mut requests = self.requests_served.write().await;
+            for server in servers {
+                current_weights.entry(server.clone()).or_insert(0);
+                requests.entry(server.clone()).or_insert(0);
+            }
         })
     }
 
@@ -427,6 +919,15 @@ impl LoadBalancingAlgorithm for WeightedRoundRobin {
         Box::pin(async {})
     }
 
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.requests_served.write().await.clear();
+        })
+    }
+
     fn get_metrics(
         &self,
     ) -> std::pin::Pin<
@@ -447,17 +948,37 @@ impl LoadBalancingAlgorithm for WeightedRoundRobin {
 #[derive(Clone)]
 pub struct IpHash {
     requests_served: Arc<RwLock<HashMap<String, usize>>>,
-    ip_distribution: Arc<RwLock<HashMap<String, String>>>,
+    // (IP, server) for the most recently seen clients, oldest first
+    ip_distribution: Arc<RwLock<VecDeque<(String, String)>>>,
+    rng: SharedRng,
+}
+
+impl Default for IpHash {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IpHash {
     pub fn new() -> Self {
         Self {
             requests_served: Arc::new(RwLock::new(HashMap::new())),
-            ip_distribution: Arc::new(RwLock::new(HashMap::new())),
+            ip_distribution: Arc::new(RwLock::new(VecDeque::new())),
+            rng: SharedRng::default(),
         }
     }
 
+    /// Pick the stand-in IPs used without a client address from `rng`
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Requests sent to each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        ServerState::from_requests(&*self.requests_served.read().await)
+    }
+
     fn hash(ip: &str) -> u64 {
         use std::hash::{Hash, Hasher};
         let mut hasher = std::collections::hash_map::DefaultHasher::new();
@@ -465,11 +986,26 @@ impl IpHash {
         hasher.finish()
     }
 
+    // Hash `ip` onto one of `servers`
+    async fn select(&self, servers: &[String], ip: &str) -> Option<String> {
+        if servers.is_empty() {
+            return None;
+        }
+        let index = (Self::hash(ip) % servers.len() as u64) as usize;
+        let server = servers[index].clone();
+        self.record_request(&server, ip).await;
+        Some(server)
+    }
+
     async fn record_request(&self, server: &str, ip: &str) {
         let mut requests = self.requests_served.write().await;
         let mut dist = self.ip_distribution.write().await;
         *requests.entry(server.to_string()).or_insert(0) += 1;
-        dist.insert(ip.to_string(), server.to_string());
+        dist.retain(|(seen, _)| seen != ip);
+        if dist.len() >= MAX_TRACKED_IPS {
+            dist.pop_front();
+        }
+        dist.push_back((ip.to_string(), server.to_string()));
     }
 }
 
@@ -495,15 +1031,28 @@ impl LoadBalancingAlgorithm for IpHash {
                 "10.0.0.7",
                 "10.0.0.8",
             ];
-            let ip = test_ips[rand::thread_rng().gen_range(0..test_ips.len())];
-            let hash = Self::hash(ip);
-            let index = (hash % servers.len() as u64) as usize;
-            let server = servers[index].clone();
-            self.record_request(&server, ip).await;
-            Some(server)
+            let ip = test_ips[self.rng.clone().gen_range(0..test_ips.len())];
+            self.select(servers, ip).await
         })
     }
 
+    /// Hash the client's address when known, so each client (IPv4 or IPv6)
+    /// sticks to one backend
+    fn next_server_for<'a>(
+        &'a self,
+        servers: &'a [String],
+        request: &'a RequestContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        match request.client_addr {
+            // An IPv4-mapped IPv6 peer hashes the same as its IPv4 address
+            Some(addr) => Box::pin(async move {
+                let ip = addr.ip().to_canonical().to_string();
+                self.select(servers, &ip).await
+            }),
+            None => self.next_server(servers),
+        }
+    }
+
     fn connection_started(
         &self,
         _: &str,
@@ -518,6 +1067,16 @@ impl LoadBalancingAlgorithm for IpHash {
         Box::pin(async {})
     }
 
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.requests_served.write().await.clear();
+            this.ip_distribution.write().await.clear();
+        })
+    }
+
     fn get_metrics(
         &self,
     ) -> std::pin::Pin<
@@ -539,7 +1098,7 @@ impl LoadBalancingAlgorithm for IpHash {
 
                 let ip_mappings: Vec<String> = dist
                     .iter()
-                    .filter(|(_, s)| *s == server)
+                    .filter(|(_, s)| s == server)
                     .map(|(ip, _)| ip.clone())
                     .collect();
 
@@ -0,0 +1,111 @@
+use super::{LoadBalancingAlgorithm, ServerState};
+use crate::rng::SharedRng;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Uniformly random selection, useful as a benchmark baseline
+#[derive(Clone)]
+pub struct Random {
+    requests_served: Arc<RwLock<HashMap<String, usize>>>,
+    rng: SharedRng,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Random {
+    pub fn new() -> Self {
+        Self {
+            requests_served: Arc::new(RwLock::new(HashMap::new())),
+            rng: SharedRng::default(),
+        }
+    }
+
+    /// Draw picks from `rng` instead of a fresh entropy source
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Requests sent to each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        ServerState::from_requests(&*self.requests_served.read().await)
+    }
+
+    async fn record_request(&self, server: &str) {
+        let mut requests = self.requests_served.write().await;
+        *requests.entry(server.to_string()).or_insert(0) += 1;
+    }
+}
+
+impl LoadBalancingAlgorithm for Random {
+    fn next_server<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        Box::pin(async move {
+            if servers.is_empty() {
+                return None;
+            }
+            let index = self.rng.clone().gen_range(0..servers.len());
+            let server = servers[index].clone();
+            self.record_request(&server).await;
+            Some(server)
+        })
+    }
+
+    fn connection_started(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn connection_ended(
+        &self,
+        _: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.requests_served.write().await.clear();
+        })
+    }
+
+    fn get_metrics(
+        &self,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = HashMap<String, String>> + Send + 'static>,
+    > {
+        let this = self.clone();
+        Box::pin(async move {
+            let requests = this.requests_served.read().await;
+            let total_requests: usize = requests.values().sum();
+
+            requests
+                .iter()
+                .map(|(server, count)| {
+                    let percentage = if total_requests > 0 {
+                        (*count as f64 / total_requests as f64) * 100.0
+                    } else {
+                        0.0
+                    };
+                    (
+                        server.clone(),
+                        format!("Requests: {}, Distribution: {:.1}%", count, percentage),
+                    )
+                })
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,39 @@
+//! Typed snapshot of an algorithm's selection state, for embedders who want
+//! numbers rather than the strings `get_metrics` formats
+
+use std::collections::HashMap;
+
+/// What an algorithm tracks about one server. Fields an algorithm doesn't
+/// track are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerState {
+    /// Connections open to the server now
+    pub active_connections: Option<usize>,
+    /// Requests the algorithm has sent the server since the last reset
+    pub total_requests: usize,
+    pub weight: Option<u32>,
+}
+
+/// Selection state for every server an algorithm knows about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AlgorithmState {
+    /// Name the algorithm is registered under, as in `Algorithm::name`
+    pub algorithm: &'static str,
+    pub servers: HashMap<String, ServerState>,
+}
+
+impl ServerState {
+    // State for algorithms that only count requests per server
+    pub(crate) fn from_requests(requests: &HashMap<String, usize>) -> HashMap<String, Self> {
+        requests
+            .iter()
+            .map(|(server, count)| {
+                let state = Self {
+                    total_requests: *count,
+                    ..Self::default()
+                };
+                (server.clone(), state)
+            })
+            .collect()
+    }
+}
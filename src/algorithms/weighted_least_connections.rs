@@ -0,0 +1,146 @@
+use super::{LoadBalancingAlgorithm, ServerState};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Least connections scaled by capacity: picks the lowest active/weight ratio
+#[derive(Clone)]
+pub struct WeightedLeastConnections {
+    weights: Arc<RwLock<HashMap<String, u32>>>,
+    connections: Arc<RwLock<HashMap<String, usize>>>,
+    total_requests: Arc<RwLock<HashMap<String, usize>>>,
+}
+
+impl WeightedLeastConnections {
+    /// Servers missing from `weights` get a weight of 1
+    pub fn new(weights: Option<HashMap<String, u32>>) -> Self {
+        Self {
+            weights: Arc::new(RwLock::new(weights.unwrap_or_default())),
+            connections: Arc::new(RwLock::new(HashMap::new())),
+            total_requests: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Weight, active connections and requests sent for each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        let weights = self.weights.read().await;
+        let connections = self.connections.read().await;
+        let total = self.total_requests.read().await;
+        weights
+            .keys()
+            .chain(connections.keys())
+            .chain(total.keys())
+            .map(|server| {
+                let state = ServerState {
+                    active_connections: Some(*connections.get(server).unwrap_or(&0)),
+                    total_requests: *total.get(server).unwrap_or(&0),
+                    // Servers without a weight count as weight 1
+                    weight: Some(*weights.get(server).unwrap_or(&1)),
+                };
+                (server.clone(), state)
+            })
+            .collect()
+    }
+
+    pub async fn connection_started(&self, server: &str) {
+        let mut connections = self.connections.write().await;
+        let mut total = self.total_requests.write().await;
+        *connections.entry(server.to_string()).or_insert(0) += 1;
+        *total.entry(server.to_string()).or_insert(0) += 1;
+    }
+
+    pub async fn connection_ended(&self, server: &str) {
+        let mut connections = self.connections.write().await;
+        if let Some(count) = connections.get_mut(server) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    pub async fn get_metrics(&self) -> HashMap<String, String> {
+        let weights = self.weights.read().await;
+        let connections = self.connections.read().await;
+        let total = self.total_requests.read().await;
+
+        let mut metrics = HashMap::new();
+        for (server, total_reqs) in total.iter() {
+            metrics.insert(
+                server.clone(),
+                format!(
+                    "Weight: {}, Active: {}, Total: {}",
+                    weights.get(server).unwrap_or(&1),
+                    connections.get(server).unwrap_or(&0),
+                    total_reqs
+                ),
+            );
+        }
+        metrics
+    }
+}
+
+impl LoadBalancingAlgorithm for WeightedLeastConnections {
+    fn next_server<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let weights = self.weights.read().await;
+            let connections = self.connections.read().await;
+
+            // Compare active/weight ratios by cross-multiplying to stay in integers
+            let load = |server: &String| {
+                let active = *connections.get(server).unwrap_or(&0) as u64;
+                let weight = (*weights.get(server).unwrap_or(&1)).max(1) as u64;
+                (active, weight)
+            };
+            servers
+                .iter()
+                .min_by(|a, b| {
+                    let (a_active, a_weight) = load(a);
+                    let (b_active, b_weight) = load(b);
+                    (a_active * b_weight).cmp(&(b_active * a_weight))
+                })
+                .cloned()
+        })
+    }
+
+    fn connection_started(
+        &self,
+        server: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let server = server.to_string();
+        let this = self.clone();
+        Box::pin(async move {
+            this.connection_started(&server).await;
+        })
+    }
+
+    fn connection_ended(
+        &self,
+        server: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let server = server.to_string();
+        let this = self.clone();
+        Box::pin(async move {
+            this.connection_ended(&server).await;
+        })
+    }
+
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.connections.write().await.clear();
+            this.total_requests.write().await.clear();
+        })
+    }
+
+    fn get_metrics(
+        &self,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = HashMap<String, String>> + Send + 'static>,
+    > {
+        let this = self.clone();
+        Box::pin(async move { this.get_metrics().await })
+    }
+}
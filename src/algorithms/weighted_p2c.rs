@@ -0,0 +1,204 @@
+use super::{LoadBalancingAlgorithm, ServerState};
+use crate::rng::SharedRng;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Power of two choices with capacity: samples two distinct servers with
+/// probability proportional to their weights and sends the request to the one
+/// with fewer active connections
+#[derive(Clone)]
+pub struct WeightedP2C {
+    weights: Arc<RwLock<HashMap<String, u32>>>,
+    connections: Arc<RwLock<HashMap<String, usize>>>,
+    total_requests: Arc<RwLock<HashMap<String, usize>>>,
+    // Times each server was one of the sampled pair
+    sampled: Arc<RwLock<HashMap<String, usize>>>,
+    rng: SharedRng,
+}
+
+impl WeightedP2C {
+    /// Servers missing from `weights` get a weight of 1
+    pub fn new(weights: Option<HashMap<String, u32>>) -> Self {
+        Self {
+            weights: Arc::new(RwLock::new(weights.unwrap_or_default())),
+            connections: Arc::new(RwLock::new(HashMap::new())),
+            total_requests: Arc::new(RwLock::new(HashMap::new())),
+            sampled: Arc::new(RwLock::new(HashMap::new())),
+            rng: SharedRng::default(),
+        }
+    }
+
+    /// Draw pairs from `rng` instead of a fresh entropy source
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Weight, active connections and requests sent for each server
+    pub async fn state(&self) -> HashMap<String, ServerState> {
+        let weights = self.weights.read().await;
+        let connections = self.connections.read().await;
+        let total = self.total_requests.read().await;
+        weights
+            .keys()
+            .chain(connections.keys())
+            .chain(total.keys())
+            .map(|server| {
+                let state = ServerState {
+                    active_connections: Some(*connections.get(server).unwrap_or(&0)),
+                    total_requests: *total.get(server).unwrap_or(&0),
+                    weight: Some(*weights.get(server).unwrap_or(&1)),
+                };
+                (server.clone(), state)
+            })
+            .collect()
+    }
+
+    /// Times each server was one of the two sampled for a request
+    pub async fn pair_selections(&self) -> HashMap<String, usize> {
+        self.sampled.read().await.clone()
+    }
+
+    pub async fn connection_started(&self, server: &str) {
+        let mut connections = self.connections.write().await;
+        *connections.entry(server.to_string()).or_insert(0) += 1;
+    }
+
+    pub async fn connection_ended(&self, server: &str) {
+        let mut connections = self.connections.write().await;
+        if let Some(count) = connections.get_mut(server) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    pub async fn get_metrics(&self) -> HashMap<String, String> {
+        let weights = self.weights.read().await;
+        let connections = self.connections.read().await;
+        let total = self.total_requests.read().await;
+        let sampled = self.sampled.read().await;
+
+        let mut metrics = HashMap::new();
+        for (server, total_reqs) in total.iter() {
+            metrics.insert(
+                server.clone(),
+                format!(
+                    "Weight: {}, Active: {}, Total: {}, Sampled: {}",
+                    weights.get(server).unwrap_or(&1),
+                    connections.get(server).unwrap_or(&0),
+                    total_reqs,
+                    sampled.get(server).unwrap_or(&0)
+                ),
+            );
+        }
+        metrics
+    }
+
+    // Index into `weights` drawn with probability proportional to its weight,
+    // leaving out `skip`
+    fn sample(&self, weights: &[u64], skip: Option<usize>) -> usize {
+        let total: u64 = weights
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != skip)
+            .map(|(_, weight)| weight)
+            .sum();
+        let mut roll = self.rng.clone().gen_range(0..total);
+        for (i, weight) in weights.iter().enumerate() {
+            if Some(i) == skip {
+                continue;
+            }
+            if roll < *weight {
+                return i;
+            }
+            roll -= weight;
+        }
+        unreachable!("roll is below the total weight")
+    }
+}
+
+impl LoadBalancingAlgorithm for WeightedP2C {
+    fn next_server<'a>(
+        &'a self,
+        servers: &'a [String],
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Option<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let server = match servers {
+                [] => return None,
+                [only] => only.clone(),
+                _ => {
+                    let weights: Vec<u64> = {
+                        let weights = self.weights.read().await;
+                        servers
+                            .iter()
+                            .map(|server| (*weights.get(server).unwrap_or(&1)).max(1) as u64)
+                            .collect()
+                    };
+                    let first = &servers[self.sample(&weights, None)];
+                    let second =
+                        &servers[self.sample(&weights, servers.iter().position(|s| s == first))];
+
+                    let mut sampled = self.sampled.write().await;
+                    *sampled.entry(first.clone()).or_insert(0) += 1;
+                    *sampled.entry(second.clone()).or_insert(0) += 1;
+                    drop(sampled);
+
+                    // Fewer active connections wins; ties go to the first draw
+                    let connections = self.connections.read().await;
+                    let active = |server: &String| *connections.get(server).unwrap_or(&0);
+                    if active(second) < active(first) {
+                        second.clone()
+                    } else {
+                        first.clone()
+                    }
+                }
+            };
+            let mut total = self.total_requests.write().await;
+            *total.entry(server.clone()).or_insert(0) += 1;
+            Some(server)
+        })
+    }
+
+    fn connection_started(
+        &self,
+        server: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let server = server.to_string();
+        let this = self.clone();
+        Box::pin(async move {
+            this.connection_started(&server).await;
+        })
+    }
+
+    fn connection_ended(
+        &self,
+        server: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let server = server.to_string();
+        let this = self.clone();
+        Box::pin(async move {
+            this.connection_ended(&server).await;
+        })
+    }
+
+    fn reset_metrics(
+        &self,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>> {
+        let this = self.clone();
+        Box::pin(async move {
+            this.connections.write().await.clear();
+            this.total_requests.write().await.clear();
+            this.sampled.write().await.clear();
+        })
+    }
+
+    fn get_metrics(
+        &self,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = HashMap<String, String>> + Send + 'static>,
+    > {
+        let this = self.clone();
+        Box::pin(async move { this.get_metrics().await })
+    }
+}
@@ -0,0 +1,21 @@
+//! Access log output: one structured event per forwarded request
+
+use clap::ValueEnum;
+
+/// Target used for access log events so they can be filtered separately
+pub const ACCESS_LOG_TARGET: &str = "access";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Plain,
+    Json,
+}
+
+/// Install a global subscriber printing INFO and above to stdout
+pub fn init_logging(format: LogFormat) {
+    let builder = tracing_subscriber::fmt().with_max_level(tracing::Level::INFO);
+    match format {
+        LogFormat::Plain => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
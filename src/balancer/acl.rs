@@ -0,0 +1,75 @@
+//! Method and path access control, checked before a request is routed
+
+use serde::{Deserialize, Serialize};
+
+/// Allow and deny lists for request methods and path prefixes. A request is
+/// refused if its method or path is denied, or if an allow list is set and
+/// it isn't on it. Empty lists allow everything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessControl {
+    pub allow_methods: Vec<String>,
+    pub deny_methods: Vec<String>,
+    /// Path prefixes; `/admin` covers `/admin` and `/admin/...` but not `/administrator`
+    pub allow_paths: Vec<String>,
+    pub deny_paths: Vec<String>,
+}
+
+impl AccessControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_allowed_method(mut self, method: &str) -> Self {
+        self.allow_methods.push(method.to_string());
+        self
+    }
+
+    pub fn with_denied_method(mut self, method: &str) -> Self {
+        self.deny_methods.push(method.to_string());
+        self
+    }
+
+    pub fn with_allowed_path(mut self, prefix: &str) -> Self {
+        self.allow_paths.push(prefix.to_string());
+        self
+    }
+
+    pub fn with_denied_path(mut self, prefix: &str) -> Self {
+        self.deny_paths.push(prefix.to_string());
+        self
+    }
+
+    /// Whether no rule is set, so every request passes
+    pub fn is_empty(&self) -> bool {
+        self.allow_methods.is_empty()
+            && self.deny_methods.is_empty()
+            && self.allow_paths.is_empty()
+            && self.deny_paths.is_empty()
+    }
+
+    /// Whether a `method` request for `path` may go through
+    pub fn permits(&self, method: &str, path: &str) -> bool {
+        let method_listed =
+            |methods: &[String]| methods.iter().any(|m| m.eq_ignore_ascii_case(method));
+        let path_listed = |prefixes: &[String]| prefixes.iter().any(|p| under_prefix(path, p));
+        if method_listed(&self.deny_methods) || path_listed(&self.deny_paths) {
+            return false;
+        }
+        (self.allow_methods.is_empty() || method_listed(&self.allow_methods))
+            && (self.allow_paths.is_empty() || path_listed(&self.allow_paths))
+    }
+}
+
+// `path` is `prefix` itself or something beneath it, ignoring any query
+fn under_prefix(path: &str, prefix: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => {
+            rest.is_empty()
+                || prefix.ends_with('/')
+                || rest.starts_with('/')
+                || rest.starts_with('?')
+        }
+        None => false,
+    }
+}
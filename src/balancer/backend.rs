@@ -0,0 +1,183 @@
+//! Connections to backend servers over TCP (`host:port`), TLS
+//! (`https://host:port`) or, on Unix, a domain socket (`unix:/path/to/socket`)
+
+use std::io::{self, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+
+/// Server-list prefix marking a Unix domain socket backend
+pub const UNIX_PREFIX: &str = "unix:";
+/// Server-list prefix marking a backend dialled over TLS
+pub const HTTPS_PREFIX: &str = "https://";
+
+// Where a backend server entry points
+enum BackendAddr<'a> {
+    Tcp(&'a str),
+    Tls(&'a str),
+    Unix(&'a str),
+}
+
+impl<'a> BackendAddr<'a> {
+    fn parse(addr: &'a str) -> Self {
+        if let Some(path) = addr.strip_prefix(UNIX_PREFIX) {
+            return Self::Unix(path);
+        }
+        match addr.strip_prefix(HTTPS_PREFIX) {
+            Some(addr) => Self::Tls(addr.trim_end_matches('/')),
+            None => Self::Tcp(addr),
+        }
+    }
+}
+
+/// Value for a `Host` header naming the backend at `addr`: its `host:port`,
+/// or `localhost` for a Unix socket
+pub fn host_header(addr: &str) -> &str {
+    match BackendAddr::parse(addr) {
+        BackendAddr::Tcp(addr) | BackendAddr::Tls(addr) => addr,
+        BackendAddr::Unix(_) => "localhost",
+    }
+}
+
+/// An open connection to a backend
+pub enum BackendStream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl BackendStream {
+    /// Dial the backend named by a server-list entry. `https://` entries need
+    /// a `tls` connector to dial with.
+    pub async fn connect(addr: &str, tls: Option<&TlsConnector>) -> io::Result<Self> {
+        match BackendAddr::parse(addr) {
+            BackendAddr::Tcp(addr) => Ok(Self::Tcp(TcpStream::connect(addr).await?)),
+            BackendAddr::Tls(addr) => {
+                let connector = tls.ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("no TLS client configured for {}{}", HTTPS_PREFIX, addr),
+                    )
+                })?;
+                let tcp = TcpStream::connect(addr).await?;
+                let stream = connector.connect(server_name(addr)?, tcp).await?;
+                Ok(Self::Tls(Box::new(stream)))
+            }
+            #[cfg(unix)]
+            BackendAddr::Unix(path) => Ok(Self::Unix(UnixStream::connect(path).await?)),
+            #[cfg(not(unix))]
+            BackendAddr::Unix(_) => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Unix socket backends are not supported on this platform",
+            )),
+        }
+    }
+
+    /// Disable Nagle's algorithm on TCP backends; Unix sockets have no such delay
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_nodelay(nodelay),
+            Self::Tls(stream) => stream.get_ref().0.set_nodelay(nodelay),
+            #[cfg(unix)]
+            Self::Unix(_) => Ok(()),
+        }
+    }
+
+    /// Whether `TCP_NODELAY` is set (always false for Unix sockets)
+    pub fn nodelay(&self) -> io::Result<bool> {
+        match self {
+            Self::Tcp(stream) => stream.nodelay(),
+            Self::Tls(stream) => stream.get_ref().0.nodelay(),
+            #[cfg(unix)]
+            Self::Unix(_) => Ok(false),
+        }
+    }
+
+    /// Whether an idle connection is still usable. It should have nothing to
+    /// read; EOF or stray bytes mean the backend closed it or is out of sync.
+    /// TLS connections are checked through the session, which takes in
+    /// records such as session tickets without surfacing them as data.
+    pub fn is_open(&mut self) -> bool {
+        let mut probe = [0; 1];
+        let read = match self {
+            Self::Tcp(stream) => stream.try_read(&mut probe),
+            Self::Tls(stream) => {
+                let mut buf = ReadBuf::new(&mut probe);
+                let mut cx = Context::from_waker(Waker::noop());
+                match Pin::new(stream).poll_read(&mut cx, &mut buf) {
+                    Poll::Pending => Err(ErrorKind::WouldBlock.into()),
+                    Poll::Ready(result) => result.map(|()| buf.filled().len()),
+                }
+            }
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.try_read(&mut probe),
+        };
+        matches!(read, Err(e) if e.kind() == ErrorKind::WouldBlock)
+    }
+}
+
+// Name to verify a backend's certificate against: the host part of
+// `host:port`, without brackets for IPv6
+fn server_name(addr: &str) -> io::Result<ServerName<'static>> {
+    let host = match addr.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => addr,
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host.to_string()).map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))
+}
+
+impl AsyncRead for BackendStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for BackendStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Per-backend concurrency limits, so one slow backend can't tie up every
+//! connection slot the balancer has
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Caps on simultaneous in-flight requests to each backend: one default for
+/// every backend, overridden per server
+#[derive(Clone, Default)]
+pub struct BackendLimits {
+    default: Option<usize>,
+    overrides: HashMap<String, usize>,
+    semaphores: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+/// A backend's slot, held for as long as a request is with it. Backends
+/// without a limit hand out empty ones.
+pub struct BackendPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl BackendLimits {
+    pub fn new(default: Option<usize>) -> Self {
+        Self {
+            default,
+            ..Self::default()
+        }
+    }
+
+    /// Limit every backend without its own limit to `limit`
+    pub fn with_default(mut self, limit: usize) -> Self {
+        self.default = Some(limit);
+        self.semaphores = Arc::default();
+        self
+    }
+
+    /// Give `server` its own limit in place of the default
+    pub fn with_limit(mut self, server: &str, limit: usize) -> Self {
+        self.overrides.insert(server.to_string(), limit);
+        self.semaphores
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(server);
+        self
+    }
+
+    /// The limit that applies to `server`, if any
+    pub fn limit_for(&self, server: &str) -> Option<usize> {
+        self.overrides.get(server).copied().or(self.default)
+    }
+
+    fn semaphore(&self, server: &str) -> Option<Arc<Semaphore>> {
+        let limit = self.limit_for(server)?;
+        let mut semaphores = self.semaphores.lock().unwrap_or_else(|e| e.into_inner());
+        let semaphore = semaphores
+            .entry(server.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(limit)));
+        Some(Arc::clone(semaphore))
+    }
+
+    /// Whether `server` already has as many requests in flight as it may
+    pub fn at_limit(&self, server: &str) -> bool {
+        self.semaphore(server)
+            .is_some_and(|semaphore| semaphore.available_permits() == 0)
+    }
+
+    /// The members of `servers` with room for another request, or all of
+    /// them if none has, leaving the request to queue for a slot
+    pub fn with_room(&self, servers: Vec<String>) -> Vec<String> {
+        let open: Vec<String> = servers
+            .iter()
+            .filter(|server| !self.at_limit(server))
+            .cloned()
+            .collect();
+        if open.is_empty() {
+            servers
+        } else {
+            open
+        }
+    }
+
+    /// Take a slot on `server` if one is free right now
+    pub fn try_acquire(&self, server: &str) -> Option<BackendPermit> {
+        match self.semaphore(server) {
+            Some(semaphore) => semaphore
+                .try_acquire_owned()
+                .ok()
+                .map(|permit| BackendPermit {
+                    _permit: Some(permit),
+                }),
+            None => Some(BackendPermit { _permit: None }),
+        }
+    }
+
+    /// Take a slot on `server`, waiting for one to free up if it's at its limit
+    pub async fn acquire(&self, server: &str) -> BackendPermit {
+        match self.semaphore(server) {
+            Some(semaphore) => BackendPermit {
+                _permit: semaphore.acquire_owned().await.ok(),
+            },
+            None => BackendPermit { _permit: None },
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Chainable construction of a `LoadBalancer` for embedders
+
+use super::{AccessControl, LoadBalancer};
+use crate::config::Config;
+use std::collections::HashMap;
+use tokio::time::Duration;
+
+/// Collects balancer settings, starting from the same defaults as `Config`
+/// (port 8000, no servers, round-robin, `MAX_CONNECTIONS`)
+#[derive(Debug, Clone, Default)]
+pub struct LoadBalancerBuilder {
+    config: Config,
+    queue_timeout: Option<Duration>,
+    header_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    ready_timeout: Option<Duration>,
+    dynamic_servers: bool,
+    max_body_size: Option<usize>,
+    rate_limit: Option<f64>,
+    rewrite_host: bool,
+    preserve_host: Option<bool>,
+    warm_connections: usize,
+    nodelay: bool,
+    count_by_status: bool,
+    backlog: Option<u32>,
+    seed: Option<u64>,
+    http2: bool,
+}
+
+impl LoadBalancerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.config.port = port;
+        self
+    }
+
+    pub fn with_servers(mut self, servers: Vec<String>) -> Self {
+        self.config.servers = servers;
+        self
+    }
+
+    /// Algorithm name, one of `ALGORITHM_NAMES`
+    pub fn with_algorithm(mut self, algorithm: &str) -> Self {
+        self.config.algorithm = algorithm.to_string();
+        self
+    }
+
+    /// Per-server weights for the weighted algorithms
+    pub fn with_weights(mut self, weights: HashMap<String, u32>) -> Self {
+        self.config.weights = weights;
+        self
+    }
+
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = max_connections;
+        self
+    }
+
+    /// Cap on requests in flight to any one backend
+    pub fn with_max_per_backend(mut self, limit: usize) -> Self {
+        self.config.max_per_backend = Some(limit);
+        self
+    }
+
+    /// Cap on requests in flight to `server`, overriding the default
+    pub fn with_backend_limit(mut self, server: &str, limit: usize) -> Self {
+        self.config.backend_limits.insert(server.to_string(), limit);
+        self
+    }
+
+    /// Methods and path prefixes to allow or refuse with a 403
+    pub fn with_acl(mut self, acl: AccessControl) -> Self {
+        self.config.acl = acl;
+        self
+    }
+
+    pub fn with_queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = Some(queue_timeout);
+        self
+    }
+
+    pub fn with_header_timeout(mut self, header_timeout: Duration) -> Self {
+        self.header_timeout = Some(header_timeout);
+        self
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Wait up to `ready_timeout` at startup for a reachable backend
+    pub fn with_readiness_gate(mut self, ready_timeout: Duration) -> Self {
+        self.ready_timeout = Some(ready_timeout);
+        self
+    }
+
+    /// Allow starting with no servers, to add them later with `add_server`
+    pub fn with_dynamic_servers(mut self) -> Self {
+        self.dynamic_servers = true;
+        self
+    }
+
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
+    pub fn with_rate_limit(mut self, rate: f64) -> Self {
+        self.rate_limit = Some(rate);
+        self
+    }
+
+    pub fn with_rewrite_host(mut self, rewrite_host: bool) -> Self {
+        self.rewrite_host = rewrite_host;
+        self
+    }
+
+    pub fn with_preserve_host(mut self, preserve_host: bool) -> Self {
+        self.preserve_host = Some(preserve_host);
+        self
+    }
+
+    pub fn with_warm_connections(mut self, count: usize) -> Self {
+        self.warm_connections = count;
+        self
+    }
+
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn with_count_by_status(mut self, count_by_status: bool) -> Self {
+        self.count_by_status = count_by_status;
+        self
+    }
+
+    pub fn with_backlog(mut self, backlog: u32) -> Self {
+        self.backlog = Some(backlog);
+        self
+    }
+
+    pub fn with_http2(mut self, http2: bool) -> Self {
+        self.http2 = http2;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> LoadBalancer {
+        let mut balancer = LoadBalancer::from_config(&self.config)
+            .with_rewrite_host(self.rewrite_host)
+            .with_warm_connections(self.warm_connections)
+            .with_nodelay(self.nodelay)
+            .with_count_by_status(self.count_by_status)
+            .with_http2(self.http2);
+        if let Some(preserve_host) = self.preserve_host {
+            balancer = balancer.with_preserve_host(preserve_host);
+        }
+        if let Some(queue_timeout) = self.queue_timeout {
+            balancer = balancer.with_queue_timeout(queue_timeout);
+        }
+        if let Some(header_timeout) = self.header_timeout {
+            balancer = balancer.with_header_timeout(header_timeout);
+        }
+        if let Some(idle_timeout) = self.idle_timeout {
+            balancer = balancer.with_idle_timeout(idle_timeout);
+        }
+        if self.dynamic_servers {
+            balancer = balancer.with_dynamic_servers();
+        }
+        if let Some(ready_timeout) = self.ready_timeout {
+            balancer = balancer.with_readiness_gate(ready_timeout);
+        }
+        if let Some(max_body_size) = self.max_body_size {
+            balancer = balancer.with_max_body_size(max_body_size);
+        }
+        if let Some(rate) = self.rate_limit {
+            balancer = balancer.with_rate_limit(rate);
+        }
+        if let Some(backlog) = self.backlog {
+            balancer = balancer.with_backlog(backlog);
+        }
+        if let Some(seed) = self.seed {
+            balancer = balancer.with_seed(seed);
+        }
+        balancer
+    }
+}
@@ -0,0 +1,160 @@
+//! In-balancer cache of backend responses to idempotent GETs
+
+use super::http::find_head_end;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::{sync::RwLock, time::Duration};
+
+/// Default cap on cached responses
+pub const DEFAULT_CACHE_ENTRIES: usize = 1024;
+
+/// A response exactly as its backend sent it, so anything specific to the
+/// client (like `--rewrite-host`) is applied per hit
+pub struct CachedResponse {
+    pub backend: String,
+    pub response: Vec<u8>,
+}
+
+struct CacheEntry {
+    response: Arc<CachedResponse>,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, CacheEntry>,
+    // Monotonic use counter standing in for recency
+    clock: u64,
+}
+
+/// Whole responses keyed by method and path, each kept for `ttl` and evicted
+/// least-recently-used first once `max_entries` is reached
+#[derive(Clone)]
+pub struct Cache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Arc<RwLock<Entries>>,
+    hits: Arc<AtomicUsize>,
+    misses: Arc<AtomicUsize>,
+}
+
+impl Cache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries: max_entries.max(1),
+            entries: Arc::new(RwLock::new(Entries::default())),
+            hits: Arc::new(AtomicUsize::new(0)),
+            misses: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Key for a request, or `None` if its method isn't cacheable
+    pub fn key(method: &str, path: &str) -> Option<String> {
+        (method == "GET").then(|| format!("{} {}", method, path))
+    }
+
+    /// Whether a response may be shared with other clients: not when it sets
+    /// a cookie or its `Cache-Control` says `no-store` or `private`
+    pub fn storable(response: &[u8]) -> bool {
+        let head_end = find_head_end(response).unwrap_or(response.len());
+        let head = String::from_utf8_lossy(&response[..head_end]);
+        let personal = |name: &str, value: &str| {
+            name.eq_ignore_ascii_case("Set-Cookie")
+                || name.eq_ignore_ascii_case("Cache-Control")
+                    && value.split(',').any(|directive| {
+                        // `private` may name fields, as in `private="Set-Cookie"`
+                        let directive = directive.split('=').next().unwrap_or("").trim();
+                        directive.eq_ignore_ascii_case("no-store")
+                            || directive.eq_ignore_ascii_case("private")
+                    })
+        };
+        !head
+            .split("\r\n")
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .any(|(name, value)| personal(name.trim(), value))
+    }
+
+    /// Fresh cached response for `key`, counting the hit or miss
+    pub async fn get(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let mut entries = self.entries.write().await;
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        let response = match entries.map.get_mut(key) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => {
+                entry.last_used = clock;
+                Some(Arc::clone(&entry.response))
+            }
+            Some(_) => {
+                entries.map.remove(key);
+                None
+            }
+            None => None,
+        };
+        let counter = if response.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        response
+    }
+
+    /// Store `backend`'s response, evicting the least recently used entry if
+    /// full
+    pub async fn put(&self, key: &str, backend: &str, response: Vec<u8>) {
+        let mut entries = self.entries.write().await;
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        if !entries.map.contains_key(key) && entries.map.len() >= self.max_entries {
+            let oldest = entries
+                .map
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.map.remove(&oldest);
+            }
+        }
+        entries.map.insert(
+            key.to_string(),
+            CacheEntry {
+                response: Arc::new(CachedResponse {
+                    backend: backend.to_string(),
+                    response,
+                }),
+                stored_at: Instant::now(),
+                last_used: clock,
+            },
+        );
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Zero the hit and miss counters, keeping cached responses
+    pub fn reset_counts(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Number of responses currently stored (including expired ones not yet hit)
+    pub async fn len(&self) -> usize {
+        self.entries.read().await.map.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+}
@@ -0,0 +1,87 @@
+//! Single-flight coalescing: identical GETs arriving while one is already
+//! with a backend wait for and share its response instead of sending their own
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+type Response = Option<Arc<Vec<u8>>>;
+
+/// Requests currently with a backend, keyed by method and path
+#[derive(Clone, Default)]
+pub struct SingleFlight {
+    flights: Arc<Mutex<HashMap<String, watch::Sender<Response>>>>,
+}
+
+/// The request a key's followers are waiting on. Dropping it ends the flight;
+/// followers still waiting then fall back to sending their own request.
+pub struct Leader {
+    key: String,
+    flights: Arc<Mutex<HashMap<String, watch::Sender<Response>>>>,
+}
+
+/// A request waiting on the leader for the same key
+pub struct Follower(watch::Receiver<Response>);
+
+/// Whether a request goes to the backend itself or waits on another
+pub enum Flight {
+    Leader(Leader),
+    Follower(Follower),
+}
+
+impl SingleFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Key for a request, or `None` if its method can't be shared
+    pub fn key(method: &str, path: &str) -> Option<String> {
+        (method == "GET").then(|| format!("{} {}", method, path))
+    }
+
+    /// Lead a new flight for `key`, or follow the one already in progress
+    pub fn join(&self, key: &str) -> Flight {
+        let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(sender) = flights.get(key) {
+            return Flight::Follower(Follower(sender.subscribe()));
+        }
+        let (sender, _) = watch::channel(None);
+        flights.insert(key.to_string(), sender);
+        Flight::Leader(Leader {
+            key: key.to_string(),
+            flights: Arc::clone(&self.flights),
+        })
+    }
+
+    /// Number of keys with a request in progress
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+impl Leader {
+    /// Hand the backend's complete response to every follower
+    pub fn publish(&self, response: &[u8]) {
+        let flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(sender) = flights.get(&self.key) {
+            sender.send_replace(Some(Arc::new(response.to_vec())));
+        }
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+        flights.remove(&self.key);
+    }
+}
+
+impl Follower {
+    /// Wait for the leader's response; `None` if it finished without one
+    pub async fn response(mut self) -> Option<Arc<Vec<u8>>> {
+        match self.0.wait_for(Option::is_some).await {
+            Ok(response) => response.clone(),
+            Err(_) => None,
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Self-contained HTML page summarising the balancer, served at `/dashboard`
+
+use super::stats::AggregateMetrics;
+
+/// Seconds between automatic page reloads
+pub const DASHBOARD_REFRESH: u64 = 2;
+
+/// One backend's line on the dashboard
+pub struct DashboardRow {
+    pub server: String,
+    pub active_connections: usize,
+    pub requests: usize,
+    pub state: &'static str,
+}
+
+/// Render the page, with each backend's share of all requests served
+pub fn render(aggregate: &AggregateMetrics, rows: &[DashboardRow]) -> String {
+    let total: usize = rows.iter().map(|row| row.requests).sum();
+    let mut body = String::new();
+    for row in rows {
+        let distribution = if total == 0 {
+            0.0
+        } else {
+            row.requests as f64 * 100.0 / total as f64
+        };
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td><td class=\"{}\">{}</td></tr>\n",
+            escape(&row.server),
+            row.active_connections,
+            row.requests,
+            distribution,
+            row.state.to_lowercase(),
+            row.state
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{refresh}">
+<title>Load Balancer</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid #ccc; padding: 0.4em 0.8em; text-align: left; }}
+.healthy {{ color: #080; }}
+.warming {{ color: #a60; }}
+.draining, .ejected, .unhealthy {{ color: #c00; }}
+</style>
+</head>
+<body>
+<h1>Load Balancer</h1>
+<p>{aggregate}</p>
+<table>
+<tr><th>Server</th><th>Active connections</th><th>Requests</th><th>Distribution</th><th>State</th></tr>
+{body}</table>
+</body>
+</html>
+"#,
+        refresh = DASHBOARD_REFRESH,
+        aggregate = escape(&aggregate.to_string()),
+        body = body
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,533 @@
+//! Minimal HTTP/1.x request head parsing used by the balancer
+
+/// Upper bound on the size of a request head we are willing to buffer
+pub const MAX_HEAD_SIZE: usize = 8192;
+
+/// Connection preface an HTTP/2 client sends before its first frame
+pub const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Whether `buffer` opens with the HTTP/2 preface: `Some(true)` once it
+/// does, `Some(false)` as soon as it can't, `None` while it could still
+pub fn h2_preface(buffer: &[u8]) -> Option<bool> {
+    let len = buffer.len().min(H2_PREFACE.len());
+    if buffer[..len] != H2_PREFACE[..len] {
+        Some(false)
+    } else if len == H2_PREFACE.len() {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Parsed request line and headers of an HTTP/1.x request
+#[derive(Debug, Clone)]
+pub struct RequestHead {
+    pub method: String,
+    pub path: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl RequestHead {
+    /// Parse a request head (everything before the blank line). One whose
+    /// `Content-Length` is malformed or contradicts itself is rejected, as
+    /// its body has no reliable end.
+    pub fn parse(head: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(head).ok()?;
+        let mut lines = text.split("\r\n");
+
+        let mut request_line = lines.next()?.split(' ');
+        let method = request_line.next()?.to_string();
+        let path = request_line.next()?.to_string();
+        let version = request_line.next()?.to_string();
+        if method.is_empty() || !version.starts_with("HTTP/") {
+            return None;
+        }
+
+        let mut headers = Vec::new();
+        for line in lines.filter(|l| !l.is_empty()) {
+            let (name, value) = line.split_once(':')?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        declared_length(&headers).ok()?;
+
+        Some(Self {
+            method,
+            path,
+            version,
+            headers,
+        })
+    }
+
+    /// Get the first value of a header (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Body length declared by `Content-Length`, if any
+    pub fn content_length(&self) -> Option<usize> {
+        declared_length(&self.headers).ok().flatten()
+    }
+
+    /// Get the value of a cookie from the `Cookie` header
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.header("Cookie")?
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// Whether the request carries credentials, so its response may be
+    /// meant for this client alone (RFC 9111 §3.5)
+    pub fn has_credentials(&self) -> bool {
+        self.header("Authorization").is_some() || self.header("Cookie").is_some()
+    }
+
+    /// Whether the client explicitly asked to keep the connection open
+    pub fn wants_keep_alive(&self) -> bool {
+        self.header("Connection").is_some_and(|value| {
+            value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("keep-alive"))
+        })
+    }
+
+    /// Whether the client asked for the connection to close after this request
+    pub fn wants_close(&self) -> bool {
+        self.header("Connection").is_some_and(|value| {
+            value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("close"))
+        })
+    }
+
+    /// Whether sending the request twice has the same effect as sending it
+    /// once, so it's safe to repeat on another backend
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self.method.as_str(),
+            "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS"
+        )
+    }
+
+    /// Whether the client asks to switch protocols, as a WebSocket handshake
+    /// does; if the backend agrees the connection becomes a tunnel
+    pub fn is_upgrade(&self) -> bool {
+        self.header("Upgrade").is_some()
+    }
+
+    /// Whether the client holds its body back until told `100 Continue`
+    pub fn expects_continue(&self) -> bool {
+        self.header("Expect")
+            .is_some_and(|value| value.eq_ignore_ascii_case("100-continue"))
+    }
+
+    /// Whether the body is sent with chunked transfer encoding
+    pub fn is_chunked(&self) -> bool {
+        self.header("Transfer-Encoding").is_some_and(|value| {
+            value
+                .split(',')
+                .any(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+        })
+    }
+
+    /// Replace a header's value, adding it if missing
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.remove_header(name);
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    /// Drop every instance of a header
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    /// Append to a comma-separated header, adding it if missing
+    pub fn append_header(&mut self, name: &str, value: &str) {
+        match self
+            .headers
+            .iter_mut()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            Some((_, existing)) => {
+                existing.push_str(", ");
+                existing.push_str(value);
+            }
+            None => self.headers.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Serialize the head back into wire format, including the blank line
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = format!("{} {} {}\r\n", self.method, self.path, self.version);
+        for (name, value) in &self.headers {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        out.push_str("\r\n");
+        out.into_bytes()
+    }
+}
+
+// The length every `Content-Length` header agrees on. Each must be a plain
+// decimal number that fits a usize; anything else, including a list or a
+// sign, is an error rather than being read as 0 or the first value.
+fn declared_length(headers: &[(String, String)]) -> Result<Option<usize>, ()> {
+    let mut declared = None;
+    for (_, value) in headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+    {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(());
+        }
+        let len = value.parse::<usize>().map_err(|_| ())?;
+        if declared.is_some_and(|declared| declared != len) {
+            return Err(());
+        }
+        declared = Some(len);
+    }
+    Ok(declared)
+}
+
+/// Find the end of the head, returning the offset just past `\r\n\r\n`
+pub fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4)
+}
+
+/// Add a header right after the status line of a buffered response
+pub fn insert_response_header(response: &[u8], name: &str, value: &str) -> Vec<u8> {
+    let line_end = match response.windows(2).position(|w| w == b"\r\n") {
+        Some(i) => i + 2,
+        None => return response.to_vec(),
+    };
+    let mut out = Vec::with_capacity(response.len() + name.len() + value.len() + 4);
+    out.extend_from_slice(&response[..line_end]);
+    out.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    out.extend_from_slice(&response[line_end..]);
+    out
+}
+
+/// Replace (or add) a header in a buffered response head
+pub fn set_response_header(response: &[u8], name: &str, value: &str) -> Vec<u8> {
+    let head_end = match find_head_end(response) {
+        Some(end) => end,
+        None => return insert_response_header(response, name, value),
+    };
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    let kept: Vec<&str> = head
+        .split("\r\n")
+        .filter(|line| {
+            !matches!(line.split_once(':'), Some((n, _)) if n.trim().eq_ignore_ascii_case(name))
+        })
+        .collect();
+    let mut out = kept.join("\r\n").into_bytes();
+    out.extend_from_slice(&response[head_end..]);
+    insert_response_header(&out, name, value)
+}
+
+/// Whether `bytes` open with another request line, or as much of one as has
+/// arrived, as when a client pipelines requests
+pub fn starts_request_line(bytes: &[u8]) -> bool {
+    let line_end = bytes.windows(2).position(|w| w == b"\r\n");
+    let Ok(line) = std::str::from_utf8(&bytes[..line_end.unwrap_or(bytes.len())]) else {
+        return false;
+    };
+    let mut parts = line.split(' ');
+    let method = parts.next().unwrap_or_default();
+    if method.is_empty() || !method.bytes().all(|b| b.is_ascii_uppercase()) {
+        return false;
+    }
+    match line_end {
+        Some(_) => matches!(
+            (parts.next(), parts.next(), parts.next()),
+            (Some(target), Some(version), None) if !target.is_empty() && version.starts_with("HTTP/")
+        ),
+        None => true,
+    }
+}
+
+/// How much of a chunked body has arrived
+#[derive(Debug, Default, PartialEq)]
+pub struct ChunkedProgress {
+    /// Payload bytes declared by the chunk sizes read so far
+    pub payload: usize,
+    /// Length of the whole encoded body, through the last chunk and any
+    /// trailers, once it has all arrived
+    pub complete: Option<usize>,
+}
+
+/// Walk the chunked body at the start of `body` as far as it has arrived
+pub fn chunked_progress(body: &[u8]) -> ChunkedProgress {
+    let line_end = |from: usize| {
+        body.get(from..)?
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .map(|i| from + i)
+    };
+    let chunk_size = |line: &[u8]| {
+        let line = std::str::from_utf8(line).ok()?;
+        usize::from_str_radix(line.split(';').next()?.trim(), 16).ok()
+    };
+
+    let mut progress = ChunkedProgress::default();
+    let mut pos = 0;
+    loop {
+        let Some(end) = line_end(pos) else {
+            return progress;
+        };
+        let Some(size) = chunk_size(&body[pos..end]) else {
+            return progress;
+        };
+        progress.payload = progress.payload.saturating_add(size);
+        pos = end + 2;
+        if size == 0 {
+            // Trailer fields, ended by an empty line
+            while let Some(end) = line_end(pos) {
+                if end == pos {
+                    progress.complete = Some(pos + 2);
+                    break;
+                }
+                pos = end + 2;
+            }
+            return progress;
+        }
+        pos = pos.saturating_add(size).saturating_add(2);
+        if pos > body.len() {
+            return progress;
+        }
+    }
+}
+
+/// Follows a chunked body fed to it piece by piece, so one too large to
+/// buffer can be passed on as it arrives while still spotting where it ends
+#[derive(Debug, Default)]
+pub struct ChunkedScanner {
+    state: ChunkState,
+    // Size or trailer line split across pieces
+    line: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ChunkState {
+    #[default]
+    Size,
+    // Payload bytes left in the current chunk
+    Data(usize),
+    // The CRLF closing a chunk's payload
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+impl ChunkedScanner {
+    /// Take the next `piece` of the body; once its end is in `piece`, how
+    /// many of `piece`'s bytes belong to the body
+    pub fn feed(&mut self, piece: &[u8]) -> std::io::Result<Option<usize>> {
+        let mut pos = 0;
+        while pos < piece.len() && self.state != ChunkState::Done {
+            if let ChunkState::Data(left) = self.state {
+                let take = left.min(piece.len() - pos);
+                pos += take;
+                self.state = match left - take {
+                    0 => ChunkState::DataEnd,
+                    left => ChunkState::Data(left),
+                };
+                continue;
+            }
+            let rest = &piece[pos..];
+            let Some(i) = rest.iter().position(|&b| b == b'\n') else {
+                self.line.extend_from_slice(rest);
+                pos = piece.len();
+                break;
+            };
+            self.line.extend_from_slice(&rest[..=i]);
+            pos += i + 1;
+            let line = std::mem::take(&mut self.line);
+            self.end_line(&line)?;
+        }
+        if self.line.len() > MAX_HEAD_SIZE {
+            return Err(Self::invalid("chunk line too long"));
+        }
+        Ok((self.state == ChunkState::Done).then_some(pos))
+    }
+
+    fn end_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let line = line.strip_suffix(b"\r\n").unwrap_or(line);
+        self.state = match self.state {
+            ChunkState::Size => {
+                let size = std::str::from_utf8(line)
+                    .ok()
+                    .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+                    .ok_or_else(|| Self::invalid("invalid chunk size"))?;
+                match size {
+                    0 => ChunkState::Trailers,
+                    size => ChunkState::Data(size),
+                }
+            }
+            ChunkState::DataEnd if line.is_empty() => ChunkState::Size,
+            ChunkState::DataEnd => return Err(Self::invalid("chunk longer than its size")),
+            ChunkState::Trailers if line.is_empty() => ChunkState::Done,
+            state => state,
+        };
+        Ok(())
+    }
+
+    fn invalid(reason: &str) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, reason.to_string())
+    }
+}
+
+/// Split `host:port` into host and port, keeping a bracketed IPv6 host like
+/// `[::1]:8001` whole (brackets removed) instead of cutting at its last colon
+pub fn split_host_port(addr: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = addr.strip_prefix('[') {
+        if let Some((host, after)) = rest.split_once(']') {
+            return (host, after.strip_prefix(':'));
+        }
+    }
+    match addr.rsplit_once(':') {
+        // More than one colon without brackets is a bare IPv6 address
+        Some((host, port)) if !host.contains(':') => (host, Some(port)),
+        _ => (addr, None),
+    }
+}
+
+/// Point `Location` and `Content-Location` URLs and `Set-Cookie` domains that
+/// name the backend at the balancer's public `host[:port]` instead
+pub fn rewrite_response_host(response: &[u8], backend: &str, public: &str) -> Vec<u8> {
+    let head_end = match find_head_end(response) {
+        Some(end) => end,
+        None => return response.to_vec(),
+    };
+    // The backend knows itself by `host:port`, without any `https://`
+    let backend = super::backend::host_header(backend);
+    let host = |addr: &str| split_host_port(addr).0.to_string();
+    let (backend_host, public_host) = (host(backend), host(public));
+
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    let lines: Vec<String> = head
+        .split("\r\n")
+        .map(|line| match line.split_once(':') {
+            Some((name, value))
+                if name.trim().eq_ignore_ascii_case("Location")
+                    || name.trim().eq_ignore_ascii_case("Content-Location") =>
+            {
+                let value = value.replace(&format!("//{}", backend), &format!("//{}", public));
+                format!("{}:{}", name, value)
+            }
+            Some((name, value)) if name.trim().eq_ignore_ascii_case("Set-Cookie") => {
+                let attributes: Vec<String> = value
+                    .split(';')
+                    .map(|attribute| match attribute.trim().split_once('=') {
+                        Some((key, domain))
+                            if key.eq_ignore_ascii_case("Domain") && domain == backend_host =>
+                        {
+                            format!(" {}={}", key, public_host)
+                        }
+                        _ => attribute.to_string(),
+                    })
+                    .collect();
+                format!("{}:{}", name, attributes.join(";"))
+            }
+            _ => line.to_string(),
+        })
+        .collect();
+
+    let mut out = lines.join("\r\n").into_bytes();
+    out.extend_from_slice(&response[head_end..]);
+    out
+}
+
+/// Whether the backend leaves its connection open after this response: an
+/// HTTP/1.1 response without `Connection: close` whose body is delimited by
+/// `Content-Length` or chunked encoding
+pub fn backend_keeps_alive(head: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(head) else {
+        return false;
+    };
+    let mut lines = text.split("\r\n");
+    if !lines
+        .next()
+        .is_some_and(|line| line.starts_with("HTTP/1.1 "))
+    {
+        return false;
+    }
+    let close = lines
+        .filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("Connection")
+                && value.trim().eq_ignore_ascii_case("close")
+        });
+    !close && (response_body_len(head).is_some() || response_is_chunked(head))
+}
+
+/// Whether a response head says its body is chunked
+pub fn response_is_chunked(head: &[u8]) -> bool {
+    String::from_utf8_lossy(head)
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("Transfer-Encoding"))
+        .flat_map(|(_, value)| value.split(','))
+        .any(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+/// Body length of a response delimited by `Content-Length`, or `None` if it
+/// is chunked or runs until the connection closes
+pub fn response_body_len(head: &[u8]) -> Option<usize> {
+    let text = std::str::from_utf8(head).ok()?;
+    let mut content_length = None;
+    for (name, value) in text
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+    {
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("Transfer-Encoding") {
+            return None;
+        }
+        if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = Some(value.parse().ok()?);
+        }
+    }
+    content_length
+}
+
+/// Whether a status is an interim response that another follows, which is
+/// any 1xx but `101 Switching Protocols`
+pub fn is_interim(status: Option<u16>) -> bool {
+    matches!(status, Some(100..=199)) && status != Some(101)
+}
+
+/// Whether a response carries no body whatever its framing headers say: any
+/// answer to HEAD, and 1xx, 204 and 304 responses
+pub fn response_is_bodyless(method: &str, status: Option<u16>) -> bool {
+    method == "HEAD" || matches!(status, Some(100..=199 | 204 | 304))
+}
+
+/// Status code from a buffered response's status line
+pub fn response_status(response: &[u8]) -> Option<u16> {
+    let line_end = response.windows(2).position(|w| w == b"\r\n")?;
+    let line = std::str::from_utf8(&response[..line_end]).ok()?;
+    line.split(' ').nth(1)?.parse().ok()
+}
+
+/// Random (version 4) UUID used to tag a request as it passes through
+pub fn new_request_id() -> String {
+    let mut bytes = rand::random::<u128>().to_be_bytes();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
@@ -1,16 +1,159 @@
-use crate::algorithms::{Algorithm, LoadBalancingAlgorithm};
+mod access_log;
+mod acl;
+mod backend;
+mod backend_limit;
+mod builder;
+mod cache;
+mod coalesce;
+mod dashboard;
+mod http;
+mod outlier;
+mod pool;
+mod priority;
+mod rate_limit;
+mod route;
+mod servers_file;
+mod slow_start;
+mod stats;
+mod supervisor;
+mod tls;
+mod tuning;
+
+pub use access_log::{init_logging, LogFormat, ACCESS_LOG_TARGET};
+pub use acl::AccessControl;
+pub use backend::{host_header, BackendStream, HTTPS_PREFIX, UNIX_PREFIX};
+pub use backend_limit::{BackendLimits, BackendPermit};
+pub use builder::LoadBalancerBuilder;
+pub use cache::{Cache, CachedResponse, DEFAULT_CACHE_ENTRIES};
+pub use coalesce::{Flight, Follower, Leader, SingleFlight};
+pub use outlier::{OutlierDetector, OUTLIER_MIN_REQUESTS};
+pub use pool::{ConnectionPool, BACKOFF_BASE, BACKOFF_MAX};
+pub use priority::{parse_server_entry, PRIORITY_SUFFIX};
+pub use rate_limit::RateLimiter;
+pub use route::RouteGroup;
+pub use servers_file::read_servers_file;
+pub use slow_start::SlowStart;
+pub use stats::{AggregateMetrics, BackendStats};
+pub use supervisor::{supervise, RESTART_DELAY};
+pub use tuning::{weights_for, WeightTuner, MAX_TUNED_WEIGHT};
+
+use crate::algorithms::{Algorithm, AlgorithmState, LoadBalancingAlgorithm, RequestContext};
+use crate::config::Config;
+use crate::rng::SharedRng;
+use dashboard::DashboardRow;
+use http::{
+    backend_keeps_alive, chunked_progress, find_head_end, h2_preface, insert_response_header,
+    is_interim, new_request_id, response_body_len, response_is_bodyless, response_is_chunked,
+    response_status, rewrite_response_host, set_response_header, starts_request_line,
+    ChunkedScanner, RequestHead, MAX_HEAD_SIZE,
+};
+use priority::{lowest_tier, split_server_entries};
+use stats::Throughput;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpSocket},
     signal,
-    sync::{RwLock, Semaphore},
-    time::{interval, Duration},
+    sync::{OwnedSemaphorePermit, RwLock, Semaphore},
+    time::{interval, interval_at, sleep_until, timeout, Duration},
 };
+use tokio_rustls::{TlsAcceptor, TlsConnector};
 
-const MAX_CONNECTIONS: usize = 500;
+/// Default cap on concurrently handled client connections
+pub const MAX_CONNECTIONS: usize = 500;
+/// Default length of the listener's pending-connection queue
+pub const DEFAULT_BACKLOG: u32 = 1024;
+/// Largest response body held back until it has fully arrived; bigger ones
+/// are passed on as they stream in
+pub const MAX_BUFFERED_BODY: usize = 1024 * 1024;
 const METRICS_INTERVAL: u64 = 5; // seconds
+const THROUGHPUT_WINDOW: u64 = 10; // seconds of history behind requests/sec
+const MAX_IDLE_PER_SERVER: usize = 32;
+const WARM_INTERVAL: u64 = 1; // seconds between warm pool top-ups
+const RATE_LIMIT_IDLE: u64 = 60; // seconds before an idle client's bucket is dropped
+const KEEP_ALIVE_TIMEOUT: u64 = 5; // seconds a kept-alive client may sit idle
+const READY_PROBE_TIMEOUT: u64 = 1; // seconds a readiness probe waits to connect
+const READY_RETRY: u64 = 200; // milliseconds between startup readiness probes
+const MAX_ADMIN_BODY: usize = 1024; // bytes an admin request body may hold
+
+/// A request ready to send to a backend, and how its response may be handled
+struct ProxyRequest<'a> {
+    head: &'a RequestHead,
+    bytes: &'a [u8],
+    // Length-delimited, so the backend connection can be pooled
+    reusable: bool,
+    // Client asked to keep its connection open afterwards
+    keep_alive: bool,
+    set_cookie: Option<&'a str>,
+    cache_key: Option<&'a str>,
+    // Identical requests waiting on this one's response
+    leader: Option<&'a Leader>,
+    // Host the client addressed, swapped in for the backend's in response headers
+    public_host: Option<&'a str>,
+}
+
+/// How reading a chunked request body ended
+enum ChunkedBody {
+    // Encoded length of the whole body, through the trailers
+    Complete(usize),
+    TooLarge,
+    // The client closed before the last chunk
+    Incomplete,
+}
+
+/// Why a request couldn't be proxied, which decides what happens next
+enum ProxyError {
+    // The backend couldn't be reached, so nothing was sent to it and the
+    // request can go to another backend
+    Unsent(std::io::Error),
+    // The request went out but nothing came back and nothing reached the
+    // client; only an idempotent request can go to another backend, since
+    // this one may have acted on it
+    Unanswered(std::io::Error),
+    // The backend failed after it started answering; the client has had a
+    // 502 or a cut-off response
+    Backend(std::io::Error),
+    // The client went away; there's no one left to answer
+    Client(std::io::Error),
+}
+
+/// Where a length-delimited response body streamed to the client ends
+enum BodyFraming {
+    // Bytes still to come
+    Length(usize),
+    Chunked(ChunkedScanner),
+}
+
+/// How much of a pooled backend's response to read before answering
+enum ResponseBody {
+    // Buffer it whole, this many bytes including the head, before replying
+    Buffered(usize),
+    // Too big to buffer; pass it on as it arrives
+    Streamed(BodyFraming),
+    // No length, so relay until the backend closes
+    UntilClose,
+}
+
+/// Which end of a copy failed
+enum CopyError {
+    Read(std::io::Error),
+    Write(std::io::Error),
+}
+
+/// What came back from a backend, for the access log
+struct ProxyOutcome {
+    status: Option<u16>,
+    // Request bytes sent to the backend and response bytes relayed back
+    bytes_in: u64,
+    bytes_out: u64,
+    // Whether the client connection is still open for another request
+    keep_alive: bool,
+}
 
 #[derive(Clone)]
 pub struct LoadBalancer {
@@ -18,22 +161,758 @@ pub struct LoadBalancer {
     servers: Arc<RwLock<Vec<String>>>,
     algorithm: Algorithm,
     connection_limiter: Arc<Semaphore>,
+    max_connections: usize,
+    queue_timeout: Option<Duration>,
+    header_timeout: Option<Duration>,
+    connections_accepted: Arc<AtomicUsize>,
+    connections_queued: Arc<AtomicUsize>,
+    connections_rejected: Arc<AtomicUsize>,
+    backend_limits: BackendLimits,
+    pool: ConnectionPool,
+    warm_connections: usize,
+    stats: Arc<RwLock<HashMap<String, BackendStats>>>,
+    in_flight: Arc<RwLock<HashMap<String, usize>>>,
+    throughput: Arc<RwLock<Throughput>>,
+    started_at: Instant,
+    tls: Option<TlsAcceptor>,
+    // Dials `https://` backends
+    backend_tls: Option<TlsConnector>,
+    rate_limiter: Option<RateLimiter>,
+    acl: AccessControl,
+    routes: Vec<RouteGroup>,
+    slow_start: Option<SlowStart>,
+    draining: Arc<RwLock<HashSet<String>>>,
+    unhealthy: Arc<RwLock<HashSet<String>>>,
+    priorities: Arc<RwLock<HashMap<String, u32>>>,
+    cache: Option<Cache>,
+    single_flight: Option<SingleFlight>,
+    servers_file: Option<PathBuf>,
+    // Where to write the final metrics as JSON on shutdown
+    metrics_out: Option<PathBuf>,
+    outlier_detector: Option<OutlierDetector>,
+    weight_tuner: Option<WeightTuner>,
+    rewrite_host: bool,
+    // Forward the client's Host header as is, rather than the backend's address
+    preserve_host: bool,
+    max_body_size: Option<usize>,
+    // List 2xx/4xx/5xx response counts in each backend's metrics
+    count_by_status: bool,
+    nodelay: bool,
+    backlog: u32,
+    http2: bool,
+    idle_timeout: Option<Duration>,
+    // Longest to wait at startup for a reachable backend before accepting
+    ready_timeout: Option<Duration>,
+    // Backends may all be added after startup, so starting with none is fine
+    dynamic_servers: bool,
+    // Set by `with_seed`; shared by every component making random choices
+    rng: Option<SharedRng>,
 }
 
 impl LoadBalancer {
     pub fn new(port: u16, servers: Vec<String>, algorithm_type: &str) -> Self {
+        let (servers, priorities) = split_server_entries(&servers);
         Self {
             port,
             servers: Arc::new(RwLock::new(servers)),
             algorithm: Algorithm::new(algorithm_type, None),
             connection_limiter: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+            max_connections: MAX_CONNECTIONS,
+            queue_timeout: None,
+            header_timeout: None,
+            connections_accepted: Arc::new(AtomicUsize::new(0)),
+            connections_queued: Arc::new(AtomicUsize::new(0)),
+            connections_rejected: Arc::new(AtomicUsize::new(0)),
+            backend_limits: BackendLimits::default(),
+            pool: ConnectionPool::new(MAX_IDLE_PER_SERVER),
+            warm_connections: 0,
+            stats: Arc::new(RwLock::new(HashMap::new())),
+            in_flight: Arc::new(RwLock::new(HashMap::new())),
+            throughput: Arc::new(RwLock::new(Throughput::new(Duration::from_secs(
+                THROUGHPUT_WINDOW,
+            )))),
+            started_at: Instant::now(),
+            tls: None,
+            backend_tls: None,
+            rate_limiter: None,
+            acl: AccessControl::default(),
+            routes: Vec::new(),
+            slow_start: None,
+            draining: Arc::new(RwLock::new(HashSet::new())),
+            unhealthy: Arc::new(RwLock::new(HashSet::new())),
+            priorities: Arc::new(RwLock::new(priorities)),
+            cache: None,
+            single_flight: None,
+            servers_file: None,
+            metrics_out: None,
+            outlier_detector: None,
+            weight_tuner: None,
+            rewrite_host: false,
+            preserve_host: true,
+            max_body_size: None,
+            count_by_status: false,
+            nodelay: false,
+            backlog: DEFAULT_BACKLOG,
+            http2: false,
+            idle_timeout: None,
+            ready_timeout: None,
+            dynamic_servers: false,
+            rng: None,
+        }
+    }
+
+    /// Start a builder with default settings, for setting only what differs
+    pub fn builder() -> LoadBalancerBuilder {
+        LoadBalancerBuilder::new()
+    }
+
+    /// Build a balancer from a loaded config, including per-server weights
+    pub fn from_config(config: &Config) -> Self {
+        let mut balancer = Self::new(config.port, config.servers.clone(), &config.algorithm)
+            .with_max_connections(config.max_connections);
+        balancer.algorithm = Algorithm::new(&config.algorithm, Some(config.weights.clone()));
+        if let Some(limit) = config.max_per_backend {
+            balancer = balancer.with_max_per_backend(limit);
+        }
+        for (server, limit) in &config.backend_limits {
+            balancer = balancer.with_backend_limit(server, *limit);
+        }
+        if !config.acl.is_empty() {
+            balancer = balancer.with_acl(config.acl.clone());
+        }
+        for (name, route) in &config.routes {
+            let algorithm = Algorithm::new(&route.algorithm, Some(route.weights.clone()));
+            balancer = balancer.with_route(RouteGroup::new(
+                name,
+                &route.prefix,
+                route.servers.clone(),
+                algorithm,
+            ));
+        }
+        balancer
+    }
+
+    /// Cap the number of client connections handled at once
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.connection_limiter = Arc::new(Semaphore::new(max_connections));
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Cap every backend at `limit` requests in flight at once. Backends at
+    /// their cap are skipped; if they all are, requests queue for a slot.
+    pub fn with_max_per_backend(mut self, limit: usize) -> Self {
+        self.backend_limits = self.backend_limits.with_default(limit);
+        self
+    }
+
+    /// Cap `server` at `limit` requests in flight, in place of any default
+    pub fn with_backend_limit(mut self, server: &str, limit: usize) -> Self {
+        self.backend_limits = self.backend_limits.with_limit(server, limit);
+        self
+    }
+
+    /// Answer 503 to connections still waiting for a slot after `queue_timeout`
+    /// instead of queueing them indefinitely
+    pub fn with_queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = Some(queue_timeout);
+        self
+    }
+
+    /// Close connections that haven't sent a complete request head within
+    /// `header_timeout`, so silent or trickling clients (slowloris) can't hold
+    /// connection slots
+    pub fn with_header_timeout(mut self, header_timeout: Duration) -> Self {
+        self.header_timeout = Some(header_timeout);
+        self
+    }
+
+    /// Shut down (printing final metrics, as on Ctrl-C) once `idle_timeout`
+    /// passes without a new client connection
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Hold off accepting clients until some backend is reachable, or until
+    /// `ready_timeout` passes, so the first clients don't get errors
+    pub fn with_readiness_gate(mut self, ready_timeout: Duration) -> Self {
+        self.ready_timeout = Some(ready_timeout);
+        self
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn algorithm(&self) -> &Algorithm {
+        &self.algorithm
+    }
+
+    /// Route the default pool with an already-configured algorithm
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = match &self.rng {
+            Some(rng) => algorithm.with_rng(rng.clone()),
+            None => algorithm,
+        };
+        self
+    }
+
+    /// Current backend list
+    pub async fn servers(&self) -> Vec<String> {
+        self.servers.read().await.clone()
+    }
+
+    /// Add a backend to the default pool while running; with slow start
+    /// enabled it ramps up to its full share instead of taking it at once
+    pub async fn add_server(&self, server: &str) {
+        let (server, priority) = parse_server_entry(server);
+        {
+            let mut servers = self.servers.write().await;
+            if servers.contains(&server) {
+                return;
+            }
+            servers.push(server.clone());
+        }
+        self.priorities
+            .write()
+            .await
+            .insert(server.clone(), priority);
+        if let Some(slow_start) = &self.slow_start {
+            slow_start.begin(&server).await;
+        }
+    }
+
+    /// Allow starting with no backends at all, for embedders that add them
+    /// later with `add_server`
+    pub fn with_dynamic_servers(mut self) -> Self {
+        self.dynamic_servers = true;
+        self
+    }
+
+    /// Fail unless there's a backend to send requests to, or a way for one
+    /// to be added later (dynamic servers or a servers file)
+    pub async fn check_servers(&self) -> std::io::Result<()> {
+        if self.dynamic_servers
+            || self.servers_file.is_some()
+            || !self.all_servers().await.is_empty()
+        {
+            return Ok(());
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "no backend servers configured; pass --servers, --servers-file or routes",
+        ))
+    }
+
+    /// Re-read the default pool from `path` whenever the process gets SIGHUP
+    pub fn with_servers_file(mut self, path: &Path) -> Self {
+        self.servers_file = Some(path.to_path_buf());
+        self
+    }
+
+    /// On shutdown, also write the final metrics to `path` as JSON, in the
+    /// same shape as `/metrics?format=json`
+    pub fn with_metrics_out(mut self, path: &Path) -> Self {
+        self.metrics_out = Some(path.to_path_buf());
+        self
+    }
+
+    /// Swap in the backend list and tiers from the servers file, returning its
+    /// length. Requests already in flight keep the backend they were given.
+    pub async fn reload_servers(&self) -> std::io::Result<usize> {
+        let path = match &self.servers_file {
+            Some(path) => path,
+            None => return Ok(self.servers.read().await.len()),
+        };
+        let (servers, priorities) = split_server_entries(&read_servers_file(path)?);
+        *self.priorities.write().await = priorities;
+        let added: Vec<String> = {
+            let mut current = self.servers.write().await;
+            let added = servers
+                .iter()
+                .filter(|s| !current.contains(s))
+                .cloned()
+                .collect();
+            *current = servers;
+            added
+        };
+        if let Some(slow_start) = &self.slow_start {
+            for server in &added {
+                slow_start.begin(server).await;
+            }
+        }
+        Ok(self.servers.read().await.len())
+    }
+
+    /// Stop routing new requests to `server`; in-flight requests and existing
+    /// sticky sessions still complete
+    pub async fn drain(&self, server: &str) {
+        self.draining.write().await.insert(server.to_string());
+    }
+
+    /// Let a drained backend take new requests again
+    pub async fn undrain(&self, server: &str) {
+        self.draining.write().await.remove(server);
+    }
+
+    /// Backends currently draining
+    pub async fn draining_servers(&self) -> Vec<String> {
+        self.draining.read().await.iter().cloned().collect()
+    }
+
+    /// Record whether `server` is up. Unhealthy backends get no new requests,
+    /// and a tier with none left healthy hands its traffic to the next tier.
+    pub async fn set_healthy(&self, server: &str, healthy: bool) {
+        let mut unhealthy = self.unhealthy.write().await;
+        if healthy {
+            unhealthy.remove(server);
+        } else {
+            unhealthy.insert(server.to_string());
+        }
+    }
+
+    /// Backends currently marked unhealthy
+    pub async fn unhealthy_servers(&self) -> Vec<String> {
+        self.unhealthy.read().await.iter().cloned().collect()
+    }
+
+    /// Priority tier of `server`; 0 unless its entry gave another
+    pub async fn priority(&self, server: &str) -> u32 {
+        self.priorities
+            .read()
+            .await
+            .get(server)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Backends in the default pool followed by those only in route groups
+    async fn all_servers(&self) -> Vec<String> {
+        let mut servers = self.servers.read().await.clone();
+        for route in &self.routes {
+            for server in route.servers.read().await.iter() {
+                if !servers.contains(server) {
+                    servers.push(server.clone());
+                }
+            }
+        }
+        servers
+    }
+
+    // Whether `server` belongs to the default pool or any route group
+    async fn is_known_server(&self, server: &str) -> bool {
+        if self.servers.read().await.iter().any(|s| s == server) {
+            return true;
+        }
+        for route in &self.routes {
+            if route.servers.read().await.iter().any(|s| s == server) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Let identical GETs arriving while one is already with a backend wait
+    /// for and share its response instead of each sending their own
+    pub fn with_coalescing(mut self) -> Self {
+        self.single_flight = Some(SingleFlight::new());
+        self
+    }
+
+    /// Serve repeated GETs from a cache of 200 responses kept for `ttl`
+    pub fn with_cache(mut self, ttl: Duration, max_entries: usize) -> Self {
+        self.cache = Some(Cache::new(ttl, max_entries));
+        self
+    }
+
+    /// Eject backends whose average latency exceeds the pool mean by
+    /// `multiplier`, reinstating them after `cooldown`
+    pub fn with_outlier_detection(mut self, multiplier: f64, cooldown: Duration) -> Self {
+        self.outlier_detector = Some(OutlierDetector::new(multiplier, cooldown));
+        self
+    }
+
+    /// Backends currently ejected by outlier detection
+    pub async fn ejected_servers(&self) -> Vec<String> {
+        match &self.outlier_detector {
+            Some(detector) => detector.ejected_servers().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// Every `interval`, reweight weighted round-robin pools inversely to
+    /// each backend's average latency over the interval
+    pub fn with_weight_tuning(mut self, interval: Duration) -> Self {
+        self.weight_tuner = Some(WeightTuner::new(interval));
+        self
+    }
+
+    /// Run one weight-tuning cycle now, returning the weights it set
+    pub async fn tune_weights(&self) -> HashMap<String, u32> {
+        let Some(tuner) = &self.weight_tuner else {
+            return HashMap::new();
+        };
+        let stats = self.stats.read().await.clone();
+        let weights = tuner.cycle(&stats).await;
+        if weights.is_empty() {
+            return weights;
+        }
+        let algorithms =
+            std::iter::once(&self.algorithm).chain(self.routes.iter().map(|r| &r.algorithm));
+        for algorithm in algorithms {
+            if let Algorithm::WeightedRoundRobin(wrr) = algorithm {
+                // Leave drained (weight 0) servers drained
+                let current = wrr.weights().await;
+                let tuned: HashMap<String, u32> = weights
+                    .iter()
+                    .filter(|(server, _)| current.get(*server) != Some(&0))
+                    .map(|(server, weight)| (server.clone(), *weight))
+                    .collect();
+                wrr.set_weights(&tuned).await;
+            }
+        }
+        weights
+    }
+
+    /// Rewrite `Location`, `Content-Location` and `Set-Cookie` domains that
+    /// point at a backend to the host the client used to reach the balancer
+    pub fn with_rewrite_host(mut self, rewrite_host: bool) -> Self {
+        self.rewrite_host = rewrite_host;
+        self
+    }
+
+    /// Whether backends see the `Host` header the client sent (the default)
+    /// or one naming the backend the request went to, for backends doing
+    /// name-based virtual hosting on their own address
+    pub fn with_preserve_host(mut self, preserve_host: bool) -> Self {
+        self.preserve_host = preserve_host;
+        self
+    }
+
+    /// Answer 413 to requests whose body is larger than `max_body_size` bytes
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Ramp newly added backends linearly to their full share over `window`
+    pub fn with_slow_start(mut self, window: Duration) -> Self {
+        let slow_start = SlowStart::new(window);
+        self.slow_start = Some(match &self.rng {
+            Some(rng) => slow_start.with_rng(rng.clone()),
+            None => slow_start,
+        });
+        self
+    }
+
+    /// Seed every random routing choice (algorithm picks and slow-start
+    /// admission), so runs with the same seed and traffic route identically
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        let rng = SharedRng::seeded(seed);
+        self.algorithm = self.algorithm.with_rng(rng.clone());
+        for route in &mut self.routes {
+            route.algorithm = route.algorithm.clone().with_rng(rng.clone());
+        }
+        self.slow_start = self.slow_start.map(|s| s.with_rng(rng.clone()));
+        self.rng = Some(rng);
+        self
+    }
+
+    /// Hold `count` idle connections open to every backend, opened at startup
+    /// and topped up as they close or as unreachable backends come back
+    pub fn with_warm_connections(mut self, count: usize) -> Self {
+        self.pool = ConnectionPool::new(MAX_IDLE_PER_SERVER.max(count))
+            .with_nodelay(self.nodelay)
+            .with_tls(self.backend_tls.clone());
+        self.warm_connections = count;
+        self
+    }
+
+    /// Set `TCP_NODELAY` on client and backend connections so small
+    /// requests and responses go out without waiting on Nagle's algorithm
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.pool = self.pool.with_nodelay(nodelay);
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Break each backend's metrics down by response status class (2xx, 4xx,
+    /// 5xx)
+    pub fn with_count_by_status(mut self, count_by_status: bool) -> Self {
+        self.count_by_status = count_by_status;
+        self
+    }
+
+    /// Treat every client connection as HTTP/2: skip request parsing (and with
+    /// it metrics, admin and route paths) and pipe each connection untouched
+    /// to one backend of the default pool. Without this, connections opening
+    /// with the HTTP/2 preface are still passed through.
+    pub fn with_http2(mut self, http2: bool) -> Self {
+        self.http2 = http2;
+        self
+    }
+
+    /// Queue up to `backlog` connections the balancer hasn't accepted yet
+    pub fn with_backlog(mut self, backlog: u32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Idle pooled connections currently held to `server`
+    pub async fn idle_connections(&self, server: &str) -> usize {
+        self.pool.idle_connections(server).await
+    }
+
+    /// Whether at least one backend accepts a connection right now, even one
+    /// the pool is backing off from. Probe connections that succeed go into
+    /// the pool for the next request.
+    pub async fn is_ready(&self) -> bool {
+        let probe_timeout = Duration::from_secs(READY_PROBE_TIMEOUT);
+        for server in self.all_servers().await {
+            if let Ok(Ok(stream)) = timeout(probe_timeout, self.pool.probe(&server)).await {
+                self.pool.put(&server, stream).await;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Open idle connections to each non-draining backend up to the warm count;
+    // backends that refuse are retried on the next pass
+    async fn warm_pool(&self) {
+        let servers = self.all_servers().await;
+        let draining = self.draining.read().await.clone();
+        for server in servers.iter().filter(|s| !draining.contains(*s)) {
+            let _ = self.pool.warm(server, self.warm_connections).await;
+        }
+    }
+
+    /// Cap on client connections handled at once
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// Connection slots not currently in use
+    pub fn available_connections(&self) -> usize {
+        self.connection_limiter.available_permits()
+    }
+
+    /// Send requests under the route's path prefix to its own pool; paths
+    /// matching no route fall back to the default servers, or 404 without any
+    pub fn with_route(mut self, mut route: RouteGroup) -> Self {
+        if let Some(rng) = &self.rng {
+            route.algorithm = route.algorithm.with_rng(rng.clone());
+        }
+        self.routes.push(route);
+        // Longest prefix first so the most specific route wins
+        self.routes
+            .sort_by_key(|route| std::cmp::Reverse(route.prefix().len()));
+        self
+    }
+
+    /// Typed selection state of the default pool's algorithm
+    pub async fn algorithm_state(&self) -> AlgorithmState {
+        self.algorithm.state().await
+    }
+
+    /// Configured route groups, most specific prefix first
+    pub fn routes(&self) -> &[RouteGroup] {
+        &self.routes
+    }
+
+    fn route_for(&self, path: &str) -> Option<&RouteGroup> {
+        self.routes.iter().find(|route| route.matches(path))
+    }
+
+    /// Answer requests `acl` refuses with 403 Forbidden, before they reach
+    /// a backend or any of the balancer's own endpoints
+    pub fn with_acl(mut self, acl: AccessControl) -> Self {
+        self.acl = acl;
+        self
+    }
+
+    /// Limit each client IP to `rate` requests per second, answering 429 beyond it
+    pub fn with_rate_limit(mut self, rate: f64) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(rate));
+        self
+    }
+
+    /// Terminate TLS from clients using a PEM certificate chain and key;
+    /// backends are still spoken to in plaintext unless listed as `https://`
+    pub fn with_tls(mut self, cert_path: &Path, key_path: &Path) -> std::io::Result<Self> {
+        self.tls = Some(tls::load_acceptor(cert_path, key_path)?);
+        Ok(self)
+    }
+
+    /// Dial `https://` backends over TLS, trusting the PEM certificates in
+    /// `ca_path`
+    pub fn with_backend_tls(self, ca_path: &Path) -> std::io::Result<Self> {
+        Ok(self.with_backend_connector(tls::load_connector(ca_path)?))
+    }
+
+    /// Dial `https://` backends over TLS without verifying their
+    /// certificates. Only for testing against self-signed backends.
+    pub fn with_insecure_backend_tls(self) -> Self {
+        self.with_backend_connector(tls::insecure_connector())
+    }
+
+    fn with_backend_connector(mut self, connector: TlsConnector) -> Self {
+        self.pool = self.pool.with_tls(Some(connector.clone()));
+        self.backend_tls = Some(connector);
+        self
+    }
+
+    /// Algorithm metrics with the balancer's own per-backend latency appended.
+    /// Every configured backend is listed, with zeroed counters until it sees
+    /// traffic.
+    pub async fn get_metrics(&self) -> HashMap<String, String> {
+        let mut metrics = self.algorithm.get_metrics().await;
+        for route in &self.routes {
+            metrics.extend(route.algorithm.get_metrics().await);
+        }
+        for server in self.draining.read().await.iter() {
+            metrics
+                .entry(server.clone())
+                .and_modify(|metric| metric.push_str(", Draining"))
+                .or_insert_with(|| "Draining".to_string());
+        }
+        for server in self.ejected_servers().await {
+            metrics
+                .entry(server)
+                .and_modify(|metric| metric.push_str(", Ejected"))
+                .or_insert_with(|| "Ejected".to_string());
+        }
+        if let Some(tuner) = &self.weight_tuner {
+            for (server, weight) in tuner.tuned_weights().await {
+                let tuned = format!("Tuned weight: {}", weight);
+                metrics
+                    .entry(server)
+                    .and_modify(|metric| {
+                        metric.push_str(", ");
+                        metric.push_str(&tuned);
+                    })
+                    .or_insert(tuned);
+            }
+        }
+        let configured = self.all_servers().await;
+        let mut stats = self.stats.write().await;
+        for server in configured {
+            stats.entry(server).or_default();
+        }
+        for (server, backend) in stats.iter() {
+            let mut latency = format!(
+                "Avg latency: {:.1}ms, Total latency: {:.1}ms, Bytes in: {}, Bytes out: {}",
+                backend.average_latency().as_secs_f64() * 1000.0,
+                backend.total_latency.as_secs_f64() * 1000.0,
+                backend.bytes_in,
+                backend.bytes_out
+            );
+            if self.count_by_status {
+                latency.push_str(&format!(
+                    ", 2xx: {}, 4xx: {}, 5xx: {}",
+                    backend.status_2xx, backend.status_4xx, backend.status_5xx
+                ));
+            }
+            metrics
+                .entry(server.clone())
+                .and_modify(|metric| {
+                    metric.push_str(", ");
+                    metric.push_str(&latency);
+                })
+                .or_insert(latency);
+        }
+        metrics
+    }
+
+    /// Totals across all backends plus current throughput and uptime
+    pub async fn aggregate_metrics(&self) -> AggregateMetrics {
+        let uptime = self.started_at.elapsed();
+        let total_requests = self.stats.read().await.values().map(|s| s.requests).sum();
+        AggregateMetrics {
+            total_requests,
+            requests_per_second: self.throughput.write().await.rate(uptime),
+            active_connections: self.max_connections - self.available_connections(),
+            connections_accepted: self.connections_accepted.load(Ordering::Relaxed),
+            connections_queued: self.connections_queued.load(Ordering::Relaxed),
+            connections_rejected: self.connections_rejected.load(Ordering::Relaxed),
+            uptime_secs: uptime.as_secs(),
+            cache_hits: self.cache.as_ref().map(Cache::hits),
+            cache_misses: self.cache.as_ref().map(Cache::misses),
+        }
+    }
+
+    /// Totals, per-backend metric strings and per-backend request counts as
+    /// one JSON document
+    pub async fn metrics_json(&self) -> serde_json::Result<serde_json::Value> {
+        let mut body = serde_json::to_value(self.aggregate_metrics().await)?;
+        body["servers"] = serde_json::json!(self.get_metrics().await);
+        let requests: HashMap<String, usize> = self
+            .stats
+            .read()
+            .await
+            .iter()
+            .map(|(server, backend)| (server.clone(), backend.requests))
+            .collect();
+        body["requests"] = serde_json::json!(requests);
+        Ok(body)
+    }
+
+    // Write the final metrics to the `--metrics-out` file, if one was given
+    async fn export_metrics(&self) {
+        let Some(path) = &self.metrics_out else {
+            return;
+        };
+        let result = match self.metrics_json().await {
+            Ok(body) => tokio::fs::write(path, body.to_string()).await,
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(()) => println!("Wrote final metrics to {}", path.display()),
+            Err(e) => eprintln!("Failed to write metrics to {}: {}", path.display(), e),
         }
     }
 
+    // Per-backend figures for the dashboard page
+    async fn dashboard_rows(&self) -> Vec<DashboardRow> {
+        let draining = self.draining_servers().await;
+        let ejected = self.ejected_servers().await;
+        let unhealthy = self.unhealthy_servers().await;
+        let stats = self.stats.read().await.clone();
+        let in_flight = self.in_flight.read().await.clone();
+
+        let mut rows = Vec::new();
+        for server in self.all_servers().await {
+            let warming = match &self.slow_start {
+                Some(slow_start) => slow_start.fraction(&server).await < 1.0,
+                None => false,
+            };
+            let state = if draining.contains(&server) {
+                "Draining"
+            } else if ejected.contains(&server) {
+                "Ejected"
+            } else if unhealthy.contains(&server) {
+                "Unhealthy"
+            } else if warming {
+                "Warming"
+            } else {
+                "Healthy"
+            };
+            rows.push(DashboardRow {
+                active_connections: in_flight.get(&server).copied().unwrap_or(0),
+                requests: stats.get(&server).map_or(0, |s| s.requests),
+                state,
+                server,
+            });
+        }
+        rows
+    }
+
     async fn print_metrics(&self, prefix: &str) {
-        let metrics = self.algorithm.get_metrics().await;
+        let metrics = self.get_metrics().await;
         if !metrics.is_empty() {
             println!("\n{}", prefix);
+            println!("{}", self.aggregate_metrics().await);
             for (server, metric) in metrics {
                 println!("{}: {}", server, metric);
             }
@@ -42,121 +921,1574 @@ impl LoadBalancer {
 
     pub async fn run(&self) {
         let addr = SocketAddr::from(([127, 0, 0, 1], self.port));
-        let listener = TcpListener::bind(addr).await.unwrap();
-        println!("Load balancer listening on {}", addr);
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.set_reuseaddr(true).unwrap();
+        socket.bind(addr).unwrap();
+        let listener = socket.listen(self.backlog).unwrap();
+        self.run_with_listener(listener).await;
+    }
 
-        // Start metrics reporting
-        let algorithm = self.algorithm.clone();
-        let metrics_task = tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(METRICS_INTERVAL));
-            loop {
-                interval.tick().await;
-                let metrics = algorithm.get_metrics().await;
-                if !metrics.is_empty() {
-                    println!("\nServer Metrics:");
-                    for (server, metric) in metrics {
-                        println!("{}: {}", server, metric);
+    /// Serve clients accepted on an already-bound `listener` until Ctrl-C.
+    /// Bind port 0 to let the OS pick a free port; the balancer takes on
+    /// whichever port the listener has.
+    pub async fn run_with_listener(&self, listener: TcpListener) {
+        let mut this = self.clone();
+        this.port = listener.local_addr().unwrap().port();
+        this.serve(listener).await;
+    }
+
+    async fn serve(&self, listener: TcpListener) {
+        // Serving with no backends would only ever answer 503
+        if let Err(e) = self.check_servers().await {
+            eprintln!("Not starting load balancer: {}", e);
+            return;
+        }
+
+        // Listen for SIGHUP before serving so an early signal isn't fatal
+        #[cfg(unix)]
+        let reload_task = self.servers_file.as_ref().map(|path| {
+            let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+                .expect("Failed to install SIGHUP handler");
+            let path = path.clone();
+            let this = self.clone();
+            tokio::spawn(async move {
+                while hangup.recv().await.is_some() {
+                    match this.reload_servers().await {
+                        Ok(count) => {
+                            println!("Reloaded {} servers from {}", count, path.display())
+                        }
+                        Err(e) => eprintln!("Failed to reload {}: {}", path.display(), e),
                     }
                 }
+            })
+        });
+
+        // Populate algorithm state for the starting servers up front
+        self.algorithm.initialize(&self.servers().await).await;
+        for route in &self.routes {
+            let servers = route.servers.read().await.clone();
+            route.algorithm.initialize(&servers).await;
+        }
+
+        if let Some(ready_timeout) = self.ready_timeout {
+            let deadline = tokio::time::Instant::now() + ready_timeout;
+            while !self.is_ready().await {
+                if tokio::time::Instant::now() >= deadline {
+                    eprintln!(
+                        "No backend reachable after {:?}; accepting clients anyway",
+                        ready_timeout
+                    );
+                    break;
+                }
+                sleep_until(
+                    deadline.min(tokio::time::Instant::now() + Duration::from_millis(READY_RETRY)),
+                )
+                .await;
             }
+        }
+
+        println!(
+            "Load balancer listening on {}",
+            listener.local_addr().unwrap()
+        );
+
+        // Start metrics reporting, restarted if it ever panics
+        let this = self.clone();
+        let metrics_task = supervise("metrics", RESTART_DELAY, move || {
+            let this = this.clone();
+            async move {
+                let mut interval = interval(Duration::from_secs(METRICS_INTERVAL));
+                loop {
+                    interval.tick().await;
+                    this.print_metrics("Server Metrics:").await;
+                }
+            }
+        });
+
+        // Keep the warm pool topped up
+        let warm_task = (self.warm_connections > 0).then(|| {
+            let this = self.clone();
+            tokio::spawn(async move {
+                let mut interval = interval(Duration::from_secs(WARM_INTERVAL));
+                loop {
+                    interval.tick().await;
+                    this.warm_pool().await;
+                }
+            })
+        });
+
+        // Forget rate-limit buckets for clients that went quiet
+        let eviction_task = self.rate_limiter.clone().map(|limiter| {
+            tokio::spawn(async move {
+                let idle = Duration::from_secs(RATE_LIMIT_IDLE);
+                let mut interval = interval(idle);
+                loop {
+                    interval.tick().await;
+                    limiter.evict_idle(idle).await;
+                }
+            })
+        });
+
+        // Reweight from the latency seen since the last cycle
+        let tuning_task = self.weight_tuner.as_ref().map(|tuner| {
+            let this = self.clone();
+            let period = tuner.interval();
+            tokio::spawn(async move {
+                let mut interval = interval_at(tokio::time::Instant::now() + period, period);
+                loop {
+                    interval.tick().await;
+                    this.tune_weights().await;
+                }
+            })
         });
 
         // Handle shutdown signal
         let shutdown = signal::ctrl_c();
         tokio::pin!(shutdown);
 
+        let mut last_accept = tokio::time::Instant::now();
         loop {
+            let idle_deadline = last_accept + self.idle_timeout.unwrap_or_default();
             tokio::select! {
                 accept_result = listener.accept() => {
-                    let (client, _) = accept_result.unwrap();
-                    let servers = Arc::clone(&self.servers);
-                    let algorithm = self.algorithm.clone();
+                    let (client, client_addr) = accept_result.unwrap();
+                    last_accept = tokio::time::Instant::now();
+                    self.connections_accepted.fetch_add(1, Ordering::Relaxed);
+                    if self.nodelay {
+                        if let Err(e) = client.set_nodelay(true) {
+                            eprintln!("Failed to set nodelay for {}: {}", client_addr, e);
+                        }
+                    }
                     let this = self.clone();
-                    let permit = Arc::clone(&self.connection_limiter)
-                        .acquire_owned()
-                        .await
-                        .unwrap();
+                    // Without a queue timeout, stop accepting until a slot
+                    // frees up; with one, each connection waits on its own
+                    let permit = match self.queue_timeout {
+                        Some(_) => None,
+                        None => self.acquire_slot(None).await,
+                    };
 
                     tokio::spawn(async move {
-                        let server = {
-                            let servers = servers.read().await;
-                            match algorithm.next_server(&servers).await {
-                                Some(server) => server,
-                                None => return,
-                            }
+                        let result = match &this.tls {
+                            Some(acceptor) => match acceptor.accept(client).await {
+                                Ok(stream) => this.admit(stream, client_addr, permit).await,
+                                Err(e) => Err(e),
+                            },
+                            None => this.admit(client, client_addr, permit).await,
                         };
-
-                        algorithm.connection_started(&server).await;
-                        let result = this.forward_request(client, server.clone()).await;
-                        algorithm.connection_ended(&server).await;
-
                         if let Err(e) = result {
-                            eprintln!("Error forwarding request to {}: {}", server, e);
+                            eprintln!("Error handling client {}: {}", client_addr, e);
                         }
-
-                        drop(permit);
                     });
                 }
                 _ = &mut shutdown => {
                     println!("\nShutdown signal received. Printing final metrics...");
-                    self.print_metrics("Final Server Metrics:").await;
-                    metrics_task.abort();
+                    break;
+                }
+                _ = sleep_until(idle_deadline), if self.idle_timeout.is_some() => {
+                    println!(
+                        "\nNo connections for {:?}. Printing final metrics...",
+                        self.idle_timeout.unwrap()
+                    );
                     break;
                 }
             }
         }
 
+        self.print_metrics("Final Server Metrics:").await;
+        self.export_metrics().await;
+        metrics_task.abort();
+        if let Some(task) = &eviction_task {
+            task.abort();
+        }
+        if let Some(task) = &warm_task {
+            task.abort();
+        }
+        if let Some(task) = &tuning_task {
+            task.abort();
+        }
+        #[cfg(unix)]
+        if let Some(task) = &reload_task {
+            task.abort();
+        }
+
         println!("Load balancer shutting down.");
     }
 
-    async fn forward_request(
+    // Serve the client once it holds a connection slot, waiting up to the
+    // queue timeout for one if it doesn't have one yet
+    async fn admit<S>(
         &self,
-        mut client: TcpStream,
-        server_addr: String,
-    ) -> std::io::Result<()> {
-        // Read the request first
-        let mut buffer = [0; 1024];
-        let n = client.read(&mut buffer).await?;
-        let request = String::from_utf8_lossy(&buffer[..n]);
-
-        // Check if it's a metrics request
-        if request.contains("GET /metrics") {
-            let metrics = self.algorithm.get_metrics().await;
-            let mut response = String::new();
-            for (server, metric) in metrics {
-                response.push_str(&format!("{}: {}\n", server, metric));
+        mut client: S,
+        client_addr: SocketAddr,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> std::io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let permit = match permit {
+            Some(permit) => Some(permit),
+            None => self.acquire_slot(self.queue_timeout).await,
+        };
+        let Some(_permit) = permit else {
+            return Self::reply(
+                &mut client,
+                "503 Service Unavailable",
+                &[("Retry-After", "1")],
+                "Too many connections\n",
+            )
+            .await;
+        };
+        self.serve_client(client, client_addr).await
+    }
+
+    // Take a connection slot, waiting at most `wait` (if set) while counted
+    // as queued. `None` means the wait ran out and the connection is rejected.
+    async fn acquire_slot(&self, wait: Option<Duration>) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = Arc::clone(&self.connection_limiter).try_acquire_owned() {
+            return Some(permit);
+        }
+        self.connections_queued.fetch_add(1, Ordering::Relaxed);
+        let acquire = Arc::clone(&self.connection_limiter).acquire_owned();
+        let permit = match wait {
+            Some(wait) => timeout(wait, acquire).await.ok(),
+            None => Some(acquire.await),
+        };
+        self.connections_queued.fetch_sub(1, Ordering::Relaxed);
+        if permit.is_none() {
+            self.connections_rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        permit.map(|permit| permit.unwrap())
+    }
+
+    // Serve requests from one client connection until either side closes it
+    async fn serve_client<S>(&self, mut client: S, client_addr: SocketAddr) -> std::io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut buffer = Vec::with_capacity(1024);
+        // HTTP/2 (always in http2 mode, otherwise when the client opens with
+        // its preface) is passed through untouched instead of parsed
+        let is_h2 = self.http2 || {
+            let preface = Self::read_h2_preface(&mut client, &mut buffer);
+            match self.header_timeout {
+                Some(limit) => match timeout(limit, preface).await {
+                    Ok(is_h2) => is_h2?,
+                    Err(_) => return Self::header_timed_out(&mut client, &buffer).await,
+                },
+                None => preface.await?,
             }
+        };
+        if is_h2 {
+            return self.tunnel(client, client_addr, buffer).await;
+        }
+        let mut idle_timeout = None;
+        while self
+            .forward_request(&mut client, client_addr, &mut buffer, idle_timeout)
+            .await?
+        {
+            idle_timeout = Some(Duration::from_secs(KEEP_ALIVE_TIMEOUT));
+        }
+        Ok(())
+    }
+
+    // Handle one request, starting from any bytes already in `buffer`, and
+    // return whether the connection stays open for another. `buffer` keeps
+    // whatever the client sent past the end of this request.
+    async fn forward_request<S>(
+        &self,
+        client: &mut S,
+        client_addr: SocketAddr,
+        buffer: &mut Vec<u8>,
+        idle_timeout: Option<Duration>,
+    ) -> std::io::Result<bool>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        // Read until the end of the request head (or the size limit). The
+        // header timeout runs from the start of the request, or at once on a
+        // new connection.
+        let mut chunk = [0; 1024];
+        let mut head_deadline = None;
+        let head_end = loop {
+            if let Some(end) = find_head_end(buffer) {
+                break Some(end);
+            }
+            if buffer.len() >= MAX_HEAD_SIZE {
+                break None;
+            }
+            if head_deadline.is_none() && (idle_timeout.is_none() || !buffer.is_empty()) {
+                head_deadline = self.header_timeout.map(|limit| Instant::now() + limit);
+            }
+            let read = client.read(&mut chunk);
+            let n = match (idle_timeout.filter(|_| buffer.is_empty()), head_deadline) {
+                (Some(idle), _) => match timeout(idle, read).await {
+                    Ok(n) => n?,
+                    Err(_) => return Ok(false),
+                },
+                (None, Some(deadline)) => {
+                    match timeout(deadline.saturating_duration_since(Instant::now()), read).await {
+                        Ok(n) => n?,
+                        Err(_) => {
+                            Self::header_timed_out(client, buffer).await?;
+                            return Ok(false);
+                        }
+                    }
+                }
+                (None, None) => read.await?,
+            };
+            if n == 0 {
+                // Closed without starting another request
+                if buffer.is_empty() {
+                    return Ok(false);
+                }
+                break None;
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+        };
+        // Anything that doesn't open with a valid request line and headers is
+        // turned away before it can reach a backend
+        let parsed =
+            head_end.and_then(|end| RequestHead::parse(&buffer[..end]).map(|head| (head, end)));
+        let (mut head, head_end) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                Self::reply(client, "400 Bad Request", &[], "Bad Request\n").await?;
+                return Ok(false);
+            }
+        };
+        let keep_alive = head.wants_keep_alive();
+        // The transfer coding frames the body whatever length is declared
+        // alongside it. Forwarding both would let a backend that goes by the
+        // length read part of the body as another request.
+        if head.header("Transfer-Encoding").is_some() {
+            head.remove_header("Content-Length");
+        }
 
-            let response = format!(
-                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-                response.len(),
-                response
+        if !self.acl.permits(&head.method, &head.path) {
+            Self::reply(client, "403 Forbidden", &[], "Forbidden\n").await?;
+            return Ok(false);
+        }
+
+        // Live HTML view of the backends
+        if head.method == "GET" && head.path == "/dashboard" {
+            let page = dashboard::render(
+                &self.aggregate_metrics().await,
+                &self.dashboard_rows().await,
             );
-            client.write_all(response.as_bytes()).await?;
-            client.shutdown().await?;
-            return Ok(());
+            Self::reply(
+                client,
+                "200 OK",
+                &[("Content-Type", "text/html; charset=utf-8")],
+                &page,
+            )
+            .await?;
+            return Ok(false);
+        }
+
+        // Readiness for orchestrators: whether any backend is reachable
+        if head.method == "GET" && head.path == "/ready" {
+            if self.is_ready().await {
+                Self::reply(client, "200 OK", &[], "Ready\n").await?;
+            } else {
+                Self::reply(
+                    client,
+                    "503 Service Unavailable",
+                    &[],
+                    "No backend reachable\n",
+                )
+                .await?;
+            }
+            return Ok(false);
+        }
+
+        // Check if it's a metrics request, as text or `?format=json`
+        let metrics_request = match (head.method.as_str(), head.path.as_str()) {
+            ("GET", "/metrics") => Some(false),
+            ("GET", "/metrics?format=json") => Some(true),
+            _ => None,
+        };
+        if let Some(json) = metrics_request {
+            let (content_type, body) = if json {
+                ("application/json", self.metrics_json().await?.to_string())
+            } else {
+                let mut body = format!("{}\n", self.aggregate_metrics().await);
+                for (server, metric) in self.get_metrics().await {
+                    body.push_str(&format!("{}: {}\n", server, metric));
+                }
+                ("text/plain", body)
+            };
+
+            Self::reply(client, "200 OK", &[("Content-Type", content_type)], &body).await?;
+            return Ok(false);
+        }
+
+        // Zero the counters so later metrics reflect only new traffic
+        if head.method == "POST" && head.path == "/admin/metrics/reset" {
+            self.algorithm.reset_metrics().await;
+            for route in &self.routes {
+                route.algorithm.reset_metrics().await;
+            }
+            self.stats.write().await.clear();
+            self.throughput.write().await.clear();
+            self.connections_accepted.store(0, Ordering::Relaxed);
+            self.connections_rejected.store(0, Ordering::Relaxed);
+            if let Some(cache) = &self.cache {
+                cache.reset_counts();
+            }
+
+            Self::reply(client, "200 OK", &[], "Metrics reset\n").await?;
+            return Ok(false);
+        }
+
+        // Operator-initiated drain: stop sending new traffic to a backend
+        let drain_request = match (head.method.as_str(), head.path.as_str()) {
+            ("POST", "/admin/servers/drain") => Some(true),
+            ("POST", "/admin/servers/undrain") => Some(false),
+            _ => None,
+        };
+        if let Some(drain) = drain_request {
+            let body_len = head.content_length().unwrap_or(0);
+            // A server address is short; don't buffer whatever else is sent
+            if body_len > MAX_ADMIN_BODY {
+                Self::reply(client, "413 Payload Too Large", &[], "Payload Too Large\n").await?;
+                return Ok(false);
+            }
+            Self::read_body(client, buffer, head_end + body_len).await?;
+            let body = buffer
+                .get(head_end..head_end + body_len)
+                .unwrap_or_default();
+            let server = String::from_utf8_lossy(body).trim().to_string();
+
+            let (status, message) = if server.is_empty() {
+                ("400 Bad Request", "Expected a server address\n".to_string())
+            } else if !self.is_known_server(&server).await {
+                ("404 Not Found", format!("Unknown server {}\n", server))
+            } else if drain {
+                self.drain(&server).await;
+                ("200 OK", format!("Draining {}\n", server))
+            } else {
+                self.undrain(&server).await;
+                ("200 OK", format!("Undrained {}\n", server))
+            };
+            Self::reply(client, status, &[], &message).await?;
+            return Ok(false);
+        }
+
+        // Turn away clients over their rate limit before touching a backend
+        if let Some(limiter) = &self.rate_limiter {
+            if !limiter.check(client_addr.ip()).await {
+                Self::reply(
+                    client,
+                    "429 Too Many Requests",
+                    &[("Retry-After", "1")],
+                    "Too Many Requests\n",
+                )
+                .await?;
+                return Ok(false);
+            }
+        }
+
+        // Refuse oversized bodies before any of them reaches a backend. A
+        // chunked body declares no size, so it's buffered up to the cap
+        // whatever length is declared alongside it.
+        if let Some(limit) = self.max_body_size {
+            let too_large = if head.is_chunked() {
+                matches!(
+                    Self::read_chunked_body(client, buffer, head_end, Some(limit)).await?,
+                    ChunkedBody::TooLarge
+                )
+            } else {
+                head.content_length().is_some_and(|len| len > limit)
+            };
+            if too_large {
+                Self::reply(client, "413 Payload Too Large", &[], "Payload Too Large\n").await?;
+                return Ok(false);
+            }
+        }
+
+        // Pick the pool serving this path
+        let route = self.route_for(&head.path);
+        let (servers, algorithm) = match route {
+            Some(route) => (&route.servers, &route.algorithm),
+            None => (&self.servers, &self.algorithm),
+        };
+        if route.is_none() && !self.routes.is_empty() && servers.read().await.is_empty() {
+            Self::reply(client, "404 Not Found", &[], "Not Found\n").await?;
+            return Ok(false);
+        }
+
+        // Host the client addressed, for pointing backend URLs back at it
+        let public_host = self.rewrite_host.then(|| {
+            head.header("Host")
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("127.0.0.1:{}", self.port))
+        });
+
+        // Only a request without a body can be answered with a response
+        // fetched for another, as nothing is left of it to read afterwards
+        let bodiless =
+            head.header("Transfer-Encoding").is_none() && head.content_length().unwrap_or(0) == 0;
+
+        // Answer repeated GETs without touching a backend, pointing the
+        // stored response at this client's host. One sent with credentials
+        // is neither answered from nor stored in the shared cache.
+        let cache_key = self
+            .cache
+            .as_ref()
+            .filter(|_| bodiless && !head.has_credentials())
+            .and_then(|_| Cache::key(&head.method, &head.path));
+        if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+            if let Some(cached) = cache.get(key).await {
+                let rewrite = public_host
+                    .as_deref()
+                    .map(|public| (cached.backend.as_str(), public));
+                return Self::reply_shared(
+                    client,
+                    buffer,
+                    head_end,
+                    keep_alive,
+                    &cached.response,
+                    rewrite,
+                )
+                .await;
+            }
+        }
+
+        // Identical body-less GETs already with a backend share its response.
+        // If that request fails, or its response is personal, each waiting
+        // one is sent on its own. One with credentials never shares.
+        let flight = match &self.single_flight {
+            Some(single_flight) if bodiless && !head.has_credentials() => {
+                SingleFlight::key(&head.method, &head.path).map(|key| single_flight.join(&key))
+            }
+            _ => None,
+        };
+        let leader = match flight {
+            Some(Flight::Leader(leader)) => Some(leader),
+            Some(Flight::Follower(follower)) => {
+                if let Some(response) = follower.response().await {
+                    return Self::reply_shared(
+                        client, buffer, head_end, keep_alive, &response, None,
+                    )
+                    .await;
+                }
+                None
+            }
+            None => None,
+        };
+
+        // Sticky sessions: reuse the client's cookie or mint a new one
+        let mut context = RequestContext {
+            client_addr: Some(client_addr),
+            draining: self.draining_servers().await,
+            ..Default::default()
+        };
+        let mut new_session = None;
+        if let Some(cookie_name) = algorithm.session_cookie() {
+            context.session = head.cookie(cookie_name).map(str::to_string);
+            if context.session.is_none() {
+                let session = format!("{:032x}", rand::random::<u128>());
+                context.session = Some(session.clone());
+                new_session = Some(format!("{}={}; Path=/", cookie_name, session));
+            }
+        }
+        if let Some(header) = algorithm.affinity_header() {
+            context.affinity_key = head.header(header).map(str::to_string);
+        }
+
+        let server_addr = {
+            let servers = self.available_servers(servers, &context.draining).await;
+            let server = match algorithm.next_server_for(&servers, &context).await {
+                Some(server) => server,
+                None => {
+                    // Every backend is gone or draining; say so instead of hanging up
+                    Self::reply(
+                        client,
+                        "503 Service Unavailable",
+                        &[("Retry-After", "1")],
+                        "No backend available\n",
+                    )
+                    .await?;
+                    return Ok(false);
+                }
+            };
+            match &self.slow_start {
+                // A warming server that loses the draw hands the request to
+                // the rest of the pool, scaling its effective weight down
+                Some(slow_start) if !slow_start.admit(&server).await => {
+                    let others: Vec<String> =
+                        servers.iter().filter(|s| **s != server).cloned().collect();
+                    algorithm
+                        .next_server_for(&others, &context)
+                        .await
+                        .unwrap_or(server)
+                }
+                _ => server,
+            }
+        };
+
+        let (method, path) = (head.method.clone(), head.path.clone());
+        // Keep the client's request ID if it sent one so traces line up
+        let request_id = head
+            .header("X-Request-Id")
+            .map(str::to_string)
+            .unwrap_or_else(new_request_id);
+
+        // Buffer a length-delimited or chunked body in full so the backend
+        // connection can go back to the pool afterwards. Chunked bodies are
+        // forwarded still encoded, exactly as the client framed them. One too
+        // large to hold is streamed over a connection of its own instead, as
+        // is one the client won't send until the backend says to continue,
+        // and any upgrade request, whose connection may become a tunnel.
+        let body_len = if head.is_upgrade() || head.expects_continue() {
+            None
+        } else if head.is_chunked() {
+            match Self::read_chunked_body(client, buffer, head_end, Some(MAX_BUFFERED_BODY)).await?
+            {
+                ChunkedBody::Complete(len) => Some(len),
+                _ => None,
+            }
+        } else if head.header("Transfer-Encoding").is_none() {
+            match head.content_length().unwrap_or(0) {
+                len if len > MAX_BUFFERED_BODY => None,
+                len => Self::read_body(client, buffer, head_end + len)
+                    .await?
+                    .then_some(len),
+            }
+        } else {
+            None
+        };
+        // A client that pipelined its next request behind this one gets its
+        // answers in order on this connection, unless it asked to close
+        let pipelined = body_len.is_some_and(|len| starts_request_line(&buffer[head_end + len..]));
+        let keep_alive = keep_alive || (pipelined && !head.wants_close());
+        let mut reusable = false;
+        let mut body_end = None;
+        if let Some(body_len) = body_len {
+            body_end = Some(head_end + body_len);
+            head.set_header("Connection", "keep-alive");
+            reusable = true;
+        } else if !head.is_upgrade() {
+            // Relayed until the backend closes, so have it close when done
+            head.set_header("Connection", "close");
+        }
+
+        // Tag the request with the client's address before forwarding
+        head.append_header("X-Forwarded-For", &client_addr.ip().to_string());
+        let proto = if self.tls.is_some() { "https" } else { "http" };
+        head.set_header("X-Forwarded-Proto", proto);
+        head.set_header("X-Request-Id", &request_id);
+        let body = match body_end {
+            // Anything past the body is the start of the next request
+            Some(body_end) => buffer.drain(..body_end).skip(head_end).collect(),
+            None => std::mem::take(buffer).split_off(head_end),
+        };
+        let build_request = |head: &RequestHead| {
+            let mut request = head.to_bytes();
+            request.extend_from_slice(&body);
+            request
+        };
+        let mut request = build_request(&head);
+
+        // A backend that can't be reached hands the request to one not yet
+        // tried, as does one that fails before answering if the request is
+        // idempotent; once they're all gone the client gets a 502
+        let idempotent = head.is_idempotent();
+        let mut server_addr = server_addr;
+        let mut tried = Vec::new();
+        let (result, elapsed) = loop {
+            let mut skip = context.draining.clone();
+            skip.extend(tried.iter().cloned());
+            let permit = self
+                .claim_backend(algorithm, servers, &context, &skip, &mut server_addr)
+                .await;
+            if !self.preserve_host {
+                head.set_header("Host", host_header(&server_addr));
+                request = build_request(&head);
+            }
+            algorithm.connection_started(&server_addr).await;
+            *self
+                .in_flight
+                .write()
+                .await
+                .entry(server_addr.clone())
+                .or_default() += 1;
+            let start = Instant::now();
+            let proxied = ProxyRequest {
+                head: &head,
+                bytes: &request,
+                reusable,
+                keep_alive: keep_alive && reusable,
+                set_cookie: new_session.as_deref(),
+                cache_key: cache_key.as_deref(),
+                leader: leader.as_ref(),
+                public_host: public_host.as_deref(),
+            };
+            let result = self.proxy(client, &server_addr, proxied).await;
+            drop(permit);
+            algorithm.connection_ended(&server_addr).await;
+            if let Some(count) = self.in_flight.write().await.get_mut(&server_addr) {
+                *count = count.saturating_sub(1);
+            }
+            let elapsed = start.elapsed();
+
+            let e = match result {
+                Err(ProxyError::Unsent(e)) => e,
+                Err(ProxyError::Unanswered(e)) if idempotent => e,
+                _ => break (result, elapsed),
+            };
+            algorithm.record_result(&server_addr, None, false).await;
+            tried.push(server_addr.clone());
+            let mut skip = context.draining.clone();
+            skip.extend(tried.iter().cloned());
+            let remaining = self.available_servers(servers, &skip).await;
+            match algorithm.next_server_for(&remaining, &context).await {
+                Some(next) => {
+                    eprintln!(
+                        "Couldn't send request to {}: {}; retrying on {}",
+                        server_addr, e, next
+                    );
+                    server_addr = next;
+                }
+                None => {
+                    eprintln!("Error forwarding request to {}: {}", server_addr, e);
+                    let _ = Self::reply(client, "502 Bad Gateway", &[], "Bad Gateway\n").await;
+                    return Err(e);
+                }
+            }
+        };
+
+        match &result {
+            Ok(outcome) => {
+                let success = outcome.status.is_none_or(|status| status < 500);
+                algorithm
+                    .record_result(&server_addr, Some(elapsed), success)
+                    .await;
+
+                let mut stats = self.stats.write().await;
+                let backend = stats.entry(server_addr.clone()).or_default();
+                backend.record(elapsed);
+                backend.record_bytes(outcome.bytes_in, outcome.bytes_out);
+                if let Some(status) = outcome.status {
+                    backend.record_status(status);
+                }
+                drop(stats);
+                self.throughput.write().await.record();
+                if let Some(detector) = &self.outlier_detector {
+                    detector.record(&server_addr, elapsed).await;
+                }
+
+                tracing::info!(
+                    target: ACCESS_LOG_TARGET,
+                    client = %client_addr,
+                    method = %method,
+                    path = %path,
+                    request_id = %request_id,
+                    backend = %server_addr,
+                    status = outcome.status,
+                    bytes_in = outcome.bytes_in,
+                    bytes_out = outcome.bytes_out,
+                    duration_ms = elapsed.as_secs_f64() * 1000.0,
+                    "request"
+                );
+            }
+            Err(ProxyError::Backend(e)) => {
+                algorithm.record_result(&server_addr, None, false).await;
+                eprintln!("Error forwarding request to {}: {}", server_addr, e);
+            }
+            // A request that isn't safe to repeat stops at the first backend
+            Err(ProxyError::Unsent(e)) | Err(ProxyError::Unanswered(e)) => {
+                algorithm.record_result(&server_addr, None, false).await;
+                eprintln!("Error forwarding request to {}: {}", server_addr, e);
+                let _ = Self::reply(client, "502 Bad Gateway", &[], "Bad Gateway\n").await;
+            }
+            // Not the backend's fault, so it isn't held against it
+            Err(ProxyError::Client(e)) => {
+                eprintln!("Client {} went away: {}", client_addr, e);
+            }
+        }
+        match result {
+            Ok(outcome) => Ok(outcome.keep_alive),
+            Err(
+                ProxyError::Unsent(e)
+                | ProxyError::Unanswered(e)
+                | ProxyError::Backend(e)
+                | ProxyError::Client(e),
+            ) => Err(e),
+        }
+    }
+
+    // Take a slot on `server_addr`, or if it filled up since it was picked, on
+    // another backend that still has room. Only when none has does the
+    // request wait for a slot on the one first picked.
+    async fn claim_backend(
+        &self,
+        algorithm: &Algorithm,
+        servers: &RwLock<Vec<String>>,
+        context: &RequestContext,
+        skip: &[String],
+        server_addr: &mut String,
+    ) -> BackendPermit {
+        if let Some(permit) = self.backend_limits.try_acquire(server_addr) {
+            return permit;
         }
+        let mut skip = skip.to_vec();
+        skip.push(server_addr.clone());
+        let others: Vec<String> = self
+            .available_servers(servers, &skip)
+            .await
+            .into_iter()
+            .filter(|server| !self.backend_limits.at_limit(server))
+            .collect();
+        if let Some(other) = algorithm.next_server_for(&others, context).await {
+            if let Some(permit) = self.backend_limits.try_acquire(&other) {
+                *server_addr = other;
+                return permit;
+            }
+        }
+        self.backend_limits.acquire(server_addr).await
+    }
 
-        // Regular request forwarding
-        let mut server = TcpStream::connect(&server_addr).await?;
-        server.write_all(&buffer[..n]).await?;
+    // Draining backends only see sessions already pinned to them, and ejected
+    // outliers see nothing until their cooldown ends. Of what's left (less
+    // anything in `skip`), only the most preferred tier is considered, and of
+    // that only backends below their concurrency limit if any are.
+    async fn available_servers(
+        &self,
+        servers: &RwLock<Vec<String>>,
+        skip: &[String],
+    ) -> Vec<String> {
+        let ejected = self.ejected_servers().await;
+        let unhealthy = self.unhealthy.read().await.clone();
+        let available: Vec<String> = servers
+            .read()
+            .await
+            .iter()
+            .filter(|s| !skip.contains(s) && !ejected.contains(s) && !unhealthy.contains(*s))
+            .cloned()
+            .collect();
+        let tier = lowest_tier(&available, &*self.priorities.read().await);
+        self.backend_limits.with_room(tier)
+    }
 
-        let (mut client_reader, mut client_writer) = client.split();
-        let (mut server_reader, mut server_writer) = server.split();
+    // Drop a client that didn't send its request head within the header
+    // timeout, answering 408 if it had started one
+    async fn header_timed_out<S>(client: &mut S, buffer: &[u8]) -> std::io::Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        if buffer.is_empty() {
+            return client.shutdown().await;
+        }
+        Self::reply(client, "408 Request Timeout", &[], "Request Timeout\n").await
+    }
 
-        let client_to_server = tokio::io::copy(&mut client_reader, &mut server_writer);
-        let server_to_client = tokio::io::copy(&mut server_reader, &mut client_writer);
+    // Read from the client until it's clear whether it opened with the
+    // HTTP/2 preface, keeping what was read in `buffer`
+    async fn read_h2_preface<S>(client: &mut S, buffer: &mut Vec<u8>) -> std::io::Result<bool>
+    where
+        S: AsyncRead + Unpin,
+    {
+        let mut chunk = [0; 1024];
+        loop {
+            if let Some(is_h2) = h2_preface(buffer) {
+                return Ok(is_h2);
+            }
+            let n = client.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(false);
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
 
-        let (_client_bytes, server_bytes) = match tokio::join!(client_to_server, server_to_client) {
-            (Ok(c), Ok(s)) => (c, s),
-            _ => return Ok(()),
+    // Pipe the whole connection to one backend of the default pool, for
+    // HTTP/2 whose binary frames can't be read as HTTP/1 requests
+    async fn tunnel<S>(
+        &self,
+        mut client: S,
+        client_addr: SocketAddr,
+        buffer: Vec<u8>,
+    ) -> std::io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let context = RequestContext {
+            client_addr: Some(client_addr),
+            draining: self.draining_servers().await,
+            ..Default::default()
+        };
+        let servers = self
+            .available_servers(&self.servers, &context.draining)
+            .await;
+        // Nothing HTTP/1 to answer with, so just hang up
+        let Some(server_addr) = self.algorithm.next_server_for(&servers, &context).await else {
+            return Ok(());
         };
+        let _permit = self.backend_limits.acquire(&server_addr).await;
+        let mut server = self.pool.connect(&server_addr).await?;
 
-        if server_bytes > 0 {
+        self.algorithm.connection_started(&server_addr).await;
+        *self
+            .in_flight
+            .write()
+            .await
+            .entry(server_addr.clone())
+            .or_default() += 1;
+        let result = async {
+            server.write_all(&buffer).await?;
+            tokio::io::copy_bidirectional(&mut client, &mut server).await
+        }
+        .await;
+        self.algorithm.connection_ended(&server_addr).await;
+        if let Some(count) = self.in_flight.write().await.get_mut(&server_addr) {
+            *count = count.saturating_sub(1);
+        }
+
+        match result {
+            Ok((bytes_in, bytes_out)) => {
+                self.stats
+                    .write()
+                    .await
+                    .entry(server_addr)
+                    .or_default()
+                    .record_bytes(buffer.len() as u64 + bytes_in, bytes_out);
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("Error forwarding request to {}: {}", server_addr, e);
+                Err(e)
+            }
+        }
+    }
+
+    // Read from the client until `buffer` holds `len` bytes, returning false
+    // if it closed first
+    async fn read_body<S>(client: &mut S, buffer: &mut Vec<u8>, len: usize) -> std::io::Result<bool>
+    where
+        S: AsyncRead + Unpin,
+    {
+        let mut chunk = [0; 1024];
+        while buffer.len() < len {
+            let n = client.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(false);
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+        }
+        Ok(true)
+    }
+
+    // Read a chunked body following the head at `head_end` into `buffer`,
+    // stopping as soon as its chunks declare more than `limit` bytes
+    async fn read_chunked_body<S>(
+        client: &mut S,
+        buffer: &mut Vec<u8>,
+        head_end: usize,
+        limit: Option<usize>,
+    ) -> std::io::Result<ChunkedBody>
+    where
+        S: AsyncRead + Unpin,
+    {
+        let mut chunk = [0; 1024];
+        loop {
+            let body = &buffer[head_end..];
+            let progress = chunked_progress(body);
+            if let Some(limit) = limit {
+                // Framing can't account for more than twice the payload plus
+                // a little slack; anything beyond that is garbage, not chunks
+                let garbage = body.len() > limit.saturating_mul(2).saturating_add(chunk.len());
+                if progress.payload > limit || (progress.complete.is_none() && garbage) {
+                    return Ok(ChunkedBody::TooLarge);
+                }
+            }
+            if let Some(len) = progress.complete {
+                return Ok(ChunkedBody::Complete(len));
+            }
+            let n = client.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(ChunkedBody::Incomplete);
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    // Answer a body-less request with a response fetched for another, then
+    // drop its head before reading the next
+    async fn reply_shared<S>(
+        client: &mut S,
+        buffer: &mut Vec<u8>,
+        head_end: usize,
+        keep_alive: bool,
+        response: &[u8],
+        rewrite: Option<(&str, &str)>,
+    ) -> std::io::Result<bool>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        client
+            .write_all(&Self::client_response(response, keep_alive, None, rewrite))
+            .await?;
+        if !keep_alive {
             client.shutdown().await?;
-            drop(client);
+            return Ok(false);
         }
+        buffer.drain(..head_end);
+        Ok(true)
+    }
 
-        Ok(())
+    // Answer the client directly and close the connection
+    async fn reply<S>(
+        client: &mut S,
+        status: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> std::io::Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let mut response = format!("HTTP/1.1 {}\r\n", status);
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        client.write_all(response.as_bytes()).await?;
+        client.shutdown().await
+    }
+
+    // Send the request over a pooled connection when the whole exchange is
+    // length-delimited, otherwise over a fresh one relayed until close
+    async fn proxy<S>(
+        &self,
+        client: &mut S,
+        server_addr: &str,
+        request: ProxyRequest<'_>,
+    ) -> Result<ProxyOutcome, ProxyError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let set_cookie = request.set_cookie;
+        if !request.reusable {
+            let server = self
+                .pool
+                .connect(server_addr)
+                .await
+                .map_err(ProxyError::Unsent)?;
+            let rewrite = request.public_host.map(|public| (server_addr, public));
+            return Self::relay(client, server, request.bytes, set_cookie, rewrite).await;
+        }
+
+        let (mut server, reused) = self
+            .pool
+            .get(server_addr)
+            .await
+            .map_err(ProxyError::Unsent)?;
+        let written = Self::write_request(&mut server, request.bytes).await;
+        let unsent = matches!(written, Err((0, _)));
+        let mut response = match written {
+            Ok(()) => Self::read_response_head(&mut server).await,
+            Err((_, e)) => Err(e),
+        };
+        let stale = reused && !matches!(&response, Ok(r) if !r.is_empty());
+        if stale && (unsent || request.head.is_idempotent()) {
+            // The pooled connection went stale; retry once on a fresh one if
+            // none of the request got out, or if it's idempotent. Anything
+            // else may have reached the backend before it dropped the
+            // connection, so it's left to the caller as unanswered.
+            server = self.pool.connect(server_addr).await.map_err(|e| {
+                if unsent {
+                    ProxyError::Unsent(e)
+                } else {
+                    ProxyError::Unanswered(e)
+                }
+            })?;
+            response = Self::exchange(&mut server, request.bytes).await;
+        }
+        // Kept as the backend sent it, so the cache can rewrite it per client
+        let mut response = Self::response_head(client, response).await?;
+        let interim = match Self::forward_interim(&mut server, client, &mut response).await {
+            Ok(interim) => interim,
+            Err(CopyError::Write(e)) => return Err(ProxyError::Client(e)),
+            Err(CopyError::Read(e)) => return Err(Self::bad_gateway(client, e).await),
+        };
+        let rewrite = request.public_host.map(|public| (server_addr, public));
+        let status = response_status(&response);
+
+        // A known length lets the client connection outlive this response even
+        // when the backend connection can't go back to the pool
+        let head_end = find_head_end(&response);
+        let body = match head_end {
+            // Content-Length on these describes a body that never follows
+            Some(end) if response_is_bodyless(&request.head.method, status) => {
+                ResponseBody::Buffered(end)
+            }
+            Some(end) if response_is_chunked(&response[..end]) => {
+                // A chunked body's length is only known once its last chunk
+                // arrives, so read up to there unless it outgrows the buffer
+                let mut scanner = ChunkedScanner::default();
+                let mut scanned = end;
+                let mut chunk = [0; 8192];
+                loop {
+                    match scanner.feed(&response[scanned..]) {
+                        Ok(Some(len)) => break ResponseBody::Buffered(scanned + len),
+                        Ok(None) => scanned = response.len(),
+                        Err(e) => return Err(Self::bad_gateway(client, e).await),
+                    }
+                    if response.len() - end >= MAX_BUFFERED_BODY {
+                        break ResponseBody::Streamed(BodyFraming::Chunked(scanner));
+                    }
+                    let n = match server.read(&mut chunk).await {
+                        Ok(0) => return Err(Self::bad_gateway(client, Self::closed_early()).await),
+                        Ok(n) => n,
+                        Err(e) => return Err(Self::bad_gateway(client, e).await),
+                    };
+                    response.extend_from_slice(&chunk[..n]);
+                }
+            }
+            Some(end) => match response_body_len(&response[..end]) {
+                Some(len) if len > MAX_BUFFERED_BODY => ResponseBody::Streamed(
+                    BodyFraming::Length((end + len).saturating_sub(response.len())),
+                ),
+                Some(len) => ResponseBody::Buffered(end + len),
+                None => ResponseBody::UntilClose,
+            },
+            None => ResponseBody::UntilClose,
+        };
+        let backend_reusable = head_end.is_some_and(|end| backend_keeps_alive(&response[..end]));
+        match body {
+            ResponseBody::Buffered(total_len) => {
+                // Nothing reaches the client until the whole response is in,
+                // so a backend that drops out partway gets a clean 502
+                let mut chunk = [0; 1024];
+                while response.len() < total_len {
+                    let n = match server.read(&mut chunk).await {
+                        Ok(0) => return Err(Self::bad_gateway(client, Self::closed_early()).await),
+                        Ok(n) => n,
+                        Err(e) => return Err(Self::bad_gateway(client, e).await),
+                    };
+                    response.extend_from_slice(&chunk[..n]);
+                }
+                let complete = response.len() == total_len;
+                let keep_alive = request.keep_alive && complete;
+
+                client
+                    .write_all(&Self::client_response(
+                        &response, keep_alive, set_cookie, rewrite,
+                    ))
+                    .await
+                    .map_err(ProxyError::Client)?;
+                if !keep_alive {
+                    client.shutdown().await.map_err(ProxyError::Client)?;
+                }
+
+                if complete && backend_reusable {
+                    self.pool.put(server_addr, server).await;
+                }
+                if let (Some(cache), Some(key)) = (&self.cache, request.cache_key) {
+                    if complete && status == Some(200) && Cache::storable(&response) {
+                        cache.put(key, server_addr, response.clone()).await;
+                    }
+                }
+                // Only a response fit for any client goes to the followers
+                if let Some(leader) = request
+                    .leader
+                    .filter(|_| complete && Cache::storable(&response))
+                {
+                    match rewrite {
+                        Some((backend, public)) => {
+                            leader.publish(&rewrite_response_host(&response, backend, public))
+                        }
+                        None => leader.publish(&response),
+                    }
+                }
+                Ok(ProxyOutcome {
+                    status,
+                    bytes_in: request.bytes.len() as u64,
+                    bytes_out: interim + response.len() as u64,
+                    keep_alive,
+                })
+            }
+            ResponseBody::Streamed(framing) => {
+                // Too big to hold back, so a backend that drops out partway
+                // leaves the client with a cut-off response instead of a 502
+                client
+                    .write_all(&Self::client_response(
+                        &response,
+                        request.keep_alive,
+                        set_cookie,
+                        rewrite,
+                    ))
+                    .await
+                    .map_err(ProxyError::Client)?;
+                let rest = match Self::copy_body(&mut server, client, framing).await {
+                    Ok(rest) => rest,
+                    Err(CopyError::Write(e)) => return Err(ProxyError::Client(e)),
+                    Err(CopyError::Read(e)) => {
+                        let _ = client.shutdown().await;
+                        return Err(ProxyError::Backend(e));
+                    }
+                };
+                if !request.keep_alive {
+                    client.shutdown().await.map_err(ProxyError::Client)?;
+                }
+                if backend_reusable {
+                    self.pool.put(server_addr, server).await;
+                }
+                Ok(ProxyOutcome {
+                    status,
+                    bytes_in: request.bytes.len() as u64,
+                    bytes_out: interim + response.len() as u64 + rest,
+                    keep_alive: request.keep_alive,
+                })
+            }
+            ResponseBody::UntilClose => {
+                client
+                    .write_all(&Self::client_response(
+                        &response, false, set_cookie, rewrite,
+                    ))
+                    .await
+                    .map_err(ProxyError::Client)?;
+                let mut rest = 0;
+                Self::copy_counted(&mut server, client, &mut rest)
+                    .await
+                    .map_err(|e| match e {
+                        CopyError::Read(e) => ProxyError::Backend(e),
+                        CopyError::Write(e) => ProxyError::Client(e),
+                    })?;
+                client.shutdown().await.map_err(ProxyError::Client)?;
+                Ok(ProxyOutcome {
+                    status,
+                    bytes_in: request.bytes.len() as u64,
+                    bytes_out: interim + response.len() as u64 + rest,
+                    keep_alive: false,
+                })
+            }
+        }
+    }
+
+    // Take what `exchange` got back: a response head to carry on with, or an
+    // error saying whether the backend answered at all
+    async fn response_head<S>(
+        client: &mut S,
+        response: std::io::Result<Vec<u8>>,
+    ) -> Result<Vec<u8>, ProxyError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        match response {
+            Ok(response) if Self::has_head(&response) => Ok(response),
+            Ok(response) if response.is_empty() => {
+                Err(ProxyError::Unanswered(Self::closed_early()))
+            }
+            Ok(_) => Err(Self::bad_gateway(client, Self::closed_early()).await),
+            Err(e) => Err(ProxyError::Unanswered(e)),
+        }
+    }
+
+    // Write the request and read back the response head (plus any body bytes
+    // that arrived with it)
+    async fn exchange(server: &mut BackendStream, request: &[u8]) -> std::io::Result<Vec<u8>> {
+        server.write_all(request).await?;
+        Self::read_response_head(server).await
+    }
+
+    // Write the whole request like `write_all`, but on failure also say how
+    // many bytes went out first
+    async fn write_request(
+        server: &mut BackendStream,
+        request: &[u8],
+    ) -> Result<(), (usize, std::io::Error)> {
+        let mut written = 0;
+        while written < request.len() {
+            match server.write(&request[written..]).await {
+                Ok(0) => return Err((written, std::io::ErrorKind::WriteZero.into())),
+                Ok(n) => written += n,
+                Err(e) => return Err((written, e)),
+            }
+        }
+        server.flush().await.map_err(|e| (written, e))
+    }
+
+    // Read the response head (plus any body bytes that arrived with it)
+    async fn read_response_head<R>(server: &mut R) -> std::io::Result<Vec<u8>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut response = Vec::with_capacity(1024);
+        let mut chunk = [0; 1024];
+        while find_head_end(&response).is_none() && response.len() < MAX_HEAD_SIZE {
+            let n = server.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            response.extend_from_slice(&chunk[..n]);
+        }
+        Ok(response)
+    }
+
+    // The client connection closes after the response unless the client asked
+    // for keep-alive, whatever the backend said. `rewrite` names the backend
+    // and the public host to point its URLs at instead.
+    fn client_response(
+        response: &[u8],
+        keep_alive: bool,
+        set_cookie: Option<&str>,
+        rewrite: Option<(&str, &str)>,
+    ) -> Vec<u8> {
+        if find_head_end(response).is_none() {
+            return response.to_vec();
+        }
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        let response = set_response_header(response, "Connection", connection);
+        let response = match rewrite {
+            Some((backend, public)) => rewrite_response_host(&response, backend, public),
+            None => response,
+        };
+        match set_cookie {
+            Some(cookie) => insert_response_header(&response, "Set-Cookie", cookie),
+            None => response,
+        }
+    }
+
+    // Send the request to the backend and relay traffic in both directions
+    async fn relay<S>(
+        client: S,
+        mut server: BackendStream,
+        request: &[u8],
+        set_cookie: Option<&str>,
+        rewrite: Option<(&str, &str)>,
+    ) -> Result<ProxyOutcome, ProxyError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut outcome = ProxyOutcome {
+            status: None,
+            bytes_in: request.len() as u64,
+            bytes_out: 0,
+            keep_alive: false,
+        };
+        server
+            .write_all(request)
+            .await
+            .map_err(ProxyError::Unanswered)?;
+
+        let (mut client_reader, mut client_writer) = tokio::io::split(client);
+        let (mut server_reader, mut server_writer) = tokio::io::split(server);
+
+        let mut client_bytes = 0;
+        let mut server_bytes = 0;
+        // The exchange is over once the backend finishes, whether or not the
+        // client has stopped sending
+        let server_result = {
+            // The client finishing is passed on, which ends an upgraded
+            // connection's tunnel
+            let client_to_server = async {
+                Self::copy_counted(&mut client_reader, &mut server_writer, &mut client_bytes)
+                    .await?;
+                server_writer.shutdown().await.map_err(CopyError::Write)
+            };
+            let server_to_client = async {
+                // Pin a new session by adding Set-Cookie to the response head,
+                // and point backend URLs at the balancer. The rest of the body
+                // keeps streaming in meanwhile.
+                if set_cookie.is_some() || rewrite.is_some() {
+                    let mut response = Self::read_response_head(&mut server_reader)
+                        .await
+                        .map_err(CopyError::Read)?;
+                    if response.is_empty() {
+                        return Ok(());
+                    }
+                    if !Self::has_head(&response) {
+                        return Err(CopyError::Read(Self::closed_early()));
+                    }
+                    server_bytes += Self::forward_interim(
+                        &mut server_reader,
+                        &mut client_writer,
+                        &mut response,
+                    )
+                    .await?;
+                    outcome.status = response_status(&response);
+                    server_bytes += response.len() as u64;
+                    if let Some((backend, public)) = rewrite {
+                        response = rewrite_response_host(&response, backend, public);
+                    }
+                    if let Some(cookie) = set_cookie {
+                        response = insert_response_header(&response, "Set-Cookie", cookie);
+                    }
+                    client_writer
+                        .write_all(&response)
+                        .await
+                        .map_err(CopyError::Write)?;
+                }
+                Self::copy_counted(&mut server_reader, &mut client_writer, &mut server_bytes).await
+            };
+            tokio::pin!(client_to_server, server_to_client);
+            let mut client_done = false;
+            loop {
+                tokio::select! {
+                    _ = &mut client_to_server, if !client_done => client_done = true,
+                    result = &mut server_to_client => break result,
+                }
+            }
+        };
+        outcome.bytes_in += client_bytes;
+        outcome.bytes_out += server_bytes;
+
+        // A backend that hangs up before answering can be retried elsewhere
+        // unless it already took part of a streamed body, in which case the
+        // client gets a 502; one that fails partway through a response can
+        // only be cut off
+        let answered = outcome.bytes_out > 0;
+        let retryable = !answered && client_bytes == 0;
+        match server_result {
+            Ok(()) if answered => {
+                client_writer.shutdown().await.map_err(ProxyError::Client)?;
+                Ok(outcome)
+            }
+            Ok(()) if retryable => Err(ProxyError::Unanswered(Self::closed_early())),
+            Ok(()) => Err(Self::bad_gateway(&mut client_writer, Self::closed_early()).await),
+            Err(CopyError::Write(e)) => Err(ProxyError::Client(e)),
+            Err(CopyError::Read(e)) if answered => {
+                let _ = client_writer.shutdown().await;
+                Err(ProxyError::Backend(e))
+            }
+            Err(CopyError::Read(e)) if retryable => Err(ProxyError::Unanswered(e)),
+            Err(CopyError::Read(e)) => Err(Self::bad_gateway(&mut client_writer, e).await),
+        }
+    }
+
+    // Pass interim 1xx responses at the start of `response` on to the client
+    // as they are, reading on until the final response's head is in, and
+    // return how many bytes were passed on
+    async fn forward_interim<R, W>(
+        server: &mut R,
+        client: &mut W,
+        response: &mut Vec<u8>,
+    ) -> Result<u64, CopyError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut forwarded = 0;
+        let mut chunk = [0; 1024];
+        while let Some(end) = find_head_end(response) {
+            if !is_interim(response_status(response)) {
+                break;
+            }
+            client
+                .write_all(&response[..end])
+                .await
+                .map_err(CopyError::Write)?;
+            response.drain(..end);
+            forwarded += end as u64;
+            while !Self::has_head(response) {
+                let n = server.read(&mut chunk).await.map_err(CopyError::Read)?;
+                if n == 0 {
+                    return Err(CopyError::Read(Self::closed_early()));
+                }
+                response.extend_from_slice(&chunk[..n]);
+            }
+        }
+        Ok(forwarded)
+    }
+
+    // Copy until EOF like `tokio::io::copy`, but keep count of the bytes
+    // taken from `reader` even when the copy fails partway, and say which
+    // side failed
+    async fn copy_counted<R, W>(
+        reader: &mut R,
+        writer: &mut W,
+        copied: &mut u64,
+    ) -> Result<(), CopyError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut chunk = [0; 8192];
+        loop {
+            let n = reader.read(&mut chunk).await.map_err(CopyError::Read)?;
+            if n == 0 {
+                return Ok(());
+            }
+            *copied += n as u64;
+            writer
+                .write_all(&chunk[..n])
+                .await
+                .map_err(CopyError::Write)?;
+        }
+    }
+
+    // Pass on the rest of a body too large to buffer, reading no further than
+    // its end so the backend connection can be reused, and count the bytes
+    async fn copy_body<S>(
+        server: &mut BackendStream,
+        client: &mut S,
+        mut framing: BodyFraming,
+    ) -> Result<u64, CopyError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let mut chunk = [0; 8192];
+        let mut copied = 0;
+        loop {
+            let want = match &framing {
+                BodyFraming::Length(0) => return Ok(copied),
+                BodyFraming::Length(left) => (*left).min(chunk.len()),
+                BodyFraming::Chunked(_) => chunk.len(),
+            };
+            let n = server
+                .read(&mut chunk[..want])
+                .await
+                .map_err(CopyError::Read)?;
+            if n == 0 {
+                return Err(CopyError::Read(Self::closed_early()));
+            }
+            let (n, done) = match &mut framing {
+                BodyFraming::Length(left) => {
+                    *left -= n;
+                    (n, false)
+                }
+                BodyFraming::Chunked(scanner) => match scanner.feed(&chunk[..n]) {
+                    Ok(Some(end)) => (end, true),
+                    Ok(None) => (n, false),
+                    Err(e) => return Err(CopyError::Read(e)),
+                },
+            };
+            copied += n as u64;
+            client
+                .write_all(&chunk[..n])
+                .await
+                .map_err(CopyError::Write)?;
+            if done {
+                return Ok(copied);
+            }
+        }
+    }
+
+    // Whether a backend's first bytes hold a full response head, or at least
+    // more than the head cap so they're passed on as they are
+    fn has_head(response: &[u8]) -> bool {
+        find_head_end(response).is_some() || response.len() >= MAX_HEAD_SIZE
+    }
+
+    fn closed_early() -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "backend closed before sending a complete response",
+        )
+    }
+
+    // Tell the client the backend failed before answering, then hand the
+    // error back so it's logged against the backend
+    async fn bad_gateway<S>(client: &mut S, error: std::io::Error) -> ProxyError
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let _ = Self::reply(client, "502 Bad Gateway", &[], "Bad Gateway\n").await;
+        ProxyError::Backend(error)
     }
 }
@@ -0,0 +1,83 @@
+//! Passive outlier detection: temporarily eject backends far slower than the pool
+
+use super::stats::BackendStats;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::{sync::RwLock, time::Duration};
+
+/// Requests a backend must serve before its latency is compared to the pool
+pub const OUTLIER_MIN_REQUESTS: usize = 5;
+
+/// Ejects a backend whose average latency exceeds the pool mean by
+/// `multiplier`, letting it back in after `cooldown` with a clean slate
+#[derive(Clone)]
+pub struct OutlierDetector {
+    multiplier: f64,
+    cooldown: Duration,
+    samples: Arc<RwLock<HashMap<String, BackendStats>>>,
+    ejected: Arc<RwLock<HashMap<String, Instant>>>,
+}
+
+impl OutlierDetector {
+    pub fn new(multiplier: f64, cooldown: Duration) -> Self {
+        Self {
+            multiplier,
+            cooldown,
+            samples: Arc::new(RwLock::new(HashMap::new())),
+            ejected: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Feed in a completed request and re-check the pool for outliers
+    pub async fn record(&self, server: &str, latency: Duration) {
+        let mut samples = self.samples.write().await;
+        samples
+            .entry(server.to_string())
+            .or_default()
+            .record(latency);
+
+        let judged: Vec<(String, f64)> = samples
+            .iter()
+            .filter(|(_, stats)| stats.requests >= OUTLIER_MIN_REQUESTS)
+            .map(|(server, stats)| (server.clone(), stats.average_latency().as_secs_f64()))
+            .collect();
+        if judged.len() < 2 {
+            return;
+        }
+        let mean = judged.iter().map(|(_, avg)| avg).sum::<f64>() / judged.len() as f64;
+
+        let mut ejected = self.ejected.write().await;
+        ejected.retain(|_, at| at.elapsed() < self.cooldown);
+        for (server, avg) in judged.iter() {
+            // Always leave at least one judged backend in service
+            if *avg > mean * self.multiplier
+                && !ejected.contains_key(server)
+                && ejected.len() + 1 < judged.len()
+            {
+                ejected.insert(server.clone(), Instant::now());
+                samples.remove(server);
+            }
+        }
+    }
+
+    /// Whether `server` is currently sitting out its cooldown
+    pub async fn is_ejected(&self, server: &str) -> bool {
+        self.ejected
+            .read()
+            .await
+            .get(server)
+            .is_some_and(|at| at.elapsed() < self.cooldown)
+    }
+
+    /// Backends currently ejected
+    pub async fn ejected_servers(&self) -> Vec<String> {
+        self.ejected
+            .read()
+            .await
+            .iter()
+            .filter(|(_, at)| at.elapsed() < self.cooldown)
+            .map(|(server, _)| server.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,179 @@
+//! Idle keep-alive connections to backend servers, keyed by address
+
+use super::backend::BackendStream;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::time::{Duration, Instant};
+use tokio_rustls::TlsConnector;
+
+/// Wait before redialing a backend after its first failed connection
+pub const BACKOFF_BASE: Duration = Duration::from_millis(50);
+/// Longest wait between dials to a backend that keeps refusing
+pub const BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+// Failed dials to one backend, and when it may next be dialed
+#[derive(Default)]
+struct Backoff {
+    failures: u32,
+    retry_at: Option<Instant>,
+    attempts: u64,
+}
+
+#[derive(Clone)]
+pub struct ConnectionPool {
+    idle: Arc<RwLock<HashMap<String, Vec<BackendStream>>>>,
+    backoff: Arc<RwLock<HashMap<String, Backoff>>>,
+    max_idle_per_server: usize,
+    nodelay: bool,
+    tls: Option<TlsConnector>,
+}
+
+impl ConnectionPool {
+    pub fn new(max_idle_per_server: usize) -> Self {
+        Self {
+            idle: Arc::new(RwLock::new(HashMap::new())),
+            backoff: Arc::new(RwLock::new(HashMap::new())),
+            max_idle_per_server,
+            nodelay: false,
+            tls: None,
+        }
+    }
+
+    /// Dial `https://` backends with `tls`
+    pub fn with_tls(mut self, tls: Option<TlsConnector>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Set `TCP_NODELAY` on every connection the pool opens
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Dial a new connection with the pool's socket options, bypassing any
+    /// idle ones. After a failed dial the backend isn't dialed again until
+    /// its backoff runs out (doubling from `BACKOFF_BASE` up to
+    /// `BACKOFF_MAX`); until then this fails straight away.
+    pub async fn connect(&self, addr: &str) -> io::Result<BackendStream> {
+        let retry_at = self
+            .backoff
+            .read()
+            .await
+            .get(addr)
+            .and_then(|state| state.retry_at);
+        if let Some(retry_at) = retry_at {
+            let now = Instant::now();
+            if now < retry_at {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!(
+                        "backing off {} for another {}ms",
+                        addr,
+                        (retry_at - now).as_millis()
+                    ),
+                ));
+            }
+        }
+        self.probe(addr).await
+    }
+
+    /// Dial `addr` even while it's backing off, as a health probe would. The
+    /// outcome still counts: success clears the backoff, failure extends it.
+    pub async fn probe(&self, addr: &str) -> io::Result<BackendStream> {
+        self.backoff
+            .write()
+            .await
+            .entry(addr.to_string())
+            .or_default()
+            .attempts += 1;
+
+        match self.dial(addr).await {
+            Ok(stream) => {
+                if let Some(state) = self.backoff.write().await.get_mut(addr) {
+                    state.failures = 0;
+                    state.retry_at = None;
+                }
+                Ok(stream)
+            }
+            Err(e) => {
+                if let Some(state) = self.backoff.write().await.get_mut(addr) {
+                    let delay = BACKOFF_BASE
+                        .saturating_mul(1 << state.failures.min(16))
+                        .min(BACKOFF_MAX);
+                    state.failures += 1;
+                    state.retry_at = Some(Instant::now() + delay);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn dial(&self, addr: &str) -> io::Result<BackendStream> {
+        let stream = BackendStream::connect(addr, self.tls.as_ref()).await?;
+        if self.nodelay {
+            stream.set_nodelay(true)?;
+        }
+        Ok(stream)
+    }
+
+    /// Times `addr` has actually been dialed, not counting calls turned
+    /// away by its backoff
+    pub async fn connect_attempts(&self, addr: &str) -> u64 {
+        self.backoff
+            .read()
+            .await
+            .get(addr)
+            .map_or(0, |state| state.attempts)
+    }
+
+    /// Take an idle connection to `addr`, or open a new one if none is usable.
+    /// The flag is true when the connection came from the pool.
+    pub async fn get(&self, addr: &str) -> io::Result<(BackendStream, bool)> {
+        {
+            let mut idle = self.idle.write().await;
+            if let Some(streams) = idle.get_mut(addr) {
+                while let Some(mut stream) = streams.pop() {
+                    if stream.is_open() {
+                        return Ok((stream, true));
+                    }
+                }
+            }
+        }
+        Ok((self.connect(addr).await?, false))
+    }
+
+    /// Return a connection whose last response was fully read
+    pub async fn put(&self, addr: &str, stream: BackendStream) {
+        let mut idle = self.idle.write().await;
+        let streams = idle.entry(addr.to_string()).or_default();
+        if streams.len() < self.max_idle_per_server {
+            streams.push(stream);
+        }
+    }
+
+    /// Open connections to `addr` until `count` idle ones are held, first
+    /// dropping any the backend has closed. Returns how many were opened.
+    pub async fn warm(&self, addr: &str, count: usize) -> io::Result<usize> {
+        let missing = {
+            let mut idle = self.idle.write().await;
+            let streams = idle.entry(addr.to_string()).or_default();
+            streams.retain_mut(BackendStream::is_open);
+            count
+                .min(self.max_idle_per_server)
+                .saturating_sub(streams.len())
+        };
+        for _ in 0..missing {
+            let stream = self.connect(addr).await?;
+            self.put(addr, stream).await;
+        }
+        Ok(missing)
+    }
+
+    /// Number of idle connections currently held for `addr`
+    pub async fn idle_connections(&self, addr: &str) -> usize {
+        self.idle.read().await.get(addr).map_or(0, Vec::len)
+    }
+}
@@ -0,0 +1,42 @@
+//! Priority tiers: backup backends that only see traffic once every backend
+//! in a more preferred tier is unavailable
+
+use std::collections::HashMap;
+
+/// Server-list suffix giving a backend's tier, e.g. `127.0.0.1:8002|priority=1`
+pub const PRIORITY_SUFFIX: &str = "|priority=";
+
+/// Split a server entry into its address and tier. Entries without a suffix
+/// (or with an unreadable one) are in tier 0, the most preferred.
+pub fn parse_server_entry(entry: &str) -> (String, u32) {
+    match entry.split_once(PRIORITY_SUFFIX) {
+        Some((addr, priority)) => (addr.to_string(), priority.trim().parse().unwrap_or(0)),
+        None => (entry.to_string(), 0),
+    }
+}
+
+/// Split every entry in a server list, returning the addresses in order and
+/// the tier of each
+pub fn split_server_entries(entries: &[String]) -> (Vec<String>, HashMap<String, u32>) {
+    let mut servers = Vec::with_capacity(entries.len());
+    let mut priorities = HashMap::new();
+    for entry in entries {
+        let (addr, priority) = parse_server_entry(entry);
+        priorities.insert(addr.clone(), priority);
+        servers.push(addr);
+    }
+    (servers, priorities)
+}
+
+/// The members of `servers` in the lowest tier any of them belongs to
+pub fn lowest_tier(servers: &[String], priorities: &HashMap<String, u32>) -> Vec<String> {
+    let tier = |server: &String| priorities.get(server).copied().unwrap_or(0);
+    match servers.iter().map(tier).min() {
+        Some(lowest) => servers
+            .iter()
+            .filter(|s| tier(s) == lowest)
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,63 @@
+//! Per-client token buckets used to throttle abusive clients
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::{sync::RwLock, time::Duration};
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token-bucket rate limiter keyed by client IP. Each client may burst up to
+/// one second's worth of requests, refilling at `rate` tokens per second.
+#[derive(Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Arc<RwLock<HashMap<IpAddr, TokenBucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            burst: rate.max(1.0),
+            buckets: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Take a token for `ip`, returning false if its bucket is empty
+    pub async fn check(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.write().await;
+        let bucket = buckets.entry(ip).or_insert(TokenBucket {
+            tokens: self.burst,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drop buckets for clients not seen within `idle`
+    pub async fn evict_idle(&self, idle: Duration) {
+        let mut buckets = self.buckets.write().await;
+        buckets.retain(|_, bucket| bucket.last_refill.elapsed() < idle);
+    }
+
+    /// Number of clients currently being tracked
+    pub async fn tracked_clients(&self) -> usize {
+        self.buckets.read().await.len()
+    }
+}
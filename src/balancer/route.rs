@@ -0,0 +1,53 @@
+use crate::algorithms::Algorithm;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// A named backend pool that serves every request under a path prefix
+#[derive(Clone)]
+pub struct RouteGroup {
+    name: String,
+    prefix: String,
+    pub(crate) servers: Arc<RwLock<Vec<String>>>,
+    pub(crate) algorithm: Algorithm,
+}
+
+impl RouteGroup {
+    pub fn new(name: &str, prefix: &str, servers: Vec<String>, algorithm: Algorithm) -> Self {
+        Self {
+            name: name.to_string(),
+            prefix: prefix.to_string(),
+            servers: Arc::new(RwLock::new(servers)),
+            algorithm,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn algorithm(&self) -> &Algorithm {
+        &self.algorithm
+    }
+
+    pub async fn servers(&self) -> Vec<String> {
+        self.servers.read().await.clone()
+    }
+
+    /// Whether `path` falls under this prefix on a segment boundary, so
+    /// `/api` matches `/api`, `/api/users` and `/api?x=1` but not `/apis`
+    pub fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(&self.prefix) {
+            Some(rest) => {
+                rest.is_empty()
+                    || self.prefix.ends_with('/')
+                    || rest.starts_with('/')
+                    || rest.starts_with('?')
+            }
+            None => false,
+        }
+    }
+}
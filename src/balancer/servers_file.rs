@@ -0,0 +1,15 @@
+//! Backend list kept in a plain text file, one address per line
+
+use std::io;
+use std::path::Path;
+
+/// Read backend addresses from `path`, skipping blank lines and `#` comments
+pub fn read_servers_file(path: &Path) -> io::Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
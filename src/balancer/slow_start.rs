@@ -0,0 +1,70 @@
+//! Gradual traffic ramp for backends that just joined the pool
+
+use crate::rng::SharedRng;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::{sync::RwLock, time::Duration};
+
+/// Tracks when each new backend joined and scales its share of traffic
+/// linearly from zero to its full share over `window`
+#[derive(Clone)]
+pub struct SlowStart {
+    window: Duration,
+    started: Arc<RwLock<HashMap<String, Instant>>>,
+    rng: SharedRng,
+}
+
+impl SlowStart {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            started: Arc::new(RwLock::new(HashMap::new())),
+            rng: SharedRng::default(),
+        }
+    }
+
+    /// Draw admission decisions from `rng`
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Start (or restart) the ramp for `server`
+    pub async fn begin(&self, server: &str) {
+        self.started
+            .write()
+            .await
+            .insert(server.to_string(), Instant::now());
+    }
+
+    /// Share of its normal traffic `server` should get right now, in 0.0..=1.0
+    pub async fn fraction(&self, server: &str) -> f64 {
+        let started = self.started.read().await;
+        match started.get(server) {
+            Some(start) if self.window > Duration::ZERO => {
+                (start.elapsed().as_secs_f64() / self.window.as_secs_f64()).min(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Whether a request the algorithm routed to `server` should keep it;
+    /// warming servers are accepted with probability equal to their fraction
+    pub async fn admit(&self, server: &str) -> bool {
+        let fraction = self.fraction(server).await;
+        if fraction < 1.0 {
+            return self.rng.clone().gen::<f64>() < fraction;
+        }
+        // Done ramping, stop tracking it
+        if self.started.read().await.contains_key(server) {
+            self.started.write().await.remove(server);
+        }
+        true
+    }
+}
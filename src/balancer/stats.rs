@@ -0,0 +1,136 @@
+//! Per-backend counters kept by the balancer regardless of algorithm
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Instant;
+use tokio::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct BackendStats {
+    pub requests: usize,
+    pub total_latency: Duration,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub status_2xx: usize,
+    pub status_4xx: usize,
+    pub status_5xx: usize,
+}
+
+impl BackendStats {
+    pub fn record(&mut self, latency: Duration) {
+        self.requests += 1;
+        self.total_latency += latency;
+    }
+
+    /// Add one request's traffic: `bytes_in` sent to the backend, `bytes_out`
+    /// received from it and relayed to the client
+    pub fn record_bytes(&mut self, bytes_in: u64, bytes_out: u64) {
+        self.bytes_in += bytes_in;
+        self.bytes_out += bytes_out;
+    }
+
+    /// Count a backend response by its status class; 1xx and 3xx aren't
+    /// broken out
+    pub fn record_status(&mut self, status: u16) {
+        match status {
+            200..=299 => self.status_2xx += 1,
+            400..=499 => self.status_4xx += 1,
+            500..=599 => self.status_5xx += 1,
+            _ => {}
+        }
+    }
+
+    pub fn average_latency(&self) -> Duration {
+        if self.requests == 0 {
+            Duration::ZERO
+        } else {
+            self.total_latency / self.requests as u32
+        }
+    }
+}
+
+/// Request completions over a sliding window, for a current requests/sec figure
+#[derive(Debug)]
+pub struct Throughput {
+    window: Duration,
+    completed: VecDeque<Instant>,
+}
+
+impl Throughput {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            completed: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self) {
+        let now = Instant::now();
+        self.completed.push_back(now);
+        self.prune(now);
+    }
+
+    /// Requests per second over the window, or over `uptime` if that's shorter
+    pub fn rate(&mut self, uptime: Duration) -> f64 {
+        self.prune(Instant::now());
+        let span = self.window.min(uptime).as_secs_f64();
+        if span > 0.0 {
+            self.completed.len() as f64 / span
+        } else {
+            0.0
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.completed.clear();
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while let Some(&oldest) = self.completed.front() {
+            if now.duration_since(oldest) <= self.window {
+                break;
+            }
+            self.completed.pop_front();
+        }
+    }
+}
+
+/// Balancer-wide totals shown above the per-backend metrics
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateMetrics {
+    pub total_requests: usize,
+    pub requests_per_second: f64,
+    pub active_connections: usize,
+    /// Client connections accepted since startup
+    pub connections_accepted: usize,
+    /// Connections currently waiting for a slot under the connection limit
+    pub connections_queued: usize,
+    /// Connections turned away with a 503 after the queue timeout
+    pub connections_rejected: usize,
+    pub uptime_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_hits: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_misses: Option<usize>,
+}
+
+impl std::fmt::Display for AggregateMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Total requests: {}, Requests/sec: {:.1}, Active connections: {}, \
+             Connections accepted: {}, Queued: {}, Rejected: {}, Uptime: {}s",
+            self.total_requests,
+            self.requests_per_second,
+            self.active_connections,
+            self.connections_accepted,
+            self.connections_queued,
+            self.connections_rejected,
+            self.uptime_secs
+        )?;
+        if let (Some(hits), Some(misses)) = (self.cache_hits, self.cache_misses) {
+            write!(f, ", Cache hits: {}, Cache misses: {}", hits, misses)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+//! Restart long-running background tasks that panic, so a bug in one doesn't
+//! quietly switch it off for the rest of the run
+
+use futures::FutureExt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+
+/// Pause before restarting a task that panicked
+pub const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Spawn the future `task` builds, logging and rebuilding it `delay` after
+/// each panic. Ends once a run returns normally; aborting the handle stops it
+/// for good.
+pub fn supervise<F, Fut>(name: &'static str, delay: Duration, task: F) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        while let Err(panic) = AssertUnwindSafe(task()).catch_unwind().await {
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            eprintln!(
+                "The {} task panicked ({}); restarting in {:?}",
+                name, reason, delay
+            );
+            sleep(delay).await;
+        }
+    })
+}
@@ -0,0 +1,84 @@
+//! Weight auto-tuning: periodically reweight weighted round-robin pools
+//! inversely to each backend's recent average latency
+
+use super::stats::BackendStats;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::{sync::RwLock, time::Duration};
+
+/// Weight given to the fastest backend; slower ones scale down from it
+pub const MAX_TUNED_WEIGHT: u32 = 10;
+
+/// Turns the latency seen since the previous cycle into new weights
+#[derive(Clone)]
+pub struct WeightTuner {
+    interval: Duration,
+    // Each backend's counters as of the last cycle, so a cycle only looks at
+    // requests completed since then
+    previous: Arc<RwLock<HashMap<String, BackendStats>>>,
+    tuned: Arc<RwLock<HashMap<String, u32>>>,
+}
+
+impl WeightTuner {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            previous: Arc::new(RwLock::new(HashMap::new())),
+            tuned: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Weights for backends that completed requests since the last cycle;
+    /// backends without new traffic are left out and keep their weight
+    pub async fn cycle(&self, stats: &HashMap<String, BackendStats>) -> HashMap<String, u32> {
+        let mut previous = self.previous.write().await;
+        let mut recent = HashMap::new();
+        for (server, current) in stats {
+            let before = previous.get(server).cloned().unwrap_or_default();
+            // Counters went backwards, so they were reset: count from zero
+            let before = if current.requests < before.requests {
+                BackendStats::default()
+            } else {
+                before
+            };
+            let requests = current.requests - before.requests;
+            if requests > 0 {
+                let latency = current.total_latency.saturating_sub(before.total_latency);
+                recent.insert(server.clone(), latency / requests as u32);
+            }
+            previous.insert(server.clone(), current.clone());
+        }
+        drop(previous);
+
+        let weights = weights_for(&recent);
+        self.tuned.write().await.extend(weights.clone());
+        weights
+    }
+
+    /// The weight each backend was last given by a cycle
+    pub async fn tuned_weights(&self) -> HashMap<String, u32> {
+        self.tuned.read().await.clone()
+    }
+}
+
+/// Weights inversely proportional to `latencies`: the fastest backend gets
+/// `MAX_TUNED_WEIGHT` and none drops below 1
+pub fn weights_for(latencies: &HashMap<String, Duration>) -> HashMap<String, u32> {
+    let floor = Duration::from_micros(1).as_secs_f64();
+    let fastest = latencies
+        .values()
+        .map(|latency| latency.as_secs_f64().max(floor))
+        .fold(f64::INFINITY, f64::min);
+    latencies
+        .iter()
+        .map(|(server, latency)| {
+            let share = fastest / latency.as_secs_f64().max(floor);
+            let weight = (f64::from(MAX_TUNED_WEIGHT) * share).round() as u32;
+            (server.clone(), weight.clamp(1, MAX_TUNED_WEIGHT))
+        })
+        .collect()
+}
@@ -0,0 +1,37 @@
+//! Argument parsers shared by the command-line front ends
+
+/// Parse a share of traffic, rejecting anything outside `[0.0, 1.0]` rather
+/// than skewing the mix
+pub fn parse_ratio(s: &str) -> Result<f64, String> {
+    let ratio: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid ratio '{}': expected a number from 0.0 to 1.0", s))?;
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(format!(
+            "ratio {} is out of range: expected 0.0 to 1.0",
+            ratio
+        ));
+    }
+    Ok(ratio)
+}
+
+/// Parse a dispatch rate in requests per second
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid rate '{}': expected requests per second", s))?;
+    check_rate(rate)
+}
+
+/// Accept only a finite rate above zero; anything else can't be paced
+pub fn check_rate(rate: f64) -> Result<f64, String> {
+    if !(rate.is_finite() && rate > 0.0) {
+        return Err(format!(
+            "rate {} is out of range: expected a finite number above 0",
+            rate
+        ));
+    }
+    Ok(rate)
+}
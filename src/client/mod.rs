@@ -1,15 +1,75 @@
-use reqwest::{Client, Error, Response};
+use crate::rng::SharedRng;
+use rand::Rng;
+use reqwest::{Client, Error, RequestBuilder, Response, StatusCode};
 use std::sync::Arc;
 use tokio::time::Duration;
 
-const MAX_RETRIES: u32 = 3;
+const MAX_RETRIES: u32 = 2;
 const RETRY_DELAY_MS: u64 = 100;
+const MAX_RETRY_DELAY_MS: u64 = 2000;
+
+/// Exponential backoff between retries: `base * 2^attempt`, capped at `max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+    /// Randomize each delay to between half and all of its nominal value
+    pub jitter: bool,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(RETRY_DELAY_MS),
+            max: Duration::from_millis(MAX_RETRY_DELAY_MS),
+            jitter: true,
+        }
+    }
+}
+
+impl Backoff {
+    /// Nominal delay before retry number `attempt` (0-based), without jitter
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    /// Delay before retry number `attempt`, with jitter drawn from `rng`
+    pub fn jittered_delay(&self, attempt: u32, rng: &mut impl Rng) -> Duration {
+        let delay = self.delay(attempt);
+        if self.jitter {
+            delay.mul_f64(rng.gen_range(0.5..=1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+/// Decides whether a response with this status is worth another attempt
+pub type RetryableStatus = Arc<dyn Fn(StatusCode) -> bool + Send + Sync>;
+
+/// 502, 503 and 504: the gateway errors a retry can plausibly get past
+pub fn is_gateway_error(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
 
 #[derive(Clone)]
 pub struct SenderClient {
     pub client: Arc<Client>,
     pub id: String,
     pub url: String,
+    /// Retries after the first attempt fails
+    pub max_retries: u32,
+    pub backoff: Backoff,
+    /// Reuse pooled connections instead of sending `Connection: close`
+    pub keep_alive: bool,
+    /// Responses retried like transport errors; none by default
+    pub retryable_status: RetryableStatus,
+    /// Source of backoff jitter
+    pub rng: SharedRng,
 }
 
 impl SenderClient {
@@ -18,35 +78,101 @@ impl SenderClient {
             client: Arc::new(Client::new()),
             id: id.to_string(),
             url: url.to_string(),
+            max_retries: MAX_RETRIES,
+            backoff: Backoff::default(),
+            keep_alive: false,
+            retryable_status: Arc::new(|_| false),
+            rng: SharedRng::default(),
+        }
+    }
+
+    pub fn with_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Draw backoff jitter from `rng`, so a seeded source reproduces retry
+    /// timing
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Also retry responses whose status matches `retryable` (such as
+    /// `is_gateway_error`), not just transport errors. Once retries run out
+    /// the last response is returned as is.
+    pub fn with_retryable_status<F>(mut self, retryable: F) -> Self
+    where
+        F: Fn(StatusCode) -> bool + Send + Sync + 'static,
+    {
+        self.retryable_status = Arc::new(retryable);
+        self
+    }
+
+    /// Keep connections open between requests so reqwest can reuse them
+    pub fn with_keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Fail any single attempt that takes longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        let client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("Failed to build HTTP client");
+        self.client = Arc::new(client);
+        self
+    }
+
+    // Without keep-alive every request asks for its own connection
+    fn connection(&self, request: RequestBuilder) -> RequestBuilder {
+        if self.keep_alive {
+            request
+        } else {
+            request.header("Connection", "close")
         }
     }
 
-    async fn retry_request<F, Fut>(retries: u32, f: F) -> Result<Response, Error>
+    // Transport errors are always retried; responses only when `retryable`
+    // accepts their status
+    async fn retry_request<F, Fut>(
+        &self,
+        f: F,
+        retryable: &(dyn Fn(StatusCode) -> bool + Send + Sync),
+    ) -> Result<Response, Error>
     where
         F: Fn() -> Fut,
         Fut: std::future::Future<Output = Result<Response, Error>>,
     {
         let mut attempt = 0;
         loop {
-            match f().await {
-                Ok(resp) => return Ok(resp),
-                Err(e) => {
-                    attempt += 1;
-                    if attempt >= retries {
-                        return Err(e);
-                    }
-                    tokio::time::sleep(Duration::from_millis(RETRY_DELAY_MS)).await;
-                }
+            let result = f().await;
+            let retry = match &result {
+                Ok(resp) => retryable(resp.status()),
+                Err(_) => true,
+            };
+            if !retry || attempt >= self.max_retries {
+                return result;
             }
+            let delay = self.backoff.jittered_delay(attempt, &mut self.rng.clone());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
     }
 
     pub async fn get_read_request(&self, endpoint: &str) -> Result<Response, Error> {
         let full_url = format!("{}/{}", self.url, endpoint);
         let client = self.client.clone();
-        Self::retry_request(MAX_RETRIES, || {
-            client.get(&full_url).header("Connection", "close").send()
-        })
+        self.retry_request(
+            || self.connection(client.get(&full_url)).send(),
+            &*self.retryable_status,
+        )
         .await
     }
 
@@ -57,13 +183,42 @@ impl SenderClient {
     ) -> Result<Response, Error> {
         let full_url = format!("{}/{}", self.url, endpoint);
         let client = self.client.clone();
-        Self::retry_request(MAX_RETRIES, || {
-            client
-                .post(&full_url)
-                .header("Connection", "close")
-                .body(body.clone())
-                .send()
-        })
+        self.retry_request(
+            || {
+                self.connection(client.post(&full_url))
+                    .body(body.clone())
+                    .send()
+            },
+            &*self.retryable_status,
+        )
+        .await
+    }
+
+    pub async fn put_update_request(
+        &self,
+        endpoint: &str,
+        body: String,
+    ) -> Result<Response, Error> {
+        let full_url = format!("{}/{}", self.url, endpoint);
+        let client = self.client.clone();
+        self.retry_request(
+            || {
+                self.connection(client.put(&full_url))
+                    .body(body.clone())
+                    .send()
+            },
+            &*self.retryable_status,
+        )
+        .await
+    }
+
+    pub async fn delete_request(&self, endpoint: &str) -> Result<Response, Error> {
+        let full_url = format!("{}/{}", self.url, endpoint);
+        let client = self.client.clone();
+        self.retry_request(
+            || self.connection(client.delete(&full_url)).send(),
+            &*self.retryable_status,
+        )
         .await
     }
 }
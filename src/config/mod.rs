@@ -0,0 +1,107 @@
+//! Balancer configuration loaded from a TOML or JSON file
+
+use crate::algorithms::ALGORITHM_NAMES;
+use crate::balancer::{AccessControl, MAX_CONNECTIONS, PRIORITY_SUFFIX};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Everything needed to construct a `LoadBalancer`; missing fields take defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub port: u16,
+    pub servers: Vec<String>,
+    /// Per-server weights for the weighted algorithms
+    pub weights: HashMap<String, u32>,
+    pub algorithm: String,
+    pub max_connections: usize,
+    /// Cap on requests in flight to any one backend
+    pub max_per_backend: Option<usize>,
+    /// Per-server caps on requests in flight, overriding `max_per_backend`
+    pub backend_limits: HashMap<String, usize>,
+    /// Named path-prefix routes, each with its own backend pool
+    pub routes: HashMap<String, RouteConfig>,
+    /// Methods and path prefixes to allow or refuse with a 403
+    pub acl: AccessControl,
+}
+
+/// One route group: requests under `prefix` go to `servers`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteConfig {
+    pub prefix: String,
+    pub servers: Vec<String>,
+    #[serde(default = "default_algorithm")]
+    pub algorithm: String,
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+}
+
+fn default_algorithm() -> String {
+    "round-robin".to_string()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 8000,
+            servers: Vec::new(),
+            weights: HashMap::new(),
+            algorithm: default_algorithm(),
+            max_connections: MAX_CONNECTIONS,
+            max_per_backend: None,
+            backend_limits: HashMap::new(),
+            routes: HashMap::new(),
+            acl: AccessControl::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load from `path`, parsed as JSON for `.json` files and TOML otherwise
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let config: Self = if is_json {
+            serde_json::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else {
+            toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Reject algorithm names that `Algorithm::new` would silently replace
+    // with round-robin, and priority tiers it would read as 0
+    fn validate(&self) -> io::Result<()> {
+        let algorithms =
+            std::iter::once(&self.algorithm).chain(self.routes.values().map(|r| &r.algorithm));
+        for algorithm in algorithms {
+            if !ALGORITHM_NAMES.contains(&algorithm.as_str()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "unknown algorithm '{}' (expected one of: {})",
+                        algorithm,
+                        ALGORITHM_NAMES.join(", ")
+                    ),
+                ));
+            }
+        }
+        for server in &self.servers {
+            if let Some((_, priority)) = server.split_once(PRIORITY_SUFFIX) {
+                if priority.trim().parse::<u32>().is_err() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid priority in server '{}'", server),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
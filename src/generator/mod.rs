@@ -0,0 +1,1006 @@
+mod plan;
+mod report;
+mod stream;
+
+pub use plan::{PlannedRequests, RunPlan};
+pub use report::{
+    Buckets, ClientStats, FailureBreakdown, FailureReason, GeneratorReport, LatencyHistogram,
+    LatencyStats, OutputFormat, ReportSummary, TargetStats, DEFAULT_BUCKETS,
+};
+pub use stream::{RequestRecord, ResultStream, STDOUT_TARGET};
+
+use crate::cli::{check_rate, parse_rate, parse_ratio};
+use crate::client::SenderClient;
+use crate::rng::SharedRng;
+use clap::Parser;
+use futures::future::join_all;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+use tokio::{
+    sync::Mutex,
+    time::{interval, Interval},
+};
+
+// Progress and summary lines go to stderr when stdout carries the JSON stream
+macro_rules! say {
+    ($to_stderr:expr, $($arg:tt)*) => {
+        if $to_stderr {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "Generator")]
+pub struct GeneratorArgs {
+    /// Target URL; repeat or comma-separate to split the clients across
+    /// several targets and compare them
+    #[arg(
+        short = 'u',
+        long = "url",
+        value_delimiter = ',',
+        default_value = "http://127.0.0.1:8000"
+    )]
+    pub urls: Vec<String>,
+
+    #[arg(short = 'n', long, default_value = "10", conflicts_with = "duration")]
+    pub num_requests: usize,
+
+    #[arg(short = 'c', long, default_value = "5")]
+    pub concurrent_clients: usize,
+
+    #[arg(short = 'r', long, default_value = "0.7", value_parser = parse_ratio)]
+    pub get_ratio: f64,
+
+    /// Full method distribution, e.g. `GET=0.5,POST=0.3,PUT=0.15,DELETE=0.05`
+    #[arg(short = 'm', long, conflicts_with_all = ["get_ratio", "put_ratio", "delete_ratio"])]
+    pub mix: Option<MethodMix>,
+
+    /// Paths picked at random for each request, e.g. `/api,/static,/slow`;
+    /// the root path if none are given
+    #[arg(long, value_delimiter = ',')]
+    pub paths: Vec<String>,
+
+    /// Share of requests sent as PUT (taken out of the POST share)
+    #[arg(long, default_value = "0.0", value_parser = parse_ratio)]
+    pub put_ratio: f64,
+
+    /// Share of requests sent as DELETE (taken out of the POST share)
+    #[arg(long, default_value = "0.0", value_parser = parse_ratio)]
+    pub delete_ratio: f64,
+
+    /// Pace dispatch at this many requests per second instead of bursting
+    #[arg(long, value_parser = parse_rate)]
+    pub rate: Option<f64>,
+
+    /// Keep sending requests for this many seconds instead of a fixed count
+    #[arg(short = 'd', long)]
+    pub duration: Option<u64>,
+
+    /// Unmeasured warmup traffic: a request count (`50`) or seconds (`5s`)
+    #[arg(short = 'w', long)]
+    pub warmup: Option<Warmup>,
+
+    /// Give up on a request attempt after this many milliseconds
+    #[arg(short = 't', long)]
+    pub timeout: Option<u64>,
+
+    /// Write structured results to this path
+    #[arg(short = 'o', long)]
+    pub output: Option<PathBuf>,
+
+    /// Format of the structured results file
+    #[arg(short = 'f', long, value_enum, default_value = "json")]
+    pub format: OutputFormat,
+
+    /// Reuse connections between requests instead of opening one per request
+    #[arg(short = 'k', long)]
+    pub keep_alive: bool,
+
+    /// Latency histogram bucket boundaries in milliseconds, e.g. `10,50,100`
+    #[arg(short = 'b', long)]
+    pub buckets: Option<Buckets>,
+
+    /// Print what the run would send and exit without sending anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print only the final summary, with no per-request lines
+    #[arg(short = 'q', long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print a line for every request, successful or not, with its latency
+    #[arg(short = 'v', long)]
+    pub verbose: bool,
+
+    /// Stream one JSON line per completed request to this path (stdout if
+    /// given no path or `-`, which moves the other output to stderr)
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = STDOUT_TARGET)]
+    pub stream_json: Option<PathBuf>,
+}
+
+/// How much the generator prints while a run is in progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// The final summary only
+    Quiet,
+    /// Failed requests as they happen, then the summary
+    #[default]
+    Normal,
+    /// Every request as it completes, then the summary
+    Verbose,
+}
+
+impl Verbosity {
+    /// Level chosen by the `--quiet` and `--verbose` flags
+    pub fn from_flags(quiet: bool, verbose: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, true) => Verbosity::Verbose,
+            (false, false) => Verbosity::Normal,
+        }
+    }
+}
+
+/// Traffic sent before measurement begins, excluded from the results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warmup {
+    Requests(usize),
+    Duration(Duration),
+}
+
+impl FromStr for Warmup {
+    type Err = String;
+
+    /// Parse `N` as a request count or `Ns` as a number of seconds
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid warmup '{}': expected N requests or Ns seconds", s);
+        match s.strip_suffix('s') {
+            Some(secs) => secs
+                .parse()
+                .map(|secs| Warmup::Duration(Duration::from_secs(secs)))
+                .map_err(|_| invalid()),
+            None => s.parse().map(Warmup::Requests).map_err(|_| invalid()),
+        }
+    }
+}
+
+/// HTTP method used for a generated request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestMethod {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl RequestMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestMethod::Get => "GET",
+            RequestMethod::Post => "POST",
+            RequestMethod::Put => "PUT",
+            RequestMethod::Delete => "DELETE",
+        }
+    }
+}
+
+/// Share of each method in the traffic; POST takes whatever is left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MethodMix {
+    get: f64,
+    put: f64,
+    delete: f64,
+}
+
+impl MethodMix {
+    pub fn get(&self) -> f64 {
+        self.get
+    }
+
+    pub fn post(&self) -> f64 {
+        (1.0 - self.get - self.put - self.delete).max(0.0)
+    }
+
+    pub fn put(&self) -> f64 {
+        self.put
+    }
+
+    pub fn delete(&self) -> f64 {
+        self.delete
+    }
+
+    // Independently decide each request's method so the mix interleaves
+    fn roll(&self, rng: &mut impl Rng) -> RequestMethod {
+        let roll = rng.gen::<f64>();
+        if roll < self.get {
+            RequestMethod::Get
+        } else if roll < self.get + self.put {
+            RequestMethod::Put
+        } else if roll < self.get + self.put + self.delete {
+            RequestMethod::Delete
+        } else {
+            RequestMethod::Post
+        }
+    }
+}
+
+impl FromStr for MethodMix {
+    type Err = String;
+
+    /// Parse `METHOD=share` pairs; omitted methods get no traffic and the
+    /// shares must add up to 1.0
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut shares: HashMap<RequestMethod, f64> = HashMap::new();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, share) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("invalid mix entry '{}': expected METHOD=share", pair))?;
+            let method = match name.trim().to_ascii_uppercase().as_str() {
+                "GET" => RequestMethod::Get,
+                "POST" => RequestMethod::Post,
+                "PUT" => RequestMethod::Put,
+                "DELETE" => RequestMethod::Delete,
+                other => return Err(format!("unknown method '{}' in mix", other)),
+            };
+            let share: f64 = share
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid share '{}' for {}", share, method.as_str()))?;
+            if !(0.0..=1.0).contains(&share) {
+                return Err(format!(
+                    "share for {} must be between 0 and 1",
+                    method.as_str()
+                ));
+            }
+            if shares.insert(method, share).is_some() {
+                return Err(format!("{} appears more than once in mix", method.as_str()));
+            }
+        }
+
+        let total: f64 = shares.values().sum();
+        if (total - 1.0).abs() > 1e-6 {
+            return Err(format!("mix shares add up to {}, expected 1.0", total));
+        }
+        let share = |method| shares.get(&method).copied().unwrap_or(0.0);
+        Ok(Self {
+            get: share(RequestMethod::Get),
+            put: share(RequestMethod::Put),
+            delete: share(RequestMethod::Delete),
+        })
+    }
+}
+
+// Counters shared by every request task in a phase of the run
+#[derive(Clone, Default)]
+struct RunCounters {
+    sent: Arc<AtomicUsize>,
+    successful: Arc<AtomicUsize>,
+    failures: Arc<Mutex<FailureBreakdown>>,
+    latencies: Arc<Mutex<HashMap<RequestMethod, Vec<Duration>>>>,
+    clients: Arc<Mutex<HashMap<usize, ClientSamples>>>,
+    // Where each completed request is written as it finishes, if anywhere
+    stream: Option<ResultStream>,
+}
+
+// What one client sent and how its successful requests performed
+#[derive(Default)]
+struct ClientSamples {
+    sent: usize,
+    successful: usize,
+    latencies: Vec<Duration>,
+}
+
+impl RunCounters {
+    async fn latency_stats(&self, method: RequestMethod) -> LatencyStats {
+        let latencies = self.latencies.lock().await;
+        LatencyStats::from_samples(latencies.get(&method).map_or(&[], |v| v.as_slice()))
+    }
+
+    async fn histogram(&self, buckets: &Buckets) -> LatencyHistogram {
+        let latencies = self.latencies.lock().await;
+        let samples: Vec<Duration> = latencies.values().flatten().copied().collect();
+        LatencyHistogram::from_samples(buckets, &samples)
+    }
+
+    async fn client_stats(&self) -> Vec<ClientStats> {
+        let clients = self.clients.lock().await;
+        let mut stats: Vec<ClientStats> = clients
+            .iter()
+            .map(|(client_id, samples)| ClientStats {
+                client_id: *client_id,
+                total_requests: samples.sent,
+                successful_requests: samples.successful,
+                latency: LatencyStats::from_samples(&samples.latencies),
+            })
+            .collect();
+        stats.sort_by_key(|client| client.client_id);
+        stats
+    }
+
+    // Client results pooled by target, each client having hit `urls` in turn
+    async fn target_stats(&self, urls: &[String]) -> Vec<TargetStats> {
+        let clients = self.clients.lock().await;
+        urls.iter()
+            .enumerate()
+            .map(|(index, url)| {
+                let mut stats = TargetStats {
+                    url: url.clone(),
+                    ..Default::default()
+                };
+                let mut latencies = Vec::new();
+                for (_, samples) in clients
+                    .iter()
+                    .filter(|(client_id, _)| *client_id % urls.len() == index)
+                {
+                    stats.total_requests += samples.sent;
+                    stats.successful_requests += samples.successful;
+                    latencies.extend_from_slice(&samples.latencies);
+                }
+                stats.latency = LatencyStats::from_samples(&latencies);
+                stats
+            })
+            .collect()
+    }
+}
+
+// When duration-mode clients stop sending, and the pacer they share when a
+// rate is set
+#[derive(Clone)]
+struct Schedule {
+    deadline: Instant,
+    pacer: Option<Arc<Mutex<Interval>>>,
+}
+
+// What each request looks like: its method and the path it's sent to
+#[derive(Clone)]
+struct Traffic {
+    mix: MethodMix,
+    paths: Arc<Vec<String>>,
+}
+
+impl Traffic {
+    fn roll(&self, rng: &mut impl Rng) -> (RequestMethod, String) {
+        let method = self.mix.roll(rng);
+        let path = self
+            .paths
+            .choose(rng)
+            .map(|path| path.trim_start_matches('/').to_string())
+            .unwrap_or_default();
+        (method, path)
+    }
+}
+
+impl std::fmt::Display for MethodMix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.0}% GET, {:.0}% POST",
+            self.get * 100.0,
+            self.post() * 100.0
+        )?;
+        if self.put > 0.0 {
+            write!(f, ", {:.0}% PUT", self.put * 100.0)?;
+        }
+        if self.delete > 0.0 {
+            write!(f, ", {:.0}% DELETE", self.delete * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Generator {
+    // Clients take these in turn: client i targets `urls[i % urls.len()]`
+    urls: Vec<String>,
+    num_clients: usize,
+    mix: MethodMix,
+    // Empty means every request goes to the root path
+    paths: Vec<String>,
+    rate: Option<f64>,
+    duration: Option<Duration>,
+    warmup: Option<Warmup>,
+    timeout: Option<Duration>,
+    output: Option<(PathBuf, OutputFormat)>,
+    keep_alive: bool,
+    dry_run: bool,
+    buckets: Buckets,
+    rng: SharedRng,
+    verbosity: Verbosity,
+    stream_json: Option<PathBuf>,
+}
+
+impl Generator {
+    pub fn new(url: &str, num_clients: usize, get_ratio: f64) -> Self {
+        Self {
+            urls: vec![url.to_string()],
+            num_clients,
+            mix: MethodMix {
+                get: get_ratio,
+                put: 0.0,
+                delete: 0.0,
+            },
+            paths: Vec::new(),
+            rate: None,
+            duration: None,
+            warmup: None,
+            timeout: None,
+            output: None,
+            keep_alive: false,
+            dry_run: false,
+            buckets: Buckets::default(),
+            rng: SharedRng::default(),
+            verbosity: Verbosity::default(),
+            stream_json: None,
+        }
+    }
+
+    /// Build a generator from parsed flags. Fails when the GET, PUT and
+    /// DELETE shares add up to more than 1.0, leaving POST a negative share,
+    /// when the rate isn't a finite number above zero, or when there are no
+    /// clients to send anything.
+    pub fn from_args(args: &GeneratorArgs) -> Result<Self, String> {
+        if args.concurrent_clients == 0 {
+            return Err("--concurrent-clients must be at least 1".to_string());
+        }
+        let claimed = args.get_ratio + args.put_ratio + args.delete_ratio;
+        if args.mix.is_none() && claimed > 1.0 + f64::EPSILON {
+            return Err(format!(
+                "--get-ratio {}, --put-ratio {} and --delete-ratio {} add up to {}; \
+                 together they can't exceed 1.0 (POST takes whatever is left)",
+                args.get_ratio, args.put_ratio, args.delete_ratio, claimed
+            ));
+        }
+        let url = args.urls.first().map(String::as_str).unwrap_or_default();
+        let mut generator = Self::new(url, args.concurrent_clients, args.get_ratio)
+            .with_urls(args.urls.clone())
+            .with_paths(args.paths.clone())
+            .with_put_ratio(args.put_ratio)
+            .with_delete_ratio(args.delete_ratio)
+            .with_keep_alive(args.keep_alive)
+            .with_dry_run(args.dry_run)
+            .with_verbosity(Verbosity::from_flags(args.quiet, args.verbose));
+        if let Some(mix) = args.mix {
+            generator = generator.with_mix(mix);
+        }
+        if let Some(rate) = args.rate {
+            let rate = check_rate(rate).map_err(|err| format!("--rate: {}", err))?;
+            generator = generator.with_rate(rate);
+        }
+        if let Some(secs) = args.duration {
+            generator = generator.with_duration(Duration::from_secs(secs));
+        }
+        if let Some(warmup) = args.warmup {
+            generator = generator.with_warmup(warmup);
+        }
+        if let Some(ms) = args.timeout {
+            generator = generator.with_timeout(Duration::from_millis(ms));
+        }
+        if let Some(path) = &args.output {
+            generator = generator.with_output(path.clone(), args.format);
+        }
+        if let Some(buckets) = &args.buckets {
+            generator = generator.with_buckets(buckets.clone());
+        }
+        if let Some(path) = &args.stream_json {
+            generator = generator.with_stream_json(path.clone());
+        }
+        Ok(generator)
+    }
+
+    /// Split the clients round-robin across `urls` in place of the URL given
+    /// to `new`, reporting results per target
+    pub fn with_urls(mut self, urls: Vec<String>) -> Self {
+        if !urls.is_empty() {
+            self.urls = urls;
+        }
+        self
+    }
+
+    /// Send each request to one of `paths`, picked at random
+    pub fn with_paths(mut self, paths: Vec<String>) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    fn traffic(&self) -> Traffic {
+        Traffic {
+            mix: self.mix,
+            paths: Arc::new(self.paths.clone()),
+        }
+    }
+
+    // Target of the client with this id
+    fn url_for(&self, client_id: usize) -> &str {
+        &self.urls[client_id % self.urls.len()]
+    }
+
+    /// Send this share of requests as PUT, taken out of the POST share
+    pub fn with_put_ratio(mut self, put_ratio: f64) -> Self {
+        self.mix.put = put_ratio;
+        self
+    }
+
+    /// Send this share of requests as DELETE, taken out of the POST share
+    pub fn with_delete_ratio(mut self, delete_ratio: f64) -> Self {
+        self.mix.delete = delete_ratio;
+        self
+    }
+
+    /// Replace the GET/PUT/DELETE ratios with a full method distribution
+    pub fn with_mix(mut self, mix: MethodMix) -> Self {
+        self.mix = mix;
+        self
+    }
+
+    /// Dispatch requests at a constant rate (requests/second)
+    pub fn with_rate(mut self, rate: f64) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Send requests until the duration elapses rather than a fixed count
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Send unmeasured traffic before the measured run starts
+    pub fn with_warmup(mut self, warmup: Warmup) -> Self {
+        self.warmup = Some(warmup);
+        self
+    }
+
+    /// Time out each request attempt after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Write structured results to `path` once the run completes
+    pub fn with_output(mut self, path: PathBuf, format: OutputFormat) -> Self {
+        self.output = Some((path, format));
+        self
+    }
+
+    /// Reuse each client's connections to measure steady-state throughput
+    pub fn with_keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Bucket boundaries for the latency histogram printed after the run
+    pub fn with_buckets(mut self, buckets: Buckets) -> Self {
+        self.buckets = buckets;
+        self
+    }
+
+    /// Seed the method mix so the same seed rolls the same methods
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = SharedRng::seeded(seed);
+        self
+    }
+
+    /// Print the plan from `run` instead of sending any requests
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// How much to print while the run is in progress
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Write each measured request to `path` as a JSON line as soon as it
+    /// completes; `-` means stdout
+    pub fn with_stream_json(mut self, path: PathBuf) -> Self {
+        self.stream_json = Some(path);
+        self
+    }
+
+    /// What `run(num_requests)` would send, without sending it
+    pub fn plan(&self, num_requests: usize) -> RunPlan {
+        RunPlan {
+            urls: self.urls.clone(),
+            num_clients: self.num_clients,
+            requests: match self.duration {
+                Some(duration) => PlannedRequests::Duration(duration),
+                None => PlannedRequests::Counts(self.client_request_counts(num_requests)),
+            },
+            mix: self.mix,
+            paths: self.paths.clone(),
+            rate: self.rate,
+            warmup: self.warmup,
+        }
+    }
+
+    async fn send_request(
+        client: SenderClient,
+        (method, path): (RequestMethod, String),
+        client_id: usize,
+        request_id: usize,
+        counters: RunCounters,
+        verbosity: Verbosity,
+    ) {
+        counters.sent.fetch_add(1, Ordering::Relaxed);
+        let start = Instant::now();
+        let result = match method {
+            RequestMethod::Get => client.get_read_request(&path).await,
+            RequestMethod::Post => {
+                client
+                    .post_write_request(&path, format!("test{}", client_id))
+                    .await
+            }
+            RequestMethod::Put => {
+                client
+                    .put_update_request(&path, format!("test{}", client_id))
+                    .await
+            }
+            RequestMethod::Delete => client.delete_request(&path).await,
+        };
+
+        // reqwest returns Ok for any status, so only 2xx responses count as successes
+        let failure = match &result {
+            Ok(response) if response.status().is_success() => None,
+            Ok(response) if response.status().is_server_error() => {
+                Some((FailureReason::ServerError, response.status().to_string()))
+            }
+            Ok(response) => Some((FailureReason::Other, response.status().to_string())),
+            Err(e) => Some((FailureReason::from_error(e), e.to_string())),
+        };
+
+        let elapsed = start.elapsed();
+        let to_stderr = counters
+            .stream
+            .as_ref()
+            .is_some_and(ResultStream::is_stdout);
+        if let Some(stream) = &counters.stream {
+            let record = RequestRecord {
+                client_id,
+                request_id,
+                method: method.as_str().to_string(),
+                status: result.as_ref().ok().map(|r| r.status().as_u16()),
+                latency_ms: elapsed.as_secs_f64() * 1000.0,
+                success: failure.is_none(),
+            };
+            if let Err(e) = stream.emit(&record) {
+                eprintln!("Failed to stream request result: {}", e);
+            }
+        }
+        {
+            let mut clients = counters.clients.lock().await;
+            let samples = clients.entry(client_id).or_default();
+            samples.sent += 1;
+            if failure.is_none() {
+                samples.successful += 1;
+                samples.latencies.push(elapsed);
+            }
+        }
+
+        match failure {
+            None => {
+                counters
+                    .latencies
+                    .lock()
+                    .await
+                    .entry(method)
+                    .or_default()
+                    .push(elapsed);
+                counters.successful.fetch_add(1, Ordering::Relaxed);
+                if verbosity == Verbosity::Verbose {
+                    say!(
+                        to_stderr,
+                        "Client {} - {} request {} successful in {:.1}ms",
+                        client_id,
+                        method.as_str(),
+                        request_id,
+                        elapsed.as_secs_f64() * 1000.0
+                    );
+                }
+            }
+            Some((reason, message)) => {
+                counters.failures.lock().await.record(reason);
+                if verbosity != Verbosity::Quiet {
+                    eprintln!(
+                        "Client {} - {} request {} failed: {}",
+                        client_id,
+                        method.as_str(),
+                        request_id,
+                        message
+                    );
+                }
+            }
+        }
+    }
+
+    /// Split `num_requests` across clients, giving the remainder to the first few
+    pub fn client_request_counts(&self, num_requests: usize) -> Vec<usize> {
+        let base = num_requests / self.num_clients;
+        let remainder = num_requests % self.num_clients;
+        (0..self.num_clients)
+            .map(|client_id| base + usize::from(client_id < remainder))
+            .collect()
+    }
+
+    // Issue requests back-to-back from one client until the deadline
+    async fn run_client_until(
+        client: SenderClient,
+        client_id: usize,
+        schedule: Schedule,
+        traffic: Traffic,
+        mut rng: SharedRng,
+        counters: RunCounters,
+        verbosity: Verbosity,
+    ) {
+        let mut request_id = 0;
+        while Instant::now() < schedule.deadline {
+            if let Some(pacer) = &schedule.pacer {
+                pacer.lock().await.tick().await;
+                if Instant::now() >= schedule.deadline {
+                    break;
+                }
+            }
+            Self::send_request(
+                client.clone(),
+                traffic.roll(&mut rng),
+                client_id,
+                request_id,
+                counters.clone(),
+                verbosity,
+            )
+            .await;
+            request_id += 1;
+        }
+    }
+
+    // Spaces dispatches evenly when a target rate is set; a rate too high to
+    // pace still ticks no faster than once a nanosecond
+    fn pacer(&self) -> Option<Interval> {
+        self.rate
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| interval(Duration::from_secs_f64(1.0 / rate).max(Duration::from_nanos(1))))
+    }
+
+    // Each client keeps sending until the deadline passes
+    async fn dispatch_until(
+        &self,
+        clients: &[SenderClient],
+        deadline: Instant,
+        counters: &RunCounters,
+    ) {
+        // Clients take turns on one pacer, so together they keep to the rate
+        let schedule = Schedule {
+            deadline,
+            pacer: self.pacer().map(|pacer| Arc::new(Mutex::new(pacer))),
+        };
+        let all_futures: Vec<_> = clients
+            .iter()
+            .enumerate()
+            .map(|(client_id, client)| {
+                tokio::spawn(Self::run_client_until(
+                    client.clone(),
+                    client_id,
+                    schedule.clone(),
+                    self.traffic(),
+                    self.rng.clone(),
+                    counters.clone(),
+                    self.verbosity,
+                ))
+            })
+            .collect();
+
+        join_all(all_futures).await;
+    }
+
+    // Send exactly `num_requests`, spread across the clients
+    async fn dispatch_count(
+        &self,
+        clients: &[SenderClient],
+        num_requests: usize,
+        counters: &RunCounters,
+    ) {
+        let request_counts = self.client_request_counts(num_requests);
+        let traffic = self.traffic();
+        let mut all_futures = Vec::new();
+
+        let mut pacer = self.pacer();
+
+        // Interleave clients so paced requests are spread across all of them
+        let max_requests = request_counts.iter().copied().max().unwrap_or(0);
+        for request_id in 0..max_requests {
+            for (client_id, client) in clients.iter().enumerate() {
+                if request_id >= request_counts[client_id] {
+                    continue;
+                }
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.tick().await;
+                }
+
+                let future = tokio::spawn(Self::send_request(
+                    client.clone(),
+                    traffic.roll(&mut self.rng.clone()),
+                    client_id,
+                    request_id,
+                    counters.clone(),
+                    self.verbosity,
+                ));
+
+                all_futures.push(future);
+            }
+        }
+
+        // Run all requests concurrently
+        join_all(all_futures).await;
+    }
+
+    pub async fn run(&self, num_requests: usize) -> GeneratorReport {
+        if self.dry_run {
+            println!("Dry run, no requests will be sent");
+            print!("{}", self.plan(num_requests));
+            return GeneratorReport::default();
+        }
+
+        let clients: Vec<SenderClient> = (0..self.num_clients)
+            .map(|client_id| {
+                let client = SenderClient::new(&client_id.to_string(), self.url_for(client_id))
+                    .with_keep_alive(self.keep_alive)
+                    .with_rng(self.rng.clone());
+                match self.timeout {
+                    Some(timeout) => client.with_timeout(timeout),
+                    None => client,
+                }
+            })
+            .collect();
+
+        // Only measured requests are streamed
+        let stream = match &self.stream_json {
+            Some(path) => match ResultStream::open(path) {
+                Ok(stream) => Some(stream),
+                Err(e) => {
+                    eprintln!("Failed to open {}: {}", path.display(), e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let to_stderr = stream.as_ref().is_some_and(ResultStream::is_stdout);
+
+        // Warmup traffic hits the target but is tallied separately
+        let quiet = self.verbosity == Verbosity::Quiet;
+        let warmup_counters = RunCounters::default();
+        match self.warmup {
+            Some(Warmup::Requests(count)) => {
+                if !quiet {
+                    say!(to_stderr, "Warming up with {} requests", count);
+                }
+                self.dispatch_count(&clients, count, &warmup_counters).await;
+            }
+            Some(Warmup::Duration(duration)) => {
+                if !quiet {
+                    say!(to_stderr, "Warming up for {:?}", duration);
+                }
+                self.dispatch_until(&clients, Instant::now() + duration, &warmup_counters)
+                    .await;
+            }
+            None => {}
+        }
+        let warmup_requests = warmup_counters.sent.load(Ordering::Relaxed);
+
+        match self.duration {
+            _ if quiet => {}
+            Some(duration) => say!(
+                to_stderr,
+                "Starting load test with {} clients for {:?} ({})",
+                self.num_clients,
+                duration,
+                self.mix
+            ),
+            None => say!(
+                to_stderr,
+                "Starting load test with {} clients, {} total requests ({})",
+                self.num_clients,
+                num_requests,
+                self.mix
+            ),
+        }
+
+        let counters = RunCounters {
+            stream,
+            ..Default::default()
+        };
+        let start_time = Instant::now();
+        match self.duration {
+            Some(duration) => {
+                self.dispatch_until(&clients, start_time + duration, &counters)
+                    .await
+            }
+            None => self.dispatch_count(&clients, num_requests, &counters).await,
+        }
+
+        let duration = start_time.elapsed();
+        let report = GeneratorReport {
+            total_requests: counters.sent.load(Ordering::Relaxed),
+            successful_requests: counters.successful.load(Ordering::Relaxed),
+            failures: counters.failures.lock().await.clone(),
+            warmup_requests,
+            duration,
+            get_latency: counters.latency_stats(RequestMethod::Get).await,
+            post_latency: counters.latency_stats(RequestMethod::Post).await,
+            put_latency: counters.latency_stats(RequestMethod::Put).await,
+            delete_latency: counters.latency_stats(RequestMethod::Delete).await,
+            histogram: counters.histogram(&self.buckets).await,
+            clients: counters.client_stats().await,
+            targets: counters.target_stats(&self.urls).await,
+        };
+
+        say!(to_stderr, "Load test completed in {:?}", duration);
+        if warmup_requests > 0 {
+            say!(to_stderr, "Warmup requests (excluded): {}", warmup_requests);
+        }
+        say!(
+            to_stderr,
+            "Successful requests: {}/{} ({:.1}%)",
+            report.successful_requests,
+            report.total_requests,
+            report.success_rate()
+        );
+        if report.failures.total() > 0 {
+            say!(to_stderr, "Failures: {}", report.failures);
+        }
+        say!(
+            to_stderr,
+            "Average request rate: {:.2} requests/second",
+            report.successful_requests as f64 / duration.as_secs_f64()
+        );
+        say!(to_stderr, "GET latency: {}", report.get_latency);
+        say!(to_stderr, "POST latency: {}", report.post_latency);
+        if report.put_latency.count > 0 {
+            say!(to_stderr, "PUT latency: {}", report.put_latency);
+        }
+        if report.delete_latency.count > 0 {
+            say!(to_stderr, "DELETE latency: {}", report.delete_latency);
+        }
+        say!(to_stderr, "Latency histogram:");
+        say!(to_stderr, "{}", report.histogram.to_string().trim_end());
+        say!(to_stderr, "Per-client results:");
+        say!(to_stderr, "{}", report.client_summary().trim_end());
+        if self.urls.len() > 1 {
+            say!(to_stderr, "Per-target results:");
+            say!(to_stderr, "{}", report.target_summary().trim_end());
+        }
+
+        if let Some((path, format)) = &self.output {
+            match report.write_to(path, *format) {
+                Ok(()) => say!(to_stderr, "Results written to {}", path.display()),
+                Err(e) => eprintln!("Failed to write results to {}: {}", path.display(), e),
+            }
+        }
+
+        report
+    }
+}
+
+#[tokio::main]
+#[allow(dead_code)]
+async fn main() {
+    let args = GeneratorArgs::parse();
+    let generator = match Generator::from_args(&args) {
+        Ok(generator) => generator,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    generator.run(args.num_requests).await;
+}
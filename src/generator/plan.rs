@@ -0,0 +1,78 @@
+//! What a load test would send, printed by `--dry-run` instead of running it
+use super::{MethodMix, Warmup};
+use std::time::Duration;
+
+/// How much traffic each client would send
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlannedRequests {
+    /// Fixed counts, indexed by client id
+    Counts(Vec<usize>),
+    /// Every client sends back-to-back until the duration elapses
+    Duration(Duration),
+}
+
+/// The traffic a run would generate, worked out without sending any of it
+#[derive(Debug, Clone)]
+pub struct RunPlan {
+    /// Clients take these in turn
+    pub urls: Vec<String>,
+    pub num_clients: usize,
+    pub requests: PlannedRequests,
+    pub mix: MethodMix,
+    /// Picked from at random per request; empty means the root path
+    pub paths: Vec<String>,
+    pub rate: Option<f64>,
+    pub warmup: Option<Warmup>,
+}
+
+impl RunPlan {
+    /// Requests the measured run would send, if it's count-based
+    pub fn total_requests(&self) -> Option<usize> {
+        match &self.requests {
+            PlannedRequests::Counts(counts) => Some(counts.iter().sum()),
+            PlannedRequests::Duration(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RunPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.urls.as_slice() {
+            [url] => writeln!(f, "Target: {}", url)?,
+            urls => writeln!(
+                f,
+                "Targets: {} (clients take them in turn)",
+                urls.join(", ")
+            )?,
+        }
+        writeln!(f, "Clients: {}", self.num_clients)?;
+        match &self.requests {
+            PlannedRequests::Counts(counts) => {
+                writeln!(f, "Total requests: {}", counts.iter().sum::<usize>())?;
+                for (client_id, count) in counts.iter().enumerate() {
+                    writeln!(f, "Client {}: {} requests", client_id, count)?;
+                }
+            }
+            PlannedRequests::Duration(duration) => {
+                writeln!(
+                    f,
+                    "Duration: {:?} (request count depends on latency)",
+                    duration
+                )?;
+            }
+        }
+        writeln!(f, "Method mix: {}", self.mix)?;
+        if !self.paths.is_empty() {
+            writeln!(f, "Paths: {} (picked at random)", self.paths.join(", "))?;
+        }
+        if let Some(rate) = self.rate {
+            writeln!(f, "Rate: {} requests/second", rate)?;
+        }
+        match self.warmup {
+            Some(Warmup::Requests(count)) => writeln!(f, "Warmup: {} requests", count)?,
+            Some(Warmup::Duration(duration)) => writeln!(f, "Warmup: {:?}", duration)?,
+            None => {}
+        }
+        Ok(())
+    }
+}
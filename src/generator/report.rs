@@ -0,0 +1,413 @@
+//! Load test results and their structured export
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Latency percentiles over a set of completed requests
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        Self {
+            count: sorted.len(),
+            p50: Self::percentile(&sorted, 50.0),
+            p95: Self::percentile(&sorted, 95.0),
+            p99: Self::percentile(&sorted, 99.0),
+            max: sorted.last().copied().unwrap_or_default(),
+        }
+    }
+
+    // Nearest-rank percentile over sorted samples
+    fn percentile(sorted: &[Duration], pct: f64) -> Duration {
+        if sorted.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+}
+
+impl std::fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "n={}, p50={:?}, p95={:?}, p99={:?}, max={:?}",
+            self.count, self.p50, self.p95, self.p99, self.max
+        )
+    }
+}
+
+/// Why a request failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureReason {
+    ConnectionRefused,
+    Timeout,
+    ServerError,
+    Other,
+}
+
+impl FailureReason {
+    /// Categorize a transport-level error from the client
+    pub fn from_error(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            FailureReason::Timeout
+        } else if error.is_connect() {
+            FailureReason::ConnectionRefused
+        } else {
+            FailureReason::Other
+        }
+    }
+}
+
+/// Failed request counts by reason
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FailureBreakdown {
+    pub connection_refused: usize,
+    pub timeout: usize,
+    pub server_error: usize,
+    pub other: usize,
+}
+
+impl FailureBreakdown {
+    pub fn record(&mut self, reason: FailureReason) {
+        match reason {
+            FailureReason::ConnectionRefused => self.connection_refused += 1,
+            FailureReason::Timeout => self.timeout += 1,
+            FailureReason::ServerError => self.server_error += 1,
+            FailureReason::Other => self.other += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.connection_refused + self.timeout + self.server_error + self.other
+    }
+}
+
+impl std::fmt::Display for FailureBreakdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "connection refused={}, timeout={}, 5xx={}, other={}",
+            self.connection_refused, self.timeout, self.server_error, self.other
+        )
+    }
+}
+
+/// Default latency bucket boundaries in milliseconds
+pub const DEFAULT_BUCKETS: &[u64] = &[10, 50, 100, 250, 500, 1000];
+
+/// Ascending latency bucket boundaries in milliseconds, e.g. `10,50,100`
+/// for <10ms, 10-50ms, 50-100ms and >=100ms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Buckets(Vec<u64>);
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self(DEFAULT_BUCKETS.to_vec())
+    }
+}
+
+impl std::str::FromStr for Buckets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bounds = s
+            .split(',')
+            .map(str::trim)
+            .filter(|b| !b.is_empty())
+            .map(|b| {
+                b.trim_end_matches("ms")
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid bucket boundary '{}'", b))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if bounds.is_empty() {
+            return Err("expected at least one bucket boundary".to_string());
+        }
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("bucket boundaries must be strictly increasing".to_string());
+        }
+        Ok(Self(bounds))
+    }
+}
+
+/// Request counts per latency bucket, to show multimodal latency that
+/// percentiles blur together
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    // Boundaries in milliseconds; counts has one more entry for the overflow
+    bounds: Vec<u64>,
+    pub counts: Vec<usize>,
+}
+
+impl LatencyHistogram {
+    pub fn from_samples(buckets: &Buckets, samples: &[Duration]) -> Self {
+        let bounds = buckets.0.clone();
+        let mut counts = vec![0; bounds.len() + 1];
+        for sample in samples {
+            let ms = sample.as_secs_f64() * 1000.0;
+            let bucket = bounds.partition_point(|bound| (*bound as f64) <= ms);
+            counts[bucket] += 1;
+        }
+        Self { bounds, counts }
+    }
+
+    /// Label and count for each bucket, lowest first
+    pub fn buckets(&self) -> Vec<(String, usize)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                let label = match (i.checked_sub(1).map(|j| self.bounds[j]), self.bounds.get(i)) {
+                    (None, Some(upper)) => format!("<{}ms", upper),
+                    (Some(lower), Some(upper)) => format!("{}-{}ms", lower, upper),
+                    (Some(lower), None) => format!(">={}ms", lower),
+                    (None, None) => "all".to_string(),
+                };
+                (label, *count)
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (label, count) in self.buckets() {
+            writeln!(f, "{:>12}: {}", label, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// One client's share of a load test, to spot a connection that is
+/// systematically slower than the rest
+#[derive(Debug, Clone)]
+pub struct ClientStats {
+    pub client_id: usize,
+    pub total_requests: usize,
+    pub successful_requests: usize,
+    pub latency: LatencyStats,
+}
+
+impl std::fmt::Display for ClientStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Client {}: {}/{} successful, {}",
+            self.client_id, self.successful_requests, self.total_requests, self.latency
+        )
+    }
+}
+
+/// One target URL's share of a load test, for comparing targets hit in the
+/// same run
+#[derive(Debug, Clone, Default)]
+pub struct TargetStats {
+    pub url: String,
+    pub total_requests: usize,
+    pub successful_requests: usize,
+    pub latency: LatencyStats,
+}
+
+impl std::fmt::Display for TargetStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}/{} successful, {}",
+            self.url, self.successful_requests, self.total_requests, self.latency
+        )
+    }
+}
+
+/// Summary of a completed load test
+#[derive(Debug, Clone, Default)]
+pub struct GeneratorReport {
+    pub total_requests: usize,
+    pub successful_requests: usize,
+    pub failures: FailureBreakdown,
+    pub warmup_requests: usize,
+    pub duration: Duration,
+    pub get_latency: LatencyStats,
+    pub post_latency: LatencyStats,
+    pub put_latency: LatencyStats,
+    pub delete_latency: LatencyStats,
+    /// Successful requests of every method by latency bucket
+    pub histogram: LatencyHistogram,
+    // Ordered by client id
+    pub clients: Vec<ClientStats>,
+    // In the order the targets were given
+    pub targets: Vec<TargetStats>,
+}
+
+impl GeneratorReport {
+    pub fn failed_requests(&self) -> usize {
+        self.total_requests - self.successful_requests
+    }
+
+    /// Percentage of measured requests that got a 2xx response
+    pub fn success_rate(&self) -> f64 {
+        if self.total_requests == 0 {
+            return 0.0;
+        }
+        self.successful_requests as f64 / self.total_requests as f64 * 100.0
+    }
+
+    /// One line per client, for spotting an outlier connection
+    pub fn client_summary(&self) -> String {
+        self.clients
+            .iter()
+            .map(|client| format!("{}\n", client))
+            .collect()
+    }
+
+    /// One line per target URL, for comparing them side by side
+    pub fn target_summary(&self) -> String {
+        self.targets
+            .iter()
+            .map(|target| format!("{}\n", target))
+            .collect()
+    }
+
+    /// Flatten the report into stable, machine-readable fields
+    pub fn summary(&self) -> ReportSummary {
+        let duration_secs = self.duration.as_secs_f64();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        ReportSummary {
+            total_requests: self.total_requests,
+            successful_requests: self.successful_requests,
+            failed_requests: self.failed_requests(),
+            failed_connection_refused: self.failures.connection_refused,
+            failed_timeout: self.failures.timeout,
+            failed_server_error: self.failures.server_error,
+            failed_other: self.failures.other,
+            success_rate: self.success_rate(),
+            warmup_requests: self.warmup_requests,
+            duration_secs,
+            requests_per_second: if duration_secs > 0.0 {
+                self.successful_requests as f64 / duration_secs
+            } else {
+                0.0
+            },
+            get_p50_ms: ms(self.get_latency.p50),
+            get_p95_ms: ms(self.get_latency.p95),
+            get_p99_ms: ms(self.get_latency.p99),
+            get_max_ms: ms(self.get_latency.max),
+            post_p50_ms: ms(self.post_latency.p50),
+            post_p95_ms: ms(self.post_latency.p95),
+            post_p99_ms: ms(self.post_latency.p99),
+            post_max_ms: ms(self.post_latency.max),
+            put_p50_ms: ms(self.put_latency.p50),
+            put_p95_ms: ms(self.put_latency.p95),
+            put_p99_ms: ms(self.put_latency.p99),
+            put_max_ms: ms(self.put_latency.max),
+            delete_p50_ms: ms(self.delete_latency.p50),
+            delete_p95_ms: ms(self.delete_latency.p95),
+            delete_p99_ms: ms(self.delete_latency.p99),
+            delete_max_ms: ms(self.delete_latency.max),
+        }
+    }
+
+    /// Write the summary to `path` in the given format
+    pub fn write_to(&self, path: &Path, format: OutputFormat) -> std::io::Result<()> {
+        let summary = self.summary();
+        let contents = match format {
+            OutputFormat::Json => serde_json::to_string_pretty(&summary)?,
+            OutputFormat::Csv => summary.to_csv(),
+        };
+        std::fs::write(path, contents)
+    }
+}
+
+/// Structured output format for load test results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+}
+
+/// Flat view of a report, suitable for CSV rows or JSON objects
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportSummary {
+    pub total_requests: usize,
+    pub successful_requests: usize,
+    pub failed_requests: usize,
+    pub failed_connection_refused: usize,
+    pub failed_timeout: usize,
+    pub failed_server_error: usize,
+    pub failed_other: usize,
+    pub success_rate: f64,
+    pub warmup_requests: usize,
+    pub duration_secs: f64,
+    pub requests_per_second: f64,
+    pub get_p50_ms: f64,
+    pub get_p95_ms: f64,
+    pub get_p99_ms: f64,
+    pub get_max_ms: f64,
+    pub post_p50_ms: f64,
+    pub post_p95_ms: f64,
+    pub post_p99_ms: f64,
+    pub post_max_ms: f64,
+    pub put_p50_ms: f64,
+    pub put_p95_ms: f64,
+    pub put_p99_ms: f64,
+    pub put_max_ms: f64,
+    pub delete_p50_ms: f64,
+    pub delete_p95_ms: f64,
+    pub delete_p99_ms: f64,
+    pub delete_max_ms: f64,
+}
+
+impl ReportSummary {
+    const CSV_HEADER: &'static str = "total_requests,successful_requests,failed_requests,\
+failed_connection_refused,failed_timeout,failed_server_error,failed_other,success_rate,\
+warmup_requests,duration_secs,requests_per_second,get_p50_ms,get_p95_ms,get_p99_ms,get_max_ms,\
+post_p50_ms,post_p95_ms,post_p99_ms,post_max_ms,\
+put_p50_ms,put_p95_ms,put_p99_ms,put_max_ms,\
+delete_p50_ms,delete_p95_ms,delete_p99_ms,delete_max_ms";
+
+    /// Header line followed by a single data row
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{}\n{},{},{},{},{},{},{},{:.1},{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},\
+             {:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3}\n",
+            Self::CSV_HEADER,
+            self.total_requests,
+            self.successful_requests,
+            self.failed_requests,
+            self.failed_connection_refused,
+            self.failed_timeout,
+            self.failed_server_error,
+            self.failed_other,
+            self.success_rate,
+            self.warmup_requests,
+            self.duration_secs,
+            self.requests_per_second,
+            self.get_p50_ms,
+            self.get_p95_ms,
+            self.get_p99_ms,
+            self.get_max_ms,
+            self.post_p50_ms,
+            self.post_p95_ms,
+            self.post_p99_ms,
+            self.post_max_ms,
+            self.put_p50_ms,
+            self.put_p95_ms,
+            self.put_p99_ms,
+            self.put_max_ms,
+            self.delete_p50_ms,
+            self.delete_p95_ms,
+            self.delete_p99_ms,
+            self.delete_max_ms
+        )
+    }
+}
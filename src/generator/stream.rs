@@ -0,0 +1,59 @@
+//! Live per-request results as JSON lines, for tools consuming a run as it
+//! happens rather than waiting for the summary
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Stream target meaning standard output rather than a file
+pub const STDOUT_TARGET: &str = "-";
+
+/// One completed request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestRecord {
+    pub client_id: usize,
+    pub request_id: usize,
+    pub method: String,
+    /// HTTP status, absent when no response came back
+    pub status: Option<u16>,
+    pub latency_ms: f64,
+    pub success: bool,
+}
+
+/// Destination for request records, one JSON object per line, flushed as
+/// each is written
+#[derive(Clone)]
+pub struct ResultStream {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+    to_stdout: bool,
+}
+
+impl ResultStream {
+    /// Stream to `path`, or to stdout when it's `-`
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if path.as_os_str() == STDOUT_TARGET {
+            return Ok(Self {
+                out: Arc::new(Mutex::new(Box::new(io::stdout()))),
+                to_stdout: true,
+            });
+        }
+        Ok(Self {
+            out: Arc::new(Mutex::new(Box::new(BufWriter::new(File::create(path)?)))),
+            to_stdout: false,
+        })
+    }
+
+    /// Whether records go to stdout, leaving it no room for other output
+    pub fn is_stdout(&self) -> bool {
+        self.to_stdout
+    }
+
+    pub fn emit(&self, record: &RequestRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        out.write_all(&line)?;
+        out.flush()
+    }
+}
@@ -1,6 +1,9 @@
 //! Simple load balancer implementation
 pub mod algorithms;
 pub mod balancer;
+pub mod cli;
 pub mod client;
+pub mod config;
 pub mod generator;
+pub mod rng;
 pub mod server;
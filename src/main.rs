@@ -1,23 +1,190 @@
 //! Main entry point for the load balancer application
-use clap::Parser;
-use rust_load_balancer::balancer::LoadBalancer;
-use rust_load_balancer::generator::{Generator, GeneratorArgs};
-use rust_load_balancer::server::Server;
+use clap::{builder::PossibleValuesParser, Parser, Subcommand};
+use rust_load_balancer::algorithms::{
+    Algorithm, HeaderHash, LeastScore, ALGORITHM_NAMES, DEFAULT_AFFINITY_HEADER,
+    DEFAULT_ERROR_WEIGHT, DEFAULT_LATENCY_WEIGHT,
+};
+use rust_load_balancer::balancer::{
+    init_logging, read_servers_file, LoadBalancer, LogFormat, DEFAULT_BACKLOG,
+    DEFAULT_CACHE_ENTRIES, HTTPS_PREFIX,
+};
+use rust_load_balancer::cli::parse_ratio;
+use rust_load_balancer::config::Config;
+use rust_load_balancer::generator::{Generator, GeneratorArgs, STDOUT_TARGET};
+use rust_load_balancer::server::{PathDelays, Server};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(name = "Rust Load Balancer")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    // Seed every random choice so a run can be reproduced exactly
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+}
+
+// Parsed once at startup, so the balancer's many flags needn't be boxed
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
 enum Command {
     #[command(name = "balancer")]
     Balancer {
-        #[arg(short = 'p', long, default_value = "8000")]
-        port: u16,
+        // TOML or JSON config file; the flags below override its values
+        #[arg(short = 'c', long)]
+        config: Option<PathBuf>,
+
+        #[arg(short = 'p', long)]
+        port: Option<u16>,
 
         #[arg(short = 's', long = "servers", value_delimiter = ',')]
         servers: Vec<String>,
 
-        #[arg(short = 'a', long = "algorithm", default_value = "round-robin")]
-        #[arg(value_enum)]
-        algorithm: String,
+        // Newline-delimited backend list, re-read on SIGHUP
+        #[arg(long, conflicts_with = "servers")]
+        servers_file: Option<PathBuf>,
+
+        // One of ALGORITHM_NAMES; round-robin unless the config file says otherwise
+        #[arg(
+            short = 'a',
+            long = "algorithm",
+            value_parser = PossibleValuesParser::new(ALGORITHM_NAMES)
+        )]
+        algorithm: Option<String>,
+
+        // least-score points per millisecond of p95 latency
+        #[arg(long, default_value_t = DEFAULT_LATENCY_WEIGHT)]
+        score_latency_weight: f64,
+
+        // least-score points per percentage point of failed requests
+        #[arg(long, default_value_t = DEFAULT_ERROR_WEIGHT)]
+        score_error_weight: f64,
+
+        // Request header header-hash pins backends by
+        #[arg(long, default_value = DEFAULT_AFFINITY_HEADER)]
+        hash_header: String,
+
+        #[arg(long)]
+        max_connections: Option<usize>,
+
+        // Requests allowed in flight to each backend at once
+        #[arg(long)]
+        max_per_backend: Option<usize>,
+
+        // Milliseconds a connection may wait for a free slot before getting a 503
+        #[arg(long)]
+        queue_timeout: Option<u64>,
+
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
+
+        // PEM certificates trusted when dialling https:// backends
+        #[arg(long)]
+        backend_ca: Option<PathBuf>,
+
+        // Dial https:// backends without verifying their certificates (testing only)
+        #[arg(long, conflicts_with = "backend_ca")]
+        backend_insecure: bool,
+
+        #[arg(long)]
+        rate_limit: Option<f64>,
+
+        // Methods to refuse with a 403, e.g. DELETE,PUT
+        #[arg(long, value_delimiter = ',')]
+        deny_method: Vec<String>,
+
+        // Path prefixes to refuse with a 403, e.g. /admin
+        #[arg(long, value_delimiter = ',')]
+        deny_path: Vec<String>,
+
+        // Only these methods get through; everything else gets a 403
+        #[arg(long, value_delimiter = ',')]
+        allow_method: Vec<String>,
+
+        // Only paths under these prefixes get through; everything else gets a 403
+        #[arg(long, value_delimiter = ',')]
+        allow_path: Vec<String>,
+
+        // Cache 200 responses to GETs for this many seconds
+        #[arg(long)]
+        cache_ttl: Option<u64>,
+
+        // Let identical in-flight GETs share one backend response
+        #[arg(long)]
+        coalesce: bool,
+
+        // Eject backends slower than this multiple of the pool's mean latency
+        #[arg(long)]
+        outlier_multiplier: Option<f64>,
+
+        // Seconds an ejected backend sits out before rejoining
+        #[arg(long, default_value = "30")]
+        outlier_cooldown: u64,
+
+        // Reweight weighted-round-robin backends by recent latency every N seconds
+        #[arg(long)]
+        tune_weights: Option<u64>,
+
+        // Point backend addresses in Location/Set-Cookie headers at the balancer
+        #[arg(long)]
+        rewrite_host: bool,
+
+        // Forward the Host header the client sent (the default)
+        #[arg(long, overrides_with = "no_preserve_host")]
+        preserve_host: bool,
+
+        // Set the Host header to the address of the backend each request goes to
+        #[arg(long, overrides_with = "preserve_host")]
+        no_preserve_host: bool,
+
+        // Close connections that take longer than this many milliseconds to
+        // send a request head
+        #[arg(long)]
+        header_timeout: Option<u64>,
+
+        // Reject request bodies larger than this many bytes with a 413
+        #[arg(long)]
+        max_body_size: Option<usize>,
+
+        // Idle connections to hold open to each backend ahead of traffic
+        #[arg(long)]
+        warm_connections: Option<usize>,
+
+        // Set TCP_NODELAY on client and backend connections
+        #[arg(long)]
+        nodelay: bool,
+
+        // Break each backend's metrics down into 2xx/4xx/5xx response counts
+        #[arg(long)]
+        count_by_status: bool,
+
+        // Pass every connection through to a backend as HTTP/2, unparsed
+        #[arg(long)]
+        http2: bool,
+
+        // Shut down after this many seconds without a new connection
+        #[arg(long)]
+        idle_timeout: Option<u64>,
+
+        // Wait up to this many seconds at startup for a reachable backend
+        #[arg(long)]
+        ready_timeout: Option<u64>,
+
+        // Pending connections the listener queues before they're accepted
+        #[arg(long, default_value_t = DEFAULT_BACKLOG)]
+        backlog: u32,
+
+        // Also write the final metrics as JSON to this file on shutdown
+        #[arg(long)]
+        metrics_out: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value = "plain")]
+        log_format: LogFormat,
     },
     #[command(name = "server")]
     Server {
@@ -29,6 +196,42 @@ enum Command {
 
         #[arg(short = 'o', long, default_value = "200")]
         post_delay: u64,
+
+        #[arg(short = 'u', long, default_value = "200")]
+        put_delay: u64,
+
+        #[arg(short = 'd', long, default_value = "100")]
+        delete_delay: u64,
+
+        #[arg(long)]
+        unhealthy: bool,
+
+        // Fraction of requests (0.0-1.0) answered with 500
+        #[arg(short = 'e', long, default_value = "0.0", value_parser = parse_ratio)]
+        error_rate: f64,
+
+        #[arg(short = 'j', long, default_value = "0.0")]
+        jitter: f64,
+
+        // Per-path delays overriding the method delays, e.g. `/slow=2000,/fast=10`
+        #[arg(long = "delay")]
+        path_delays: Option<PathDelays>,
+
+        // Respond immediately, ignoring every configured delay
+        #[arg(long)]
+        no_delay: bool,
+
+        // Body for GET/DELETE responses instead of "Request Received of type: ..."
+        #[arg(long)]
+        response_body: Option<String>,
+
+        // Content-Type header to send with each response
+        #[arg(long)]
+        content_type: Option<String>,
+
+        // Shut down after this many seconds without a new connection
+        #[arg(long)]
+        idle_timeout: Option<u64>,
     },
     #[command(name = "generator")]
     Generator {
@@ -39,35 +242,273 @@ enum Command {
 
 #[tokio::main]
 async fn main() {
-    match Command::parse() {
+    let Cli { command, seed } = Cli::parse();
+    // A generator streaming JSON lines to stdout keeps everything else off it
+    let json_on_stdout = matches!(
+        &command,
+        Command::Generator { args }
+            if args.stream_json.as_deref() == Some(Path::new(STDOUT_TARGET))
+    );
+    if let Some(seed) = seed {
+        if json_on_stdout {
+            eprintln!("Using random seed {}", seed);
+        } else {
+            println!("Using random seed {}", seed);
+        }
+    }
+    match command {
         Command::Balancer {
+            config,
             port,
             servers,
+            servers_file,
             algorithm,
+            score_latency_weight,
+            score_error_weight,
+            hash_header,
+            max_connections,
+            max_per_backend,
+            queue_timeout,
+            header_timeout,
+            tls_cert,
+            tls_key,
+            backend_ca,
+            backend_insecure,
+            rate_limit,
+            deny_method,
+            deny_path,
+            allow_method,
+            allow_path,
+            cache_ttl,
+            coalesce,
+            outlier_multiplier,
+            outlier_cooldown,
+            tune_weights,
+            rewrite_host,
+            preserve_host,
+            no_preserve_host,
+            max_body_size,
+            warm_connections,
+            nodelay,
+            count_by_status,
+            http2,
+            idle_timeout,
+            ready_timeout,
+            backlog,
+            metrics_out,
+            log_format,
         } => {
+            init_logging(log_format);
+            let mut config = match config {
+                Some(path) => Config::load(&path).expect("Failed to load config file"),
+                None => Config::default(),
+            };
+            if let Some(port) = port {
+                config.port = port;
+            }
+            if !servers.is_empty() {
+                config.servers = servers;
+            }
+            if let Some(path) = &servers_file {
+                config.servers = read_servers_file(path).expect("Failed to read servers file");
+            }
+            if let Some(algorithm) = algorithm {
+                config.algorithm = algorithm;
+            }
+            if let Some(max_connections) = max_connections {
+                config.max_connections = max_connections;
+            }
+            if let Some(limit) = max_per_backend {
+                config.max_per_backend = Some(limit);
+            }
+            config.acl.deny_methods.extend(deny_method);
+            config.acl.deny_paths.extend(deny_path);
+            config.acl.allow_methods.extend(allow_method);
+            config.acl.allow_paths.extend(allow_path);
+
             println!(
                 "Starting load balancer on port {} with servers: {:?}",
-                port, servers
+                config.port, config.servers
             );
-            println!("Using {} algorithm", algorithm);
-            let balancer = LoadBalancer::new(port, servers, &algorithm);
+            println!("Using {} algorithm", config.algorithm);
+            if !config.acl.is_empty() {
+                println!("Filtering requests with {:?}", config.acl);
+            }
+            let mut balancer = LoadBalancer::from_config(&config);
+            if config.algorithm == "least-score" {
+                balancer = balancer.with_algorithm(Algorithm::LeastScore(LeastScore::new(
+                    score_latency_weight,
+                    score_error_weight,
+                )));
+            }
+            if config.algorithm == "header-hash" {
+                println!("Pinning requests by their {} header", hash_header);
+                balancer =
+                    balancer.with_algorithm(Algorithm::HeaderHash(HeaderHash::new(&hash_header)));
+            }
+            if let Some(path) = &servers_file {
+                println!("Reloading servers from {} on SIGHUP", path.display());
+                balancer = balancer.with_servers_file(path);
+            }
+            if let Some(path) = &metrics_out {
+                println!("Writing final metrics to {} on shutdown", path.display());
+                balancer = balancer.with_metrics_out(path);
+            }
+            if let (Some(cert), Some(key)) = (tls_cert, tls_key) {
+                println!("Terminating TLS with certificate {}", cert.display());
+                balancer = balancer
+                    .with_tls(&cert, &key)
+                    .expect("Failed to load TLS certificate and key");
+            }
+            if let Some(ca) = &backend_ca {
+                println!("Dialling https:// backends trusting {}", ca.display());
+                balancer = balancer
+                    .with_backend_tls(ca)
+                    .expect("Failed to load backend CA certificates");
+            } else if backend_insecure {
+                println!("Dialling https:// backends WITHOUT verifying their certificates");
+                balancer = balancer.with_insecure_backend_tls();
+            } else if config.servers.iter().any(|s| s.starts_with(HTTPS_PREFIX)) {
+                eprintln!(
+                    "Warning: https:// backends need --backend-ca or --backend-insecure; \
+                     requests to them will fail"
+                );
+            }
+            if let Some(rate) = rate_limit {
+                println!("Rate limiting clients to {} requests/sec", rate);
+                balancer = balancer.with_rate_limit(rate);
+            }
+            if let Some(ttl) = cache_ttl {
+                println!("Caching GET responses for {}s", ttl);
+                balancer = balancer.with_cache(Duration::from_secs(ttl), DEFAULT_CACHE_ENTRIES);
+            }
+            if coalesce {
+                println!("Coalescing identical in-flight GETs");
+                balancer = balancer.with_coalescing();
+            }
+            if let Some(multiplier) = outlier_multiplier {
+                println!(
+                    "Ejecting backends slower than {}x the pool mean for {}s",
+                    multiplier, outlier_cooldown
+                );
+                balancer = balancer
+                    .with_outlier_detection(multiplier, Duration::from_secs(outlier_cooldown));
+            }
+            if let Some(period) = tune_weights {
+                println!("Tuning weights from observed latency every {}s", period);
+                balancer = balancer.with_weight_tuning(Duration::from_secs(period));
+            }
+            if rewrite_host {
+                println!("Rewriting backend hosts in response headers");
+                balancer = balancer.with_rewrite_host(true);
+            }
+            if no_preserve_host && !preserve_host {
+                println!("Sending each backend's own address as the Host header");
+                balancer = balancer.with_preserve_host(false);
+            }
+            if let Some(wait) = queue_timeout {
+                println!(
+                    "Answering 503 after {}ms waiting for a connection slot",
+                    wait
+                );
+                balancer = balancer.with_queue_timeout(Duration::from_millis(wait));
+            }
+            if let Some(wait) = header_timeout {
+                println!("Closing connections silent for {}ms before a request", wait);
+                balancer = balancer.with_header_timeout(Duration::from_millis(wait));
+            }
+            if let Some(limit) = max_body_size {
+                println!("Rejecting request bodies over {} bytes", limit);
+                balancer = balancer.with_max_body_size(limit);
+            }
+            if let Some(count) = warm_connections {
+                println!("Holding {} warm connections to each backend", count);
+                balancer = balancer.with_warm_connections(count);
+            }
+            if let Some(secs) = idle_timeout {
+                println!("Shutting down after {}s without a connection", secs);
+                balancer = balancer.with_idle_timeout(Duration::from_secs(secs));
+            }
+            if let Some(secs) = ready_timeout {
+                println!("Waiting up to {}s for a reachable backend", secs);
+                balancer = balancer.with_readiness_gate(Duration::from_secs(secs));
+            }
+            if nodelay {
+                println!("Setting TCP_NODELAY on client and backend connections");
+            }
+            if http2 {
+                println!("Passing connections through to backends as HTTP/2");
+            }
+            balancer = balancer
+                .with_nodelay(nodelay)
+                .with_count_by_status(count_by_status)
+                .with_http2(http2)
+                .with_backlog(backlog);
+            if let Some(seed) = seed {
+                balancer = balancer.with_seed(seed);
+            }
+            if let Err(e) = balancer.check_servers().await {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
             balancer.run().await;
         }
         Command::Server {
             port,
             get_delay,
             post_delay,
+            put_delay,
+            delete_delay,
+            unhealthy,
+            error_rate,
+            jitter,
+            path_delays,
+            no_delay,
+            response_body,
+            content_type,
+            idle_timeout,
         } => {
             println!(
-                "Starting server on port {} (GET delay: {}ms, POST delay: {}ms)",
-                port, get_delay, post_delay
+                "Starting server on port {} (GET delay: {}ms, POST delay: {}ms, PUT delay: {}ms, DELETE delay: {}ms)",
+                port, get_delay, post_delay, put_delay, delete_delay
             );
-            let server = Server::new(port, get_delay, post_delay);
+            let mut server = Server::new(port, get_delay, post_delay)
+                .with_put_delay(put_delay)
+                .with_delete_delay(delete_delay)
+                .with_error_rate(error_rate)
+                .with_jitter(jitter)
+                .with_path_delays(path_delays.unwrap_or_default())
+                .with_no_delay(no_delay);
+            if let Some(seed) = seed {
+                server = server.with_seed(seed);
+            }
+            if let Some(body) = &response_body {
+                server = server.with_response_body(body);
+            }
+            if let Some(content_type) = &content_type {
+                server = server.with_content_type(content_type);
+            }
+            if let Some(secs) = idle_timeout {
+                println!("Shutting down after {}s without a connection", secs);
+                server = server.with_idle_timeout(Duration::from_secs(secs));
+            }
+            server.set_healthy(!unhealthy);
             server.run().await;
         }
         Command::Generator { args } => {
-            println!("Starting load generator");
-            let generator = Generator::new(&args.url, args.concurrent_clients, args.get_ratio);
+            if !args.quiet && !json_on_stdout {
+                println!("Starting load generator");
+            }
+            let mut generator = match Generator::from_args(&args) {
+                Ok(generator) => generator,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(seed) = seed {
+                generator = generator.with_seed(seed);
+            }
             generator.run(args.num_requests).await;
         }
     }
@@ -0,0 +1,51 @@
+//! Random number source shared by components that make random choices, so a
+//! run can be reproduced from its seed
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Handle to one `StdRng`; clones draw from the same sequence
+#[derive(Clone)]
+pub struct SharedRng(Arc<Mutex<StdRng>>);
+
+impl Default for SharedRng {
+    fn default() -> Self {
+        Self::from_entropy()
+    }
+}
+
+impl SharedRng {
+    /// Seeded by the OS, so every run differs
+    pub fn from_entropy() -> Self {
+        Self(Arc::new(Mutex::new(StdRng::from_entropy())))
+    }
+
+    /// The same sequence every time for a given `seed`
+    pub fn seeded(seed: u64) -> Self {
+        Self(Arc::new(Mutex::new(StdRng::seed_from_u64(seed))))
+    }
+
+    // A panic mid-draw leaves the generator state valid, so ignore poisoning
+    fn lock(&self) -> MutexGuard<'_, StdRng> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl RngCore for SharedRng {
+    fn next_u32(&mut self) -> u32 {
+        self.lock().next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.lock().next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.lock().fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.lock().try_fill_bytes(dest)
+    }
+}
@@ -1,9 +1,22 @@
+use crate::cli::parse_ratio;
+use crate::rng::SharedRng;
 use clap::Parser;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
 use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
-    time::{sleep, Duration},
+    signal,
+    sync::watch,
+    task::JoinSet,
+    time::{sleep, sleep_until, Duration, Instant},
 };
 
 #[derive(Parser, Debug)]
@@ -20,12 +33,160 @@ pub struct ServerArgs {
     // Delay for POST requests in milliseconds
     #[arg(short = 'p', long, default_value = "500")]
     pub post_delay: u64,
+
+    // Delay for PUT requests in milliseconds
+    #[arg(short = 'u', long, default_value = "500")]
+    pub put_delay: u64,
+
+    // Delay for DELETE requests in milliseconds
+    #[arg(short = 'd', long, default_value = "1000")]
+    pub delete_delay: u64,
+
+    // Report unhealthy (503) on /health
+    #[arg(long)]
+    pub unhealthy: bool,
+
+    // Fraction of requests (0.0-1.0) answered with 500
+    #[arg(short = 'e', long, default_value = "0.0", value_parser = parse_ratio)]
+    pub error_rate: f64,
+
+    // Randomly vary each delay by up to this percentage in either direction
+    #[arg(short = 'j', long, default_value = "0.0")]
+    pub jitter: f64,
+
+    // Per-path delays overriding the method delays, e.g. `/slow=2000,/fast=10`
+    #[arg(long = "delay")]
+    pub path_delays: Option<PathDelays>,
+
+    // Respond immediately, ignoring every configured delay
+    #[arg(long)]
+    pub no_delay: bool,
+
+    // Body for GET/DELETE responses instead of "Request Received of type: ..."
+    #[arg(long)]
+    pub response_body: Option<String>,
+
+    // Content-Type header to send with each response
+    #[arg(long)]
+    pub content_type: Option<String>,
+
+    // Shut down after this many seconds without a new connection
+    #[arg(long)]
+    pub idle_timeout: Option<u64>,
+}
+
+/// Delays in milliseconds for specific request paths, taking precedence over
+/// the per-method delays
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PathDelays {
+    rules: Vec<(String, u64)>,
+}
+
+impl PathDelays {
+    /// Delay for `path` (query string ignored), if a rule names it
+    pub fn delay_for(&self, path: &str) -> Option<u64> {
+        let path = path.split('?').next().unwrap_or(path);
+        self.rules
+            .iter()
+            .find(|(rule, _)| rule == path)
+            .map(|(_, delay)| *delay)
+    }
+}
+
+impl FromStr for PathDelays {
+    type Err = String;
+
+    /// Parse `PATH=milliseconds` pairs
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules: Vec<(String, u64)> = Vec::new();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (path, delay) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("invalid delay entry '{}': expected PATH=ms", pair))?;
+            let path = path.trim();
+            if !path.starts_with('/') {
+                return Err(format!("delay path '{}' must start with '/'", path));
+            }
+            let delay: u64 = delay
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid delay '{}' for {}", delay, path))?;
+            if rules.iter().any(|(rule, _)| rule == path) {
+                return Err(format!("{} appears more than once in delays", path));
+            }
+            rules.push((path.to_string(), delay));
+        }
+        Ok(Self { rules })
+    }
+}
+
+/// Requests a `Server` has answered, by method, as served at `GET /stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct ServerStats {
+    pub get: usize,
+    pub post: usize,
+    pub put: usize,
+    pub delete: usize,
+    pub other: usize,
+}
+
+impl ServerStats {
+    pub fn total(&self) -> usize {
+        self.get + self.post + self.put + self.delete + self.other
+    }
+}
+
+// Live counters behind `ServerStats`
+#[derive(Default)]
+struct RequestCounters {
+    get: AtomicUsize,
+    post: AtomicUsize,
+    put: AtomicUsize,
+    delete: AtomicUsize,
+    other: AtomicUsize,
 }
 
+impl RequestCounters {
+    fn record(&self, method: &str) {
+        let counter = match method {
+            "GET" => &self.get,
+            "POST" => &self.post,
+            "PUT" => &self.put,
+            "DELETE" => &self.delete,
+            _ => &self.other,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> ServerStats {
+        ServerStats {
+            get: self.get.load(Ordering::Relaxed),
+            post: self.post.load(Ordering::Relaxed),
+            put: self.put.load(Ordering::Relaxed),
+            delete: self.delete.load(Ordering::Relaxed),
+            other: self.other.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Server {
     port: u16,
     get_delay: u64,
     post_delay: u64,
+    put_delay: u64,
+    delete_delay: u64,
+    healthy: Arc<AtomicBool>,
+    error_rate: f64,
+    jitter: f64,
+    path_delays: PathDelays,
+    no_delay: bool,
+    requests: Arc<RequestCounters>,
+    rng: SharedRng,
+    response_body: Option<String>,
+    content_type: Option<String>,
+    idle_timeout: Option<Duration>,
 }
 
 impl Server {
@@ -34,66 +195,424 @@ impl Server {
             port,
             get_delay,
             post_delay,
+            put_delay: post_delay,
+            delete_delay: get_delay,
+            healthy: Arc::new(AtomicBool::new(true)),
+            error_rate: 0.0,
+            jitter: 0.0,
+            path_delays: PathDelays::default(),
+            no_delay: false,
+            requests: Arc::new(RequestCounters::default()),
+            rng: SharedRng::default(),
+            response_body: None,
+            content_type: None,
+            idle_timeout: None,
         }
     }
 
+    /// Delay for PUT requests in milliseconds (defaults to the POST delay)
+    pub fn with_put_delay(mut self, put_delay: u64) -> Self {
+        self.put_delay = put_delay;
+        self
+    }
+
+    /// Delay for DELETE requests in milliseconds (defaults to the GET delay)
+    pub fn with_delete_delay(mut self, delete_delay: u64) -> Self {
+        self.delete_delay = delete_delay;
+        self
+    }
+
+    /// Answer this fraction of requests (0.0-1.0) with a 500 after the delay
+    pub fn with_error_rate(mut self, error_rate: f64) -> Self {
+        self.error_rate = error_rate;
+        self
+    }
+
+    /// Vary each delay randomly by up to `jitter` percent either way
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Delay requests to specific paths instead of using the method delay
+    pub fn with_path_delays(mut self, path_delays: PathDelays) -> Self {
+        self.path_delays = path_delays;
+        self
+    }
+
+    /// Skip all delays so throughput is bounded only by request handling
+    pub fn with_no_delay(mut self, no_delay: bool) -> Self {
+        self.no_delay = no_delay;
+        self
+    }
+
+    /// Answer GETs and DELETEs with `body` instead of naming the method;
+    /// POST and PUT bodies are still echoed
+    pub fn with_response_body(mut self, body: &str) -> Self {
+        self.response_body = Some(body.to_string());
+        self
+    }
+
+    /// Send `content_type` as the `Content-Type` of every response other than
+    /// `/health` and `/stats`
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// Shut down gracefully once `idle_timeout` passes without a new
+    /// connection, so a forgotten server doesn't run forever
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Seed jitter and simulated failures so runs are reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = SharedRng::seeded(seed);
+        self
+    }
+
+    // Scale a delay by a random factor in `1 ± jitter%`
+    fn jittered(&self, delay_ms: u64) -> Duration {
+        let spread = (self.jitter / 100.0).clamp(0.0, 1.0);
+        if spread == 0.0 {
+            return Duration::from_millis(delay_ms);
+        }
+        let factor = 1.0 + self.rng.clone().gen_range(-spread..=spread);
+        Duration::from_secs_f64(delay_ms as f64 * factor / 1000.0)
+    }
+
+    /// Requests answered so far by method, leaving out `/health` and `/stats`
+    pub fn stats(&self) -> ServerStats {
+        self.requests.snapshot()
+    }
+
+    /// Toggle what `GET /health` reports; takes effect on a running server too
+    pub fn set_healthy(&self, healthy: bool) {
+        self.healthy.store(healthy, Ordering::Relaxed);
+    }
+
+    /// Serve until Ctrl-C, then shut down gracefully
     pub async fn run(&self) {
+        self.run_until(async {
+            let _ = signal::ctrl_c().await;
+        })
+        .await;
+    }
+
+    /// Serve until `shutdown` completes, then stop accepting connections and
+    /// return once requests already in progress have been answered
+    pub async fn run_until<F>(&self, shutdown: F)
+    where
+        F: Future<Output = ()>,
+    {
         // Bind to localhost
         let addr = SocketAddr::from(([127, 0, 0, 1], self.port));
         let listener = TcpListener::bind(addr).await.unwrap();
+        self.run_with_listener_until(listener, shutdown).await;
+    }
+
+    /// Serve connections accepted on an already-bound `listener` (e.g. one
+    /// bound to port 0) until Ctrl-C
+    pub async fn run_with_listener(&self, listener: TcpListener) {
+        self.run_with_listener_until(listener, async {
+            let _ = signal::ctrl_c().await;
+        })
+        .await;
+    }
+
+    /// `run_until` on an already-bound `listener`
+    pub async fn run_with_listener_until<F>(&self, listener: TcpListener, shutdown: F)
+    where
+        F: Future<Output = ()>,
+    {
+        let addr = listener.local_addr().unwrap();
         println!("Server listening on {}", addr);
 
+        let this = Arc::new(self.clone());
+        let (stop, stopping) = watch::channel(false);
+        let mut connections = JoinSet::new();
+        let mut last_accept = Instant::now();
+        tokio::pin!(shutdown);
         loop {
-            // Accept connection
-            let (socket, _) = listener.accept().await.unwrap();
-            let get_delay = self.get_delay;
-            let post_delay = self.post_delay;
-
-            // Spawn new task to handle connection
-            tokio::spawn(async move {
-                Self::handle_connection(socket, get_delay, post_delay).await;
-            });
+            let idle_deadline = last_accept + self.idle_timeout.unwrap_or_default();
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (socket, _) = accepted.unwrap();
+                    last_accept = Instant::now();
+                    let this = Arc::clone(&this);
+                    let stopping = stopping.clone();
+
+                    // Spawn new task to handle connection
+                    connections.spawn(async move {
+                        this.handle_connection(socket, stopping).await;
+                    });
+                }
+                // Reap finished connections as they go
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                _ = &mut shutdown => break,
+                _ = sleep_until(idle_deadline), if self.idle_timeout.is_some() => {
+                    println!("No connections for {:?}", self.idle_timeout.unwrap());
+                    break;
+                }
+            }
         }
+
+        drop(listener);
+        println!(
+            "Server on {} shutting down, finishing {} connections",
+            addr,
+            connections.len()
+        );
+        let _ = stop.send(true);
+        while connections.join_next().await.is_some() {}
     }
 
-    async fn handle_connection(mut socket: TcpStream, get_delay: u64, post_delay: u64) {
-        // Buffer to read request from socket
-        let mut buffer = [0; 1024];
+    // Read one request off the connection: the head and its body, framed by
+    // Content-Length or chunked encoding (handed back decoded). Bytes past the
+    // request stay in `pending` for the next one. The flag is false when the
+    // request's end couldn't be found, so the connection has to close after it.
+    async fn read_request(
+        socket: &mut TcpStream,
+        pending: &mut Vec<u8>,
+    ) -> Option<(Vec<u8>, bool)> {
+        let mut chunk = [0; 1024];
+        loop {
+            if let Some(head_end) = pending.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head_end = head_end + 4;
+                let head = String::from_utf8_lossy(&pending[..head_end]).to_string();
+                let header = |name: &str| {
+                    head.lines()
+                        .skip(1)
+                        .filter_map(|line| line.split_once(':'))
+                        .find(|(n, _)| n.trim().eq_ignore_ascii_case(name))
+                        .map(|(_, value)| value.trim().to_string())
+                };
+                match header("transfer-encoding") {
+                    Some(coding) if coding.eq_ignore_ascii_case("chunked") => {
+                        match Self::decode_chunked(&pending[head_end..]) {
+                            Ok(Some((body, len))) => {
+                                let mut request: Vec<u8> =
+                                    pending.drain(..head_end + len).collect();
+                                request.truncate(head_end);
+                                request.extend_from_slice(&body);
+                                return Some((request, true));
+                            }
+                            Ok(None) => {}
+                            Err(()) => return Some((std::mem::take(pending), false)),
+                        }
+                    }
+                    // Some other coding, whose end can't be told
+                    Some(_) => return Some((std::mem::take(pending), false)),
+                    None => {
+                        let content_length = match header("content-length") {
+                            Some(value) => match value.parse::<usize>() {
+                                Ok(len) => len,
+                                Err(_) => return Some((std::mem::take(pending), false)),
+                            },
+                            None => 0,
+                        };
+                        if pending.len() >= head_end + content_length {
+                            let request = pending.drain(..head_end + content_length).collect();
+                            return Some((request, true));
+                        }
+                    }
+                }
+            }
 
-        // Read request from socket
-        let n = match socket.read(&mut buffer).await {
-            Ok(n) if n == 0 => return,
-            Ok(n) => n,
-            Err(_) => return,
+            let n = socket.read(&mut chunk).await.ok()?;
+            if n == 0 {
+                // Whatever arrived before the client closed is all there is
+                return (!pending.is_empty()).then(|| (std::mem::take(pending), false));
+            }
+            pending.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    // Decode the chunked body at the start of `body` once it has all arrived,
+    // returning the payload and how many encoded bytes it took up
+    fn decode_chunked(body: &[u8]) -> Result<Option<(Vec<u8>, usize)>, ()> {
+        let line_end = |from: usize| {
+            body[from..]
+                .windows(2)
+                .position(|w| w == b"\r\n")
+                .map(|i| from + i)
         };
+        let mut payload = Vec::new();
+        let mut pos = 0;
+        loop {
+            let Some(end) = line_end(pos) else {
+                return Ok(None);
+            };
+            let size = std::str::from_utf8(&body[pos..end])
+                .ok()
+                .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+                .ok_or(())?;
+            pos = end + 2;
+            if size == 0 {
+                // Skip any trailer fields, up to the empty line ending them
+                loop {
+                    let Some(end) = line_end(pos) else {
+                        return Ok(None);
+                    };
+                    if end == pos {
+                        return Ok(Some((payload, pos + 2)));
+                    }
+                    pos = end + 2;
+                }
+            }
+            let data_end = pos.checked_add(size).ok_or(())?;
+            if body.len() < data_end + 2 {
+                return Ok(None);
+            }
+            if &body[data_end..data_end + 2] != b"\r\n" {
+                return Err(());
+            }
+            payload.extend_from_slice(&body[pos..data_end]);
+            pos = data_end + 2;
+        }
+    }
+
+    // Serve requests on one connection until the client asks to close it or
+    // the server is stopping. Idle connections close as soon as it stops; a
+    // request already being handled is answered first.
+    async fn handle_connection(&self, mut socket: TcpStream, mut stopping: watch::Receiver<bool>) {
+        // Bytes read past the end of the last request, such as a pipelined one
+        let mut pending = Vec::new();
+        loop {
+            let request = tokio::select! {
+                request = Self::read_request(&mut socket, &mut pending) => request,
+                _ = stopping.wait_for(|stop| *stop) => break,
+            };
+            let Some((buffer, framed)) = request else {
+                break;
+            };
+            let (response, keep_alive) = self.respond(&buffer, framed, &stopping).await;
+
+            if socket.write_all(response.as_bytes()).await.is_err() || !keep_alive {
+                break;
+            }
+        }
+        let _ = socket.shutdown().await;
+    }
+
+    // HTTP/1.1 connections stay open unless the request says `Connection: close`
+    fn wants_keep_alive(buffer: &[u8]) -> bool {
+        let request = String::from_utf8_lossy(buffer);
+        let head = request.split("\r\n\r\n").next().unwrap_or("");
+        let mut lines = head.lines();
+        let http11 = lines.next().is_some_and(|line| line.ends_with("HTTP/1.1"));
+        let close = lines
+            .filter_map(|line| line.split_once(':'))
+            .any(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("connection")
+                    && value.trim().eq_ignore_ascii_case("close")
+            });
+        http11 && !close
+    }
 
+    // Build the response, and whether the connection stays open after it.
+    // It can't once a request arrived whose end wasn't `framed`.
+    async fn respond(
+        &self,
+        buffer: &[u8],
+        framed: bool,
+        stopping: &watch::Receiver<bool>,
+    ) -> (String, bool) {
         // Convert buffer to string
-        let request = String::from_utf8_lossy(&buffer[..n]);
+        let request = String::from_utf8_lossy(buffer);
+        let body = request.split_once("\r\n\r\n").map_or("", |(_, body)| body);
 
         // Get first line of request
         let first_line = request.lines().next().unwrap_or("");
-        let (method, _) = first_line.split_once(' ').unwrap_or(("", ""));
+        let mut parts = first_line.split(' ');
+        let method = parts.next().unwrap_or("");
+        let path = parts.next().unwrap_or("");
+        let health_request = method == "GET" && path == "/health";
+        let stats_request = method == "GET" && path == "/stats";
 
-        // Sleep for delay based on method
-        match method {
-            "GET" => sleep(Duration::from_millis(get_delay)).await,
-            "POST" => sleep(Duration::from_millis(post_delay)).await,
-            _ => {}
+        let (status, msg) = if health_request {
+            // Health checks answer immediately, skipping the artificial delay
+            if self.healthy.load(Ordering::Relaxed) {
+                ("200 OK", "healthy".to_string())
+            } else {
+                ("503 Service Unavailable", "unhealthy".to_string())
+            }
+        } else if stats_request {
+            // Counts so far, not including this request
+            let stats = serde_json::to_string(&self.stats()).unwrap_or_default();
+            ("200 OK", stats)
+        } else {
+            self.requests.record(method);
+
+            // Sleep for delay based on path, falling back to the method
+            let delay = self.path_delays.delay_for(path).or(match method {
+                "GET" => Some(self.get_delay),
+                "POST" => Some(self.post_delay),
+                "PUT" => Some(self.put_delay),
+                "DELETE" => Some(self.delete_delay),
+                _ => None,
+            });
+            match delay {
+                Some(delay) if !self.no_delay => sleep(self.jittered(delay)).await,
+                _ => {}
+            }
+
+            // Simulate an intermittent failure after the usual delay
+            if self.rng.clone().gen::<f64>() < self.error_rate {
+                (
+                    "500 Internal Server Error",
+                    "Internal Server Error".to_string(),
+                )
+            } else if method == "POST" || method == "PUT" {
+                // Echo the body so callers can verify it arrived intact
+                ("200 OK", body.to_string())
+            } else {
+                let body = match &self.response_body {
+                    Some(body) => body.clone(),
+                    None => format!("Request Received of type: {}", method),
+                };
+                ("200 OK", body)
+            }
+        };
+
+        // Echo the request ID so responses can be matched to balancer logs
+        let request_id = request
+            .split("\r\n\r\n")
+            .next()
+            .unwrap_or("")
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("x-request-id"))
+            .map(|(_, value)| format!("X-Request-Id: {}\r\n", value.trim()))
+            .unwrap_or_default();
+
+        // A shutdown that started during the delay closes the connection
+        let keep_alive = framed && Self::wants_keep_alive(buffer) && !*stopping.borrow();
+
+        let content_type = if stats_request {
+            Some("application/json")
+        } else if health_request {
+            None
+        } else {
+            self.content_type.as_deref()
         }
+        .map(|content_type| format!("Content-Type: {}\r\n", content_type))
+        .unwrap_or_default();
 
         // Response message
-        let msg = format!("Request Received of type: {}", method);
         let response = format!(
-            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            "HTTP/1.1 {}\r\nConnection: {}\r\n{}{}Content-Length: {}\r\n\r\n{}",
+            status,
+            if keep_alive { "keep-alive" } else { "close" },
+            request_id,
+            content_type,
             msg.len(),
             msg
         );
-
-        // Write response and shutdown
-        if let Ok(()) = socket.write_all(response.as_bytes()).await {
-            let _ = socket.shutdown().await;
-        }
-        drop(socket);
+        (response, keep_alive)
     }
 }
 
@@ -101,6 +620,22 @@ impl Server {
 #[allow(dead_code)]
 async fn main() {
     let args = ServerArgs::parse();
-    let server = Server::new(args.port, args.get_delay, args.post_delay);
+    let mut server = Server::new(args.port, args.get_delay, args.post_delay)
+        .with_put_delay(args.put_delay)
+        .with_delete_delay(args.delete_delay)
+        .with_error_rate(args.error_rate)
+        .with_jitter(args.jitter)
+        .with_path_delays(args.path_delays.unwrap_or_default())
+        .with_no_delay(args.no_delay);
+    if let Some(body) = &args.response_body {
+        server = server.with_response_body(body);
+    }
+    if let Some(content_type) = &args.content_type {
+        server = server.with_content_type(content_type);
+    }
+    if let Some(secs) = args.idle_timeout {
+        server = server.with_idle_timeout(Duration::from_secs(secs));
+    }
+    server.set_healthy(!args.unhealthy);
     server.run().await;
 }
@@ -9,7 +9,7 @@ GET_DELAY=200
 # POST request delay
 POST_DELAY=100
 # Load balancing algorithm
-# Available algorithms: round-robin, least-connections, ip-hash, weighted-round-robin
+# Available algorithms: round-robin, least-connections, ip-hash, weighted-round-robin, weighted-least-connections, consistent-hash, random, ewma
 LB_ALGORITHM="round-robin"
 
 # Test Loads
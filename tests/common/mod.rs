@@ -0,0 +1,466 @@
+// Fixtures shared by the integration tests; each test crate uses a subset
+#![allow(dead_code)]
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::time::{sleep, Duration};
+use tokio_rustls::rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+pub const OK_RESPONSE: &[u8] =
+    b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok";
+
+// 200 response carrying `body`, closing the connection after it
+pub fn ok_response(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+// Hand each connection `listener` accepts to `serve`, counting them
+fn serve<F, Fut>(listener: TcpListener, serve: F) -> (Arc<AtomicUsize>, tokio::task::JoinHandle<()>)
+where
+    F: Fn(TcpStream) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&connections);
+    let handle = tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(serve(socket));
+        }
+    });
+    (connections, handle)
+}
+
+// Backend that hands each connection it accepts to `serve`, counting them
+pub async fn spawn_backend<F, Fut>(
+    serve_connection: F,
+) -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>)
+where
+    F: Fn(TcpStream) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    spawn_backend_at("127.0.0.1:0", serve_connection).await
+}
+
+// `spawn_backend` listening on `addr`, which may be an IPv6 one like `[::1]:0`
+pub async fn spawn_backend_at<F, Fut>(
+    addr: &str,
+    serve_connection: F,
+) -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>)
+where
+    F: Fn(TcpStream) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (connections, handle) = serve(listener, serve_connection);
+    (addr, connections, handle)
+}
+
+// Backend that reads one request per connection and answers it with
+// `reply`, given the backend's own address and the request, then closes.
+// Counts the requests.
+pub async fn spawn_replying_backend<F>(
+    reply: F,
+) -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>)
+where
+    F: Fn(&str, &str) -> String + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let own_addr = Arc::new(addr.clone());
+    let reply = Arc::new(reply);
+    let (requests, handle) = serve(listener, move |mut socket| {
+        let (own_addr, reply) = (Arc::clone(&own_addr), Arc::clone(&reply));
+        async move {
+            let mut buffer = [0; 4096];
+            let n = socket.read(&mut buffer).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buffer[..n]);
+            let response = reply(&own_addr, &request);
+            let _ = socket.write_all(response.as_bytes()).await;
+            let _ = socket.shutdown().await;
+        }
+    });
+    (addr, requests, handle)
+}
+
+// Read one request or response head off `socket`, or None once it closes
+pub async fn read_head(socket: &mut TcpStream) -> Option<Vec<u8>> {
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if socket.read(&mut byte).await.ok()? == 0 {
+            return None;
+        }
+        head.push(byte[0]);
+    }
+    Some(head)
+}
+
+// Backend that answers every request with 200
+pub async fn spawn_ok_backend() -> (String, tokio::task::JoinHandle<()>) {
+    let (addr, _, _, handle) = spawn_delayed_backend(Duration::ZERO).await;
+    (addr, handle)
+}
+
+// Backend on `addr` that answers every request with 200
+pub async fn spawn_ok_backend_at(addr: &str) -> tokio::task::JoinHandle<()> {
+    let listener = TcpListener::bind(addr).await.unwrap();
+    serve_delayed(listener, Duration::ZERO).2
+}
+
+// Backend that answers every request with 200 and counts them
+pub async fn spawn_counting_backend() -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>) {
+    let (addr, hits, _, handle) = spawn_delayed_backend(Duration::ZERO).await;
+    (addr, hits, handle)
+}
+
+// Backend that waits `delay` before answering 200, counting requests and the
+// most it was ever handling at once
+pub async fn spawn_delayed_backend(
+    delay: Duration,
+) -> (
+    String,
+    Arc<AtomicUsize>,
+    Arc<AtomicUsize>,
+    tokio::task::JoinHandle<()>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (hits, peak, handle) = serve_delayed(listener, delay);
+    (addr, hits, peak, handle)
+}
+
+fn serve_delayed(
+    listener: TcpListener,
+    delay: Duration,
+) -> (
+    Arc<AtomicUsize>,
+    Arc<AtomicUsize>,
+    tokio::task::JoinHandle<()>,
+) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let active = Arc::new(AtomicUsize::new(0));
+    let (hit_counter, peak_counter) = (Arc::clone(&hits), Arc::clone(&peak));
+    let (_, handle) = serve(listener, move |mut socket| {
+        let (hits, peak, active) = (
+            Arc::clone(&hit_counter),
+            Arc::clone(&peak_counter),
+            Arc::clone(&active),
+        );
+        async move {
+            let mut buffer = [0; 4096];
+            if socket.read(&mut buffer).await.unwrap_or(0) == 0 {
+                return;
+            }
+            hits.fetch_add(1, Ordering::SeqCst);
+            let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            sleep(delay).await;
+            active.fetch_sub(1, Ordering::SeqCst);
+            let _ = socket.write_all(OK_RESPONSE).await;
+            let _ = socket.shutdown().await;
+        }
+    });
+    (hits, peak, handle)
+}
+
+// Backend that holds each request for `delay`, then answers 200 leaving the
+// connection open
+pub async fn spawn_holding_backend(delay: Duration) -> (String, tokio::task::JoinHandle<()>) {
+    let (addr, _, handle) = spawn_backend(move |mut socket| async move {
+        let mut buffer = [0; 4096];
+        let _ = socket.read(&mut buffer).await;
+        sleep(delay).await;
+        let _ = socket
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            .await;
+    })
+    .await;
+    (addr, handle)
+}
+
+// Backend that accepts connections and holds them open without reading,
+// counting each one
+pub async fn spawn_accepting_backend() -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>) {
+    let held = Arc::new(Mutex::new(Vec::new()));
+    spawn_backend(move |socket| {
+        held.lock().unwrap().push(socket);
+        async {}
+    })
+    .await
+}
+
+// Keep-alive backend that counts the connections it accepts, closing one
+// only when a request asks it to
+pub async fn spawn_keep_alive_backend() -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>) {
+    spawn_backend(|mut socket| async move {
+        let mut buffer = [0; 4096];
+        while let Ok(n) = socket.read(&mut buffer).await {
+            if n == 0 {
+                break;
+            }
+            let request = String::from_utf8_lossy(&buffer[..n]).to_ascii_lowercase();
+            let close = request.contains("connection: close");
+            let response: &[u8] = if close {
+                OK_RESPONSE
+            } else {
+                b"HTTP/1.1 200 OK\r\nConnection: keep-alive\r\nContent-Length: 2\r\n\r\nok"
+            };
+            if socket.write_all(response).await.is_err() || close {
+                break;
+            }
+        }
+    })
+    .await
+}
+
+// Keep-alive backend answering every request head with `response`, counting
+// the connections it accepts
+pub async fn spawn_keep_alive_backend_with(
+    response: Vec<u8>,
+) -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>) {
+    let response = Arc::new(response);
+    spawn_backend(move |mut socket| {
+        let response = Arc::clone(&response);
+        async move {
+            while read_head(&mut socket).await.is_some() {
+                if socket.write_all(&response).await.is_err() {
+                    return;
+                }
+            }
+        }
+    })
+    .await
+}
+
+// Backend that replies with the raw request head it received
+pub async fn spawn_echo_backend() -> (String, tokio::task::JoinHandle<()>) {
+    let (addr, _, handle) = spawn_replying_backend(|_, request| ok_response(request)).await;
+    (addr, handle)
+}
+
+// Backend that replies with its own address so tests can tell backends apart
+pub async fn spawn_named_backend() -> (String, tokio::task::JoinHandle<()>) {
+    let (addr, _, handle) = spawn_replying_backend(|own_addr, _| ok_response(own_addr)).await;
+    (addr, handle)
+}
+
+// Backend that answers every request with `body`
+pub async fn spawn_body_backend(body: &'static str) -> (String, tokio::task::JoinHandle<()>) {
+    let (addr, _, handle) = spawn_replying_backend(move |_, _| ok_response(body)).await;
+    (addr, handle)
+}
+
+// Backend that answers every request with 500
+pub async fn spawn_failing_backend() -> (String, tokio::task::JoinHandle<()>) {
+    let (addr, _, handle) = spawn_replying_backend(|_, _| {
+        "HTTP/1.1 500 Internal Server Error\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+            .to_string()
+    })
+    .await;
+    (addr, handle)
+}
+
+// Backend answering 200 with headers that depend on the path, naming itself
+// in `Content-Location`, and counting the requests it gets
+pub async fn spawn_header_backend() -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>) {
+    spawn_replying_backend(|own_addr, request| {
+        let path = request.split(' ').nth(1).unwrap_or("/");
+        let extra = match path {
+            "/cookie" => "Set-Cookie: sid=abc; Path=/\r\n",
+            "/private" => "Cache-Control: private, max-age=60\r\n",
+            "/no-store" => "Cache-Control: no-store\r\n",
+            _ => "Cache-Control: max-age=60\r\n",
+        };
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Location: http://{}{}\r\n{}Connection: close\r\nContent-Length: 2\r\n\r\nok",
+            own_addr, path, extra
+        )
+    })
+    .await
+}
+
+// Backend that answers the first `unavailable` requests with 503, then 200.
+// Returns its URL.
+pub async fn spawn_unavailable_backend(
+    unavailable: usize,
+) -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>) {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+    let (addr, _, handle) = spawn_replying_backend(move |_, _| {
+        if counter.fetch_add(1, Ordering::SeqCst) < unavailable {
+            "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+                .to_string()
+        } else {
+            ok_response("ok")
+        }
+    })
+    .await;
+    (format!("http://{}", addr), requests, handle)
+}
+
+// Backend that drops the first `failures` connections, then answers 200,
+// recording when each connection arrived. Returns its URL.
+pub async fn spawn_flaky_backend(
+    failures: usize,
+) -> (
+    String,
+    Arc<tokio::sync::Mutex<Vec<Instant>>>,
+    tokio::task::JoinHandle<()>,
+) {
+    let attempts = Arc::new(tokio::sync::Mutex::new(Vec::new()));
+    let seen = Arc::clone(&attempts);
+    let (addr, _, handle) = spawn_backend(move |mut socket| {
+        let seen = Arc::clone(&seen);
+        async move {
+            let mut seen = seen.lock().await;
+            seen.push(Instant::now());
+            if seen.len() <= failures {
+                return;
+            }
+            let mut buffer = [0; 4096];
+            let _ = socket.read(&mut buffer).await;
+            let _ = socket.write_all(OK_RESPONSE).await;
+            let _ = socket.shutdown().await;
+        }
+    })
+    .await;
+    (format!("http://{}", addr), attempts, handle)
+}
+
+// Backend that records the first `expected` bytes it receives and answers
+// them with `reply`, holding the connection until the client hangs up
+pub async fn spawn_recording_backend(
+    expected: usize,
+    reply: &'static [u8],
+) -> (
+    String,
+    oneshot::Receiver<Vec<u8>>,
+    tokio::task::JoinHandle<()>,
+) {
+    let (sender, received) = oneshot::channel();
+    let sender = Arc::new(Mutex::new(Some(sender)));
+    let (addr, _, handle) = spawn_backend(move |mut socket| {
+        let sender = Arc::clone(&sender);
+        async move {
+            let mut bytes = vec![0; expected];
+            socket.read_exact(&mut bytes).await.unwrap();
+            socket.write_all(reply).await.unwrap();
+            if let Some(sender) = sender.lock().unwrap().take() {
+                let _ = sender.send(bytes);
+            }
+            let mut rest = [0; 64];
+            while socket.read(&mut rest).await.unwrap_or(0) > 0 {}
+        }
+    })
+    .await;
+    (addr, received, handle)
+}
+
+// Acceptor with a fresh self-signed certificate for localhost
+fn tls_acceptor() -> (TlsAcceptor, rcgen::CertifiedKey) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![certified.cert.der().clone()], key)
+        .unwrap();
+    (TlsAcceptor::from(Arc::new(config)), certified)
+}
+
+// Backend that only speaks TLS, with a self-signed certificate for
+// localhost, replying with the request head it decrypted
+pub async fn spawn_tls_backend() -> (String, rcgen::CertifiedKey, tokio::task::JoinHandle<()>) {
+    spawn_tls_backend_with(|_, request| ok_response(request)).await
+}
+
+// TLS backend answering the request it decrypts on each connection with
+// `reply`, given its own `localhost:port` address and the request
+pub async fn spawn_tls_backend_with(
+    reply: fn(&str, &str) -> String,
+) -> (String, rcgen::CertifiedKey, tokio::task::JoinHandle<()>) {
+    let (acceptor, certified) = tls_acceptor();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = format!("localhost:{}", listener.local_addr().unwrap().port());
+    let own_addr = Arc::new(addr.clone());
+    let (_, handle) = serve(listener, move |socket| {
+        let (acceptor, own_addr) = (acceptor.clone(), Arc::clone(&own_addr));
+        async move {
+            let Ok(mut stream) = acceptor.accept(socket).await else {
+                return;
+            };
+            let mut buffer = [0; 4096];
+            let n = stream.read(&mut buffer).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buffer[..n]);
+            let response = reply(&own_addr, &request);
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+    });
+    (addr, certified, handle)
+}
+
+// TLS 1.3 backend that keeps each connection open across requests, counting
+// the connections it accepts
+pub async fn spawn_keep_alive_tls_backend(
+) -> (String, Arc<AtomicUsize>, tokio::task::JoinHandle<()>) {
+    let (acceptor, _) = tls_acceptor();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = format!("localhost:{}", listener.local_addr().unwrap().port());
+    let (accepted, handle) = serve(listener, move |socket| {
+        let acceptor = acceptor.clone();
+        async move {
+            let Ok(mut stream) = acceptor.accept(socket).await else {
+                return;
+            };
+            let mut buffer = [0; 4096];
+            while let Ok(n) = stream.read(&mut buffer).await {
+                if n == 0 {
+                    return;
+                }
+                let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+                if stream.write_all(response).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    (addr, accepted, handle)
+}
+
+// Body of a raw response, after its head
+pub fn body(response: &str) -> &str {
+    response.split("\r\n\r\n").nth(1).unwrap_or("")
+}
+
+// Send a raw request through the balancer and return the full response,
+// waiting for it to start listening first. The write half is closed after
+// the request, so requests the balancer can't delimit still end.
+pub async fn send_raw(port: u16, request: &str) -> String {
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", port)).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    stream.write_all(request.as_bytes()).await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
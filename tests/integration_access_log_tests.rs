@@ -0,0 +1,86 @@
+mod common;
+
+use common::spawn_ok_backend;
+use rust_load_balancer::balancer::{LoadBalancer, ACCESS_LOG_TARGET};
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, Duration};
+
+// Log sink shared between the subscriber and the test
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+const BACKEND_RESPONSE: &[u8] =
+    b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok";
+
+#[tokio::test]
+async fn test_access_log_records_request() {
+    let capture = Capture::default();
+    let writer = capture.clone();
+    tracing_subscriber::fmt()
+        .json()
+        .with_writer(move || writer.clone())
+        .init();
+
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_ok_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", load_balancer_port)).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    stream
+        .write_all(b"GET /items?id=7 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+    // The log line is written after the response is flushed
+    sleep(Duration::from_millis(100)).await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+    let entry: serde_json::Value = output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|entry| entry["target"] == ACCESS_LOG_TARGET)
+        .expect("no access log line");
+
+    assert!(entry["timestamp"].is_string());
+    let fields = &entry["fields"];
+    assert!(fields["client"].as_str().unwrap().starts_with("127.0.0.1:"));
+    assert_eq!(fields["method"], "GET");
+    assert_eq!(fields["path"], "/items?id=7");
+    assert_eq!(fields["request_id"].as_str().unwrap().len(), 36);
+    assert_eq!(fields["backend"], backend.as_str());
+    assert_eq!(fields["status"], 200);
+    assert_eq!(fields["bytes_out"], BACKEND_RESPONSE.len());
+    assert!(fields["bytes_in"].as_u64().unwrap() > 0);
+    assert!(fields["duration_ms"].as_f64().unwrap() >= 0.0);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{send_raw, spawn_ok_backend};
+use rust_load_balancer::balancer::{AccessControl, LoadBalancer};
+use rust_load_balancer::config::Config;
+
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_denied_path_gets_forbidden() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_ok_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_acl(
+            AccessControl::new()
+                .with_denied_path("/admin")
+                .with_denied_method("DELETE"),
+        );
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // The balancer's own admin endpoints are covered too
+    let reset = send_raw(
+        load_balancer_port,
+        "POST /admin/metrics/reset HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+    )
+    .await;
+    let nested = send_raw(load_balancer_port, "GET /admin/users?id=1 HTTP/1.1\r\n\r\n").await;
+    let deleted = send_raw(load_balancer_port, "DELETE /items/1 HTTP/1.1\r\n\r\n").await;
+    let allowed = send_raw(load_balancer_port, "GET /items/1 HTTP/1.1\r\n\r\n").await;
+    // Only whole path segments match the prefix
+    let lookalike = send_raw(load_balancer_port, "GET /administrator HTTP/1.1\r\n\r\n").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(reset.starts_with("HTTP/1.1 403 Forbidden"), "{}", reset);
+    assert!(nested.starts_with("HTTP/1.1 403 Forbidden"), "{}", nested);
+    assert!(deleted.starts_with("HTTP/1.1 403 Forbidden"), "{}", deleted);
+    assert!(allowed.starts_with("HTTP/1.1 200 OK"), "{}", allowed);
+    assert!(lookalike.starts_with("HTTP/1.1 200 OK"), "{}", lookalike);
+}
+
+#[test]
+fn test_allow_lists_refuse_everything_else() {
+    let acl = AccessControl::new()
+        .with_allowed_method("get")
+        .with_allowed_path("/api/");
+
+    assert!(acl.permits("GET", "/api/users"));
+    assert!(!acl.permits("POST", "/api/users"));
+    assert!(!acl.permits("GET", "/metrics"));
+    // A deny beats an allow
+    let acl = acl.with_denied_path("/api/private");
+    assert!(!acl.permits("GET", "/api/private/key"));
+    assert!(acl.permits("GET", "/api/public"));
+}
+
+#[test]
+fn test_acl_from_config() {
+    let path = std::env::temp_dir().join("rust_load_balancer_acl_config.toml");
+    std::fs::write(
+        &path,
+        r#"
+servers = ["127.0.0.1:8001"]
+
+[acl]
+deny_methods = ["DELETE"]
+deny_paths = ["/admin"]
+"#,
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(
+        config.acl,
+        AccessControl::new()
+            .with_denied_method("DELETE")
+            .with_denied_path("/admin")
+    );
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{send_raw, spawn_ok_backend};
+use rust_load_balancer::algorithms::{
+    Algorithm, LoadBalancingAlgorithm, ServerState, WeightedRoundRobin,
+};
+use rust_load_balancer::balancer::LoadBalancer;
+
+use std::collections::HashMap;
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_state_reflects_forwarded_requests() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend1, backend1_handle) = spawn_ok_backend().await;
+    let (backend2, backend2_handle) = spawn_ok_backend().await;
+
+    let algorithm = Algorithm::new("least-connections", None);
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![backend1.clone(), backend2.clone()],
+        "least-connections",
+    )
+    .with_algorithm(algorithm.clone());
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    for _ in 0..4 {
+        let response = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    }
+    let state = algorithm.state().await;
+    let from_balancer = balancer.algorithm_state().await;
+
+    backend1_handle.abort();
+    backend2_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(state.algorithm, "least-connections");
+    assert_eq!(state, from_balancer);
+    // Every request finished, so nothing is in flight and all four are counted
+    let total: usize = state.servers.values().map(|s| s.total_requests).sum();
+    assert_eq!(total, 4);
+    for (server, server_state) in &state.servers {
+        assert!(*server == backend1 || *server == backend2, "{}", server);
+        assert_eq!(server_state.active_connections, Some(0));
+        assert_eq!(server_state.weight, None);
+    }
+}
+
+#[tokio::test]
+async fn test_state_includes_weights() {
+    let servers = vec!["a:1".to_string(), "b:1".to_string()];
+    let weights = HashMap::from([(servers[0].clone(), 3), (servers[1].clone(), 1)]);
+    let algorithm = Algorithm::WeightedRoundRobin(WeightedRoundRobin::new(Some(weights)));
+
+    for _ in 0..8 {
+        algorithm.next_server(&servers).await.unwrap();
+    }
+    let state = algorithm.state().await;
+
+    assert_eq!(state.algorithm, "weighted-round-robin");
+    assert_eq!(state.servers["a:1"].weight, Some(3));
+    assert_eq!(state.servers["a:1"].total_requests, 6);
+    assert_eq!(state.servers["b:1"].weight, Some(1));
+    assert_eq!(state.servers["b:1"].total_requests, 2);
+    // Weighted round-robin doesn't track connections
+    assert_eq!(state.servers["a:1"].active_connections, None);
+
+    // Algorithms that only count requests leave the rest empty
+    let round_robin = Algorithm::new("round-robin", None);
+    round_robin.next_server(&servers).await.unwrap();
+    let state = round_robin.state().await;
+    // Round-robin advances before picking, so it starts on the second server
+    assert_eq!(
+        state.servers["b:1"],
+        ServerState {
+            active_connections: None,
+            total_requests: 1,
+            weight: None,
+        }
+    );
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{send_raw, spawn_delayed_backend};
+use rust_load_balancer::balancer::{BackendLimits, LoadBalancer};
+
+use std::sync::atomic::Ordering;
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_limit_of_one_spreads_concurrent_requests() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let mut backends = Vec::new();
+    for _ in 0..3 {
+        backends.push(spawn_delayed_backend(Duration::from_millis(300)).await);
+    }
+
+    // ip-hash would send every request from this one client to one backend
+    let servers = backends.iter().map(|(addr, ..)| addr.clone()).collect();
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, servers, "ip-hash").with_max_per_backend(1);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    let requests: Vec<_> = (0..3)
+        .map(|_| tokio::spawn(send_raw(load_balancer_port, request)))
+        .collect();
+    for request in requests {
+        let response = request.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    }
+
+    load_balancer_handle.abort();
+    for (_, hits, peak, handle) in &backends {
+        handle.abort();
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[tokio::test]
+async fn test_requests_queue_when_every_backend_is_full() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, hits, peak, backend_handle) =
+        spawn_delayed_backend(Duration::from_millis(300)).await;
+
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, vec![backend], "round-robin").with_max_per_backend(1);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    let requests: Vec<_> = (0..3)
+        .map(|_| tokio::spawn(send_raw(load_balancer_port, request)))
+        .collect();
+    for request in requests {
+        let response = request.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_per_server_limit_overrides_default() {
+    let limits = BackendLimits::new(Some(2)).with_limit("a:1", 1);
+    assert_eq!(limits.limit_for("a:1"), Some(1));
+    assert_eq!(limits.limit_for("b:1"), Some(2));
+    assert_eq!(BackendLimits::default().limit_for("a:1"), None);
+
+    let held = limits.try_acquire("a:1").unwrap();
+    assert!(limits.at_limit("a:1"));
+    assert!(limits.try_acquire("a:1").is_none());
+    let servers = vec!["a:1".to_string(), "b:1".to_string()];
+    assert_eq!(limits.with_room(servers), vec!["b:1".to_string()]);
+    drop(held);
+    assert!(!limits.at_limit("a:1"));
+}
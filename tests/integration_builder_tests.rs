@@ -0,0 +1,51 @@
+use rust_load_balancer::algorithms::LoadBalancingAlgorithm;
+use rust_load_balancer::balancer::{LoadBalancer, MAX_CONNECTIONS};
+
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn test_builder_defaults_unset_fields() {
+    let balancer = LoadBalancer::builder()
+        .with_port(9100)
+        .with_servers(vec!["127.0.0.1:8001".to_string()])
+        .build();
+
+    assert_eq!(balancer.port(), 9100);
+    assert_eq!(balancer.servers().await, vec!["127.0.0.1:8001".to_string()]);
+    assert_eq!(balancer.algorithm().name(), "round-robin");
+    assert_eq!(balancer.max_connections(), MAX_CONNECTIONS);
+    assert_eq!(balancer.available_connections(), MAX_CONNECTIONS);
+    assert!(balancer.draining_servers().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_builder_matches_default_constructor() {
+    let built = LoadBalancer::builder().build();
+    let constructed = LoadBalancer::new(8000, Vec::new(), "round-robin");
+
+    assert_eq!(built.port(), constructed.port());
+    assert_eq!(built.servers().await, constructed.servers().await);
+    assert_eq!(built.algorithm().name(), constructed.algorithm().name());
+    assert_eq!(built.max_connections(), constructed.max_connections());
+}
+
+#[tokio::test]
+async fn test_builder_applies_algorithm_and_weights() {
+    let servers = vec!["127.0.0.1:8001".to_string(), "127.0.0.1:8002".to_string()];
+    let weights = HashMap::from([(servers[0].clone(), 3), (servers[1].clone(), 1)]);
+    let balancer = LoadBalancer::builder()
+        .with_servers(servers.clone())
+        .with_algorithm("weighted-round-robin")
+        .with_weights(weights)
+        .with_max_connections(8)
+        .build();
+
+    let mut picks = Vec::new();
+    for _ in 0..4 {
+        picks.push(balancer.algorithm().next_server(&servers).await.unwrap());
+    }
+
+    assert_eq!(balancer.algorithm().name(), "weighted-round-robin");
+    assert_eq!(balancer.max_connections(), 8);
+    assert_eq!(picks.iter().filter(|s| **s == servers[0]).count(), 3);
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use common::{send_raw, spawn_counting_backend, spawn_echo_backend, spawn_header_backend};
+use rust_load_balancer::balancer::{Cache, LoadBalancer};
+
+use std::sync::atomic::Ordering;
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_repeated_get_served_from_cache() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, hits, backend_handle) = spawn_counting_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_cache(Duration::from_secs(60), 16);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let request = "GET /items HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let first = send_raw(load_balancer_port, request).await;
+    let second = send_raw(load_balancer_port, request).await;
+    // POSTs always go to the backend
+    send_raw(
+        load_balancer_port,
+        "POST /items HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+    )
+    .await;
+    let metrics = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(first.starts_with("HTTP/1.1 200 OK") && first.ends_with("ok"));
+    assert_eq!(second, first);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    assert!(
+        metrics.contains("Cache hits: 1, Cache misses: 1"),
+        "{}",
+        metrics
+    );
+}
+
+#[tokio::test]
+async fn test_get_with_body_is_not_answered_from_cache() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, hits, backend_handle) = spawn_counting_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_cache(Duration::from_secs(60), 16);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    send_raw(load_balancer_port, "GET /items HTTP/1.1\r\n\r\n").await;
+    let chunked = send_raw(
+        load_balancer_port,
+        "GET /items HTTP/1.1\r\nConnection: keep-alive\r\nTransfer-Encoding: chunked\r\n\r\n\
+         5\r\nhello\r\n0\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // The body went to the backend with its request instead of being left
+    // behind and read as another one
+    assert!(chunked.starts_with("HTTP/1.1 200 OK"), "{}", chunked);
+    assert_eq!(chunked.matches("HTTP/1.1").count(), 1, "{}", chunked);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_cache_expires_and_evicts_least_recently_used() {
+    let cache = Cache::new(Duration::from_millis(200), 2);
+    cache.put("GET /a", "127.0.0.1:8001", b"a".to_vec()).await;
+    cache.put("GET /b", "127.0.0.1:8001", b"b".to_vec()).await;
+
+    // Touch /a so /b is the least recently used when /c arrives
+    assert!(cache.get("GET /a").await.is_some());
+    cache.put("GET /c", "127.0.0.1:8002", b"c".to_vec()).await;
+    assert_eq!(cache.len().await, 2);
+    assert!(cache.get("GET /b").await.is_none());
+    let cached = cache.get("GET /c").await.unwrap();
+    assert_eq!(cached.response, b"c");
+    assert_eq!(cached.backend, "127.0.0.1:8002");
+
+    sleep(Duration::from_millis(250)).await;
+    assert!(cache.get("GET /a").await.is_none());
+    assert_eq!((cache.hits(), cache.misses()), (2, 2));
+
+    assert_eq!(Cache::key("GET", "/a"), Some("GET /a".to_string()));
+    assert_eq!(Cache::key("POST", "/a"), None);
+}
+
+#[tokio::test]
+async fn test_cache_hits_rewrite_host_per_client() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, hits, backend_handle) = spawn_header_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin")
+        .with_cache(Duration::from_secs(60), 16)
+        .with_rewrite_host(true);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let first = send_raw(
+        load_balancer_port,
+        "GET /page HTTP/1.1\r\nHost: one.example.com\r\n\r\n",
+    )
+    .await;
+    let second = send_raw(
+        load_balancer_port,
+        "GET /page HTTP/1.1\r\nHost: two.example.com\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // The second answer came from the cache but names its own client's host
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    assert!(
+        first.contains("Content-Location: http://one.example.com/page\r\n"),
+        "{}",
+        first
+    );
+    assert!(
+        second.contains("Content-Location: http://two.example.com/page\r\n"),
+        "{}",
+        second
+    );
+    assert!(!second.contains(&backend), "{}", second);
+}
+
+#[tokio::test]
+async fn test_requests_with_credentials_bypass_cache() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_cache(Duration::from_secs(60), 16);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut responses = Vec::new();
+    for credentials in [
+        "Authorization: Bearer alice",
+        "Authorization: Bearer bob",
+        "Cookie: sid=carol",
+        "Cookie: sid=dave",
+        "X-Anonymous: yes",
+    ] {
+        let request = format!("GET /account HTTP/1.1\r\n{}\r\n\r\n", credentials);
+        responses.push((credentials, send_raw(load_balancer_port, &request).await));
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // Each client's request reached the backend, which echoes it back, so
+    // nobody was answered with a response meant for someone else
+    for (credentials, response) in &responses {
+        assert!(response.contains(credentials), "{}", response);
+    }
+}
+
+#[tokio::test]
+async fn test_personal_responses_are_not_cached() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, hits, backend_handle) = spawn_header_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_cache(Duration::from_secs(60), 16);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut seen = Vec::new();
+    for path in ["/cookie", "/private", "/no-store", "/public"] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        let before = hits.load(Ordering::SeqCst);
+        send_raw(load_balancer_port, &request).await;
+        send_raw(load_balancer_port, &request).await;
+        seen.push((path, hits.load(Ordering::SeqCst) - before));
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // Only the shareable response is answered from the cache the second time
+    assert_eq!(
+        seen,
+        [
+            ("/cookie", 2),
+            ("/private", 2),
+            ("/no-store", 2),
+            ("/public", 1)
+        ]
+    );
+    assert!(!Cache::storable(
+        b"HTTP/1.1 200 OK\r\nCache-Control: private=\"Set-Cookie\"\r\n\r\n"
+    ));
+    assert!(Cache::storable(
+        b"HTTP/1.1 200 OK\r\nCache-Control: public, max-age=60\r\n\r\n"
+    ));
+}
@@ -0,0 +1,43 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_cli_algorithm_flag_selects_algorithm() {
+    let mut balancer = Command::new(env!("CARGO_BIN_EXE_rust_load_balancer"))
+        .args([
+            "balancer",
+            "--port",
+            "0",
+            "--servers",
+            "127.0.0.1:8001",
+            "--algorithm",
+            "least-connections",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let stdout = BufReader::new(balancer.stdout.take().unwrap());
+    let chosen = stdout
+        .lines()
+        .map_while(Result::ok)
+        .find(|line| line.starts_with("Using "));
+
+    balancer.kill().unwrap();
+    balancer.wait().unwrap();
+
+    assert_eq!(chosen.as_deref(), Some("Using least-connections algorithm"));
+}
+
+#[test]
+fn test_cli_rejects_unknown_algorithm() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust_load_balancer"))
+        .args(["balancer", "--algorithm", "fastest"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("invalid value 'fastest'"), "{}", stderr);
+    assert!(stderr.contains("least-connections"), "{}", stderr);
+}
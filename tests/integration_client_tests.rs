@@ -0,0 +1,193 @@
+mod common;
+
+use common::{spawn_flaky_backend, spawn_keep_alive_backend, spawn_unavailable_backend};
+use rust_load_balancer::client::{is_gateway_error, Backoff, SenderClient};
+use rust_load_balancer::rng::SharedRng;
+use rust_load_balancer::server::Server;
+
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::time::Duration;
+
+#[tokio::test]
+async fn test_client_retries_with_exponential_backoff() {
+    let (url, attempts, handle) = spawn_flaky_backend(2).await;
+
+    let backoff = Backoff {
+        base: Duration::from_millis(100),
+        max: Duration::from_secs(2),
+        jitter: false,
+    };
+    let client = SenderClient::new("0", &url)
+        .with_retries(3)
+        .with_backoff(backoff);
+    let response = client.get_read_request("").await;
+
+    handle.abort();
+
+    assert_eq!(response.unwrap().status(), 200);
+
+    // Two failures then a success, with the wait doubling between attempts
+    let attempts = attempts.lock().await;
+    assert_eq!(attempts.len(), 3);
+    let first_gap = attempts[1] - attempts[0];
+    let second_gap = attempts[2] - attempts[1];
+    assert!(first_gap >= Duration::from_millis(100), "{:?}", first_gap);
+    assert!(second_gap >= Duration::from_millis(200), "{:?}", second_gap);
+    assert!(second_gap > first_gap);
+}
+
+#[tokio::test]
+async fn test_client_gives_up_after_max_retries() {
+    let (url, attempts, handle) = spawn_flaky_backend(10).await;
+
+    let backoff = Backoff {
+        base: Duration::from_millis(10),
+        max: Duration::from_millis(50),
+        jitter: true,
+    };
+    let client = SenderClient::new("0", &url)
+        .with_retries(2)
+        .with_backoff(backoff);
+    let response = client.get_read_request("").await;
+
+    handle.abort();
+
+    assert!(response.is_err());
+    assert_eq!(attempts.lock().await.len(), 3);
+}
+
+#[tokio::test]
+async fn test_client_retries_retryable_status() {
+    let (url, requests, handle) = spawn_unavailable_backend(2).await;
+    let backoff = Backoff {
+        base: Duration::from_millis(10),
+        max: Duration::from_millis(50),
+        jitter: false,
+    };
+
+    // By default a 503 is a response like any other
+    let plain = SenderClient::new("0", &url).with_backoff(backoff);
+    let response = plain.get_read_request("").await.unwrap();
+    assert_eq!(response.status(), 503);
+    assert_eq!(requests.swap(0, Ordering::SeqCst), 1);
+
+    let retrying = SenderClient::new("1", &url)
+        .with_retries(3)
+        .with_backoff(backoff)
+        .with_retryable_status(is_gateway_error);
+    let response = retrying.get_read_request("").await.unwrap();
+
+    handle.abort();
+
+    // Two 503s then a success
+    assert_eq!(response.status(), 200);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_client_returns_last_response_when_status_retries_run_out() {
+    let (url, requests, handle) = spawn_unavailable_backend(10).await;
+    let backoff = Backoff {
+        base: Duration::from_millis(10),
+        max: Duration::from_millis(50),
+        jitter: false,
+    };
+
+    let client = SenderClient::new("0", &url)
+        .with_retries(2)
+        .with_backoff(backoff)
+        .with_retryable_status(is_gateway_error);
+    let response = client.get_read_request("").await;
+
+    handle.abort();
+
+    assert_eq!(response.unwrap().status(), 503);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_backoff_delay_is_capped() {
+    let backoff = Backoff {
+        base: Duration::from_millis(100),
+        max: Duration::from_millis(500),
+        jitter: false,
+    };
+
+    assert_eq!(backoff.delay(0), Duration::from_millis(100));
+    assert_eq!(backoff.delay(1), Duration::from_millis(200));
+    assert_eq!(backoff.delay(2), Duration::from_millis(400));
+    assert_eq!(backoff.delay(3), Duration::from_millis(500));
+    assert_eq!(backoff.delay(40), Duration::from_millis(500));
+}
+
+#[test]
+fn test_backoff_jitter_follows_seed() {
+    let backoff = Backoff {
+        base: Duration::from_millis(100),
+        max: Duration::from_secs(10),
+        jitter: true,
+    };
+    let delays = |rng: &mut SharedRng| {
+        (0..6)
+            .map(|attempt| backoff.jittered_delay(attempt, rng))
+            .collect::<Vec<_>>()
+    };
+
+    let first = delays(&mut SharedRng::seeded(42));
+    assert_eq!(first, delays(&mut SharedRng::seeded(42)));
+    assert_ne!(first, delays(&mut SharedRng::seeded(43)));
+    for (attempt, delay) in first.iter().enumerate() {
+        let nominal = backoff.delay(attempt as u32);
+        assert!(*delay >= nominal / 2 && *delay <= nominal, "{:?}", delay);
+    }
+}
+
+#[tokio::test]
+async fn test_client_request_times_out() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 2000, 2000);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port))
+        .with_retries(0)
+        .with_timeout(Duration::from_millis(200));
+    let start = Instant::now();
+    let response = client.get_read_request("").await;
+    let elapsed = start.elapsed();
+
+    server_handle.abort();
+
+    let err = response.unwrap_err();
+    assert!(err.is_timeout(), "{:?}", err);
+    assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+}
+
+#[tokio::test]
+async fn test_client_keep_alive_reuses_connections() {
+    let (backend, connections, handle) = spawn_keep_alive_backend().await;
+    let url = format!("http://{}", backend);
+
+    let close = SenderClient::new("0", &url);
+    for _ in 0..5 {
+        assert_eq!(close.get_read_request("").await.unwrap().status(), 200);
+    }
+    let close_connections = connections.swap(0, Ordering::SeqCst);
+
+    let keep_alive = SenderClient::new("1", &url).with_keep_alive(true);
+    for _ in 0..5 {
+        assert_eq!(keep_alive.get_read_request("").await.unwrap().status(), 200);
+    }
+    let keep_alive_connections = connections.load(Ordering::SeqCst);
+
+    handle.abort();
+
+    assert_eq!(close_connections, 5);
+    assert_eq!(keep_alive_connections, 1);
+}
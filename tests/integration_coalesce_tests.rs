@@ -0,0 +1,161 @@
+mod common;
+
+use common::{send_raw, spawn_backend, spawn_delayed_backend};
+use rust_load_balancer::balancer::{Flight, LoadBalancer, SingleFlight};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_concurrent_identical_gets_share_one_backend_request() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, hits, _, backend_handle) =
+        spawn_delayed_backend(Duration::from_millis(300)).await;
+
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, vec![backend], "round-robin").with_coalescing();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    // Make sure the balancer is up so the requests land together
+    sleep(Duration::from_millis(100)).await;
+
+    let request = "GET /popular HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    let requests: Vec<_> = (0..10)
+        .map(|_| tokio::spawn(send_raw(load_balancer_port, request)))
+        .collect();
+    let mut responses = Vec::new();
+    for request in requests {
+        responses.push(request.await.unwrap());
+    }
+    let coalesced_hits = hits.load(Ordering::SeqCst);
+
+    // Once the first request is done, the next one goes to the backend again
+    let later = send_raw(load_balancer_port, request).await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    for response in &responses {
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("ok"), "{}", response);
+    }
+    assert_eq!(coalesced_hits, 1);
+    assert!(later.ends_with("ok"), "{}", later);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_personal_requests_and_responses_are_not_shared() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Slow enough for the requests to overlap. Echoes each request, and
+    // hands every `/login` its own session cookie.
+    let sessions = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&sessions);
+    let (backend, hits, backend_handle) = spawn_backend(move |mut socket| {
+        let counter = Arc::clone(&counter);
+        async move {
+            let mut buffer = [0; 4096];
+            let n = socket.read(&mut buffer).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buffer[..n]).to_string();
+            sleep(Duration::from_millis(300)).await;
+            let cookie = if request.starts_with("GET /login ") {
+                let session = counter.fetch_add(1, Ordering::SeqCst);
+                format!("Set-Cookie: sid={}\r\n", session)
+            } else {
+                String::new()
+            };
+            let response = format!(
+                "HTTP/1.1 200 OK\r\n{}Connection: close\r\nContent-Length: {}\r\n\r\n{}",
+                cookie,
+                request.len(),
+                request
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+            let _ = socket.shutdown().await;
+        }
+    })
+    .await;
+
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, vec![backend], "round-robin").with_coalescing();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    let requests: Vec<_> = (0..4)
+        .map(|i| {
+            format!(
+                "GET /me HTTP/1.1\r\nAuthorization: Bearer user{}\r\n\r\n",
+                i
+            )
+        })
+        .chain((0..4).map(|_| "GET /login HTTP/1.1\r\n\r\n".to_string()))
+        .map(|request| {
+            tokio::spawn(async move { (send_raw(load_balancer_port, &request).await, request) })
+        })
+        .collect();
+    let mut responses = Vec::new();
+    for request in requests {
+        responses.push(request.await.unwrap());
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // Every credentialed request got the answer to itself, and every login
+    // a session of its own
+    for (response, request) in &responses[..4] {
+        let authorization = request.lines().nth(1).unwrap();
+        assert!(response.contains(authorization), "{}", response);
+    }
+    let mut cookies: Vec<_> = responses[4..]
+        .iter()
+        .map(|(response, _)| response.lines().find(|line| line.starts_with("Set-Cookie")))
+        .collect();
+    cookies.sort();
+    cookies.dedup();
+    assert_eq!(cookies.len(), 4, "{:?}", cookies);
+    assert_eq!(hits.load(Ordering::SeqCst), 8);
+}
+
+#[tokio::test]
+async fn test_single_flight_follower_gets_leader_response() {
+    let single_flight = SingleFlight::new();
+    let key = SingleFlight::key("GET", "/a").unwrap();
+    assert!(SingleFlight::key("POST", "/a").is_none());
+
+    let leader = match single_flight.join(&key) {
+        Flight::Leader(leader) => leader,
+        Flight::Follower(_) => panic!("first request should lead"),
+    };
+    let follower = match single_flight.join(&key) {
+        Flight::Follower(follower) => follower,
+        Flight::Leader(_) => panic!("second request should follow"),
+    };
+    // A different path gets its own flight
+    assert!(matches!(
+        single_flight.join(&SingleFlight::key("GET", "/b").unwrap()),
+        Flight::Leader(_)
+    ));
+
+    leader.publish(b"response");
+    drop(leader);
+    assert_eq!(follower.response().await.unwrap().as_slice(), b"response");
+    assert_eq!(single_flight.in_flight(), 0);
+
+    // A leader that gives up leaves its followers to send their own request
+    let leader = single_flight.join(&key);
+    let follower = match single_flight.join(&key) {
+        Flight::Follower(follower) => follower,
+        Flight::Leader(_) => panic!("second request should follow"),
+    };
+    drop(leader);
+    assert!(follower.response().await.is_none());
+}
@@ -0,0 +1,88 @@
+use rust_load_balancer::balancer::LoadBalancer;
+use rust_load_balancer::config::Config;
+
+#[tokio::test]
+async fn test_balancer_from_toml_config() {
+    let path = std::env::temp_dir().join("rust_load_balancer_config.toml");
+    std::fs::write(
+        &path,
+        r#"
+port = 9100
+servers = ["127.0.0.1:8001", "127.0.0.1:8002"]
+algorithm = "weighted-round-robin"
+max_connections = 64
+
+[weights]
+"127.0.0.1:8001" = 3
+"127.0.0.1:8002" = 1
+"#,
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let balancer = LoadBalancer::from_config(&config);
+
+    assert_eq!(balancer.port(), 9100);
+    assert_eq!(
+        balancer.servers().await,
+        vec!["127.0.0.1:8001".to_string(), "127.0.0.1:8002".to_string()]
+    );
+    assert_eq!(balancer.algorithm().name(), "weighted-round-robin");
+    assert_eq!(balancer.available_connections(), 64);
+    assert_eq!(config.weights["127.0.0.1:8001"], 3);
+}
+
+#[test]
+fn test_json_config_fills_defaults() {
+    let path = std::env::temp_dir().join("rust_load_balancer_config.json");
+    std::fs::write(
+        &path,
+        r#"{ "servers": ["127.0.0.1:8001"], "algorithm": "least-connections" }"#,
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(config.servers, vec!["127.0.0.1:8001".to_string()]);
+    assert_eq!(config.algorithm, "least-connections");
+    assert_eq!(config.port, Config::default().port);
+    assert_eq!(config.max_connections, Config::default().max_connections);
+    assert!(config.weights.is_empty());
+}
+
+#[test]
+fn test_invalid_config_is_an_error() {
+    let path = std::env::temp_dir().join("rust_load_balancer_bad_config.toml");
+    std::fs::write(&path, "port = \"not a number\"").unwrap();
+
+    let result = Config::load(&path);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_config_rejects_unknown_algorithm() {
+    let path = std::env::temp_dir().join("rust_load_balancer_bad_algorithm.toml");
+    std::fs::write(&path, "algorithm = \"fastest\"\n").unwrap();
+
+    let error = Config::load(&path).unwrap_err();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("fastest"), "{}", error);
+}
+
+#[test]
+fn test_config_rejects_invalid_priority() {
+    let path = std::env::temp_dir().join("rust_load_balancer_bad_priority.toml");
+    std::fs::write(&path, "servers = [\"127.0.0.1:8001|priority=backup\"]\n").unwrap();
+
+    let error = Config::load(&path).unwrap_err();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("priority"), "{}", error);
+}
@@ -0,0 +1,102 @@
+use rust_load_balancer::algorithms::{
+    Algorithm, ConsistentHash, LoadBalancingAlgorithm, RequestContext,
+};
+
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn test_consistent_hash_removal_remaps_few_keys() {
+    let servers: Vec<String> = (8001..=8004)
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect();
+    let ring = ConsistentHash::new(100);
+    let keys: Vec<String> = (0..10_000)
+        .map(|i| format!("10.0.{}.{}", i / 256, i % 256))
+        .collect();
+
+    let mut before = HashMap::new();
+    for key in &keys {
+        before.insert(key, ring.server_for_key(&servers, key).await.unwrap());
+    }
+
+    let remaining = &servers[..3];
+    let mut remapped = 0;
+    for key in &keys {
+        let server = ring.server_for_key(remaining, key).await.unwrap();
+        if server != before[key] {
+            // Only keys owned by the removed server may move
+            assert_eq!(before[key], servers[3]);
+            remapped += 1;
+        }
+    }
+
+    let share = remapped as f64 / keys.len() as f64;
+    assert!(share < 0.4, "{:.1}% of keys remapped", share * 100.0);
+}
+
+#[tokio::test]
+async fn test_consistent_hash_is_sticky_per_client() {
+    let servers: Vec<String> = (8001..=8004)
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect();
+    let algorithm = Algorithm::new("consistent-hash", None);
+    let request = RequestContext {
+        client_addr: Some("10.1.2.3:5000".parse().unwrap()),
+        ..Default::default()
+    };
+
+    let first = algorithm.next_server_for(&servers, &request).await.unwrap();
+    for _ in 0..20 {
+        let next = algorithm.next_server_for(&servers, &request).await.unwrap();
+        assert_eq!(next, first);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_consistent_hash_concurrent_server_lists_stay_apart() {
+    let servers: Vec<String> = (8001..=8004)
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect();
+    // As when one backend is draining for some requests but not others
+    let without_last = servers[..3].to_vec();
+    let keys: Vec<String> = (0..500).map(|i| format!("10.2.0.{}", i)).collect();
+
+    // Owners according to rings that only ever saw one list
+    let mut expected = Vec::new();
+    for list in [&servers, &without_last] {
+        let ring = ConsistentHash::new(100);
+        let mut owners = Vec::new();
+        for key in &keys {
+            owners.push(ring.server_for_key(list, key).await.unwrap());
+        }
+        expected.push(owners);
+    }
+
+    let ring = ConsistentHash::new(100);
+    let mut tasks = Vec::new();
+    for (list, owners) in [servers, without_last].into_iter().zip(expected) {
+        for _ in 0..2 {
+            let (ring, list, owners, keys) =
+                (ring.clone(), list.clone(), owners.clone(), keys.clone());
+            tasks.push(tokio::spawn(async move {
+                for _ in 0..5 {
+                    for (key, owner) in keys.iter().zip(&owners) {
+                        let server = ring.server_for_key(&list, key).await.unwrap();
+                        assert_eq!(&server, owner, "{} moved", key);
+                    }
+                }
+            }));
+        }
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_consistent_hash_empty_server_list() {
+    let servers: Vec<String> = vec![];
+    let ring = ConsistentHash::default();
+
+    assert!(ring.next_server(&servers).await.is_none());
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{body, send_raw, spawn_named_backend};
+use rust_load_balancer::algorithms::{
+    Algorithm, CookieAffinity, LoadBalancingAlgorithm, RequestContext, RoundRobin,
+};
+use rust_load_balancer::balancer::LoadBalancer;
+
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_cookie_affinity_pins_session() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let mut servers = Vec::new();
+    let mut handles = Vec::new();
+    for _ in 0..3 {
+        let (addr, handle) = spawn_named_backend().await;
+        servers.push(addr);
+        handles.push(handle);
+    }
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, servers, "cookie-affinity");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // First request gets a session cookie
+    let first = send_raw(load_balancer_port, "GET / HTTP/1.1\r\nHost: lb\r\n\r\n").await;
+    let cookie = first
+        .lines()
+        .find_map(|line| line.strip_prefix("Set-Cookie: "))
+        .and_then(|value| value.split(';').next())
+        .expect("first response should set a session cookie")
+        .to_string();
+    assert!(cookie.starts_with("LB_SESSION="));
+
+    // Requests presenting the cookie stick to the same backend
+    let request = format!("GET / HTTP/1.1\r\nHost: lb\r\nCookie: {}\r\n\r\n", cookie);
+    let second = send_raw(load_balancer_port, &request).await;
+    let third = send_raw(load_balancer_port, &request).await;
+
+    for handle in handles {
+        handle.abort();
+    }
+    load_balancer_handle.abort();
+
+    assert_eq!(body(&first), body(&second));
+    assert_eq!(body(&second), body(&third));
+    assert!(!second.contains("Set-Cookie"));
+}
+
+#[tokio::test]
+async fn test_cookie_affinity_repins_removed_backend() {
+    let affinity = CookieAffinity::new(Algorithm::RoundRobin(RoundRobin::new()));
+    let servers = vec!["a:1".to_string(), "b:2".to_string()];
+    let context = RequestContext {
+        session: Some("session".to_string()),
+        ..Default::default()
+    };
+
+    let pinned = affinity.next_server_for(&servers, &context).await.unwrap();
+    for _ in 0..5 {
+        assert_eq!(
+            affinity.next_server_for(&servers, &context).await.unwrap(),
+            pinned
+        );
+    }
+
+    // Drop the pinned backend; the session moves to the survivor
+    let remaining: Vec<String> = servers.into_iter().filter(|s| *s != pinned).collect();
+    let moved = affinity
+        .next_server_for(&remaining, &context)
+        .await
+        .unwrap();
+    assert_ne!(moved, pinned);
+}
+
+#[tokio::test]
+async fn test_cookie_affinity_empty_server_list() {
+    let affinity = CookieAffinity::new(Algorithm::RoundRobin(RoundRobin::new()));
+    let context = RequestContext {
+        session: Some("session".to_string()),
+        ..Default::default()
+    };
+
+    let next_server = affinity.next_server_for(&[], &context).await;
+
+    // No server should be next
+    assert!(next_server.is_none());
+}
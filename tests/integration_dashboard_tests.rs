@@ -0,0 +1,45 @@
+mod common;
+
+use common::{send_raw, spawn_ok_backend};
+use rust_load_balancer::balancer::LoadBalancer;
+
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_dashboard_lists_every_backend() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (first, first_handle) = spawn_ok_backend().await;
+    let (second, second_handle) = spawn_ok_backend().await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![first.clone(), second.clone()],
+        "round-robin",
+    );
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    for _ in 0..2 {
+        send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    }
+    balancer.drain(&second).await;
+    let response = send_raw(load_balancer_port, "GET /dashboard HTTP/1.1\r\n\r\n").await;
+
+    first_handle.abort();
+    second_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("Content-Type: text/html"), "{}", response);
+    let page = response.split("\r\n\r\n").nth(1).unwrap();
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.contains("http-equiv=\"refresh\""));
+    for server in [&first, &second] {
+        assert!(page.contains(server.as_str()), "{} missing", server);
+    }
+    assert!(page.contains("50.0%"), "{}", page);
+    assert!(page.contains(">Draining<"), "{}", page);
+}
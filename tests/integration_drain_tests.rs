@@ -0,0 +1,153 @@
+mod common;
+
+use common::{send_raw, spawn_counting_backend};
+use rust_load_balancer::balancer::LoadBalancer;
+
+use std::sync::atomic::Ordering;
+use tokio::net::TcpListener;
+
+fn admin_request(path: &str, server: &str) -> String {
+    format!(
+        "POST {} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+        path,
+        server.len(),
+        server
+    )
+}
+
+#[tokio::test]
+async fn test_drained_backend_gets_no_new_traffic() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (drained, drained_hits, drained_handle) = spawn_counting_backend().await;
+    let (active, active_hits, active_handle) = spawn_counting_backend().await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![drained.clone(), active.clone()],
+        "round-robin",
+    );
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let response = send_raw(
+        load_balancer_port,
+        &admin_request("/admin/servers/drain", &drained),
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    for _ in 0..10 {
+        send_raw(load_balancer_port, request).await;
+    }
+    assert_eq!(drained_hits.load(Ordering::SeqCst), 0);
+    assert_eq!(active_hits.load(Ordering::SeqCst), 10);
+
+    let metrics = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+    let drained_line = metrics
+        .lines()
+        .find(|line| line.starts_with(&format!("{}:", drained)))
+        .unwrap();
+    assert!(drained_line.contains("Draining"), "{}", metrics);
+
+    // Undraining puts it back into rotation
+    send_raw(
+        load_balancer_port,
+        &admin_request("/admin/servers/undrain", &drained),
+    )
+    .await;
+    for _ in 0..4 {
+        send_raw(load_balancer_port, request).await;
+    }
+
+    let unknown = send_raw(
+        load_balancer_port,
+        &admin_request("/admin/servers/drain", "127.0.0.1:1"),
+    )
+    .await;
+
+    drained_handle.abort();
+    active_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(drained_hits.load(Ordering::SeqCst), 2);
+    assert!(unknown.starts_with("HTTP/1.1 404"), "{}", unknown);
+}
+
+#[tokio::test]
+async fn test_drained_backend_keeps_sticky_sessions() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (first, first_hits, first_handle) = spawn_counting_backend().await;
+    let (second, second_hits, second_handle) = spawn_counting_backend().await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![first.clone(), second.clone()],
+        "cookie-affinity",
+    );
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Pin a session, then drain whichever backend it landed on
+    let response = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let cookie = response
+        .lines()
+        .find_map(|line| line.strip_prefix("Set-Cookie: "))
+        .and_then(|value| value.split(';').next())
+        .unwrap()
+        .to_string();
+    let (pinned, pinned_hits, other_hits) = if first_hits.load(Ordering::SeqCst) == 1 {
+        (&first, &first_hits, &second_hits)
+    } else {
+        (&second, &second_hits, &first_hits)
+    };
+    balancer.drain(pinned).await;
+
+    let sticky = format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", cookie);
+    for _ in 0..3 {
+        send_raw(load_balancer_port, &sticky).await;
+    }
+    // New sessions avoid the draining backend
+    for _ in 0..3 {
+        send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    }
+
+    first_handle.abort();
+    second_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(pinned_hits.load(Ordering::SeqCst), 4);
+    assert_eq!(other_hits.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_oversized_admin_body_is_rejected() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, hits, backend_handle) = spawn_counting_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Only the head arrives; the declared length alone is enough to refuse
+    let response = send_raw(
+        load_balancer_port,
+        "POST /admin/servers/drain HTTP/1.1\r\nContent-Length: 1000000000\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    assert!(balancer.draining_servers().await.is_empty());
+    assert_eq!(hits.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,62 @@
+use rust_load_balancer::algorithms::{Algorithm, Ewma, LoadBalancingAlgorithm};
+
+use tokio::time::Duration;
+
+#[tokio::test]
+async fn test_ewma_shifts_away_from_slowed_backend() {
+    let servers = vec!["127.0.0.1:8001".to_string(), "127.0.0.1:8002".to_string()];
+    let algorithm = Algorithm::new("ewma", None);
+
+    // Simulated latencies: the first backend is fast until request 20
+    let latency = |server: &str, request: usize| {
+        if server == servers[0] {
+            if request < 20 {
+                Duration::from_millis(10)
+            } else {
+                Duration::from_millis(200)
+            }
+        } else {
+            Duration::from_millis(40)
+        }
+    };
+
+    let mut picks = Vec::new();
+    for request in 0..30 {
+        let server = algorithm.next_server(&servers).await.unwrap();
+        algorithm
+            .record_latency(&server, latency(&server, request))
+            .await;
+        picks.push(server);
+    }
+
+    // Once warmed up the fast backend takes all the traffic...
+    assert!(picks[2..20].iter().all(|s| *s == servers[0]));
+
+    // ...and after it slows down traffic moves within a few requests and stays
+    let shifted = picks[20..].iter().position(|s| *s == servers[1]).unwrap();
+    assert!(shifted <= 3, "took {} requests to shift", shifted);
+    assert!(picks[24..].iter().all(|s| *s == servers[1]));
+}
+
+#[tokio::test]
+async fn test_ewma_decay_weights_recent_samples() {
+    let server = "127.0.0.1:8001".to_string();
+    let ewma = Ewma::new(0.5);
+
+    ewma.record_latency(&server, Duration::from_millis(100))
+        .await;
+    ewma.record_latency(&server, Duration::from_millis(200))
+        .await;
+    ewma.next_server(std::slice::from_ref(&server)).await;
+
+    let metrics = ewma.get_metrics().await;
+    assert_eq!(metrics[&server], "EWMA: 150.0ms, Requests: 1");
+}
+
+#[tokio::test]
+async fn test_ewma_empty_server_list() {
+    let servers: Vec<String> = vec![];
+    let ewma = Ewma::default();
+
+    assert!(ewma.next_server(&servers).await.is_none());
+}
@@ -0,0 +1,1497 @@
+mod common;
+
+use common::{
+    read_head, send_raw, spawn_backend, spawn_backend_at, spawn_counting_backend,
+    spawn_echo_backend, spawn_holding_backend, spawn_keep_alive_backend, spawn_recording_backend,
+    spawn_replying_backend,
+};
+use rust_load_balancer::balancer::{ConnectionPool, LoadBalancer, BACKOFF_MAX};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+#[tokio::test]
+async fn test_forwarded_headers_added() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let response = send_raw(
+        load_balancer_port,
+        "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("X-Forwarded-For: 127.0.0.1\r\n"));
+    assert!(response.contains("X-Forwarded-Proto: http\r\n"));
+}
+
+#[tokio::test]
+async fn test_forwarded_for_appends_existing_value() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let response = send_raw(
+        load_balancer_port,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nX-Forwarded-For: 10.0.0.1\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // Existing chain is preserved with the client appended
+    assert!(response.contains("X-Forwarded-For: 10.0.0.1, 127.0.0.1\r\n"));
+    assert_eq!(response.matches("X-Forwarded-For").count(), 1);
+}
+
+// Value of the X-Request-Id header the echo backend saw
+fn echoed_request_id(response: &str) -> Option<&str> {
+    response
+        .lines()
+        .find_map(|line| line.strip_prefix("X-Request-Id: "))
+}
+
+#[tokio::test]
+async fn test_request_id_added_or_preserved() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let generated = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let another = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let supplied = send_raw(
+        load_balancer_port,
+        "GET / HTTP/1.1\r\nX-Request-Id: trace-42\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // A fresh UUID per request
+    let id = echoed_request_id(&generated).unwrap();
+    assert_eq!(id.len(), 36);
+    assert_eq!(id.matches('-').count(), 4);
+    assert_ne!(Some(id), echoed_request_id(&another));
+
+    assert_eq!(echoed_request_id(&supplied), Some("trace-42"));
+    assert_eq!(supplied.matches("X-Request-Id").count(), 1);
+}
+
+#[tokio::test]
+async fn test_backend_connection_is_reused() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, connections, backend_handle) = spawn_keep_alive_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    let first = send_raw(load_balancer_port, request).await;
+    let second = send_raw(load_balancer_port, request).await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // Both responses arrive whole, over a single backend connection
+    assert!(first.starts_with("HTTP/1.1 200 OK") && first.ends_with("ok"));
+    assert!(second.starts_with("HTTP/1.1 200 OK") && second.ends_with("ok"));
+    assert!(first.contains("Connection: close\r\n"));
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_bodyless_responses_end_at_their_head() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Keep-alive backend that frames HEAD and 304 answers with the length of
+    // a body it doesn't send
+    let (backend, connections, backend_handle) = spawn_backend(|mut socket| async move {
+        let mut buffer = [0; 4096];
+        while let Ok(n) = socket.read(&mut buffer).await {
+            if n == 0 {
+                break;
+            }
+            let response: &[u8] = if buffer.starts_with(b"HEAD ") {
+                b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n"
+            } else {
+                b"HTTP/1.1 304 Not Modified\r\nContent-Length: 5\r\n\r\n"
+            };
+            if socket.write_all(response).await.is_err() {
+                break;
+            }
+        }
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let head = timeout(
+        Duration::from_secs(5),
+        send_raw(
+            load_balancer_port,
+            "HEAD / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        ),
+    )
+    .await;
+    let not_modified = timeout(
+        Duration::from_secs(5),
+        send_raw(
+            load_balancer_port,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: \"v1\"\r\n\r\n",
+        ),
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // Neither waits for the five body bytes, and the backend connection is
+    // still fit to reuse afterwards
+    let head = head.expect("HEAD response never finished");
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+    assert!(head.contains("Content-Length: 5\r\n"), "{}", head);
+    assert!(head.ends_with("\r\n\r\n"), "{}", head);
+    let not_modified = not_modified.expect("304 response never finished");
+    assert!(
+        not_modified.starts_with("HTTP/1.1 304 Not Modified"),
+        "{}",
+        not_modified
+    );
+    assert!(not_modified.ends_with("\r\n\r\n"), "{}", not_modified);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_post_on_stale_pooled_connection_is_not_resent() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Answers the first request on each connection, then reads the next one
+    // and hangs up without answering
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+    let (backend, _, backend_handle) = spawn_backend(move |mut socket| {
+        let counter = Arc::clone(&counter);
+        async move {
+            let mut buffer = [0; 4096];
+            if socket.read(&mut buffer).await.unwrap_or(0) == 0 {
+                return;
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+            let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+            if socket.write_all(response).await.is_err() {
+                return;
+            }
+            if socket.read(&mut buffer).await.unwrap_or(0) > 0 {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let first = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let post = send_raw(
+        load_balancer_port,
+        "POST /orders HTTP/1.1\r\nContent-Length: 5\r\n\r\nfirst",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // The POST reached the backend over the pooled connection, so it isn't
+    // sent again on a fresh one
+    assert!(first.starts_with("HTTP/1.1 200 OK"), "{}", first);
+    assert!(post.starts_with("HTTP/1.1 502 Bad Gateway"), "{}", post);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_interim_responses_reach_client_before_final_one() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Sends early hints ahead of each answer, keeping the connection open
+    let (backend, _, backend_handle) = spawn_backend(|mut socket| async move {
+        while read_head(&mut socket).await.is_some() {
+            let response = b"HTTP/1.1 103 Early Hints\r\nLink: </app.css>\r\n\r\n\
+                HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+            if socket.write_all(response).await.is_err() {
+                return;
+            }
+        }
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut stream = TcpStream::connect(("127.0.0.1", load_balancer_port))
+        .await
+        .unwrap();
+    let mut responses = Vec::new();
+    for _ in 0..2 {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nConnection: keep-alive\r\n\r\n")
+            .await
+            .unwrap();
+        let hints = read_head(&mut stream).await.unwrap();
+        let last = read_head(&mut stream).await.unwrap();
+        let mut body = [0; 2];
+        stream.read_exact(&mut body).await.unwrap();
+        responses.push((
+            String::from_utf8(hints).unwrap(),
+            String::from_utf8(last).unwrap(),
+            body,
+        ));
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // The final response still ends where it should, so the second one
+    // comes back on the same connection
+    for (hints, last, body) in responses {
+        assert!(hints.starts_with("HTTP/1.1 103 Early Hints"), "{}", hints);
+        assert!(hints.contains("Link: </app.css>\r\n"), "{}", hints);
+        assert!(last.starts_with("HTTP/1.1 200 OK"), "{}", last);
+        assert!(last.contains("Connection: keep-alive\r\n"), "{}", last);
+        assert_eq!(&body, b"ok");
+    }
+}
+
+#[tokio::test]
+async fn test_expect_continue_body_follows_backend_go_ahead() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Tells the client to go on, then echoes the body it sends
+    let (backend, _, backend_handle) = spawn_backend(|mut socket| async move {
+        if read_head(&mut socket).await.is_none() {
+            return;
+        }
+        socket
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .await
+            .unwrap();
+        let mut body = [0; 5];
+        socket.read_exact(&mut body).await.unwrap();
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n")
+            .await
+            .unwrap();
+        socket.write_all(&body).await.unwrap();
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut stream = TcpStream::connect(("127.0.0.1", load_balancer_port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n")
+        .await
+        .unwrap();
+    // Nothing of the body goes out until the backend says to continue
+    let interim = timeout(Duration::from_secs(5), read_head(&mut stream)).await;
+    stream.write_all(b"hello").await.unwrap();
+    let mut response = String::new();
+    let _ = timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    let interim = String::from_utf8(interim.unwrap().unwrap()).unwrap();
+    assert_eq!(interim, "HTTP/1.1 100 Continue\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("hello"), "{}", response);
+}
+
+#[tokio::test]
+async fn test_upgraded_connection_is_tunneled() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Agrees to switch protocols, then echoes whatever arrives until the
+    // client is done
+    let (backend, _, backend_handle) = spawn_backend(|mut socket| async move {
+        let head = read_head(&mut socket).await.unwrap();
+        assert!(String::from_utf8_lossy(&head).contains("Connection: Upgrade\r\n"));
+        socket
+            .write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut buffer = [0; 1024];
+        while let Ok(n) = socket.read(&mut buffer).await {
+            if n == 0 || socket.write_all(&buffer[..n]).await.is_err() {
+                break;
+            }
+        }
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut stream = TcpStream::connect(("127.0.0.1", load_balancer_port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET /socket HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n")
+        .await
+        .unwrap();
+    let switched = String::from_utf8(read_head(&mut stream).await.unwrap()).unwrap();
+    // Frames that aren't HTTP go both ways untouched
+    let mut echoes = Vec::new();
+    for frame in [&b"\x81\x04ping"[..], &b"\x81\x04pong"[..]] {
+        stream.write_all(frame).await.unwrap();
+        let mut echo = vec![0; frame.len()];
+        stream.read_exact(&mut echo).await.unwrap();
+        echoes.push(echo);
+    }
+    stream.shutdown().await.unwrap();
+    let mut rest = Vec::new();
+    let closed = timeout(Duration::from_secs(5), stream.read_to_end(&mut rest)).await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(
+        switched.starts_with("HTTP/1.1 101 Switching Protocols"),
+        "{}",
+        switched
+    );
+    assert_eq!(
+        echoes,
+        vec![b"\x81\x04ping".to_vec(), b"\x81\x04pong".to_vec()]
+    );
+    // Once the client is done the tunnel closes
+    assert!(closed.is_ok());
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn test_connection_pool_hands_back_idle_connection() {
+    let (backend, _connections, backend_handle) = spawn_keep_alive_backend().await;
+    let pool = ConnectionPool::new(4);
+
+    let (stream, reused) = pool.get(&backend).await.unwrap();
+    assert!(!reused);
+    pool.put(&backend, stream).await;
+    assert_eq!(pool.idle_connections(&backend).await, 1);
+
+    let (_stream, reused) = pool.get(&backend).await.unwrap();
+
+    backend_handle.abort();
+
+    assert!(reused);
+    assert_eq!(pool.idle_connections(&backend).await, 0);
+}
+
+#[tokio::test]
+async fn test_connection_pool_backs_off_from_refusing_backend() {
+    // Nothing listens on the port during the outage
+    let port = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let backend = format!("127.0.0.1:{}", port);
+    let pool = ConnectionPool::new(4);
+
+    let outage = Instant::now();
+    let mut calls = 0;
+    while outage.elapsed() < Duration::from_secs(1) {
+        assert!(pool.connect(&backend).await.is_err());
+        calls += 1;
+        sleep(Duration::from_millis(5)).await;
+    }
+
+    // Dials double their spacing instead of following every call
+    let attempts = pool.connect_attempts(&backend).await;
+    assert!(calls > 100, "only {} calls", calls);
+    assert!((3..=8).contains(&attempts), "{} dials", attempts);
+
+    let (_, _, backend_handle) = spawn_backend_at(&backend, |_| async {}).await;
+
+    // Once the backend is back the next allowed dial gets through
+    let recovery = Instant::now();
+    while pool.connect(&backend).await.is_err() {
+        sleep(Duration::from_millis(5)).await;
+    }
+    let recovered_in = recovery.elapsed();
+    let dialed = pool.connect_attempts(&backend).await;
+    let second = pool.connect(&backend).await;
+
+    backend_handle.abort();
+
+    assert!(
+        recovered_in <= BACKOFF_MAX + Duration::from_millis(200),
+        "took {:?} to reconnect",
+        recovered_in
+    );
+    // Success clears the backoff, so the next call dials right away
+    assert!(second.is_ok());
+    assert_eq!(pool.connect_attempts(&backend).await, dialed + 1);
+}
+
+#[tokio::test]
+async fn test_connection_pool_applies_nodelay() {
+    let (backend, _, backend_handle) = spawn_keep_alive_backend().await;
+
+    let (default_stream, _) = ConnectionPool::new(4).get(&backend).await.unwrap();
+    let (nodelay_stream, _) = ConnectionPool::new(4)
+        .with_nodelay(true)
+        .get(&backend)
+        .await
+        .unwrap();
+    let warm = ConnectionPool::new(4).with_nodelay(true);
+    warm.warm(&backend, 1).await.unwrap();
+    let (warm_stream, reused) = warm.get(&backend).await.unwrap();
+
+    backend_handle.abort();
+
+    assert!(!default_stream.nodelay().unwrap());
+    assert!(nodelay_stream.nodelay().unwrap());
+    assert!(reused);
+    assert!(warm_stream.nodelay().unwrap());
+}
+
+#[tokio::test]
+async fn test_nodelay_balancer_forwards_small_requests() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_nodelay(true)
+        .with_backlog(16);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    let mut responses = Vec::new();
+    for _ in 0..5 {
+        responses.push(send_raw(load_balancer_port, "GET /tiny HTTP/1.1\r\n\r\n").await);
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    for response in responses {
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.contains("GET /tiny HTTP/1.1"), "{}", response);
+    }
+}
+
+// Read one Content-Length framed response off a connection that stays open
+async fn read_framed_response(stream: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    let mut byte = [0; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).await.unwrap();
+        response.push(byte[0]);
+    }
+    let head = String::from_utf8(response.clone()).unwrap();
+    let length: usize = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await.unwrap();
+    response.extend_from_slice(&body);
+    String::from_utf8(response).unwrap()
+}
+
+#[tokio::test]
+async fn test_client_keep_alive_serves_multiple_requests() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", load_balancer_port)).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    stream
+        .write_all(b"GET /first HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\n\r\n")
+        .await
+        .unwrap();
+    let first = read_framed_response(&mut stream).await;
+
+    // Same connection, forwarded as a separate request
+    stream
+        .write_all(b"GET /second HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut second = String::new();
+    stream.read_to_string(&mut second).await.unwrap();
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(first.starts_with("HTTP/1.1 200 OK"), "{}", first);
+    assert!(first.contains("Connection: keep-alive\r\n"));
+    assert!(first.contains("GET /first HTTP/1.1"));
+    assert!(second.starts_with("HTTP/1.1 200 OK"), "{}", second);
+    assert!(second.contains("Connection: close\r\n"));
+    assert!(second.contains("GET /second HTTP/1.1"));
+    assert!(!second.contains("GET /first"));
+}
+
+#[tokio::test]
+async fn test_client_keep_alive_handles_pipelined_requests() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Both requests in a single write
+    let response = send_raw(
+        load_balancer_port,
+        "GET /a HTTP/1.1\r\nConnection: keep-alive\r\n\r\nGET /b HTTP/1.1\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(
+        response.matches("HTTP/1.1 200 OK").count(),
+        2,
+        "{}",
+        response
+    );
+    assert!(response.find("GET /a").unwrap() < response.find("GET /b").unwrap());
+}
+
+#[tokio::test]
+async fn test_balancer_exits_after_idle_timeout() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_idle_timeout(Duration::from_millis(800));
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Still serving while connections keep arriving
+    let response = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    let finished = timeout(Duration::from_secs(3), load_balancer_handle).await;
+    backend_handle.abort();
+    assert!(finished.is_ok(), "idle balancer didn't stop");
+}
+
+#[tokio::test]
+async fn test_no_available_backend_returns_503() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    // Servers may be added later, so the balancer starts without any
+    let empty = LoadBalancer::new(load_balancer_port, vec![], "round-robin").with_dynamic_servers();
+    let empty_handle = tokio::spawn(async move {
+        empty.run_with_listener(lb_listener).await;
+    });
+    let response = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    empty_handle.abort();
+    assert!(
+        response.starts_with("HTTP/1.1 503 Service Unavailable"),
+        "{}",
+        response
+    );
+    assert!(response.ends_with("No backend available\n"));
+
+    // Draining the only backend leaves nothing to route to either
+    let drained_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let drained_port = drained_listener.local_addr().unwrap().port();
+    let drained = LoadBalancer::new(drained_port, vec![backend.clone()], "least-connections");
+    drained.drain(&backend).await;
+    let drained_handle = tokio::spawn(async move {
+        drained.run_with_listener(drained_listener).await;
+    });
+    let response = send_raw(drained_port, "GET / HTTP/1.1\r\n\r\n").await;
+
+    drained_handle.abort();
+    backend_handle.abort();
+
+    assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+}
+
+#[tokio::test]
+async fn test_empty_connection_closes_cleanly() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Connect and hang up without sending anything
+    let response = send_raw(load_balancer_port, "").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(response.is_empty(), "{}", response);
+}
+
+#[tokio::test]
+async fn test_malformed_request_gets_400() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let garbage = send_raw(load_balancer_port, "\x16\x03\x01 not http at all\r\n\r\n").await;
+    // A head cut off by the client closing is just as unusable
+    let truncated = send_raw(load_balancer_port, "GET / HTTP/1.1\r\nHost: loc").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    for response in [garbage, truncated] {
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request"),
+            "{}",
+            response
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_ambiguous_content_length_gets_400() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, hits, backend_handle) = spawn_counting_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Each would otherwise be read as a length of 0 or 5, leaving the rest
+    // of the body to be taken for another request
+    let mut responses = Vec::new();
+    for length in [
+        "Content-Length: 5, 5",
+        "Content-Length: +5",
+        "Content-Length: five",
+        "Content-Length: 99999999999999999999999",
+        "Content-Length: 5\r\nContent-Length: 6",
+    ] {
+        let request = format!(
+            "POST / HTTP/1.1\r\n{}\r\n\r\nhelloGET /smuggled HTTP/1.1\r\n\r\n",
+            length
+        );
+        responses.push(send_raw(load_balancer_port, &request).await);
+    }
+    // Repeating the same length leaves nothing in doubt
+    let repeated = send_raw(
+        load_balancer_port,
+        "POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    for response in responses {
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request"),
+            "{}",
+            response
+        );
+    }
+    assert!(repeated.starts_with("HTTP/1.1 200 OK"), "{}", repeated);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_host_header_preserved_or_set_to_backend() {
+    let (backend, backend_handle) = spawn_echo_backend().await;
+    let preserving_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let preserving_port = preserving_listener.local_addr().unwrap().port();
+    let rewriting_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let rewriting_port = rewriting_listener.local_addr().unwrap().port();
+
+    let preserving = LoadBalancer::new(preserving_port, vec![backend.clone()], "round-robin");
+    let rewriting = LoadBalancer::new(rewriting_port, vec![backend.clone()], "round-robin")
+        .with_preserve_host(false);
+    let preserving_handle = tokio::spawn(async move {
+        preserving.run_with_listener(preserving_listener).await;
+    });
+    let rewriting_handle = tokio::spawn(async move {
+        rewriting.run_with_listener(rewriting_listener).await;
+    });
+
+    let request =
+        "POST /submit HTTP/1.1\r\nhost: site.example.com\r\nContent-Length: 5\r\n\r\nhello";
+    let preserved = send_raw(preserving_port, request).await;
+    let rewritten = send_raw(rewriting_port, request).await;
+
+    backend_handle.abort();
+    preserving_handle.abort();
+    rewriting_handle.abort();
+
+    assert!(
+        preserved.contains("host: site.example.com\r\n"),
+        "{}",
+        preserved
+    );
+    assert!(
+        rewritten.contains(&format!("Host: {}\r\n", backend)),
+        "{}",
+        rewritten
+    );
+    assert!(!rewritten.contains("site.example.com"), "{}", rewritten);
+    // The body still follows the rewritten head
+    assert!(rewritten.ends_with("\r\n\r\nhello"), "{}", rewritten);
+}
+
+#[tokio::test]
+async fn test_rewrite_host_points_location_at_balancer() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, _, backend_handle) = spawn_replying_backend(|own_addr, _| {
+        format!(
+            "HTTP/1.1 302 Found\r\nLocation: http://{}/login\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            own_addr
+        )
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin")
+        .with_rewrite_host(true);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let addressed = send_raw(
+        load_balancer_port,
+        "GET / HTTP/1.1\r\nHost: lb.example.com\r\n\r\n",
+    )
+    .await;
+    let defaulted = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(addressed.starts_with("HTTP/1.1 302 Found"), "{}", addressed);
+    assert!(
+        addressed.contains("Location: http://lb.example.com/login\r\n"),
+        "{}",
+        addressed
+    );
+    assert!(
+        defaulted.contains(&format!(
+            "Location: http://127.0.0.1:{}/login\r\n",
+            load_balancer_port
+        )),
+        "{}",
+        defaulted
+    );
+    assert!(!addressed.contains(&backend));
+}
+
+#[tokio::test]
+async fn test_max_body_size_rejects_large_bodies() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, vec![backend], "round-robin").with_max_body_size(16);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let small = send_raw(
+        load_balancer_port,
+        "POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello",
+    )
+    .await;
+    let declared = send_raw(
+        load_balancer_port,
+        &format!(
+            "POST / HTTP/1.1\r\nContent-Length: 32\r\n\r\n{}",
+            "x".repeat(32)
+        ),
+    )
+    .await;
+    let small_chunked = send_raw(
+        load_balancer_port,
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+    )
+    .await;
+    let chunked = send_raw(
+        load_balancer_port,
+        &format!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n10\r\n{0}\r\n10\r\n{0}\r\n0\r\n\r\n",
+            "y".repeat(16)
+        ),
+    )
+    .await;
+    // A small declared length doesn't excuse the chunks sent alongside it
+    let understated = send_raw(
+        load_balancer_port,
+        &format!(
+            "POST / HTTP/1.1\r\nContent-Length: 0\r\nTransfer-Encoding: chunked\r\n\r\n\
+             10\r\n{0}\r\n10\r\n{0}\r\n0\r\n\r\n",
+            "z".repeat(16)
+        ),
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(small.starts_with("HTTP/1.1 200 OK"), "{}", small);
+    assert!(
+        declared.starts_with("HTTP/1.1 413 Payload Too Large"),
+        "{}",
+        declared
+    );
+    assert!(
+        small_chunked.starts_with("HTTP/1.1 200 OK"),
+        "{}",
+        small_chunked
+    );
+    assert!(small_chunked.ends_with("0\r\n\r\n"), "{}", small_chunked);
+    assert!(
+        chunked.starts_with("HTTP/1.1 413 Payload Too Large"),
+        "{}",
+        chunked
+    );
+    assert!(!chunked.contains(&"y".repeat(16)), "{}", chunked);
+    assert!(
+        understated.starts_with("HTTP/1.1 413 Payload Too Large"),
+        "{}",
+        understated
+    );
+    assert!(!understated.contains(&"z".repeat(16)), "{}", understated);
+}
+
+#[tokio::test]
+async fn test_backend_dropping_mid_response_returns_502() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Promises a 100-byte body, sends a few bytes of it, then hangs up
+    let (backend, _, backend_handle) = spawn_backend(|mut socket| async move {
+        let mut buffer = [0; 4096];
+        let _ = socket.read(&mut buffer).await;
+        let _ = socket
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial")
+            .await;
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let pooled = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    // A chunked body the client stops short of finishing is relayed rather
+    // than pooled
+    let relayed = send_raw(
+        load_balancer_port,
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(pooled.starts_with("HTTP/1.1 502 Bad Gateway"), "{}", pooled);
+    assert!(!pooled.contains("partial"), "{}", pooled);
+    // The relayed response streams straight through, so the client sees
+    // what the backend sent before the connection is cut
+    assert!(relayed.contains("partial"), "{}", relayed);
+}
+
+#[tokio::test]
+async fn test_backend_closing_without_response_returns_502() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, _, backend_handle) = spawn_backend(|mut socket| async move {
+        let mut buffer = [0; 4096];
+        let _ = socket.read(&mut buffer).await;
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let pooled = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let relayed = send_raw(
+        load_balancer_port,
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(pooled.starts_with("HTTP/1.1 502 Bad Gateway"), "{}", pooled);
+    assert!(
+        relayed.starts_with("HTTP/1.1 502 Bad Gateway"),
+        "{}",
+        relayed
+    );
+}
+
+#[tokio::test]
+async fn test_backend_closing_before_reading_is_retried_elsewhere() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Accepts each connection and hangs up without reading a byte
+    let (closing, accepted, closing_handle) = spawn_backend(|_| async {}).await;
+    let (healthy, healthy_handle) = spawn_echo_backend().await;
+
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, vec![closing, healthy], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut pooled = Vec::new();
+    for _ in 0..4 {
+        pooled.push(send_raw(load_balancer_port, "GET /retry HTTP/1.1\r\n\r\n").await);
+    }
+    // PUT is idempotent, so it's safe to send again
+    let mut relayed = Vec::new();
+    for _ in 0..4 {
+        relayed.push(
+            send_raw(
+                load_balancer_port,
+                "PUT /retry HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n",
+            )
+            .await,
+        );
+    }
+
+    closing_handle.abort();
+    healthy_handle.abort();
+    load_balancer_handle.abort();
+
+    // Round robin sent some of these to the closing backend first
+    assert!(accepted.load(Ordering::SeqCst) > 0);
+    for response in pooled.iter().chain(relayed.iter()) {
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.contains(" /retry HTTP/1.1"), "{}", response);
+    }
+}
+
+#[tokio::test]
+async fn test_unanswered_post_is_not_sent_to_another_backend() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Reads the whole request, then hangs up without answering
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&dropped);
+    let (dropping, _, dropping_handle) = spawn_backend(move |mut socket| {
+        let counter = Arc::clone(&counter);
+        async move {
+            let mut buffer = [0; 4096];
+            if socket.read(&mut buffer).await.unwrap_or(0) > 0 {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    })
+    .await;
+    let (other, connections, other_handle) = spawn_keep_alive_backend().await;
+
+    // Round robin's first pick is the second server, the dropping one
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![other, dropping], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let first = send_raw(
+        load_balancer_port,
+        "POST /orders HTTP/1.1\r\nContent-Length: 5\r\n\r\nfirst",
+    )
+    .await;
+    let connections_after_first = connections.load(Ordering::SeqCst);
+    let second = send_raw(
+        load_balancer_port,
+        "POST /orders HTTP/1.1\r\nContent-Length: 6\r\n\r\nsecond",
+    )
+    .await;
+
+    dropping_handle.abort();
+    other_handle.abort();
+    load_balancer_handle.abort();
+
+    // The backend may have acted on the first POST, so it isn't repeated
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    assert!(first.starts_with("HTTP/1.1 502 Bad Gateway"), "{}", first);
+    assert_eq!(connections_after_first, 0);
+    assert!(second.starts_with("HTTP/1.1 200 OK"), "{}", second);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_queue_timeout_turns_away_excess_connections() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Holds each request for a second so the only slot stays taken
+    let (backend, backend_handle) = spawn_holding_backend(Duration::from_secs(1)).await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_max_connections(1)
+        .with_queue_timeout(Duration::from_millis(200));
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let first = tokio::spawn(send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n"));
+    sleep(Duration::from_millis(200)).await;
+    let start = std::time::Instant::now();
+    let excess = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let waited = start.elapsed();
+    let first = first.await.unwrap();
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(first.starts_with("HTTP/1.1 200 OK"), "{}", first);
+    assert!(
+        excess.starts_with("HTTP/1.1 503 Service Unavailable"),
+        "{}",
+        excess
+    );
+    assert!(waited < Duration::from_millis(600), "waited {:?}", waited);
+}
+
+// Decode a complete chunked body, or `None` if it hasn't all arrived
+fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return body.starts_with(b"\r\n").then_some(decoded);
+        }
+        if body.len() < size + 2 {
+            return None;
+        }
+        decoded.extend_from_slice(&body[..size]);
+        body = &body[size + 2..];
+    }
+}
+
+#[tokio::test]
+async fn test_chunked_request_drops_declared_length() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let response = send_raw(
+        load_balancer_port,
+        "POST /upload HTTP/1.1\r\nContent-Length: 0\r\nTransfer-Encoding: chunked\r\n\r\n\
+         5\r\nhello\r\n0\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // The backend only sees the chunked framing, so it can't stop reading at
+    // the declared length and take the body for another request
+    let (_, forwarded) = response.split_once("\r\n\r\n").unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(
+        forwarded.contains("Transfer-Encoding: chunked\r\n"),
+        "{}",
+        forwarded
+    );
+    assert!(!forwarded.contains("Content-Length"), "{}", forwarded);
+    assert!(
+        forwarded.ends_with("5\r\nhello\r\n0\r\n\r\n"),
+        "{}",
+        forwarded
+    );
+}
+
+#[tokio::test]
+async fn test_chunked_request_and_response_are_forwarded() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Reassembles each chunked request body and sends it back chunked
+    let (backend, _, backend_handle) = spawn_backend(|mut socket| async move {
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 1024];
+        loop {
+            let head_end = buffer.windows(4).position(|w| w == b"\r\n\r\n");
+            let body = head_end.and_then(|end| decode_chunked(&buffer[end + 4..]));
+            if let Some(body) = body {
+                let mut response =
+                    b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+                for part in body.chunks(7) {
+                    response.extend_from_slice(format!("{:x}\r\n", part.len()).as_bytes());
+                    response.extend_from_slice(part);
+                    response.extend_from_slice(b"\r\n");
+                }
+                response.extend_from_slice(b"0\r\n\r\n");
+                if socket.write_all(&response).await.is_err() {
+                    return;
+                }
+                buffer.clear();
+            }
+            match socket.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            }
+        }
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    // Send the body a chunk at a time so it spans several reads, twice over
+    // one kept-alive connection
+    let mut stream = TcpStream::connect(("127.0.0.1", load_balancer_port))
+        .await
+        .unwrap();
+    let mut bodies = Vec::new();
+    for round in 0..2 {
+        stream
+            .write_all(
+                b"POST /upload HTTP/1.1\r\nConnection: keep-alive\r\nTransfer-Encoding: chunked\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        for part in ["hello ", "chunked ", "world"] {
+            sleep(Duration::from_millis(20)).await;
+            let part = format!("{}{}", part, round);
+            stream
+                .write_all(format!("{:x}\r\n{}\r\n", part.len(), part).as_bytes())
+                .await
+                .unwrap();
+        }
+        stream.write_all(b"0\r\n\r\n").await.unwrap();
+
+        let mut response = Vec::new();
+        let mut chunk = [0; 1024];
+        let body = loop {
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "{}", String::from_utf8_lossy(&response));
+            response.extend_from_slice(&chunk[..n]);
+            let head_end = response.windows(4).position(|w| w == b"\r\n\r\n");
+            if let Some(body) = head_end.and_then(|end| decode_chunked(&response[end + 4..])) {
+                break body;
+            }
+        };
+        let head = String::from_utf8_lossy(&response);
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+        assert!(head.contains("Connection: keep-alive\r\n"), "{}", head);
+        bodies.push(String::from_utf8(body).unwrap());
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(bodies, ["hello 0chunked 0world0", "hello 1chunked 1world1"]);
+}
+
+#[tokio::test]
+async fn test_ipv6_backend_is_proxied() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, _, backend_handle) = spawn_backend_at("[::1]:0", |mut socket| async move {
+        let mut buffer = [0; 4096];
+        let _ = socket.read(&mut buffer).await;
+        let _ = socket
+            .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 4\r\n\r\nipv6")
+            .await;
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin");
+    let handle = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let response = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let metrics = handle.get_metrics().await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("ipv6"), "{}", response);
+    assert!(metrics[&backend].contains("Requests: 1"), "{:?}", metrics);
+}
+
+#[tokio::test]
+async fn test_http2_preface_is_passed_through() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Preface followed by an empty SETTINGS frame
+    let mut request = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".to_vec();
+    request.extend_from_slice(&[0, 0, 0, 4, 0, 0, 0, 0, 0]);
+    let settings_ack = &[0, 0, 0, 4, 1, 0, 0, 0, 0];
+    let (backend, received, backend_handle) =
+        spawn_recording_backend(request.len(), settings_ack).await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", load_balancer_port)).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    // Split mid-preface to exercise detection across reads
+    stream.write_all(&request[..10]).await.unwrap();
+    sleep(Duration::from_millis(50)).await;
+    stream.write_all(&request[10..]).await.unwrap();
+    let mut reply = [0; 9];
+    stream.read_exact(&mut reply).await.unwrap();
+    let received = received.await.unwrap();
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(received, request);
+    assert_eq!(&reply, settings_ack);
+}
+
+#[tokio::test]
+async fn test_http2_mode_skips_request_sniffing() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let request = b"GET /metrics HTTP/1.1\r\n\r\n";
+    let (backend, received, backend_handle) =
+        spawn_recording_backend(request.len(), b"from backend").await;
+
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, vec![backend], "round-robin").with_http2(true);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let response = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+    let received = received.await.unwrap();
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // Forwarded byte for byte, with no X-Forwarded-For or metrics reply
+    assert_eq!(received, request);
+    assert_eq!(response, "from backend");
+}
+
+#[tokio::test]
+async fn test_header_timeout_drops_silent_clients() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_max_connections(1)
+        .with_header_timeout(Duration::from_millis(300));
+    let handle = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    // Connect without sending anything; the balancer hangs up on its own
+    let mut silent = TcpStream::connect(("127.0.0.1", load_balancer_port))
+        .await
+        .unwrap();
+    let start = std::time::Instant::now();
+    let mut response = Vec::new();
+    let closed = tokio::time::timeout(Duration::from_secs(2), silent.read_to_end(&mut response))
+        .await
+        .is_ok();
+    let waited = start.elapsed();
+    sleep(Duration::from_millis(50)).await;
+    let slots_free = handle.available_connections();
+
+    // A client that stalls partway through its head gets a 408
+    let mut stalled = TcpStream::connect(("127.0.0.1", load_balancer_port))
+        .await
+        .unwrap();
+    stalled
+        .write_all(b"GET / HTTP/1.1\r\nHost: loc")
+        .await
+        .unwrap();
+    let mut timed_out = String::new();
+    stalled.read_to_string(&mut timed_out).await.unwrap();
+
+    let ok = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(closed, "silent connection left open");
+    assert!(response.is_empty());
+    assert!(
+        waited >= Duration::from_millis(300),
+        "closed after {:?}",
+        waited
+    );
+    assert_eq!(slots_free, 1);
+    assert!(
+        timed_out.starts_with("HTTP/1.1 408 Request Timeout"),
+        "{}",
+        timed_out
+    );
+    assert!(ok.starts_with("HTTP/1.1 200 OK"), "{}", ok);
+}
+
+#[tokio::test]
+async fn test_pipelined_requests_answered_in_order() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let both = send_raw(
+        load_balancer_port,
+        "GET /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\n\r\n",
+    )
+    .await;
+    // Asking to close ends the connection after the first answer
+    let closed = send_raw(
+        load_balancer_port,
+        "GET /first HTTP/1.1\r\nConnection: close\r\n\r\nGET /second HTTP/1.1\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // Each request reached the backend on its own, and the answers came back
+    // in the order they were asked
+    let responses: Vec<&str> = both.split("HTTP/1.1 200 OK").skip(1).collect();
+    assert_eq!(responses.len(), 2, "{}", both);
+    assert!(responses[0].contains("GET /first "), "{}", both);
+    assert!(!responses[0].contains("GET /second "), "{}", both);
+    assert!(responses[1].contains("GET /second "), "{}", both);
+    let first_head = responses[0].split("\r\n\r\n").next().unwrap();
+    assert!(first_head.contains("Connection: keep-alive"), "{}", both);
+
+    assert_eq!(closed.matches("HTTP/1.1 200 OK").count(), 1, "{}", closed);
+    assert!(!closed.contains("GET /second "), "{}", closed);
+}
+
+#[tokio::test]
+async fn test_empty_server_list_fails_at_startup() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+
+    let empty = LoadBalancer::new(load_balancer_port, vec![], "round-robin");
+    let err = empty.check_servers().await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(
+        err.to_string().contains("no backend servers configured"),
+        "{}",
+        err
+    );
+
+    // Running returns at once instead of serving nothing but 503s
+    let finished = timeout(Duration::from_secs(3), empty.run_with_listener(lb_listener)).await;
+    assert!(finished.is_ok(), "balancer with no servers kept running");
+
+    // Allowed when servers can still be added
+    let dynamic =
+        LoadBalancer::new(load_balancer_port, vec![], "round-robin").with_dynamic_servers();
+    assert!(dynamic.check_servers().await.is_ok());
+    let with_servers = LoadBalancer::new(
+        load_balancer_port,
+        vec!["127.0.0.1:8001".to_string()],
+        "round-robin",
+    );
+    assert!(with_servers.check_servers().await.is_ok());
+}
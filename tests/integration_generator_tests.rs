@@ -0,0 +1,894 @@
+mod common;
+
+use clap::Parser;
+use common::{ok_response, spawn_backend, spawn_counting_backend, spawn_replying_backend};
+use rust_load_balancer::generator::{
+    Buckets, Generator, GeneratorArgs, LatencyHistogram, MethodMix, OutputFormat, ReportSummary,
+    RequestRecord, Verbosity, Warmup,
+};
+use rust_load_balancer::{balancer::LoadBalancer, server::Server};
+
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::{time::timeout, time::Duration};
+
+#[tokio::test]
+async fn test_generator_latency_percentiles() {
+    // Servers
+    let server_listener1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port1 = server_listener1.local_addr().unwrap().port();
+    let server_listener2 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port2 = server_listener2.local_addr().unwrap().port();
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+
+    let server1 = Server::new(server_port1, 50, 100);
+    let server2 = Server::new(server_port2, 50, 100);
+
+    let server1_handle = tokio::spawn(async move {
+        server1.run_with_listener(server_listener1).await;
+    });
+
+    let server2_handle = tokio::spawn(async move {
+        server2.run_with_listener(server_listener2).await;
+    });
+
+    // LB Start w/LocalHost
+    let servers = vec![
+        format!("127.0.0.1:{}", server_port1),
+        format!("127.0.0.1:{}", server_port2),
+    ];
+    let load_balancer = LoadBalancer::new(load_balancer_port, servers, "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", load_balancer_port), 5, 0.5);
+    let result = timeout(Duration::from_secs(30), generator.run(50)).await;
+
+    server1_handle.abort();
+    server2_handle.abort();
+    load_balancer_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.successful_requests, 50);
+
+    for stats in [&report.get_latency, &report.post_latency] {
+        assert!(stats.count > 0);
+        assert!(stats.p99 >= stats.p95);
+        assert!(stats.p95 >= stats.p50);
+        assert!(stats.max >= stats.p99);
+    }
+
+    // POST backends sleep longer than GET backends
+    assert!(report.get_latency.p50 >= Duration::from_millis(50));
+    assert!(report.post_latency.p50 >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn test_generator_rate_paces_requests() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--url",
+        &format!("http://127.0.0.1:{}", server_port),
+        "--num-requests",
+        "100",
+        "--concurrent-clients",
+        "5",
+        "--rate",
+        "50",
+    ])
+    .unwrap();
+    let generator = Generator::from_args(&args).unwrap();
+    let result = timeout(Duration::from_secs(30), generator.run(args.num_requests)).await;
+
+    server_handle.abort();
+
+    // 100 requests at 50/s should take roughly two seconds
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.successful_requests, 100);
+    assert!(report.duration >= Duration::from_millis(1900));
+    assert!(report.duration < Duration::from_millis(3500));
+}
+
+#[tokio::test]
+async fn test_generator_duration_mode() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--url",
+        &format!("http://127.0.0.1:{}", server_port),
+        "--concurrent-clients",
+        "3",
+        "--duration",
+        "2",
+    ])
+    .unwrap();
+    let generator = Generator::from_args(&args).unwrap();
+    let result = timeout(Duration::from_secs(30), generator.run(args.num_requests)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert!(report.total_requests > 0);
+    assert!(report.successful_requests > 0);
+    assert!(report.duration >= Duration::from_secs(2));
+    assert!(report.duration < Duration::from_millis(3000));
+}
+
+#[tokio::test]
+async fn test_generator_rate_paces_duration_mode() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 0, 0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--url",
+        &format!("http://127.0.0.1:{}", server_port),
+        "--concurrent-clients",
+        "4",
+        "--duration",
+        "2",
+        "--rate",
+        "20",
+    ])
+    .unwrap();
+    let generator = Generator::from_args(&args).unwrap();
+    let result = timeout(Duration::from_secs(30), generator.run(args.num_requests)).await;
+
+    server_handle.abort();
+
+    // An unpaced run against an instant server sends far more than 40
+    let report = result.expect("Test timed out before completion");
+    assert!(
+        (30..=50).contains(&report.total_requests),
+        "{} requests in 2s at 20/s",
+        report.total_requests
+    );
+}
+
+#[test]
+fn test_generator_duration_conflicts_with_num_requests() {
+    let result =
+        GeneratorArgs::try_parse_from(["generator", "--num-requests", "100", "--duration", "2"]);
+
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("cannot be used with"));
+}
+
+#[test]
+fn test_generator_rejects_ratio_out_of_range() {
+    let err = GeneratorArgs::try_parse_from(["generator", "--get-ratio", "1.5"])
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("--get-ratio"), "{}", err);
+    assert!(err.contains("expected 0.0 to 1.0"), "{}", err);
+
+    assert!(GeneratorArgs::try_parse_from(["generator", "-r", "-0.2"]).is_err());
+    assert!(GeneratorArgs::try_parse_from(["generator", "--put-ratio", "2"]).is_err());
+    assert!(GeneratorArgs::try_parse_from(["generator", "--get-ratio", "NaN"]).is_err());
+    let args = GeneratorArgs::try_parse_from(["generator", "--get-ratio", "1.0"]).unwrap();
+    assert_eq!(args.get_ratio, 1.0);
+}
+
+#[tokio::test]
+async fn test_generator_rejects_rates_it_cannot_pace() {
+    for rate in ["0", "-5", "NaN", "inf"] {
+        let err = GeneratorArgs::try_parse_from(["generator", &format!("--rate={}", rate)])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("--rate"), "{}", err);
+    }
+    let mut args = GeneratorArgs::try_parse_from(["generator"]).unwrap();
+    args.rate = Some(0.0);
+    let err = Generator::from_args(&args)
+        .err()
+        .expect("accepted a rate of 0");
+    assert!(err.contains("expected a finite number above 0"), "{}", err);
+
+    // A rate too high to space out still runs rather than panicking
+    let (backend, hits, backend_handle) = spawn_counting_backend().await;
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--url",
+        &format!("http://{}", backend),
+        "-n",
+        "1",
+        "-c",
+        "1",
+        "--rate",
+        "1e12",
+    ])
+    .unwrap();
+    let generator = Generator::from_args(&args).unwrap();
+    let report = timeout(Duration::from_secs(10), generator.run(args.num_requests))
+        .await
+        .expect("Test timed out before completion");
+
+    backend_handle.abort();
+
+    assert_eq!(report.successful_requests, 1);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_generator_rejects_ratios_summing_past_one() {
+    let args =
+        GeneratorArgs::try_parse_from(["generator", "--get-ratio", "0.8", "--put-ratio", "0.5"])
+            .unwrap();
+    let err = Generator::from_args(&args)
+        .err()
+        .expect("accepted a 130% mix");
+    assert!(err.contains("add up to 1.3"), "{}", err);
+    assert!(err.contains("can't exceed 1.0"), "{}", err);
+
+    // Exactly filling the mix leaves POST nothing, which is fine
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--get-ratio",
+        "0.5",
+        "--put-ratio",
+        "0.3",
+        "--delete-ratio",
+        "0.2",
+    ])
+    .unwrap();
+    assert!(Generator::from_args(&args).is_ok());
+}
+
+#[test]
+fn test_generator_rejects_zero_clients() {
+    let args = GeneratorArgs::try_parse_from(["generator", "-c", "0", "-n", "3"]).unwrap();
+    let err = Generator::from_args(&args)
+        .err()
+        .expect("accepted zero clients");
+    assert!(
+        err.contains("--concurrent-clients must be at least 1"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_generator_verbosity_flags() {
+    let parse = |flags: &[&str]| {
+        let args = GeneratorArgs::try_parse_from([&["generator"], flags].concat()).unwrap();
+        Verbosity::from_flags(args.quiet, args.verbose)
+    };
+
+    assert_eq!(parse(&[]), Verbosity::Normal);
+    assert_eq!(parse(&["--quiet"]), Verbosity::Quiet);
+    assert_eq!(parse(&["-v"]), Verbosity::Verbose);
+    assert!(GeneratorArgs::try_parse_from(["generator", "-q", "-v"]).is_err());
+}
+
+#[tokio::test]
+async fn test_generator_quiet_prints_only_summary() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let url = format!("http://127.0.0.1:{}", server_port);
+    // Per-request lines look like "Client 0 - GET request 1 successful in 1.0ms"
+    let per_request = |stdout: &str| {
+        stdout
+            .lines()
+            .filter(|line| line.starts_with("Client ") && line.contains(" request "))
+            .count()
+    };
+    let run = |verbosity: &'static str| {
+        tokio::process::Command::new(env!("CARGO_BIN_EXE_rust_load_balancer"))
+            .args(["generator", "--url", &url, "--num-requests", "5", verbosity])
+            .output()
+    };
+    let quiet = run("--quiet").await.unwrap();
+    let verbose = run("--verbose").await.unwrap();
+
+    server_handle.abort();
+
+    let quiet_stdout = String::from_utf8_lossy(&quiet.stdout);
+    assert!(quiet.status.success());
+    assert_eq!(per_request(&quiet_stdout), 0, "{}", quiet_stdout);
+    assert!(
+        quiet_stdout.starts_with("Load test completed in"),
+        "{}",
+        quiet_stdout
+    );
+    assert!(quiet_stdout.contains("Successful requests: 5/5"));
+
+    let verbose_stdout = String::from_utf8_lossy(&verbose.stdout);
+    assert_eq!(per_request(&verbose_stdout), 5, "{}", verbose_stdout);
+    assert!(verbose_stdout.contains("Successful requests: 5/5"));
+}
+
+#[tokio::test]
+async fn test_generator_streams_json_line_per_request() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let path = std::env::temp_dir().join(format!("lb-stream-{}.jsonl", std::process::id()));
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", server_port), 2, 0.5)
+        .with_warmup(Warmup::Requests(3))
+        .with_stream_json(path.clone());
+    let result = timeout(Duration::from_secs(30), generator.run(6)).await;
+
+    // Straight to stdout from the binary: nothing but JSON lines there
+    let stdout_run = tokio::process::Command::new(env!("CARGO_BIN_EXE_rust_load_balancer"))
+        .args([
+            "--seed",
+            "1",
+            "generator",
+            "--stream-json",
+            "--num-requests",
+            "4",
+        ])
+        .args(["--url", &format!("http://127.0.0.1:{}", server_port)])
+        .output()
+        .await
+        .unwrap();
+
+    server_handle.abort();
+
+    result.expect("Test timed out before completion");
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    // Warmup requests aren't streamed
+    let records: Vec<RequestRecord> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 6, "{}", contents);
+    for record in &records {
+        assert!(record.client_id < 2);
+        assert!(["GET", "POST"].contains(&record.method.as_str()));
+        assert_eq!(record.status, Some(200));
+        assert!(record.success);
+        assert!(record.latency_ms >= 10.0, "{:?}", record);
+    }
+    let line: serde_json::Value = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+    for field in [
+        "client_id",
+        "request_id",
+        "method",
+        "status",
+        "latency_ms",
+        "success",
+    ] {
+        assert!(line.get(field).is_some(), "missing {}: {}", field, line);
+    }
+
+    let stdout = String::from_utf8_lossy(&stdout_run.stdout);
+    let stderr = String::from_utf8_lossy(&stdout_run.stderr);
+    assert_eq!(stdout.lines().count(), 4, "{}", stdout);
+    for line in stdout.lines() {
+        serde_json::from_str::<RequestRecord>(line).unwrap();
+    }
+    assert!(stderr.contains("Successful requests: 4/4"), "{}", stderr);
+}
+
+#[test]
+fn test_generator_distributes_request_remainder() {
+    let generator = Generator::new("http://127.0.0.1:8000", 3, 0.7);
+
+    let counts = generator.client_request_counts(10);
+
+    assert_eq!(counts, vec![4, 3, 3]);
+    assert_eq!(counts.iter().sum::<usize>(), 10);
+}
+
+#[tokio::test]
+async fn test_generator_sends_every_request() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", server_port), 3, 0.7);
+    let result = timeout(Duration::from_secs(30), generator.run(10)).await;
+
+    server_handle.abort();
+
+    // 10 requests over 3 clients should not drop the remainder
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.total_requests, 10);
+    assert_eq!(report.successful_requests, 10);
+}
+
+#[tokio::test]
+async fn test_generator_get_ratio_is_random_per_request() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 0, 0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", server_port), 10, 0.7);
+    let result = timeout(Duration::from_secs(60), generator.run(1000)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.successful_requests, 1000);
+
+    // Observed GET share should be close to the configured ratio
+    let observed = report.get_latency.count as f64 / report.successful_requests as f64;
+    assert!(
+        (observed - 0.7).abs() < 0.06,
+        "observed GET ratio {} too far from 0.7",
+        observed
+    );
+}
+
+#[tokio::test]
+async fn test_generator_writes_json_results() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let path = std::env::temp_dir().join("rust_load_balancer_generator_results.json");
+    let _ = std::fs::remove_file(&path);
+
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", server_port), 2, 0.5)
+        .with_output(path.clone(), OutputFormat::Json);
+    let result = timeout(Duration::from_secs(30), generator.run(10)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let summary: ReportSummary = serde_json::from_str(&contents).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(summary.total_requests, 10);
+    assert_eq!(summary.successful_requests, report.successful_requests);
+    assert_eq!(summary.failed_requests, 10 - report.successful_requests);
+    assert!(summary.duration_secs > 0.0);
+}
+
+#[tokio::test]
+async fn test_generator_splits_clients_across_urls() {
+    let (addr_a, hits_a, handle_a) = spawn_counting_backend().await;
+    let (addr_b, hits_b, handle_b) = spawn_counting_backend().await;
+    let url_a = format!("http://{}", addr_a);
+    let url_b = format!("http://{}", addr_b);
+
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--url",
+        &format!("{},{}", url_a, url_b),
+        "--concurrent-clients",
+        "4",
+        "--num-requests",
+        "40",
+    ])
+    .unwrap();
+    assert_eq!(args.urls, vec![url_a.clone(), url_b.clone()]);
+    let generator = Generator::from_args(&args).unwrap();
+    let plan = generator.plan(args.num_requests).to_string();
+    let result = timeout(Duration::from_secs(30), generator.run(args.num_requests)).await;
+
+    handle_a.abort();
+    handle_b.abort();
+
+    // Clients alternate between the targets, so each gets half the traffic
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(hits_a.load(Ordering::SeqCst), 20);
+    assert_eq!(hits_b.load(Ordering::SeqCst), 20);
+    assert_eq!(report.targets.len(), 2);
+    for (target, url) in report.targets.iter().zip([&url_a, &url_b]) {
+        assert_eq!(&target.url, url);
+        assert_eq!(target.total_requests, 20);
+        assert_eq!(target.successful_requests, 20);
+        assert_eq!(target.latency.count, 20);
+    }
+    let summary = report.target_summary();
+    assert!(
+        summary.contains(&format!("{}: 20/20 successful", url_a)),
+        "{}",
+        summary
+    );
+    assert!(
+        summary.contains(&format!("{}: 20/20 successful", url_b)),
+        "{}",
+        summary
+    );
+    assert!(
+        plan.contains(&format!("Targets: {}, {}", url_a, url_b)),
+        "{}",
+        plan
+    );
+
+    // The flag can also be repeated
+    let args = GeneratorArgs::try_parse_from(["generator", "-u", &url_a, "--url", &url_b]).unwrap();
+    assert_eq!(args.urls, vec![url_a, url_b]);
+}
+
+#[tokio::test]
+async fn test_generator_spreads_requests_across_paths() {
+    let paths: Arc<Mutex<HashMap<String, usize>>> = Arc::new(Mutex::new(HashMap::new()));
+    let seen = Arc::clone(&paths);
+    let (addr, _, backend) = spawn_replying_backend(move |_, request| {
+        if let Some(path) = request.split_whitespace().nth(1) {
+            *seen.lock().unwrap().entry(path.to_string()).or_insert(0) += 1;
+        }
+        ok_response("ok")
+    })
+    .await;
+
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--url",
+        &format!("http://{}", addr),
+        "--paths",
+        "/api,/static,/slow",
+        "--concurrent-clients",
+        "5",
+        "--num-requests",
+        "300",
+    ])
+    .unwrap();
+    assert_eq!(args.paths, vec!["/api", "/static", "/slow"]);
+    let generator = Generator::from_args(&args).unwrap();
+    let plan = generator.plan(args.num_requests).to_string();
+    let result = timeout(Duration::from_secs(30), generator.run(args.num_requests)).await;
+
+    backend.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.successful_requests, 300);
+    let paths = paths.lock().unwrap();
+    assert_eq!(paths.values().sum::<usize>(), 300);
+    assert_eq!(paths.len(), 3, "{:?}", paths);
+    for path in ["/api", "/static", "/slow"] {
+        let hits = paths.get(path).copied().unwrap_or(0);
+        assert!((60..=140).contains(&hits), "{} got {} of 300", path, hits);
+    }
+    assert!(
+        plan.contains("Paths: /api, /static, /slow (picked at random)"),
+        "{}",
+        plan
+    );
+}
+
+#[tokio::test]
+async fn test_generator_warmup_excluded_from_results() {
+    let (addr, hits, backend_handle) = spawn_counting_backend().await;
+
+    let generator =
+        Generator::new(&format!("http://{}", addr), 2, 0.5).with_warmup(Warmup::Requests(5));
+    let result = timeout(Duration::from_secs(30), generator.run(10)).await;
+
+    backend_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+
+    // Warmup traffic reached the backend...
+    assert_eq!(hits.load(Ordering::SeqCst), 15);
+    assert_eq!(report.warmup_requests, 5);
+
+    // ...but only the measured requests are reported
+    assert_eq!(report.total_requests, 10);
+    assert_eq!(report.successful_requests, 10);
+    assert_eq!(report.get_latency.count + report.post_latency.count, 10);
+}
+
+#[test]
+fn test_generator_warmup_parsing() {
+    assert_eq!("50".parse::<Warmup>(), Ok(Warmup::Requests(50)));
+    assert_eq!(
+        "5s".parse::<Warmup>(),
+        Ok(Warmup::Duration(Duration::from_secs(5)))
+    );
+    assert!("soon".parse::<Warmup>().is_err());
+}
+
+#[tokio::test]
+async fn test_generator_includes_put_and_delete() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 0, 0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", server_port), 5, 0.25)
+        .with_put_ratio(0.25)
+        .with_delete_ratio(0.25);
+    let result = timeout(Duration::from_secs(30), generator.run(200)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.successful_requests, 200);
+    assert!(report.put_latency.count > 0);
+    assert!(report.delete_latency.count > 0);
+    assert_eq!(
+        report.get_latency.count
+            + report.post_latency.count
+            + report.put_latency.count
+            + report.delete_latency.count,
+        200
+    );
+}
+
+#[tokio::test]
+async fn test_generator_counts_server_errors_as_failures() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 0, 0).with_error_rate(1.0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", server_port), 2, 0.5);
+    let result = timeout(Duration::from_secs(30), generator.run(10)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.successful_requests, 0);
+    assert_eq!(report.failures.server_error, 10);
+    assert_eq!(report.failures.total(), report.failed_requests());
+}
+
+#[tokio::test]
+async fn test_generator_success_rate_excludes_non_2xx() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 0, 0).with_error_rate(1.0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", server_port), 2, 0.5);
+    let result = timeout(Duration::from_secs(30), generator.run(10)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.total_requests, 10);
+    assert_eq!(report.success_rate(), 0.0);
+    assert_eq!(report.summary().success_rate, 0.0);
+}
+
+#[test]
+fn test_method_mix_parsing() {
+    let mix: MethodMix = "GET=0.5,POST=0.3,PUT=0.15,DELETE=0.05".parse().unwrap();
+    assert_eq!(mix.get(), 0.5);
+    assert!((mix.post() - 0.3).abs() < 1e-9);
+    assert_eq!(mix.put(), 0.15);
+    assert_eq!(mix.delete(), 0.05);
+
+    // Omitted methods get nothing
+    let mix: MethodMix = "get=0.25, delete=0.75".parse().unwrap();
+    assert_eq!(mix.post(), 0.0);
+    assert_eq!(mix.put(), 0.0);
+
+    assert!("GET=0.5,POST=0.3".parse::<MethodMix>().is_err());
+    assert!("GET=0.5,PATCH=0.5".parse::<MethodMix>().is_err());
+    assert!("GET=0.5,GET=0.5".parse::<MethodMix>().is_err());
+    assert!("GET=half".parse::<MethodMix>().is_err());
+
+    // --mix replaces the individual ratio flags
+    let args =
+        GeneratorArgs::try_parse_from(["generator", "--mix", "GET=1.0", "--put-ratio", "0.2"]);
+    assert!(args.is_err());
+}
+
+#[tokio::test]
+async fn test_generator_follows_method_mix() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 0, 0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let mix: MethodMix = "GET=0.5,POST=0.3,PUT=0.15,DELETE=0.05".parse().unwrap();
+    let generator =
+        Generator::new(&format!("http://127.0.0.1:{}", server_port), 10, 0.7).with_mix(mix);
+    let result = timeout(Duration::from_secs(60), generator.run(2000)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.successful_requests, 2000);
+    for (stats, expected) in [
+        (&report.get_latency, 0.5),
+        (&report.post_latency, 0.3),
+        (&report.put_latency, 0.15),
+        (&report.delete_latency, 0.05),
+    ] {
+        let observed = stats.count as f64 / 2000.0;
+        assert!(
+            (observed - expected).abs() < 0.04,
+            "observed share {} too far from {}",
+            observed,
+            expected
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_generator_reports_per_client_results() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let generator = Generator::new(&format!("http://127.0.0.1:{}", server_port), 2, 0.5);
+    let result = timeout(Duration::from_secs(30), generator.run(9)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    let ids: Vec<usize> = report.clients.iter().map(|c| c.client_id).collect();
+    assert_eq!(ids, vec![0, 1]);
+    assert_eq!(report.clients[0].total_requests, 5);
+    assert_eq!(report.clients[1].total_requests, 4);
+    for client in &report.clients {
+        assert_eq!(client.successful_requests, client.total_requests);
+        assert_eq!(client.latency.count, client.total_requests);
+    }
+
+    let summary = report.client_summary();
+    let lines: Vec<&str> = summary.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(
+        lines[0].starts_with("Client 0: 5/5 successful"),
+        "{}",
+        summary
+    );
+    assert!(
+        lines[1].starts_with("Client 1: 4/4 successful"),
+        "{}",
+        summary
+    );
+}
+
+#[tokio::test]
+async fn test_generator_dry_run_sends_nothing() {
+    // Count connections to make sure nothing reaches the target
+    let (addr, accepted, listener_handle) = spawn_backend(|_| async {}).await;
+    let url = format!("http://{}", addr);
+
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--url",
+        &url,
+        "--num-requests",
+        "20",
+        "--concurrent-clients",
+        "3",
+        "--mix",
+        "GET=0.5,POST=0.5",
+        "--dry-run",
+    ])
+    .unwrap();
+    let generator = Generator::from_args(&args).unwrap();
+    let plan = generator.plan(args.num_requests);
+    let report = timeout(Duration::from_secs(5), generator.run(args.num_requests))
+        .await
+        .expect("Dry run should return immediately");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    listener_handle.abort();
+
+    assert_eq!(report.total_requests, 0);
+    assert_eq!(accepted.load(Ordering::SeqCst), 0);
+    assert_eq!(plan.total_requests(), Some(20));
+
+    let output = plan.to_string();
+    for expected in [
+        format!("Target: {}", url),
+        "Clients: 3".to_string(),
+        "Total requests: 20".to_string(),
+        "Client 0: 7 requests".to_string(),
+        "Client 2: 6 requests".to_string(),
+        "Method mix: 50% GET, 50% POST".to_string(),
+    ] {
+        assert!(
+            output.contains(&expected),
+            "missing {:?} in\n{}",
+            expected,
+            output
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_generator_histogram_buckets_match_server_delays() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 20, 120);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let args = GeneratorArgs::try_parse_from([
+        "generator",
+        "--url",
+        &format!("http://127.0.0.1:{}", server_port),
+        "--num-requests",
+        "20",
+        "--concurrent-clients",
+        "4",
+        "--buckets",
+        "50,100,200",
+    ])
+    .unwrap();
+    let generator = Generator::from_args(&args).unwrap();
+    let result = timeout(Duration::from_secs(30), generator.run(args.num_requests)).await;
+
+    server_handle.abort();
+
+    let report = result.expect("Test timed out before completion");
+    assert_eq!(report.successful_requests, 20);
+    // GETs sleep 20ms and POSTs 120ms, so they land two buckets apart
+    assert_eq!(
+        report.histogram.counts,
+        vec![report.get_latency.count, 0, report.post_latency.count, 0]
+    );
+    let labels: Vec<String> = report
+        .histogram
+        .buckets()
+        .into_iter()
+        .map(|(label, _)| label)
+        .collect();
+    assert_eq!(labels, ["<50ms", "50-100ms", "100-200ms", ">=200ms"]);
+}
+
+#[test]
+fn test_bucket_parsing() {
+    let buckets: Buckets = "10, 50ms,100".parse().unwrap();
+    let histogram = LatencyHistogram::from_samples(
+        &buckets,
+        &[
+            Duration::from_millis(5),
+            Duration::from_millis(10),
+            Duration::from_millis(75),
+            Duration::from_millis(500),
+        ],
+    );
+    assert_eq!(histogram.counts, vec![1, 1, 1, 1]);
+
+    assert!("".parse::<Buckets>().is_err());
+    assert!("50,10".parse::<Buckets>().is_err());
+    assert!("10,10".parse::<Buckets>().is_err());
+    assert!("fast".parse::<Buckets>().is_err());
+}
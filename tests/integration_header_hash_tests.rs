@@ -0,0 +1,122 @@
+mod common;
+
+use common::{body, send_raw, spawn_named_backend};
+use rust_load_balancer::algorithms::{HeaderHash, LoadBalancingAlgorithm, RequestContext};
+use rust_load_balancer::balancer::LoadBalancer;
+
+use tokio::net::TcpListener;
+
+fn user_request(user: &str) -> String {
+    format!("GET / HTTP/1.1\r\nHost: lb\r\nX-User-Id: {}\r\n\r\n", user)
+}
+
+#[tokio::test]
+async fn test_header_hash_pins_requests_by_header() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let mut servers = Vec::new();
+    let mut handles = Vec::new();
+    for _ in 0..3 {
+        let (addr, handle) = spawn_named_backend().await;
+        servers.push(addr);
+        handles.push(handle);
+    }
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, servers.clone(), "header-hash");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let first = send_raw(load_balancer_port, &user_request("alice")).await;
+    let second = send_raw(load_balancer_port, &user_request("alice")).await;
+    let mut chosen = std::collections::HashSet::new();
+    for user in 0..30 {
+        let response = send_raw(load_balancer_port, &user_request(&format!("user-{}", user))).await;
+        chosen.insert(body(&response).to_string());
+    }
+
+    for handle in handles {
+        handle.abort();
+    }
+    load_balancer_handle.abort();
+
+    assert!(servers.contains(&body(&first).to_string()), "{}", first);
+    assert_eq!(body(&first), body(&second));
+    assert!(chosen.len() > 1, "every user landed on {:?}", chosen);
+}
+
+#[tokio::test]
+async fn test_header_hash_without_header_falls_back_to_round_robin() {
+    let hash = HeaderHash::new("X-Tenant");
+    let servers = vec!["a:1".to_string(), "b:2".to_string(), "c:3".to_string()];
+    assert_eq!(hash.affinity_header(), Some("X-Tenant"));
+
+    let keyed = RequestContext {
+        affinity_key: Some("tenant-7".to_string()),
+        ..Default::default()
+    };
+    let pinned = hash.next_server_for(&servers, &keyed).await.unwrap();
+    for _ in 0..5 {
+        assert_eq!(
+            hash.next_server_for(&servers, &keyed).await.unwrap(),
+            pinned
+        );
+    }
+
+    // No header: every backend takes a turn
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..3 {
+        seen.insert(
+            hash.next_server_for(&servers, &RequestContext::default())
+                .await
+                .unwrap(),
+        );
+    }
+    assert_eq!(seen.len(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_header_hash_stays_pinned_under_concurrent_server_lists() {
+    let servers: Vec<String> = (1..=4).map(|i| format!("backend-{}:80", i)).collect();
+    // Requests arriving while the last backend drains see it filtered out
+    let draining = servers[..3].to_vec();
+    let users: Vec<String> = (0..200).map(|i| format!("user-{}", i)).collect();
+
+    let mut expected = Vec::new();
+    for list in [&servers, &draining] {
+        let hash = HeaderHash::default();
+        let mut owners = Vec::new();
+        for user in &users {
+            let request = RequestContext {
+                affinity_key: Some(user.clone()),
+                ..Default::default()
+            };
+            owners.push(hash.next_server_for(list, &request).await.unwrap());
+        }
+        expected.push(owners);
+    }
+
+    let hash = HeaderHash::default();
+    let mut tasks = Vec::new();
+    for (list, owners) in [servers, draining].into_iter().zip(expected) {
+        for _ in 0..2 {
+            let (hash, list, owners, users) =
+                (hash.clone(), list.clone(), owners.clone(), users.clone());
+            tasks.push(tokio::spawn(async move {
+                for _ in 0..5 {
+                    for (user, owner) in users.iter().zip(&owners) {
+                        let request = RequestContext {
+                            affinity_key: Some(user.clone()),
+                            ..Default::default()
+                        };
+                        let server = hash.next_server_for(&list, &request).await.unwrap();
+                        assert_eq!(&server, owner, "{} moved", user);
+                    }
+                }
+            }));
+        }
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
@@ -1,26 +1,32 @@
-use rust_load_balancer::algorithms::{IpHash, LoadBalancingAlgorithm};
+use rust_load_balancer::algorithms::{
+    IpHash, LoadBalancingAlgorithm, RequestContext, MAX_TRACKED_IPS,
+};
 use rust_load_balancer::{balancer::LoadBalancer, generator::Generator, server::Server};
 
 use std::sync::Arc;
+use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 use tokio::{time::timeout, time::Duration};
 
 #[tokio::test]
 async fn test_round_ip_hash_no_timeout() {
     // Servers
-    let server_port1 = 8001;
-    let server_port2 = 8002;
-    let load_balancer_port = 9000;
+    let server_listener1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port1 = server_listener1.local_addr().unwrap().port();
+    let server_listener2 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port2 = server_listener2.local_addr().unwrap().port();
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
 
     let server1 = Server::new(server_port1, 100, 50);
     let server2 = Server::new(server_port2, 100, 50);
 
     let server1_handle = tokio::spawn(async move {
-        server1.run().await;
+        server1.run_with_listener(server_listener1).await;
     });
 
     let server2_handle = tokio::spawn(async move {
-        server2.run().await;
+        server2.run_with_listener(server_listener2).await;
     });
 
     // LB Start w/LocalHost
@@ -30,7 +36,7 @@ async fn test_round_ip_hash_no_timeout() {
     ];
     let load_balancer = LoadBalancer::new(load_balancer_port, servers, "ip-hash");
     let load_balancer_handle = tokio::spawn(async move {
-        load_balancer.run().await;
+        load_balancer.run_with_listener(lb_listener).await;
     });
 
     // Generator w/LB Port,
@@ -69,3 +75,62 @@ async fn test_round_ip_hash_empty_server_list() {
     // No server should be next
     assert!(next_server.is_none());
 }
+
+#[tokio::test]
+async fn test_ip_hash_pins_ipv6_clients() {
+    let servers: Vec<String> = (8001..8009).map(|p| format!("127.0.0.1:{}", p)).collect();
+    let ip_hash = IpHash::new();
+    let context = |addr: &str| RequestContext {
+        client_addr: Some(addr.parse().unwrap()),
+        ..Default::default()
+    };
+
+    let mut picks = Vec::new();
+    for port in [40000, 40001, 40002] {
+        let client = context(&format!("[2001:db8::7]:{}", port));
+        picks.push(ip_hash.next_server_for(&servers, &client).await.unwrap());
+    }
+    let mapped = ip_hash
+        .next_server_for(&servers, &context("[::ffff:10.1.2.3]:40000"))
+        .await;
+    let plain = ip_hash
+        .next_server_for(&servers, &context("10.1.2.3:40001"))
+        .await;
+
+    // Same client on any port keeps its backend
+    assert!(picks.iter().all(|pick| *pick == picks[0]), "{:?}", picks);
+    assert_eq!(mapped, plain);
+}
+
+#[tokio::test]
+async fn test_ip_hash_metrics_list_only_recent_clients() {
+    let servers = vec!["127.0.0.1:8001".to_string(), "127.0.0.1:8002".to_string()];
+    let ip_hash = IpHash::new();
+
+    let client_ip = |i: usize| format!("10.0.{}.{}", i / 256, i % 256);
+    let clients = MAX_TRACKED_IPS * 4;
+    for i in 0..clients {
+        let context = RequestContext {
+            client_addr: Some(format!("{}:40000", client_ip(i)).parse().unwrap()),
+            ..Default::default()
+        };
+        ip_hash.next_server_for(&servers, &context).await;
+    }
+    let metrics = ip_hash.get_metrics().await;
+
+    let listed: Vec<&str> = metrics
+        .values()
+        .flat_map(|line| line.split("IPs: ").nth(1).unwrap().split(", "))
+        .filter(|ip| !ip.is_empty())
+        .collect();
+    let served: usize = ip_hash
+        .state()
+        .await
+        .values()
+        .map(|s| s.total_requests)
+        .sum();
+    assert_eq!(listed.len(), MAX_TRACKED_IPS);
+    assert!(listed.contains(&client_ip(clients - 1).as_str()));
+    assert!(!listed.contains(&client_ip(0).as_str()));
+    assert_eq!(served, clients);
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use common::{ok_response, read_head, spawn_backend, spawn_keep_alive_backend_with};
+use rust_load_balancer::balancer::{LoadBalancer, MAX_BUFFERED_BODY};
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::time::{timeout, Duration};
+
+// Deterministic, non-repeating-looking body bytes
+fn body_bytes(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+async fn start_balancer(backend: String) -> (u16, tokio::task::JoinHandle<()>) {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = lb_listener.local_addr().unwrap().port();
+    let load_balancer = LoadBalancer::new(port, vec![backend], "round-robin");
+    let handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    (port, handle)
+}
+
+// Read one response with a Content-Length body off `stream`
+async fn read_sized_response(stream: &mut TcpStream) -> (String, Vec<u8>) {
+    let head = String::from_utf8(read_head(stream).await.unwrap()).unwrap();
+    let len: usize = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await.unwrap();
+    (head, body)
+}
+
+#[tokio::test]
+async fn test_multi_megabyte_response_over_kept_alive_connections() {
+    let body = body_bytes(4 * MAX_BUFFERED_BODY + 7);
+    let mut response =
+        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+    response.extend_from_slice(&body);
+    let (backend, connections, backend_handle) = spawn_keep_alive_backend_with(response).await;
+    let (port, load_balancer_handle) = start_balancer(backend).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    for _ in 0..2 {
+        stream
+            .write_all(b"GET /big HTTP/1.1\r\nConnection: keep-alive\r\n\r\n")
+            .await
+            .unwrap();
+        let (head, received) = timeout(Duration::from_secs(10), read_sized_response(&mut stream))
+            .await
+            .unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+        assert!(received == body, "body differs from what the backend sent");
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // The balancer read no further than each body's end, so the backend
+    // connection went back to the pool and served the second request too
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_multi_megabyte_chunked_response() {
+    let body = body_bytes(3 * MAX_BUFFERED_BODY);
+    let mut encoded = Vec::new();
+    for chunk in body.chunks(50_000) {
+        encoded.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+        encoded.extend_from_slice(chunk);
+        encoded.extend_from_slice(b"\r\n");
+    }
+    encoded.extend_from_slice(b"0\r\n\r\n");
+    let mut response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    response.extend_from_slice(&encoded);
+    let (backend, _, backend_handle) = spawn_keep_alive_backend_with(response).await;
+    let (port, load_balancer_handle) = start_balancer(backend).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(b"GET /big HTTP/1.1\r\n\r\n")
+        .await
+        .unwrap();
+    let mut received = Vec::new();
+    timeout(Duration::from_secs(10), stream.read_to_end(&mut received))
+        .await
+        .unwrap()
+        .unwrap();
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    let head_end = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert!(received.starts_with(b"HTTP/1.1 200 OK"));
+    assert!(received[head_end..] == encoded[..], "chunked body altered");
+}
+
+#[tokio::test]
+async fn test_large_response_streams_before_backend_finishes() {
+    let body = body_bytes(3 * MAX_BUFFERED_BODY);
+    let (first, rest) = body.split_at(2 * MAX_BUFFERED_BODY);
+    let (first, rest) = (first.to_vec(), rest.to_vec());
+    let release = Arc::new(Notify::new());
+
+    // Backend that stalls partway through its response until released
+    let stalled = release.clone();
+    let (backend, _, backend_handle) = spawn_backend(move |mut socket| {
+        let (first, rest, stalled) = (first.clone(), rest.clone(), stalled.clone());
+        async move {
+            read_head(&mut socket).await.unwrap();
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                first.len() + rest.len()
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&first).await.unwrap();
+            stalled.notified().await;
+            socket.write_all(&rest).await.unwrap();
+        }
+    })
+    .await;
+    let (port, load_balancer_handle) = start_balancer(backend).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(b"GET /big HTTP/1.1\r\n\r\n")
+        .await
+        .unwrap();
+
+    // More than the buffer limit arrives while the backend is still stalled,
+    // so the balancer isn't holding the whole response back
+    let mut received = Vec::new();
+    let mut chunk = [0; 65536];
+    timeout(Duration::from_secs(10), async {
+        while received.len() <= MAX_BUFFERED_BODY + 100 {
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "closed before the backend was released");
+            received.extend_from_slice(&chunk[..n]);
+        }
+    })
+    .await
+    .expect("response was held back until the backend finished");
+
+    release.notify_one();
+    timeout(Duration::from_secs(10), stream.read_to_end(&mut received))
+        .await
+        .unwrap()
+        .unwrap();
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    let head_end = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert!(
+        received[head_end..] == body[..],
+        "body differs from what the backend sent"
+    );
+}
+
+#[tokio::test]
+async fn test_large_request_body_streams_to_backend() {
+    // Cookie affinity has the balancer edit the response head, which it must
+    // do while the body is still going out
+    for algorithm in ["round-robin", "cookie-affinity"] {
+        let len = 3 * MAX_BUFFERED_BODY;
+        let head_seen = Arc::new(Notify::new());
+        let seen = head_seen.clone();
+        // Signals once the head arrives, then answers whether the body matched
+        let (backend, _, backend_handle) = spawn_backend(move |mut socket| {
+            let seen = seen.clone();
+            async move {
+                read_head(&mut socket).await.unwrap();
+                seen.notify_one();
+                let mut body = vec![0; len];
+                socket.read_exact(&mut body).await.unwrap();
+                let received = (body == body_bytes(len)).to_string();
+                socket
+                    .write_all(ok_response(&received).as_bytes())
+                    .await
+                    .unwrap();
+            }
+        })
+        .await;
+        let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = lb_listener.local_addr().unwrap().port();
+        let load_balancer = LoadBalancer::new(port, vec![backend], algorithm);
+        let load_balancer_handle = tokio::spawn(async move {
+            load_balancer.run_with_listener(lb_listener).await;
+        });
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let body = body_bytes(len);
+        let head = format!("POST /upload HTTP/1.1\r\nContent-Length: {}\r\n\r\n", len);
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(&body[..MAX_BUFFERED_BODY]).await.unwrap();
+        // Most of the body hasn't been sent, yet the backend already has the head
+        let forwarded = timeout(Duration::from_secs(5), head_seen.notified()).await;
+        stream.write_all(&body[MAX_BUFFERED_BODY..]).await.unwrap();
+        let (head, received) = read_sized_response(&mut stream).await;
+
+        backend_handle.abort();
+        load_balancer_handle.abort();
+
+        assert!(forwarded.is_ok(), "{}: body was buffered first", algorithm);
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+        assert_eq!(received, b"true", "{}", algorithm);
+    }
+}
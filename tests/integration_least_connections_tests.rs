@@ -2,25 +2,29 @@ use rust_load_balancer::algorithms::{LeastConnections, LoadBalancingAlgorithm};
 use rust_load_balancer::{balancer::LoadBalancer, generator::Generator, server::Server};
 
 use std::sync::Arc;
+use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 use tokio::{time::timeout, time::Duration};
 
 #[tokio::test]
 async fn test_round_least_connections_no_timeout() {
     // Servers
-    let server_port1 = 8001;
-    let server_port2 = 8002;
-    let load_balancer_port = 9000;
+    let server_listener1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port1 = server_listener1.local_addr().unwrap().port();
+    let server_listener2 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port2 = server_listener2.local_addr().unwrap().port();
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
 
     let server1 = Server::new(server_port1, 100, 50);
     let server2 = Server::new(server_port2, 100, 50);
 
     let server1_handle = tokio::spawn(async move {
-        server1.run().await;
+        server1.run_with_listener(server_listener1).await;
     });
 
     let server2_handle = tokio::spawn(async move {
-        server2.run().await;
+        server2.run_with_listener(server_listener2).await;
     });
 
     // LB Start w/LocalHost
@@ -30,7 +34,7 @@ async fn test_round_least_connections_no_timeout() {
     ];
     let load_balancer = LoadBalancer::new(load_balancer_port, servers, "least-connections");
     let load_balancer_handle = tokio::spawn(async move {
-        load_balancer.run().await;
+        load_balancer.run_with_listener(lb_listener).await;
     });
 
     // Generator w/LB Port,
@@ -69,3 +73,25 @@ async fn test_round_least_connections_empty_server_list() {
     // No server should be next
     assert!(next_server.is_none());
 }
+
+#[tokio::test]
+async fn test_least_connections_select_n_respects_active_counts() {
+    let servers: Vec<String> = ["a:1", "b:1", "c:1"].map(String::from).to_vec();
+    let least_connections = LeastConnections::new();
+    for _ in 0..2 {
+        least_connections.connection_started("a:1").await;
+    }
+    least_connections.connection_started("b:1").await;
+
+    // Picks open no connections, so the idle server takes them all
+    let counts = least_connections.select_n(&servers, 10).await;
+    assert_eq!(counts.get("c:1"), Some(&10), "{:?}", counts);
+    assert_eq!(counts.len(), 1);
+
+    // Once c is the busiest, b (one active) is the least loaded
+    for _ in 0..3 {
+        least_connections.connection_started("c:1").await;
+    }
+    let counts = least_connections.select_n(&servers, 10).await;
+    assert_eq!(counts.get("b:1"), Some(&10), "{:?}", counts);
+}
@@ -0,0 +1,103 @@
+use rust_load_balancer::algorithms::{Algorithm, LeastScore, LoadBalancingAlgorithm};
+
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_least_score_prefers_reliable_over_fast_but_failing() {
+    let fast = "127.0.0.1:8001".to_string();
+    let reliable = "127.0.0.1:8002".to_string();
+    let servers = vec![fast.clone(), reliable.clone()];
+    let algorithm = Algorithm::new("least-score", None);
+
+    // The fast backend answers in 10ms but half its requests fail; the
+    // reliable one takes 30ms and never fails
+    for request in 0..20 {
+        algorithm
+            .record_result(&fast, Some(Duration::from_millis(10)), request % 2 == 0)
+            .await;
+        algorithm
+            .record_result(&reliable, Some(Duration::from_millis(30)), true)
+            .await;
+    }
+
+    let mut picks = Vec::new();
+    for _ in 0..5 {
+        picks.push(algorithm.next_server(&servers).await.unwrap());
+    }
+
+    assert!(picks.iter().all(|pick| *pick == reliable), "{:?}", picks);
+}
+
+#[tokio::test]
+async fn test_least_score_combines_p95_and_error_rate() {
+    let scorer = LeastScore::new(1.0, 10.0);
+    let server = "127.0.0.1:8001";
+
+    // p95 of 1..=100ms is 95ms; 5 failures in 100 is 5%
+    for ms in 1..=100 {
+        scorer
+            .record_result(server, Some(Duration::from_millis(ms)), ms > 5)
+            .await;
+    }
+    let score = scorer.score(server).await.unwrap();
+    assert!((score - (95.0 + 50.0)).abs() < 1e-6, "score {}", score);
+
+    // Connection failures count as errors without adding latency
+    let latency_only = LeastScore::new(1.0, 0.0);
+    latency_only
+        .record_result(server, Some(Duration::from_millis(20)), true)
+        .await;
+    latency_only.record_result(server, None, false).await;
+    assert_eq!(latency_only.score(server).await, Some(20.0));
+    assert_eq!(latency_only.score("127.0.0.1:8002").await, None);
+}
+
+#[tokio::test]
+async fn test_least_score_weights_are_configurable() {
+    let fast = "127.0.0.1:8001".to_string();
+    let reliable = "127.0.0.1:8002".to_string();
+    let servers = vec![fast.clone(), reliable.clone()];
+    // Errors don't matter at all, so the fast backend wins despite failing
+    let algorithm = Algorithm::LeastScore(LeastScore::new(1.0, 0.0));
+
+    for request in 0..20 {
+        algorithm
+            .record_result(&fast, Some(Duration::from_millis(10)), request % 2 == 0)
+            .await;
+        algorithm
+            .record_result(&reliable, Some(Duration::from_millis(30)), true)
+            .await;
+    }
+
+    assert_eq!(algorithm.next_server(&servers).await, Some(fast));
+}
+
+#[tokio::test]
+async fn test_least_score_retries_a_backend_after_its_bad_stretch_ages_out() {
+    let flaky = "127.0.0.1:8001".to_string();
+    let steady = "127.0.0.1:8002".to_string();
+    let servers = vec![flaky.clone(), steady.clone()];
+    let algorithm =
+        Algorithm::LeastScore(LeastScore::default().with_max_age(Duration::from_millis(200)));
+
+    // Every request to the flaky backend failed, so it's shunned for now
+    for _ in 0..20 {
+        algorithm.record_result(&flaky, None, false).await;
+    }
+    algorithm
+        .record_result(&steady, Some(Duration::from_millis(30)), true)
+        .await;
+    assert_eq!(algorithm.next_server(&servers).await, Some(steady.clone()));
+
+    // Once its failures are old news it gets a request again, and a healthy
+    // answer keeps it in rotation
+    sleep(Duration::from_millis(250)).await;
+    algorithm
+        .record_result(&steady, Some(Duration::from_millis(30)), true)
+        .await;
+    assert_eq!(algorithm.next_server(&servers).await, Some(flaky.clone()));
+    algorithm
+        .record_result(&flaky, Some(Duration::from_millis(10)), true)
+        .await;
+    assert_eq!(algorithm.next_server(&servers).await, Some(flaky));
+}
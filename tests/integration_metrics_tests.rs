@@ -0,0 +1,488 @@
+mod common;
+
+use common::{send_raw, spawn_failing_backend, spawn_holding_backend, spawn_ok_backend};
+use rust_load_balancer::{
+    balancer::{supervise, LoadBalancer},
+    client::SenderClient,
+    server::Server,
+};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_metrics_reset_counts_only_new_traffic() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_ok_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    for _ in 0..3 {
+        send_raw(load_balancer_port, request).await;
+    }
+    let before = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+
+    let reset = send_raw(
+        load_balancer_port,
+        "POST /admin/metrics/reset HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+    )
+    .await;
+
+    for _ in 0..2 {
+        send_raw(load_balancer_port, request).await;
+    }
+    let after = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(before.contains(&format!("{}: Requests: 3,", backend)));
+    assert!(reset.starts_with("HTTP/1.1 200 OK"));
+    assert!(
+        after.contains(&format!("{}: Requests: 2,", backend)),
+        "{}",
+        after
+    );
+}
+
+// Pull the "Avg latency" figure reported for `server` out of /metrics
+fn average_latency_ms(metrics: &str, server: &str) -> f64 {
+    let line = metrics
+        .lines()
+        .find(|line| line.starts_with(&format!("{}:", server)))
+        .unwrap();
+    let value = line.split("Avg latency: ").nth(1).unwrap();
+    value.split("ms").next().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_metrics_report_backend_latency() {
+    let server_listener1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port1 = server_listener1.local_addr().unwrap().port();
+    let server_listener2 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port2 = server_listener2.local_addr().unwrap().port();
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+
+    let server1 = Server::new(server_port1, 50, 50);
+    let server2 = Server::new(server_port2, 250, 250);
+    let server1_handle = tokio::spawn(async move {
+        server1.run_with_listener(server_listener1).await;
+    });
+    let server2_handle = tokio::spawn(async move {
+        server2.run_with_listener(server_listener2).await;
+    });
+
+    let servers = vec![
+        format!("127.0.0.1:{}", server_port1),
+        format!("127.0.0.1:{}", server_port2),
+    ];
+    let load_balancer = LoadBalancer::new(load_balancer_port, servers.clone(), "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", load_balancer_port));
+    for _ in 0..4 {
+        client.get_read_request("").await.unwrap();
+    }
+    let metrics = client
+        .get_read_request("metrics")
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    server1_handle.abort();
+    server2_handle.abort();
+    load_balancer_handle.abort();
+
+    let fast = average_latency_ms(&metrics, &servers[0]);
+    let slow = average_latency_ms(&metrics, &servers[1]);
+    assert!(fast >= 50.0, "{}", metrics);
+    assert!(slow >= 250.0, "{}", metrics);
+    assert!(slow > fast);
+}
+
+#[tokio::test]
+async fn test_metrics_total_matches_per_server_counts() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend1, backend1_handle) = spawn_ok_backend().await;
+    let (backend2, backend2_handle) = spawn_ok_backend().await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![backend1.clone(), backend2.clone()],
+        "round-robin",
+    );
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    for _ in 0..7 {
+        send_raw(load_balancer_port, request).await;
+    }
+    let text = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+    let json = send_raw(
+        load_balancer_port,
+        "GET /metrics?format=json HTTP/1.1\r\n\r\n",
+    )
+    .await;
+
+    backend1_handle.abort();
+    backend2_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(text.contains("Total requests: 7,"), "{}", text);
+    assert!(text.contains("Uptime: "), "{}", text);
+
+    let (_, body) = json.split_once("\r\n\r\n").unwrap();
+    let metrics: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(metrics["total_requests"], 7);
+    assert!(metrics["requests_per_second"].as_f64().unwrap() > 0.0);
+    assert!(metrics["uptime_secs"].is_u64());
+    // The metrics request itself holds a connection slot
+    assert_eq!(metrics["active_connections"], 1);
+
+    // Round-robin reports "Requests: N, ..." per backend
+    let per_server: u64 = [&backend1, &backend2]
+        .iter()
+        .map(|backend| {
+            let metric = metrics["servers"][backend.as_str()].as_str().unwrap();
+            let count = metric.strip_prefix("Requests: ").unwrap();
+            count.split(',').next().unwrap().parse::<u64>().unwrap()
+        })
+        .sum();
+    assert_eq!(per_server, 7);
+}
+
+#[tokio::test]
+async fn test_metrics_report_backend_bytes() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_ok_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    for _ in 0..3 {
+        send_raw(load_balancer_port, request).await;
+    }
+    let metrics = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    let response_len = "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok".len();
+    let line = metrics
+        .lines()
+        .find(|line| line.starts_with(&format!("{}:", backend)))
+        .unwrap();
+    assert!(
+        line.ends_with(&format!("Bytes out: {}", 3 * response_len)),
+        "{}",
+        line
+    );
+    let bytes_in: usize = line
+        .split("Bytes in: ")
+        .nth(1)
+        .and_then(|value| value.split(',').next())
+        .unwrap()
+        .parse()
+        .unwrap();
+    // Forwarding headers are added on the way through
+    assert!(bytes_in > 3 * request.len(), "{}", line);
+}
+
+#[tokio::test]
+async fn test_metrics_count_responses_by_status() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (ok_backend, ok_handle) = spawn_ok_backend().await;
+    let (failing_backend, failing_handle) = spawn_failing_backend().await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![ok_backend.clone(), failing_backend.clone()],
+        "round-robin",
+    )
+    .with_count_by_status(true);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    for _ in 0..4 {
+        send_raw(
+            load_balancer_port,
+            "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .await;
+    }
+    let text = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+    let json = send_raw(
+        load_balancer_port,
+        "GET /metrics?format=json HTTP/1.1\r\n\r\n",
+    )
+    .await;
+
+    ok_handle.abort();
+    failing_handle.abort();
+    load_balancer_handle.abort();
+
+    let line = |server: &str| {
+        text.lines()
+            .find(|line| line.starts_with(&format!("{}:", server)))
+            .unwrap()
+            .to_string()
+    };
+    let ok_line = line(&ok_backend);
+    let failing_line = line(&failing_backend);
+    assert!(ok_line.ends_with("2xx: 2, 4xx: 0, 5xx: 0"), "{}", ok_line);
+    assert!(
+        failing_line.ends_with("2xx: 0, 4xx: 0, 5xx: 2"),
+        "{}",
+        failing_line
+    );
+
+    let body: serde_json::Value =
+        serde_json::from_str(json.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    let servers = &body["servers"];
+    assert!(
+        servers[&failing_backend]
+            .as_str()
+            .unwrap()
+            .ends_with("5xx: 2"),
+        "{}",
+        body
+    );
+    assert!(
+        servers[&ok_backend].as_str().unwrap().contains("2xx: 2"),
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn test_final_metrics_written_on_shutdown() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend_a, handle_a) = spawn_ok_backend().await;
+    let (backend_b, handle_b) = spawn_ok_backend().await;
+    let path = std::env::temp_dir().join(format!(
+        "rust_load_balancer_metrics_{}.json",
+        load_balancer_port
+    ));
+    let _ = std::fs::remove_file(&path);
+
+    // Going idle shuts the balancer down the same way Ctrl-C does
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![backend_a.clone(), backend_b.clone()],
+        "round-robin",
+    )
+    .with_idle_timeout(Duration::from_millis(500))
+    .with_metrics_out(&path);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    for _ in 0..4 {
+        send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    }
+    let finished = tokio::time::timeout(Duration::from_secs(3), load_balancer_handle).await;
+
+    handle_a.abort();
+    handle_b.abort();
+
+    assert!(finished.is_ok(), "balancer didn't shut down");
+    let contents = std::fs::read_to_string(&path).expect("metrics file wasn't written");
+    let _ = std::fs::remove_file(&path);
+    let metrics: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    assert_eq!(metrics["total_requests"], 4, "{}", metrics);
+    for backend in [&backend_a, &backend_b] {
+        assert_eq!(metrics["requests"][backend], 2, "{}", metrics);
+        assert!(
+            metrics["servers"][backend]
+                .as_str()
+                .unwrap()
+                .contains("Requests: 2"),
+            "{}",
+            metrics
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_metrics_list_every_server_before_traffic() {
+    let servers: Vec<String> = (8601..=8603)
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect();
+
+    for algorithm in ["weighted-round-robin", "round-robin"] {
+        let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let load_balancer_port = lb_listener.local_addr().unwrap().port();
+        let load_balancer = LoadBalancer::new(load_balancer_port, servers.clone(), algorithm);
+        let running = load_balancer.clone();
+        let load_balancer_handle = tokio::spawn(async move {
+            running.run_with_listener(lb_listener).await;
+        });
+        sleep(Duration::from_millis(100)).await;
+
+        let metrics = load_balancer.get_metrics().await;
+        load_balancer_handle.abort();
+
+        for server in &servers {
+            let metric = metrics.get(server);
+            assert!(
+                metric.is_some_and(|m| m.contains("Requests: 0")),
+                "{}: {:?}",
+                algorithm,
+                metrics
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint_lists_every_server_before_traffic() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let servers: Vec<String> = (8604..=8605)
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect();
+
+    // Least-connections keeps no per-server state until a request arrives
+    let load_balancer = LoadBalancer::new(load_balancer_port, servers.clone(), "least-connections");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let response = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+    load_balancer_handle.abort();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(
+        response.contains("Content-Type: text/plain\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.contains("Total requests: 0"), "{}", response);
+    for server in &servers {
+        assert!(
+            response.contains(&format!(
+                "{}: Avg latency: 0.0ms, Total latency: 0.0ms, Bytes in: 0, Bytes out: 0",
+                server
+            )),
+            "{}",
+            response
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_connection_counters_track_saturation() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    // Holds each request for a second so both slots stay taken
+    let (backend, backend_handle) = spawn_holding_backend(Duration::from_secs(1)).await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_max_connections(2)
+        .with_queue_timeout(Duration::from_millis(400));
+    let handle = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let request = "GET / HTTP/1.1\r\n\r\n";
+    let mut held = Vec::new();
+    for _ in 0..2 {
+        held.push(tokio::spawn(send_raw(load_balancer_port, request)));
+    }
+    sleep(Duration::from_millis(150)).await;
+    let mut excess = Vec::new();
+    for _ in 0..2 {
+        excess.push(tokio::spawn(send_raw(load_balancer_port, request)));
+    }
+    sleep(Duration::from_millis(150)).await;
+    let saturated = handle.aggregate_metrics().await;
+
+    let mut rejected = Vec::new();
+    for response in excess {
+        rejected.push(response.await.unwrap());
+    }
+    let after = handle.aggregate_metrics().await;
+    for response in held {
+        response.await.unwrap();
+    }
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(saturated.active_connections, 2);
+    assert_eq!(saturated.connections_queued, 2);
+    assert_eq!(saturated.connections_rejected, 0);
+    for response in rejected {
+        assert!(
+            response.starts_with("HTTP/1.1 503 Service Unavailable"),
+            "{}",
+            response
+        );
+    }
+    assert_eq!(after.connections_accepted, 4);
+    assert_eq!(after.connections_queued, 0);
+    assert_eq!(after.connections_rejected, 2);
+    assert!(after.to_string().contains("Rejected: 2"), "{}", after);
+}
+
+#[tokio::test]
+async fn test_supervised_task_restarts_after_panic() {
+    // Stub metrics source: the first run panics after one report, later runs
+    // keep reporting
+    let runs = Arc::new(AtomicUsize::new(0));
+    let reports = Arc::new(AtomicUsize::new(0));
+    let (task_runs, task_reports) = (runs.clone(), reports.clone());
+    let task = supervise("metrics", Duration::from_millis(10), move || {
+        let run = task_runs.fetch_add(1, Ordering::SeqCst);
+        let reports = task_reports.clone();
+        async move {
+            loop {
+                reports.fetch_add(1, Ordering::SeqCst);
+                if run == 0 {
+                    panic!("metrics source failed");
+                }
+                sleep(Duration::from_millis(5)).await;
+            }
+        }
+    });
+
+    // Printing the panic can be slow, so give the restart time to report
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while reports.load(Ordering::SeqCst) <= 5 && tokio::time::Instant::now() < deadline {
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert!(!task.is_finished());
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+    assert!(reports.load(Ordering::SeqCst) > 5);
+
+    // Aborting stops it for good
+    task.abort();
+    sleep(Duration::from_millis(20)).await;
+    let stopped_at = reports.load(Ordering::SeqCst);
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(reports.load(Ordering::SeqCst), stopped_at);
+}
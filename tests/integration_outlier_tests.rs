@@ -0,0 +1,80 @@
+mod common;
+
+use common::{send_raw, spawn_delayed_backend};
+use rust_load_balancer::balancer::{LoadBalancer, OutlierDetector, OUTLIER_MIN_REQUESTS};
+
+use std::sync::atomic::Ordering;
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_slow_backend_is_ejected() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (slow, slow_hits, _, slow_handle) = spawn_delayed_backend(Duration::from_millis(200)).await;
+    let (fast1, fast1_hits, _, fast1_handle) = spawn_delayed_backend(Duration::ZERO).await;
+    let (fast2, fast2_hits, _, fast2_handle) = spawn_delayed_backend(Duration::ZERO).await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![slow.clone(), fast1, fast2],
+        "round-robin",
+    )
+    .with_outlier_detection(2.0, Duration::from_secs(60));
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    for _ in 0..30 {
+        let response = send_raw(load_balancer_port, request).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+    let metrics = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+
+    slow_handle.abort();
+    fast1_handle.abort();
+    fast2_handle.abort();
+    load_balancer_handle.abort();
+
+    // Ejected as soon as it had enough samples; the rest absorbed the load
+    assert_eq!(balancer.ejected_servers().await, vec![slow.clone()]);
+    assert_eq!(slow_hits.load(Ordering::SeqCst), OUTLIER_MIN_REQUESTS);
+    assert_eq!(
+        fast1_hits.load(Ordering::SeqCst) + fast2_hits.load(Ordering::SeqCst),
+        30 - OUTLIER_MIN_REQUESTS
+    );
+    let slow_line = metrics
+        .lines()
+        .find(|line| line.starts_with(&format!("{}:", slow)))
+        .unwrap();
+    assert!(slow_line.contains("Ejected"), "{}", metrics);
+}
+
+#[tokio::test]
+async fn test_ejected_backend_reinstated_after_cooldown() {
+    let detector = OutlierDetector::new(1.5, Duration::from_millis(200));
+    for _ in 0..OUTLIER_MIN_REQUESTS {
+        detector.record("fast1", Duration::from_millis(10)).await;
+        detector.record("fast2", Duration::from_millis(10)).await;
+        detector.record("slow", Duration::from_millis(100)).await;
+    }
+    assert!(detector.is_ejected("slow").await);
+    assert!(!detector.is_ejected("fast1").await);
+
+    sleep(Duration::from_millis(250)).await;
+    assert!(!detector.is_ejected("slow").await);
+    assert!(detector.ejected_servers().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_outlier_detection_keeps_one_backend() {
+    let detector = OutlierDetector::new(1.1, Duration::from_secs(60));
+    for _ in 0..OUTLIER_MIN_REQUESTS * 4 {
+        detector.record("a", Duration::from_millis(10)).await;
+        detector.record("b", Duration::from_millis(50)).await;
+    }
+    // b is the outlier but a can never be ejected alongside it
+    assert_eq!(detector.ejected_servers().await, vec!["b".to_string()]);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::spawn_counting_backend;
+use rust_load_balancer::balancer::{parse_server_entry, LoadBalancer};
+
+use std::sync::atomic::Ordering;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, Duration};
+
+async fn send_requests(port: u16, count: usize) {
+    for _ in 0..count {
+        let mut stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)).await {
+                Ok(stream) => break stream,
+                Err(_) => sleep(Duration::from_millis(50)).await,
+            }
+        };
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    }
+}
+
+#[test]
+fn test_parse_server_entry() {
+    assert_eq!(
+        parse_server_entry("127.0.0.1:8001"),
+        ("127.0.0.1:8001".to_string(), 0)
+    );
+    assert_eq!(
+        parse_server_entry("127.0.0.1:8002|priority=1"),
+        ("127.0.0.1:8002".to_string(), 1)
+    );
+}
+
+#[tokio::test]
+async fn test_backups_serve_only_while_primaries_are_down() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (primary_a, primary_a_hits, primary_a_handle) = spawn_counting_backend().await;
+    let (primary_b, primary_b_hits, primary_b_handle) = spawn_counting_backend().await;
+    let (backup, backup_hits, backup_handle) = spawn_counting_backend().await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![
+            format!("{}|priority=0", primary_a),
+            primary_b.clone(),
+            format!("{}|priority=1", backup),
+        ],
+        "round-robin",
+    );
+    let handle = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Healthy primaries take everything
+    send_requests(load_balancer_port, 4).await;
+    let primaries =
+        || primary_a_hits.load(Ordering::SeqCst) + primary_b_hits.load(Ordering::SeqCst);
+    assert_eq!(primaries(), 4);
+    assert_eq!(backup_hits.load(Ordering::SeqCst), 0);
+
+    // One primary down still leaves the tier in service
+    handle.set_healthy(&primary_a, false).await;
+    send_requests(load_balancer_port, 2).await;
+    assert_eq!(primary_b_hits.load(Ordering::SeqCst), 4);
+    assert_eq!(backup_hits.load(Ordering::SeqCst), 0);
+
+    // With the whole tier down, the backup takes over
+    handle.set_healthy(&primary_b, false).await;
+    send_requests(load_balancer_port, 3).await;
+    assert_eq!(primaries(), 6);
+    assert_eq!(backup_hits.load(Ordering::SeqCst), 3);
+
+    // A recovered primary wins the traffic back
+    handle.set_healthy(&primary_a, true).await;
+    send_requests(load_balancer_port, 3).await;
+
+    primary_a_handle.abort();
+    primary_b_handle.abort();
+    backup_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(primaries(), 9);
+    assert_eq!(backup_hits.load(Ordering::SeqCst), 3);
+    assert_eq!(handle.priority(&backup).await, 1);
+    assert_eq!(handle.servers().await, vec![primary_a, primary_b, backup]);
+}
@@ -0,0 +1,35 @@
+use rust_load_balancer::algorithms::{Algorithm, LoadBalancingAlgorithm, Random};
+
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn test_random_distribution_is_roughly_uniform() {
+    let servers: Vec<String> = (8001..=8004)
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect();
+    let algorithm = Algorithm::new("random", None);
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for _ in 0..4000 {
+        let server = algorithm.next_server(&servers).await.unwrap();
+        *counts.entry(server).or_insert(0) += 1;
+    }
+
+    // Every server gets close to a quarter of the traffic
+    for server in &servers {
+        let share = counts.get(server).copied().unwrap_or(0) as f64 / 4000.0;
+        assert!(share > 0.2 && share < 0.3, "{} got {:.3}", server, share);
+    }
+
+    let metrics = algorithm.get_metrics().await;
+    assert_eq!(metrics.len(), 4);
+    assert!(metrics[&servers[0]].starts_with(&format!("Requests: {},", counts[&servers[0]])));
+}
+
+#[tokio::test]
+async fn test_random_empty_server_list() {
+    let servers: Vec<String> = vec![];
+    let random = Random::new();
+
+    assert!(random.next_server(&servers).await.is_none());
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::spawn_ok_backend;
+use rust_load_balancer::balancer::{LoadBalancer, RateLimiter};
+
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket};
+use tokio::time::{sleep, Duration};
+
+// Send a request to the balancer from a specific loopback address
+async fn send_from(source: &str, port: u16) -> String {
+    let source: IpAddr = source.parse().unwrap();
+    let target = SocketAddr::from(([127, 0, 0, 1], port));
+    let mut stream = loop {
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind(SocketAddr::new(source, 0)).unwrap();
+        match socket.connect(target).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_rate_limit_is_per_client_ip() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_ok_backend().await;
+
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, vec![backend], "round-robin").with_rate_limit(5.0);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // One client hammers well past its burst of five
+    let mut limited = 0;
+    for _ in 0..20 {
+        let response = send_from("127.0.0.1", load_balancer_port).await;
+        if response.starts_with("HTTP/1.1 429 Too Many Requests") {
+            limited += 1;
+        } else {
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+        }
+    }
+
+    // A different client still gets through
+    let other = send_from("127.0.0.2", load_balancer_port).await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(limited >= 10, "only {} requests limited", limited);
+    assert!(other.starts_with("HTTP/1.1 200 OK"), "{}", other);
+}
+
+#[tokio::test]
+async fn test_rate_limiter_refills_and_evicts() {
+    let limiter = RateLimiter::new(10.0);
+    let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+    // Burst of ten, then empty
+    for _ in 0..10 {
+        assert!(limiter.check(ip).await);
+    }
+    assert!(!limiter.check(ip).await);
+
+    // Roughly two tokens come back after 200ms
+    sleep(Duration::from_millis(200)).await;
+    assert!(limiter.check(ip).await);
+
+    assert_eq!(limiter.tracked_clients().await, 1);
+    sleep(Duration::from_millis(50)).await;
+    limiter.evict_idle(Duration::from_millis(10)).await;
+    assert_eq!(limiter.tracked_clients().await, 0);
+}
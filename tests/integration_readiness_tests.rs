@@ -0,0 +1,69 @@
+mod common;
+
+use common::{send_raw, spawn_ok_backend_at};
+use rust_load_balancer::balancer::LoadBalancer;
+
+use tokio::net::TcpListener;
+use tokio::time::{sleep, timeout, Duration};
+
+// An address nothing is listening on yet
+async fn unused_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_ready_endpoint_follows_backend_reachability() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let backend = unused_addr().await;
+
+    // The gate gives up quickly, so the balancer serves with nothing up
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin")
+        .with_readiness_gate(Duration::from_millis(100));
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let down = send_raw(load_balancer_port, "GET /ready HTTP/1.1\r\n\r\n").await;
+    let backend_handle = spawn_ok_backend_at(&backend).await;
+    let up = send_raw(load_balancer_port, "GET /ready HTTP/1.1\r\n\r\n").await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(
+        down.starts_with("HTTP/1.1 503 Service Unavailable"),
+        "{}",
+        down
+    );
+    assert!(up.starts_with("HTTP/1.1 200 OK"), "{}", up);
+}
+
+#[tokio::test]
+async fn test_gate_holds_clients_until_a_backend_is_reachable() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let backend = unused_addr().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin")
+        .with_readiness_gate(Duration::from_secs(10));
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Sent while no backend is up, answered once one is instead of with a 503
+    let request = tokio::spawn(send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n"));
+    sleep(Duration::from_millis(300)).await;
+    assert!(!request.is_finished());
+    let backend_handle = spawn_ok_backend_at(&backend).await;
+    let response = timeout(Duration::from_secs(5), request)
+        .await
+        .expect("gate never opened")
+        .unwrap();
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+}
@@ -2,25 +2,29 @@ use rust_load_balancer::algorithms::{LoadBalancingAlgorithm, RoundRobin};
 use rust_load_balancer::{balancer::LoadBalancer, generator::Generator, server::Server};
 
 use std::sync::Arc;
+use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 use tokio::{time::timeout, time::Duration};
 
 #[tokio::test]
 async fn test_round_robin_no_timeout() {
     // Servers
-    let server_port1 = 8001;
-    let server_port2 = 8002;
-    let load_balancer_port = 9000;
+    let server_listener1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port1 = server_listener1.local_addr().unwrap().port();
+    let server_listener2 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port2 = server_listener2.local_addr().unwrap().port();
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
 
     let server1 = Server::new(server_port1, 100, 50);
     let server2 = Server::new(server_port2, 100, 50);
 
     let server1_handle = tokio::spawn(async move {
-        server1.run().await;
+        server1.run_with_listener(server_listener1).await;
     });
 
     let server2_handle = tokio::spawn(async move {
-        server2.run().await;
+        server2.run_with_listener(server_listener2).await;
     });
 
     // LB Start w/LocalHost
@@ -30,7 +34,7 @@ async fn test_round_robin_no_timeout() {
     ];
     let load_balancer = LoadBalancer::new(load_balancer_port, servers, "round-robin");
     let load_balancer_handle = tokio::spawn(async move {
-        load_balancer.run().await;
+        load_balancer.run_with_listener(lb_listener).await;
     });
 
     // Generator w/LB Port,
@@ -69,3 +73,15 @@ async fn test_round_robin_empty_server_list() {
     // No server should be next
     assert!(next_server.is_none());
 }
+
+#[tokio::test]
+async fn test_round_robin_select_n_is_exactly_even() {
+    let servers: Vec<String> = ["a:1", "b:1", "c:1"].map(String::from).to_vec();
+    let round_robin = RoundRobin::new();
+
+    let counts = round_robin.select_n(&servers, 300).await;
+
+    assert_eq!(counts.len(), 3);
+    assert!(counts.values().all(|count| *count == 100), "{:?}", counts);
+    assert!(round_robin.select_n(&[], 5).await.is_empty());
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::spawn_body_backend;
+use rust_load_balancer::algorithms::Algorithm;
+use rust_load_balancer::balancer::{LoadBalancer, RouteGroup};
+use rust_load_balancer::config::Config;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, Duration};
+
+async fn get(port: u16, path: &str) -> String {
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", port)).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_path_prefix_selects_backend_pool() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (api, api_handle) = spawn_body_backend("api").await;
+    let (assets, assets_handle) = spawn_body_backend("static").await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![], "round-robin")
+        .with_route(RouteGroup::new(
+            "api",
+            "/api",
+            vec![api],
+            Algorithm::new("round-robin", None),
+        ))
+        .with_route(RouteGroup::new(
+            "static",
+            "/static",
+            vec![assets],
+            Algorithm::new("least-connections", None),
+        ));
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    for path in ["/api", "/api/users?id=1"] {
+        let response = get(load_balancer_port, path).await;
+        assert!(
+            response.ends_with("\r\n\r\napi"),
+            "{} -> {}",
+            path,
+            response
+        );
+    }
+    let response = get(load_balancer_port, "/static/app.css").await;
+    assert!(response.ends_with("\r\n\r\nstatic"), "{}", response);
+
+    // No default pool, so anything else is not found
+    for path in ["/", "/apis"] {
+        let response = get(load_balancer_port, path).await;
+        assert!(
+            response.starts_with("HTTP/1.1 404"),
+            "{} -> {}",
+            path,
+            response
+        );
+    }
+
+    api_handle.abort();
+    assets_handle.abort();
+    load_balancer_handle.abort();
+}
+
+#[tokio::test]
+async fn test_routes_from_config() {
+    let path = std::env::temp_dir().join("rust_load_balancer_routes.toml");
+    std::fs::write(
+        &path,
+        r#"
+servers = ["127.0.0.1:8001"]
+
+[routes.api]
+prefix = "/api"
+servers = ["127.0.0.1:8002", "127.0.0.1:8003"]
+algorithm = "least-connections"
+
+[routes.static]
+prefix = "/static"
+servers = ["127.0.0.1:8004"]
+"#,
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let balancer = LoadBalancer::from_config(&config);
+
+    let routes = balancer.routes();
+    assert_eq!(routes.len(), 2);
+    let api = routes.iter().find(|r| r.name() == "api").unwrap();
+    assert_eq!(api.prefix(), "/api");
+    assert_eq!(api.algorithm().name(), "least-connections");
+    assert_eq!(
+        api.servers().await,
+        vec!["127.0.0.1:8002".to_string(), "127.0.0.1:8003".to_string()]
+    );
+    let assets = routes.iter().find(|r| r.name() == "static").unwrap();
+    assert_eq!(assets.algorithm().name(), "round-robin");
+    assert!(assets.matches("/static/logo.png"));
+    assert!(!assets.matches("/statics"));
+}
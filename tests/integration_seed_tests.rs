@@ -0,0 +1,59 @@
+use rust_load_balancer::algorithms::{Algorithm, LoadBalancingAlgorithm};
+use rust_load_balancer::balancer::LoadBalancer;
+use rust_load_balancer::rng::SharedRng;
+
+fn servers() -> Vec<String> {
+    (8001..=8005).map(|p| format!("127.0.0.1:{}", p)).collect()
+}
+
+async fn picks(algorithm: &Algorithm, count: usize) -> Vec<String> {
+    let servers = servers();
+    let mut picks = Vec::new();
+    for _ in 0..count {
+        picks.push(algorithm.next_server(&servers).await.unwrap());
+    }
+    picks
+}
+
+#[tokio::test]
+async fn test_same_seed_repeats_selection_sequence() {
+    for name in [
+        "random",
+        "ip-hash",
+        "consistent-hash",
+        "weighted-round-robin",
+    ] {
+        let first = Algorithm::new(name, None).with_rng(SharedRng::seeded(42));
+        let second = Algorithm::new(name, None).with_rng(SharedRng::seeded(42));
+
+        assert_eq!(
+            picks(&first, 50).await,
+            picks(&second, 50).await,
+            "{}",
+            name
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_different_seeds_diverge() {
+    let first = Algorithm::new("random", None).with_rng(SharedRng::seeded(1));
+    let second = Algorithm::new("random", None).with_rng(SharedRng::seeded(2));
+
+    assert_ne!(picks(&first, 50).await, picks(&second, 50).await);
+}
+
+#[tokio::test]
+async fn test_balancer_seed_reaches_algorithm() {
+    let first = LoadBalancer::new(9000, servers(), "random").with_seed(7);
+    let second = LoadBalancer::builder()
+        .with_servers(servers())
+        .with_algorithm("random")
+        .with_seed(7)
+        .build();
+
+    assert_eq!(
+        picks(first.algorithm(), 50).await,
+        picks(second.algorithm(), 50).await
+    );
+}
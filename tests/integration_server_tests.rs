@@ -0,0 +1,551 @@
+use clap::Parser;
+use rust_load_balancer::{
+    balancer::LoadBalancer,
+    client::SenderClient,
+    server::{PathDelays, Server, ServerArgs, ServerStats},
+};
+
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Duration;
+
+#[tokio::test]
+async fn test_server_put_applies_put_delay() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10).with_put_delay(300);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    let start = Instant::now();
+    let response = client
+        .put_update_request("", "update".to_string())
+        .await
+        .unwrap();
+    let elapsed = start.elapsed();
+    let status = response.status();
+    let body = response.text().await.unwrap();
+
+    server_handle.abort();
+
+    assert_eq!(status, 200);
+    assert_eq!(body, "update");
+    assert!(elapsed >= Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn test_server_delete_applies_delete_delay() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10).with_delete_delay(300);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    let start = Instant::now();
+    let response = client.delete_request("").await.unwrap();
+    let elapsed = start.elapsed();
+    let status = response.status();
+
+    server_handle.abort();
+
+    assert_eq!(status, 200);
+    assert!(elapsed >= Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn test_server_health_skips_delay() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 1000, 1000);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    // First request may race the listener bind, so time a second one
+    client.get_read_request("health").await.unwrap();
+    let start = Instant::now();
+    let response = client.get_read_request("health").await.unwrap();
+    let elapsed = start.elapsed();
+    let status = response.status();
+    let body = response.text().await.unwrap();
+
+    server_handle.abort();
+
+    assert_eq!(status, 200);
+    assert_eq!(body, "healthy");
+    assert!(elapsed < Duration::from_millis(500));
+}
+
+#[tokio::test]
+async fn test_server_health_reports_unhealthy() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 1000, 1000);
+    let toggle = server.clone();
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    let healthy = client.get_read_request("health").await.unwrap().status();
+
+    // Flip the running server to unhealthy
+    toggle.set_healthy(false);
+    let unhealthy = client.get_read_request("health").await.unwrap().status();
+
+    server_handle.abort();
+
+    assert_eq!(healthy, 200);
+    assert_eq!(unhealthy, 503);
+}
+
+#[tokio::test]
+async fn test_server_error_rate_always_fails() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10).with_error_rate(1.0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    let mut statuses = Vec::new();
+    for _ in 0..10 {
+        statuses.push(client.get_read_request("").await.unwrap().status());
+    }
+
+    server_handle.abort();
+
+    assert!(statuses.iter().all(|status| *status == 500));
+}
+
+#[test]
+fn test_server_rejects_error_rate_out_of_range() {
+    for rate in ["1.7", "-0.3", "often"] {
+        let result = ServerArgs::try_parse_from(["server", "--error-rate", rate]);
+        assert!(result.is_err(), "accepted --error-rate {}", rate);
+    }
+    let args = ServerArgs::try_parse_from(["server", "--error-rate", "0.25"]).unwrap();
+    assert_eq!(args.error_rate, 0.25);
+}
+
+#[tokio::test]
+async fn test_server_error_rate_never_fails() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10).with_error_rate(0.0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    let mut statuses = Vec::new();
+    for _ in 0..10 {
+        statuses.push(client.get_read_request("").await.unwrap().status());
+    }
+
+    server_handle.abort();
+
+    assert!(statuses.iter().all(|status| *status == 200));
+}
+
+#[tokio::test]
+async fn test_server_jitter_spreads_delays() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 200, 200).with_jitter(50.0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    client.get_read_request("").await.unwrap();
+
+    let requests = (0..30).map(|_| {
+        let client = client.clone();
+        async move {
+            let start = Instant::now();
+            client.get_read_request("").await.unwrap();
+            start.elapsed()
+        }
+    });
+    let delays = futures::future::join_all(requests).await;
+
+    server_handle.abort();
+
+    // 200ms ± 50% gives 100-300ms, plus some slack for request overhead
+    let min = *delays.iter().min().unwrap();
+    let max = *delays.iter().max().unwrap();
+    assert!(min >= Duration::from_millis(100), "min delay {:?}", min);
+    assert!(max <= Duration::from_millis(400), "max delay {:?}", max);
+    assert!(max - min >= Duration::from_millis(20), "delays not spread");
+}
+
+#[tokio::test]
+async fn test_server_echoes_post_body() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    // Larger than a single read so the body spans several chunks
+    let payload = "payload-".repeat(1000);
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    let response = client
+        .post_write_request("", payload.clone())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.text().await.unwrap();
+
+    server_handle.abort();
+
+    assert_eq!(status, 200);
+    assert_eq!(body, payload);
+}
+
+#[tokio::test]
+async fn test_server_keeps_connection_alive() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Two requests on one connection both get answered
+    let mut stream = TcpStream::connect(("127.0.0.1", server_port))
+        .await
+        .unwrap();
+    let mut responses = Vec::new();
+    for _ in 0..2 {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buffer = [0; 1024];
+        let n = stream.read(&mut buffer).await.unwrap();
+        responses.push(String::from_utf8_lossy(&buffer[..n]).to_string());
+    }
+
+    server_handle.abort();
+
+    for response in responses {
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Connection: keep-alive\r\n"));
+        assert!(response.ends_with("Request Received of type: GET"));
+    }
+}
+
+#[tokio::test]
+async fn test_server_frames_pipelined_and_chunked_requests() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    // A chunked POST with a GET pipelined right behind it, then a request
+    // that closes the connection
+    let mut stream = TcpStream::connect(("127.0.0.1", server_port))
+        .await
+        .unwrap();
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n\
+              GET / HTTP/1.1\r\n\r\n\
+              DELETE / HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut responses = String::new();
+    stream.read_to_string(&mut responses).await.unwrap();
+
+    server_handle.abort();
+
+    let bodies: Vec<&str> = responses
+        .split("HTTP/1.1 ")
+        .skip(1)
+        .map(|response| response.split("\r\n\r\n").nth(1).unwrap())
+        .collect();
+    assert_eq!(
+        bodies,
+        vec![
+            "hello world",
+            "Request Received of type: GET",
+            "Request Received of type: DELETE"
+        ],
+        "{}",
+        responses
+    );
+}
+
+#[tokio::test]
+async fn test_server_echoes_request_id() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 0, 0);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", server_port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nX-Request-Id: trace-7\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    server_handle.abort();
+
+    assert!(
+        response.contains("X-Request-Id: trace-7\r\n"),
+        "{}",
+        response
+    );
+}
+
+#[tokio::test]
+async fn test_server_path_delays_override_method_delay() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let delays: PathDelays = "/slow=600,/fast=10".parse().unwrap();
+    let server = Server::new(server_port, 300, 300).with_path_delays(delays);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    // First request may race the listener bind, so time later ones
+    client.get_read_request("health").await.unwrap();
+    let time = |path: &'static str| {
+        let client = client.clone();
+        async move {
+            let start = Instant::now();
+            client.get_read_request(path).await.unwrap();
+            start.elapsed()
+        }
+    };
+    let slow = time("slow").await;
+    let fast = time("fast?x=1").await;
+    let other = time("other").await;
+
+    server_handle.abort();
+
+    assert!(slow >= Duration::from_millis(600), "slow took {:?}", slow);
+    assert!(fast < Duration::from_millis(250), "fast took {:?}", fast);
+    assert!(
+        other >= Duration::from_millis(300),
+        "other took {:?}",
+        other
+    );
+}
+
+#[test]
+fn test_path_delays_parsing() {
+    let delays: PathDelays = " /slow=2000, /fast=10 ".parse().unwrap();
+    assert_eq!(delays.delay_for("/slow"), Some(2000));
+    assert_eq!(delays.delay_for("/fast"), Some(10));
+    assert_eq!(delays.delay_for("/"), None);
+
+    assert!("slow=10".parse::<PathDelays>().is_err());
+    assert!("/slow".parse::<PathDelays>().is_err());
+    assert!("/slow=soon".parse::<PathDelays>().is_err());
+    assert!("/slow=1,/slow=2".parse::<PathDelays>().is_err());
+}
+
+#[tokio::test]
+async fn test_server_no_delay_skips_delays() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let delays: PathDelays = "/slow=2000".parse().unwrap();
+    let server = Server::new(server_port, 1000, 1000)
+        .with_path_delays(delays)
+        .with_no_delay(true);
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    client.get_read_request("health").await.unwrap();
+    let start = Instant::now();
+    let get = client.get_read_request("").await.unwrap().status();
+    let slow = client.get_read_request("slow").await.unwrap().status();
+    let post = client
+        .post_write_request("", "body".to_string())
+        .await
+        .unwrap()
+        .status();
+    let elapsed = start.elapsed();
+
+    server_handle.abort();
+
+    for status in [get, slow, post] {
+        assert_eq!(status, 200);
+    }
+    assert!(elapsed < Duration::from_millis(300), "took {:?}", elapsed);
+}
+
+#[tokio::test]
+async fn test_server_exits_after_idle_timeout() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = Server::new(0, 10, 10).with_idle_timeout(Duration::from_secs(1));
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    // No traffic at all: the server stops by itself
+    let finished = tokio::time::timeout(Duration::from_secs(3), server_handle).await;
+    assert!(finished.is_ok(), "idle server didn't stop");
+}
+
+#[tokio::test]
+async fn test_server_idle_timeout_resets_on_connection() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 10, 10).with_idle_timeout(Duration::from_millis(600));
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    // A connection at 400ms pushes the deadline out to 1s
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    drop(
+        TcpStream::connect(("127.0.0.1", server_port))
+            .await
+            .unwrap(),
+    );
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(
+        !server_handle.is_finished(),
+        "server stopped despite traffic"
+    );
+
+    let finished = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+    assert!(finished.is_ok(), "idle server didn't stop");
+}
+
+#[tokio::test]
+async fn test_server_shutdown_finishes_in_flight_requests() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 500, 500);
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        server
+            .run_with_listener_until(server_listener, async {
+                let _ = stopped.await;
+            })
+            .await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Start a request that's still sleeping on the server when it shuts down
+    let mut stream = TcpStream::connect(("127.0.0.1", server_port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    stop.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let refused = TcpStream::connect(("127.0.0.1", server_port)).await;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let finished = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+
+    assert!(refused.is_err(), "server still accepting connections");
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("Connection: close\r\n"), "{}", response);
+    assert!(finished.is_ok(), "server didn't stop");
+}
+
+#[tokio::test]
+async fn test_server_stats_counts_requests_by_method() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let server = Server::new(server_port, 0, 0);
+    let counts = server.clone();
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", server_port));
+    // Health checks aren't counted, so this one can safely wait for the bind
+    client.get_read_request("health").await.unwrap();
+    for _ in 0..5 {
+        client.get_read_request("").await.unwrap();
+    }
+    for _ in 0..3 {
+        client
+            .post_write_request("", "body".to_string())
+            .await
+            .unwrap();
+    }
+    let response = client.get_read_request("stats").await.unwrap();
+    let content_type = response.headers()["content-type"].clone();
+    let stats: ServerStats = response.json().await.unwrap();
+
+    server_handle.abort();
+
+    assert_eq!(content_type, "application/json");
+    assert_eq!(stats.get, 5);
+    assert_eq!(stats.post, 3);
+    assert_eq!(stats.total(), 8);
+    assert_eq!(counts.stats(), stats);
+}
+
+#[tokio::test]
+async fn test_server_content_type_and_body_survive_balancer() {
+    let server_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port = server_listener.local_addr().unwrap().port();
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let body = r#"{"items":[1,2,3],"ok":true}"#;
+    let server = Server::new(server_port, 0, 0)
+        .with_response_body(body)
+        .with_content_type("application/json");
+    let server_handle = tokio::spawn(async move {
+        server.run_with_listener(server_listener).await;
+    });
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![format!("127.0.0.1:{}", server_port)],
+        "round-robin",
+    );
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client = SenderClient::new("0", &format!("http://127.0.0.1:{}", load_balancer_port));
+    let response = client.get_read_request("items").await.unwrap();
+    let status = response.status();
+    let content_type = response.headers()["content-type"].clone();
+    let received = response.text().await.unwrap();
+
+    server_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(status, 200);
+    assert_eq!(content_type, "application/json");
+    assert_eq!(received, body);
+}
@@ -0,0 +1,107 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::spawn_counting_backend;
+use rust_load_balancer::balancer::{read_servers_file, LoadBalancer};
+
+use std::sync::atomic::Ordering;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout, Duration};
+
+async fn send_get(port: u16) -> String {
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", port)).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[test]
+fn test_servers_file_skips_blanks_and_comments() {
+    let path = std::env::temp_dir().join("rust_load_balancer_servers_parse.txt");
+    std::fs::write(&path, "# backends\n127.0.0.1:8001\n\n  127.0.0.1:8002  \n").unwrap();
+
+    let servers = read_servers_file(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(servers, vec!["127.0.0.1:8001", "127.0.0.1:8002"]);
+}
+
+#[tokio::test]
+async fn test_sighup_reloads_servers_file() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (old, old_hits, old_handle) = spawn_counting_backend().await;
+    let (new, new_hits, new_handle) = spawn_counting_backend().await;
+
+    let path = std::env::temp_dir().join("rust_load_balancer_servers_reload.txt");
+    std::fs::write(&path, format!("{}\n", old)).unwrap();
+
+    let servers = read_servers_file(&path).unwrap();
+    let load_balancer =
+        LoadBalancer::new(load_balancer_port, servers, "round-robin").with_servers_file(&path);
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // Once a request is served the SIGHUP handler is in place
+    assert!(send_get(load_balancer_port)
+        .await
+        .starts_with("HTTP/1.1 200 OK"));
+    assert_eq!(old_hits.load(Ordering::SeqCst), 1);
+
+    std::fs::write(&path, format!("{}\n", new)).unwrap();
+    let status = std::process::Command::new("kill")
+        .args(["-HUP", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let reloaded = timeout(Duration::from_secs(5), async {
+        while balancer.servers().await != vec![new.clone()] {
+            sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(reloaded.is_ok(), "servers never reloaded");
+
+    for _ in 0..3 {
+        send_get(load_balancer_port).await;
+    }
+
+    let _ = std::fs::remove_file(&path);
+    old_handle.abort();
+    new_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(old_hits.load(Ordering::SeqCst), 1);
+    assert_eq!(new_hits.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_reload_drops_tiers_of_removed_servers() {
+    let path = std::env::temp_dir().join("rust_load_balancer_servers_tiers.txt");
+    std::fs::write(&path, "127.0.0.1:8001\n127.0.0.1:8002|priority=1\n").unwrap();
+
+    let servers = read_servers_file(&path).unwrap();
+    let load_balancer = LoadBalancer::new(0, servers, "round-robin").with_servers_file(&path);
+    assert_eq!(load_balancer.priority("127.0.0.1:8002").await, 1);
+
+    // The backup leaves the file, so its tier goes with it
+    std::fs::write(&path, "127.0.0.1:8001\n").unwrap();
+    load_balancer.reload_servers().await.unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(load_balancer.priority("127.0.0.1:8002").await, 0);
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::spawn_counting_backend;
+use rust_load_balancer::balancer::{LoadBalancer, SlowStart};
+
+use std::sync::atomic::Ordering;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_slow_start_fraction_ramps_linearly() {
+    let slow_start = SlowStart::new(Duration::from_millis(500));
+    assert_eq!(slow_start.fraction("127.0.0.1:8001").await, 1.0);
+
+    slow_start.begin("127.0.0.1:8001").await;
+    assert!(slow_start.fraction("127.0.0.1:8001").await < 0.2);
+
+    sleep(Duration::from_millis(250)).await;
+    let halfway = slow_start.fraction("127.0.0.1:8001").await;
+    assert!(halfway > 0.4 && halfway < 0.8, "fraction {}", halfway);
+
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(slow_start.fraction("127.0.0.1:8001").await, 1.0);
+    assert!(slow_start.admit("127.0.0.1:8001").await);
+}
+
+#[tokio::test]
+async fn test_added_backend_gets_reduced_share_while_warming() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (established, established_hits, established_handle) = spawn_counting_backend().await;
+    let (added, added_hits, added_handle) = spawn_counting_backend().await;
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![established], "round-robin")
+        .with_slow_start(Duration::from_secs(20));
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    balancer.add_server(&added).await;
+    assert_eq!(balancer.servers().await.len(), 2);
+
+    for _ in 0..100 {
+        let mut stream = loop {
+            match TcpStream::connect(("127.0.0.1", load_balancer_port)).await {
+                Ok(stream) => break stream,
+                Err(_) => sleep(Duration::from_millis(50)).await,
+            }
+        };
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    established_handle.abort();
+    added_handle.abort();
+    load_balancer_handle.abort();
+
+    // Round-robin would give it half; early in the ramp it gets far less
+    let added_hits = added_hits.load(Ordering::SeqCst);
+    assert_eq!(added_hits + established_hits.load(Ordering::SeqCst), 100);
+    assert!(added_hits < 25, "new backend got {} of 100", added_hits);
+}
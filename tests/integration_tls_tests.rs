@@ -0,0 +1,230 @@
+mod common;
+
+use common::{
+    send_raw, spawn_echo_backend, spawn_keep_alive_tls_backend, spawn_tls_backend,
+    spawn_tls_backend_with,
+};
+use rust_load_balancer::balancer::LoadBalancer;
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, Duration};
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+#[tokio::test]
+async fn test_balancer_terminates_tls() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, backend_handle) = spawn_echo_backend().await;
+
+    // Self-signed certificate for localhost
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join("rust_load_balancer_tls_cert.pem");
+    let key_path = dir.join("rust_load_balancer_tls_key.pem");
+    std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+    std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend], "round-robin")
+        .with_tls(&cert_path, &key_path)
+        .unwrap();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut roots = RootCertStore::empty();
+    roots.add(certified.cert.der().clone()).unwrap();
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let tcp = loop {
+        match TcpStream::connect(("127.0.0.1", load_balancer_port)).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    let mut stream = connector
+        .connect(ServerName::try_from("localhost").unwrap(), tcp)
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response).await;
+    let response = String::from_utf8_lossy(&response);
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+    let _ = std::fs::remove_file(&cert_path);
+    let _ = std::fs::remove_file(&key_path);
+
+    // The backend saw a plaintext request tagged as HTTPS
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("GET / HTTP/1.1\r\n"));
+    assert!(response.contains("X-Forwarded-Proto: https\r\n"));
+}
+
+#[tokio::test]
+async fn test_balancer_dials_https_backend_trusting_its_ca() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, certified, backend_handle) = spawn_tls_backend().await;
+    let ca_path = std::env::temp_dir().join("rust_load_balancer_backend_ca.pem");
+    std::fs::write(&ca_path, certified.cert.pem()).unwrap();
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![format!("https://{}", backend)],
+        "round-robin",
+    )
+    .with_backend_tls(&ca_path)
+    .unwrap();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let pooled = send_raw(load_balancer_port, "GET /secure HTTP/1.1\r\n\r\n").await;
+    let relayed = send_raw(
+        load_balancer_port,
+        "POST /secure HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+    let _ = std::fs::remove_file(&ca_path);
+
+    assert!(pooled.starts_with("HTTP/1.1 200 OK"), "{}", pooled);
+    assert!(pooled.contains("GET /secure HTTP/1.1\r\n"), "{}", pooled);
+    assert!(relayed.starts_with("HTTP/1.1 200 OK"), "{}", relayed);
+    assert!(relayed.contains("POST /secure HTTP/1.1\r\n"), "{}", relayed);
+}
+
+#[tokio::test]
+async fn test_backend_certificate_is_verified_unless_insecure() {
+    let (backend, _, backend_handle) = spawn_tls_backend().await;
+    // The certificate names localhost, not the IP
+    let backend = format!("https://{}", backend.replace("localhost", "127.0.0.1"));
+
+    // Trusting an unrelated CA, the backend's certificate is refused
+    let other = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let ca_path = std::env::temp_dir().join("rust_load_balancer_other_ca.pem");
+    std::fs::write(&ca_path, other.cert.pem()).unwrap();
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let verified_port = lb_listener.local_addr().unwrap().port();
+    let verified = LoadBalancer::new(verified_port, vec![backend.clone()], "round-robin")
+        .with_backend_tls(&ca_path)
+        .unwrap();
+    let verified_handle = tokio::spawn(async move {
+        verified.run_with_listener(lb_listener).await;
+    });
+
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let insecure_port = lb_listener.local_addr().unwrap().port();
+    let insecure =
+        LoadBalancer::new(insecure_port, vec![backend], "round-robin").with_insecure_backend_tls();
+    let insecure_handle = tokio::spawn(async move {
+        insecure.run_with_listener(lb_listener).await;
+    });
+
+    let refused = send_raw(verified_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let accepted = send_raw(insecure_port, "GET / HTTP/1.1\r\n\r\n").await;
+
+    backend_handle.abort();
+    verified_handle.abort();
+    insecure_handle.abort();
+    let _ = std::fs::remove_file(&ca_path);
+
+    assert!(
+        refused.starts_with("HTTP/1.1 502 Bad Gateway"),
+        "{}",
+        refused
+    );
+    assert!(accepted.starts_with("HTTP/1.1 200 OK"), "{}", accepted);
+}
+
+#[tokio::test]
+async fn test_rewrite_host_covers_https_backends() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, _, backend_handle) = spawn_tls_backend_with(|own_addr, _| {
+        format!(
+            "HTTP/1.1 302 Found\r\nLocation: https://{}/login\r\nSet-Cookie: sid=1; Domain=localhost; Path=/\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            own_addr
+        )
+    })
+    .await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![format!("https://{}", backend)],
+        "round-robin",
+    )
+    .with_insecure_backend_tls()
+    .with_rewrite_host(true);
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let response = send_raw(
+        load_balancer_port,
+        "GET / HTTP/1.1\r\nHost: lb.example.com\r\n\r\n",
+    )
+    .await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    // The scheme on the backend's entry doesn't stop it being recognized
+    assert!(response.starts_with("HTTP/1.1 302 Found"), "{}", response);
+    assert!(
+        response.contains("Location: https://lb.example.com/login\r\n"),
+        "{}",
+        response
+    );
+    assert!(
+        response.contains("Set-Cookie: sid=1; Domain=lb.example.com; Path=/\r\n"),
+        "{}",
+        response
+    );
+}
+
+#[tokio::test]
+async fn test_warm_https_connection_survives_session_tickets() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (backend, accepted, backend_handle) = spawn_keep_alive_tls_backend().await;
+    let backend = format!("https://{}", backend);
+
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![backend.clone()], "round-robin")
+        .with_insecure_backend_tls()
+        .with_warm_connections(1);
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    // The tickets the backend sends after the handshake arrive while the
+    // warmed connection sits idle, across a few top-ups
+    sleep(Duration::from_millis(2500)).await;
+    let response = send_raw(load_balancer_port, "GET / HTTP/1.1\r\n\r\n").await;
+    let idle = balancer.idle_connections(&backend).await;
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("ok"), "{}", response);
+    // The warmed connection stayed good, so it was kept and then reused
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    assert_eq!(idle, 1);
+}
@@ -0,0 +1,65 @@
+#![cfg(unix)]
+
+use rust_load_balancer::balancer::LoadBalancer;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_proxies_to_unix_socket_backend() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let socket_path = std::env::temp_dir().join(format!("lb-backend-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket_path);
+
+    // Backend on a Unix socket that reports the request line it saw
+    let listener = UnixListener::bind(&socket_path).unwrap();
+    let backend_handle = tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buffer = [0; 4096];
+                let n = socket.read(&mut buffer).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..n]).to_string();
+                let body = request.lines().next().unwrap_or_default().to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+
+    let server = format!("unix:{}", socket_path.display());
+    let load_balancer = LoadBalancer::new(load_balancer_port, vec![server.clone()], "round-robin");
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", load_balancer_port)).await {
+            Ok(stream) => break stream,
+            Err(_) => sleep(Duration::from_millis(50)).await,
+        }
+    };
+    stream
+        .write_all(b"GET /over-unix HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    backend_handle.abort();
+    load_balancer_handle.abort();
+    let _ = std::fs::remove_file(&socket_path);
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(
+        response.ends_with("GET /over-unix HTTP/1.1"),
+        "{}",
+        response
+    );
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::spawn_accepting_backend;
+use rust_load_balancer::balancer::LoadBalancer;
+
+use std::sync::atomic::Ordering;
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_warm_connections_open_before_traffic() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let warm = 3;
+    let (first, first_accepted, first_handle) = spawn_accepting_backend().await;
+    let (second, second_accepted, second_handle) = spawn_accepting_backend().await;
+
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![first.clone(), second.clone()],
+        "round-robin",
+    )
+    .with_warm_connections(warm);
+    let balancer = load_balancer.clone();
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    sleep(Duration::from_millis(300)).await;
+    let first_idle = balancer.idle_connections(&first).await;
+    let second_idle = balancer.idle_connections(&second).await;
+
+    first_handle.abort();
+    second_handle.abort();
+    load_balancer_handle.abort();
+
+    assert!(first_accepted.load(Ordering::SeqCst) >= warm);
+    assert!(second_accepted.load(Ordering::SeqCst) >= warm);
+    assert_eq!(first_idle, warm);
+    assert_eq!(second_idle, warm);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{send_raw, spawn_delayed_backend};
+use rust_load_balancer::algorithms::{Algorithm, WeightedRoundRobin};
+use rust_load_balancer::balancer::{weights_for, LoadBalancer, MAX_TUNED_WEIGHT};
+
+use std::collections::HashMap;
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+
+#[tokio::test]
+async fn test_tuning_cycle_favours_faster_backend() {
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
+    let (fast, _, _, fast_handle) = spawn_delayed_backend(Duration::from_millis(5)).await;
+    let (slow, _, _, slow_handle) = spawn_delayed_backend(Duration::from_millis(100)).await;
+
+    // Start both on the same weight so any difference comes from tuning
+    let weights = HashMap::from([(fast.clone(), 5), (slow.clone(), 5)]);
+    let wrr = WeightedRoundRobin::new(Some(weights));
+    let load_balancer = LoadBalancer::new(
+        load_balancer_port,
+        vec![fast.clone(), slow.clone()],
+        "weighted-round-robin",
+    )
+    .with_algorithm(Algorithm::WeightedRoundRobin(wrr.clone()))
+    .with_weight_tuning(Duration::from_secs(1));
+    let load_balancer_handle = tokio::spawn(async move {
+        load_balancer.run_with_listener(lb_listener).await;
+    });
+
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    for _ in 0..8 {
+        let response = send_raw(load_balancer_port, request).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+    let before = wrr.weights().await;
+    // Let the first tuning cycle run
+    sleep(Duration::from_millis(1200)).await;
+    let after = wrr.weights().await;
+    let metrics = send_raw(load_balancer_port, "GET /metrics HTTP/1.1\r\n\r\n").await;
+
+    fast_handle.abort();
+    slow_handle.abort();
+    load_balancer_handle.abort();
+
+    assert_eq!(before[&fast], before[&slow]);
+    assert!(after[&fast] > after[&slow], "{:?}", after);
+    assert_eq!(after[&fast], MAX_TUNED_WEIGHT);
+    assert!(
+        metrics.contains(&format!("Tuned weight: {}", MAX_TUNED_WEIGHT)),
+        "{}",
+        metrics
+    );
+}
+
+#[tokio::test]
+async fn test_weights_are_inverse_to_latency() {
+    let latencies = HashMap::from([
+        ("fast".to_string(), Duration::from_millis(10)),
+        ("half".to_string(), Duration::from_millis(20)),
+        ("glacial".to_string(), Duration::from_secs(10)),
+    ]);
+    let weights = weights_for(&latencies);
+
+    assert_eq!(weights["fast"], MAX_TUNED_WEIGHT);
+    assert_eq!(weights["half"], MAX_TUNED_WEIGHT / 2);
+    // Never starved entirely
+    assert_eq!(weights["glacial"], 1);
+    assert!(weights_for(&HashMap::new()).is_empty());
+}
@@ -0,0 +1,46 @@
+use rust_load_balancer::algorithms::{Algorithm, LoadBalancingAlgorithm, WeightedLeastConnections};
+
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn test_weighted_least_connections_follows_weights() {
+    let servers = vec!["127.0.0.1:8001".to_string(), "127.0.0.1:8002".to_string()];
+    let weights = HashMap::from([(servers[0].clone(), 2), (servers[1].clone(), 1)]);
+    let algorithm = Algorithm::new("weighted-least-connections", Some(weights));
+
+    // Open connections without closing any so they pile up
+    let mut held = HashMap::new();
+    for _ in 0..30 {
+        let server = algorithm.next_server(&servers).await.unwrap();
+        algorithm.connection_started(&server).await;
+        *held.entry(server).or_insert(0) += 1;
+    }
+
+    // The heavier server holds twice as many connections
+    assert_eq!(held[&servers[0]], 20);
+    assert_eq!(held[&servers[1]], 10);
+
+    // Closing connections on the heavy server sends the next ones back to it
+    for _ in 0..4 {
+        algorithm.connection_ended(&servers[0]).await;
+    }
+    for _ in 0..2 {
+        let server = algorithm.next_server(&servers).await.unwrap();
+        algorithm.connection_started(&server).await;
+        assert_eq!(server, servers[0]);
+    }
+
+    let metrics = algorithm.get_metrics().await;
+    assert_eq!(metrics[&servers[0]], "Weight: 2, Active: 18, Total: 22");
+    assert_eq!(metrics[&servers[1]], "Weight: 1, Active: 10, Total: 10");
+}
+
+#[tokio::test]
+async fn test_weighted_least_connections_empty_server_list() {
+    let servers: Vec<String> = vec![];
+    let algorithm = WeightedLeastConnections::new(None);
+
+    let next_server = algorithm.next_server(&servers).await;
+
+    assert!(next_server.is_none());
+}
@@ -0,0 +1,100 @@
+use rust_load_balancer::algorithms::{Algorithm, LoadBalancingAlgorithm, WeightedP2C};
+use rust_load_balancer::rng::SharedRng;
+
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn test_weighted_p2c_samples_pairs_by_weight() {
+    let servers: Vec<String> = (8001..=8003)
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect();
+    let weights = HashMap::from([
+        (servers[0].clone(), 1),
+        (servers[1].clone(), 2),
+        (servers[2].clone(), 5),
+    ]);
+    let algorithm = WeightedP2C::new(Some(weights)).with_rng(SharedRng::seeded(7));
+
+    // With no connections open the first draw of each pair always wins
+    let picks = algorithm.select_n(&servers, 8000).await;
+    let sampled = algorithm.pair_selections().await;
+
+    // Each request samples two distinct servers
+    assert_eq!(sampled.values().sum::<usize>(), 16000);
+    assert!(sampled[&servers[0]] < sampled[&servers[1]], "{:?}", sampled);
+    assert!(sampled[&servers[1]] < sampled[&servers[2]], "{:?}", sampled);
+    // Inclusion odds for weights 1, 2 and 5 are 0.375, 0.70 and 0.92
+    for (server, expected) in servers.iter().zip([0.375, 0.702, 0.923]) {
+        let observed = sampled[server] as f64 / 8000.0;
+        assert!(
+            (observed - expected).abs() < 0.03,
+            "{} sampled {} of the time, expected {}",
+            server,
+            observed,
+            expected
+        );
+    }
+    for (server, weight) in servers.iter().zip([1.0, 2.0, 5.0]) {
+        let observed = picks[server] as f64 / 8000.0;
+        let expected = weight / 8.0;
+        assert!(
+            (observed - expected).abs() < 0.03,
+            "{} picked {} of the time, expected {}",
+            server,
+            observed,
+            expected
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_weighted_p2c_picks_less_loaded_of_pair() {
+    let servers = vec!["127.0.0.1:8001".to_string(), "127.0.0.1:8002".to_string()];
+    let weights = HashMap::from([(servers[0].clone(), 9), (servers[1].clone(), 1)]);
+    let algorithm = Algorithm::new("weighted-p2c", Some(weights));
+    assert_eq!(algorithm.name(), "weighted-p2c");
+
+    // With two servers both are always sampled, so the idle one wins however
+    // light it is
+    for _ in 0..3 {
+        algorithm.connection_started(&servers[0]).await;
+    }
+    for _ in 0..3 {
+        let server = algorithm.next_server(&servers).await.unwrap();
+        algorithm.connection_started(&server).await;
+        assert_eq!(server, servers[1]);
+    }
+
+    // Now level, the draw decides
+    let server = algorithm.next_server(&servers).await.unwrap();
+    algorithm.connection_started(&server).await;
+
+    let metrics = algorithm.get_metrics().await;
+    let state = algorithm.state().await;
+    assert_eq!(
+        state.servers[&servers[1]].active_connections,
+        Some(3 + usize::from(server == servers[1]))
+    );
+    assert_eq!(state.servers[&servers[0]].weight, Some(9));
+    assert!(
+        metrics[&servers[1]].starts_with("Weight: 1, Active: "),
+        "{:?}",
+        metrics
+    );
+    assert!(
+        metrics[&servers[1]].ends_with("Sampled: 4"),
+        "{:?}",
+        metrics
+    );
+}
+
+#[tokio::test]
+async fn test_weighted_p2c_edge_cases() {
+    let algorithm = Algorithm::new("weighted-p2c", None);
+    assert_eq!(algorithm.next_server(&[]).await, None);
+
+    let only = vec!["127.0.0.1:8001".to_string()];
+    for _ in 0..3 {
+        assert_eq!(algorithm.next_server(&only).await, Some(only[0].clone()));
+    }
+}
@@ -1,26 +1,31 @@
 use rust_load_balancer::algorithms::{LoadBalancingAlgorithm, WeightedRoundRobin};
 use rust_load_balancer::{balancer::LoadBalancer, generator::Generator, server::Server};
 
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 use tokio::{time::timeout, time::Duration};
 
 #[tokio::test]
 async fn test_round_robin_no_timeout() {
     // Servers
-    let server_port1 = 8001;
-    let server_port2 = 8002;
-    let load_balancer_port = 9000;
+    let server_listener1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port1 = server_listener1.local_addr().unwrap().port();
+    let server_listener2 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_port2 = server_listener2.local_addr().unwrap().port();
+    let lb_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let load_balancer_port = lb_listener.local_addr().unwrap().port();
 
     let server1 = Server::new(server_port1, 100, 50);
     let server2 = Server::new(server_port2, 100, 50);
 
     let server1_handle = tokio::spawn(async move {
-        server1.run().await;
+        server1.run_with_listener(server_listener1).await;
     });
 
     let server2_handle = tokio::spawn(async move {
-        server2.run().await;
+        server2.run_with_listener(server_listener2).await;
     });
 
     // LB Start w/LocalHost
@@ -30,7 +35,7 @@ async fn test_round_robin_no_timeout() {
     ];
     let load_balancer = LoadBalancer::new(load_balancer_port, servers, "weighted-round-robin");
     let load_balancer_handle = tokio::spawn(async move {
-        load_balancer.run().await;
+        load_balancer.run_with_listener(lb_listener).await;
     });
 
     // Generator w/LB Port,
@@ -70,3 +75,61 @@ async fn test_round_robin_empty_server_list() {
     // No server should be next
     assert!(next_server.is_none());
 }
+
+// Pick `count` servers in a row and return their names
+async fn sequence(weights: &[(&str, u32)], count: usize) -> String {
+    let servers: Vec<String> = weights.iter().map(|(s, _)| s.to_string()).collect();
+    let weights: HashMap<String, u32> = weights.iter().map(|(s, w)| (s.to_string(), *w)).collect();
+    let wrr = WeightedRoundRobin::new(Some(weights));
+
+    let mut picks = String::new();
+    for _ in 0..count {
+        picks.push_str(&wrr.next_server(&servers).await.unwrap());
+    }
+    picks
+}
+
+#[tokio::test]
+async fn test_weighted_round_robin_smooth_sequence() {
+    assert_eq!(sequence(&[("A", 5), ("B", 1)], 12).await, "AAABAAAAABAA");
+    assert_eq!(
+        sequence(&[("a", 5), ("b", 1), ("c", 1)], 7).await,
+        "aabacaa"
+    );
+    assert_eq!(sequence(&[("A", 1), ("B", 1)], 4).await, "ABAB");
+}
+
+#[tokio::test]
+async fn test_weight_zero_drains_server() {
+    assert_eq!(sequence(&[("A", 0), ("B", 1), ("C", 1)], 6).await, "BCBCBC");
+
+    let servers = vec!["A".to_string(), "B".to_string(), "C".to_string()];
+    let wrr = WeightedRoundRobin::new(Some(HashMap::from([
+        ("A".to_string(), 5),
+        ("B".to_string(), 1),
+        ("C".to_string(), 1),
+    ])));
+    // A has built up credit by the time it's drained
+    wrr.next_server(&servers).await.unwrap();
+    wrr.set_weights(&HashMap::from([("A".to_string(), 0)]))
+        .await;
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for _ in 0..100 {
+        *counts
+            .entry(wrr.next_server(&servers).await.unwrap())
+            .or_default() += 1;
+    }
+    assert_eq!(counts.get("A"), None);
+    assert_eq!(counts["B"], 50);
+    assert_eq!(counts["C"], 50);
+
+    // Still reported, with its weight
+    let metrics = wrr.get_metrics().await;
+    assert!(metrics["A"].starts_with("Weight: 0,"), "{}", metrics["A"]);
+
+    // Nothing to pick once every server is drained
+    wrr.set_weights(&HashMap::from([("B".to_string(), 0), ("C".to_string(), 0)]))
+        .await;
+    assert!(wrr.next_server(&servers).await.is_none());
+}